@@ -0,0 +1,146 @@
+//! Throughput of the parts of an update that dominate its time on large builds: extracting an
+//! archive, applying binary patches, hashing files and reading the manifest. The fixtures are
+//! generated, so `cargo bench` needs nothing from the server.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use dreamio_updater::{apply_update, hashing, manifest};
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use zip::write::{FileOptions, ZipWriter};
+
+const MB: usize = 1024 * 1024;
+
+/// Bytes that compress about as well as game assets do: runs of noise with repeated blocks.
+fn generate(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed | 1;
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        if state.is_multiple_of(4) && data.len() >= 4096 {
+            let start = data.len() - 4096;
+            data.extend_from_within(start..start + 512);
+        } else {
+            data.extend_from_slice(&state.to_le_bytes());
+        }
+    }
+    data.truncate(len);
+    data
+}
+
+fn write_archive(path: &Path, entries: &[(String, Vec<u8>)]) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in entries {
+        zip.start_file(name.as_str(), FileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn apply(zip_path: &Path, base_path: &Path) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    apply_update(zip_path, base_path, &sender).unwrap();
+    receiver.try_iter().for_each(drop);
+}
+
+fn extraction(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let base_path = dir.path().join("game");
+    fs::create_dir(&base_path).unwrap();
+    let zip_path = dir.path().join("update.zip");
+    let entries: Vec<(String, Vec<u8>)> = (0..64)
+        .map(|i| (format!("data/file{}.bin", i), generate(MB, i)))
+        .collect();
+    write_archive(&zip_path, &entries);
+
+    let mut group = c.benchmark_group("extraction");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((64 * MB) as u64));
+    group.bench_function("64 files of 1 MB", |b| b.iter(|| apply(&zip_path, &base_path)));
+    group.finish();
+}
+
+fn patching(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let base_path = dir.path().join("game");
+    fs::create_dir(&base_path).unwrap();
+    let zip_path = dir.path().join("update.zip");
+    let old = generate(16 * MB, 1);
+    let mut new = old.clone();
+    // A new build changes parts of a file and moves the rest around.
+    for (i, chunk) in new.chunks_mut(64 * 1024).step_by(7).enumerate() {
+        chunk[..1024].copy_from_slice(&generate(1024, i as u64));
+    }
+    new.rotate_left(3 * MB);
+    let mut patch = Vec::new();
+    qbsdiff::Bsdiff::new(&old, &new).compare(&mut patch).unwrap();
+    write_archive(&zip_path, &[("data.bin.patch".to_string(), patch)]);
+
+    let mut group = c.benchmark_group("patching");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(new.len() as u64));
+    group.bench_function("16 MB file", |b| {
+        b.iter_batched(
+            || fs::write(base_path.join("data.bin"), &old).unwrap(),
+            |_| apply(&zip_path, &base_path),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn hashing(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    fs::write(&path, generate(64 * MB, 2)).unwrap();
+
+    let mut group = c.benchmark_group("hashing");
+    group.sample_size(20);
+    group.throughput(Throughput::Bytes((64 * MB) as u64));
+    group.bench_function("64 MB file", |b| b.iter(|| hashing::sha256_file(&path).unwrap()));
+    group.finish();
+}
+
+/// A manifest of a long-running game: `versions` builds with a patch and a reverse patch each.
+fn manifest_json(versions: usize) -> String {
+    let artifact = |url: String| json!({ "url": url, "size": 123_456_789, "sha256": "ab".repeat(32) });
+    let patches: Vec<Value> = (1..versions)
+        .map(|to| {
+            let mut patch = artifact(format!("patches/{}.zip", to));
+            patch["from"] = json!((to - 1).to_string());
+            patch["to"] = json!(to.to_string());
+            patch["reverse"] = artifact(format!("patches/{}-reverse.zip", to));
+            patch
+        })
+        .collect();
+    let notes: serde_json::Map<String, Value> = (1..versions)
+        .map(|version| (version.to_string(), json!("### Fixes\n- Something that was broken")))
+        .collect();
+    json!({
+        "schemaVersion": "1.0",
+        "latest": (versions - 1).to_string(),
+        "full": artifact("full.zip".to_string()),
+        "patches": patches,
+        "releaseNotes": notes,
+    })
+    .to_string()
+}
+
+fn manifest_parsing(c: &mut Criterion) {
+    let text = manifest_json(1000);
+    let mut group = c.benchmark_group("manifest parsing");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("1000 versions", |b| {
+        b.iter(|| {
+            let json: Value = serde_json::from_str(&text).unwrap();
+            manifest::parse_manifest(&json, "https://example.com/builds").unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, extraction, patching, hashing, manifest_parsing);
+criterion_main!(benches);
@@ -0,0 +1,190 @@
+//! `analyze` looks for byte-identical files in the install, common in Unity builds that ship the
+//! same asset in several bundles, and reports the space they waste. With `--consolidate`, duplicates
+//! matching `hardlinkSafe` in `manifest.json` are replaced with hard links to a single copy. Only
+//! files the game never writes belong there: linked copies share their data, so a change to one
+//! would change all of them. Updates always write a new file instead of changing a linked one.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::lock::UpdateLock;
+use crate::manifest::cached_manifest_json;
+use crate::mods::MOD_BACKUP_DIR;
+use crate::peercache;
+use crate::plugins::PLUGINS_DIR;
+use crate::protect::{self, ProtectedPaths};
+use crate::publish::collect_files;
+use crate::{format_bytes, hashing, platform, PREFETCH_DIR};
+
+/// Smaller files take up a single allocation unit either way, so linking them saves next to
+/// nothing.
+const MIN_SIZE: u64 = 4096;
+/// Folders of the updater itself, whose copies of game files are duplicates on purpose.
+const SKIPPED_DIRS: [&str; 5] = [PREFETCH_DIR, "update_backup", MOD_BACKUP_DIR, PLUGINS_DIR, peercache::CACHE_DIR_NAME];
+const LISTED_GROUPS: usize = 20;
+
+struct DuplicateGroup {
+    size: u64,
+    /// Relative paths, sorted. The first one is kept when consolidating.
+    names: Vec<String>,
+}
+
+impl DuplicateGroup {
+    fn wasted(&self) -> u64 {
+        self.size * (self.names.len() as u64 - 1)
+    }
+}
+
+pub fn run_analyze(args: &[String]) -> i32 {
+    let mut consolidate = false;
+    let mut base_path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--consolidate" => consolidate = true,
+            _ if base_path.is_none() && !arg.starts_with('-') => base_path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                eprintln!("Usage: DreamioUpdater analyze [<install dir>] [--consolidate]");
+                return 2;
+            }
+        }
+    }
+    let base_path = base_path.unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+
+    println!("Looking for duplicate files in {}...", base_path.display());
+    let groups = match find_duplicates(&base_path) {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("Failed to scan {}: {}", base_path.display(), e);
+            return 1;
+        }
+    };
+    if groups.is_empty() {
+        println!("No duplicate files found.");
+        return 0;
+    }
+
+    for group in groups.iter().take(LISTED_GROUPS) {
+        println!(
+            "\n{} copies of {} each, {} wasted:",
+            group.names.len(),
+            format_bytes(group.size),
+            format_bytes(group.wasted())
+        );
+        for name in &group.names {
+            println!("  {}", name);
+        }
+    }
+    if groups.len() > LISTED_GROUPS {
+        println!("\n...and {} more sets of duplicates.", groups.len() - LISTED_GROUPS);
+    }
+    let wasted: u64 = groups.iter().map(DuplicateGroup::wasted).sum();
+    println!(
+        "\n{} sets of duplicate files waste {} in total.",
+        groups.len(),
+        format_bytes(wasted)
+    );
+
+    if !consolidate {
+        return 0;
+    }
+    // Updating at the same time would replace files while they are being linked.
+    let _lock = match UpdateLock::acquire(&base_path) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let safe: Vec<String> = cached_manifest_json(&base_path)["hardlinkSafe"]
+        .as_array()
+        .map(|values| values.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default();
+    if safe.is_empty() {
+        println!("The manifest marks no files as safe to link, so nothing was consolidated.");
+        return 0;
+    }
+    let protected = ProtectedPaths::load(&base_path);
+    let mut saved = 0;
+    let mut failed = 0;
+    for group in &groups {
+        let linkable = group
+            .names
+            .iter()
+            .all(|name| protect::matches_any(&safe, name) && !protected.covers(name));
+        if !linkable {
+            continue;
+        }
+        let original = base_path.join(&group.names[0]);
+        for name in &group.names[1..] {
+            match link(&original, &base_path.join(name)) {
+                Ok(()) => saved += group.size,
+                Err(e) => {
+                    eprintln!("Failed to link {}: {}", name, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!("Consolidated duplicates with hard links, saving {}.", format_bytes(saved));
+    if failed > 0 {
+        println!("{} file(s) could not be linked and were left as they are.", failed);
+        return 1;
+    }
+    0
+}
+
+/// Sets of byte-identical files, the most wasteful first. Files already hard linked to each other
+/// count as one.
+fn find_duplicates(base_path: &Path) -> io::Result<Vec<DuplicateGroup>> {
+    let mut by_size: HashMap<u64, Vec<String>> = HashMap::new();
+    for name in collect_files(base_path)? {
+        if SKIPPED_DIRS.iter().any(|dir| name.starts_with(&format!("{}/", dir))) {
+            continue;
+        }
+        let Ok(metadata) = fs::symlink_metadata(base_path.join(&name)) else {
+            continue;
+        };
+        if metadata.is_file() && metadata.len() >= MIN_SIZE {
+            by_size.entry(metadata.len()).or_default().push(name);
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (size, names) in by_size.into_iter().filter(|(_, names)| names.len() > 1) {
+        let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+        let mut seen = Vec::new();
+        for name in names {
+            let path = base_path.join(&name);
+            if let Some(identity) = platform::file_identity(&path) {
+                if seen.contains(&(identity.volume, identity.index)) {
+                    continue;
+                }
+                seen.push((identity.volume, identity.index));
+            }
+            by_hash.entry(hashing::sha256_file(&path)?).or_default().push(name);
+        }
+        for (_, mut names) in by_hash.into_iter().filter(|(_, names)| names.len() > 1) {
+            names.sort();
+            groups.push(DuplicateGroup { size, names });
+        }
+    }
+    groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then_with(|| a.names.cmp(&b.names)));
+    Ok(groups)
+}
+
+/// Replaces `duplicate` with a hard link to `original`. The link is made next to it and renamed
+/// over it, so the duplicate is never missing.
+fn link(original: &Path, duplicate: &Path) -> io::Result<()> {
+    let mut temporary = duplicate.as_os_str().to_owned();
+    temporary.push(".link");
+    let temporary = PathBuf::from(temporary);
+    fs::remove_file(&temporary).ok();
+    fs::hard_link(original, &temporary)?;
+    fs::rename(&temporary, duplicate).inspect_err(|_| {
+        fs::remove_file(&temporary).ok();
+    })
+}
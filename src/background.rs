@@ -0,0 +1,104 @@
+//! Background downloads give way to other traffic. While postponed updates and preloads are
+//! prefetched, the network interfaces are sampled every few seconds. When something else, like
+//! Steam, the Epic launcher or Windows Update, is receiving a lot, the prefetch slows to a trickle
+//! that keeps its connection alive, and goes back to full speed once the link is free again.
+//! Downloads the player is waiting for are never slowed.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use sysinfo::{NetworkExt, NetworksExt, System, SystemExt};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+/// Other traffic above this, or above a tenth of the prefetch's own speed to allow for protocol
+/// overhead, counts as another program using the link.
+const BUSY_THRESHOLD: u64 = 512 * 1024;
+const OVERHEAD_DIVISOR: u64 = 10;
+/// Speed of a prefetch while the link is busy, in bytes per second.
+const YIELDING_RATE: u64 = 64 * 1024;
+/// Quiet samples in a row before going back to full speed, so short pauses in the other download
+/// do not make both fight over the link again.
+const CALM_SAMPLES: u32 = 3;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Marks downloads as background downloads until dropped.
+pub struct BackgroundScope;
+
+impl BackgroundScope {
+    pub fn enter() -> Self {
+        ACTIVE.store(true, Ordering::Relaxed);
+        BackgroundScope
+    }
+}
+
+impl Drop for BackgroundScope {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+pub struct LinkMonitor {
+    system: System,
+    last_sample: Instant,
+    last_downloaded: u64,
+    yielding: bool,
+    calm_samples: u32,
+}
+
+impl LinkMonitor {
+    /// A monitor for a download, or `None` when the player is waiting for it.
+    pub fn for_download() -> Option<Self> {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut system = System::new();
+        system.refresh_networks_list();
+        Some(Self {
+            system,
+            last_sample: Instant::now(),
+            last_downloaded: 0,
+            yielding: false,
+            calm_samples: 0,
+        })
+    }
+
+    /// How long to wait after receiving `chunk_len` more bytes, `downloaded` in total.
+    pub fn delay(&mut self, chunk_len: usize, downloaded: u64) -> Duration {
+        let elapsed = self.last_sample.elapsed();
+        if elapsed >= SAMPLE_INTERVAL {
+            self.system.refresh_networks();
+            let received: u64 = self.system.networks().iter().map(|(_, network)| network.received()).sum();
+            let own = downloaded - self.last_downloaded;
+            let other_rate = received.saturating_sub(own) * 1000 / (elapsed.as_millis() as u64).max(1);
+            let own_rate = own * 1000 / (elapsed.as_millis() as u64).max(1);
+            self.update(other_rate, own_rate);
+            self.last_sample = Instant::now();
+            self.last_downloaded = downloaded;
+        }
+        if self.yielding {
+            Duration::from_secs_f64(chunk_len as f64 / YIELDING_RATE as f64)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    fn update(&mut self, other_rate: u64, own_rate: u64) {
+        let busy = other_rate > BUSY_THRESHOLD.max(own_rate / OVERHEAD_DIVISOR);
+        if busy {
+            self.calm_samples = 0;
+            if !self.yielding {
+                log::info!(
+                    "Other programs are downloading at {} KiB/s, slowing the background download",
+                    other_rate / 1024
+                );
+                self.yielding = true;
+            }
+        } else if self.yielding {
+            self.calm_samples += 1;
+            if self.calm_samples >= CALM_SAMPLES {
+                log::info!("The connection is free again, resuming the background download at full speed");
+                self.yielding = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Keeps what an update replaces in `update_backup/`, so an update that turns out broken can be
+//! rolled back to the build it started from. Originals are moved or hard linked there, which costs
+//! neither time nor disk space on the same volume, and files the update adds are marked so rolling
+//! back removes them. The backup spans every archive of an update, including attempts interrupted
+//! and resumed later, and is discarded once the update is known to work.
+//!
+//! With `recycleDeletedFiles` in `updater.json`, files the update deleted go to the Recycle Bin
+//! when the backup is discarded, so players can still get back what a wrong delete entry in a
+//! published patch removed. Files the update replaced are removed as usual.
+//!
+//! Files patched in place, with `inPlacePatching`, have no original left to keep. Their backup is
+//! the reverse delta of each patch, in `inplace/<file>/<n>`, applied newest first when rolling back.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::inplace;
+use crate::platform;
+use crate::publish::collect_files;
+
+const BACKUP_DIR_NAME: &str = "update_backup";
+
+fn backup_dir(base_path: &Path) -> PathBuf {
+    base_path.join(BACKUP_DIR_NAME)
+}
+
+/// Where the original of `path` is kept, and where its marker goes when the update adds it.
+/// `None` for paths outside the install.
+fn backup_paths(base_path: &Path, path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let relative = path.strip_prefix(base_path).ok()?;
+    let dir = backup_dir(base_path);
+    Some((dir.join("files").join(relative), dir.join("added").join(relative)))
+}
+
+/// Prepares for the update changing `path`. Returns the path the original is kept at, or `None`
+/// when there is nothing to keep: the update adds the file, or an earlier archive or attempt of
+/// this update already recorded it.
+fn record(base_path: &Path, path: &Path) -> io::Result<Option<PathBuf>> {
+    let Some((kept, added)) = backup_paths(base_path, path) else {
+        return Ok(None);
+    };
+    if fs::symlink_metadata(&kept).is_ok() || added.exists() {
+        return Ok(None);
+    }
+    if fs::symlink_metadata(path).is_err() {
+        create_parent(&added)?;
+        fs::write(&added, b"")?;
+        return Ok(None);
+    }
+    create_parent(&kept)?;
+    Ok(Some(kept))
+}
+
+/// Moves `path` into the backup before it is overwritten or deleted, so the caller writes a new
+/// file instead of changing the original. Returns whether it was moved.
+pub fn move_aside(base_path: &Path, path: &Path) -> io::Result<bool> {
+    match record(base_path, path)? {
+        Some(kept) => fs::rename(path, kept).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Keeps a copy of `path` before it is replaced by renaming another file over it, as patching
+/// does. The original is still read while patching, so it stays in place.
+pub fn keep_copy(base_path: &Path, path: &Path) -> io::Result<()> {
+    let Some(kept) = record(base_path, path)? else {
+        return Ok(());
+    };
+    if fs::hard_link(path, &kept).is_ok() {
+        return Ok(());
+    }
+    // File systems without hard links, like exFAT, get a copy. It is renamed into place so an
+    // interrupted copy is never taken for the original.
+    let mut partial = kept.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    if let Err(e) = fs::copy(path, &partial) {
+        // Most likely the drive is full; a partial copy would only take the space patching needs.
+        fs::remove_file(&partial).ok();
+        return Err(e);
+    }
+    fs::rename(&partial, &kept)
+}
+
+/// Where the reverse delta of an in-place patch of `path` is kept, or `None` when rolling back
+/// does not need it: the update adds the file, or the backup has a copy from before it was
+/// patched. A hard link to `path` is no copy once `path` is written in place, so it is removed.
+pub fn reverse_patch_path(base_path: &Path, path: &Path) -> io::Result<Option<PathBuf>> {
+    let Some((kept, added)) = backup_paths(base_path, path) else {
+        return Ok(None);
+    };
+    if added.exists() {
+        return Ok(None);
+    }
+    if fs::symlink_metadata(&kept).is_ok() {
+        let identity = platform::file_identity(&kept);
+        if identity.is_none() || identity != platform::file_identity(path) {
+            return Ok(None);
+        }
+        fs::remove_file(&kept)?;
+    }
+    let relative = path.strip_prefix(base_path).unwrap_or(path);
+    let dir = backup_dir(base_path).join("inplace").join(relative);
+    fs::create_dir_all(&dir)?;
+    let count = fs::read_dir(&dir)?.count();
+    Ok(Some(dir.join(count.to_string())))
+}
+
+pub fn exists(base_path: &Path) -> bool {
+    backup_dir(base_path).exists()
+}
+
+/// Puts the install back the way it was before the update and discards the backup.
+pub fn restore(base_path: &Path) -> io::Result<()> {
+    let dir = backup_dir(base_path);
+    let added_dir = dir.join("added");
+    if added_dir.exists() {
+        for name in collect_files(&added_dir)? {
+            let path = base_path.join(&name);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path)?,
+                Ok(_) => fs::remove_file(&path)?,
+                Err(_) => {}
+            }
+            remove_empty_parents(base_path, &path);
+        }
+    }
+    let files_dir = dir.join("files");
+    if files_dir.exists() {
+        for name in collect_files(&files_dir)? {
+            let path = base_path.join(&name);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path)?,
+                Ok(_) => fs::remove_file(&path)?,
+                Err(_) => {}
+            }
+            create_parent(&path)?;
+            fs::rename(files_dir.join(&name), &path)?;
+        }
+    }
+    let inplace_dir = dir.join("inplace");
+    if inplace_dir.exists() {
+        let mut reverses: Vec<(String, usize)> = collect_files(&inplace_dir)?
+            .into_iter()
+            .filter_map(|name| {
+                let (file, n) = name.rsplit_once('/')?;
+                Some((file.to_string(), n.parse().ok()?))
+            })
+            .collect();
+        reverses.sort_by(|a, b| b.cmp(a));
+        for (file, n) in reverses {
+            let reverse = inplace_dir.join(&file).join(n.to_string());
+            inplace::undo(&base_path.join(&file), &reverse)?;
+        }
+    }
+    discard(base_path);
+    Ok(())
+}
+
+/// Forgets the backup once the update is known to work.
+pub fn discard(base_path: &Path) {
+    let dir = backup_dir(base_path);
+    if dir.exists() {
+        if Config::load(base_path).recycle_deleted_files {
+            if let Err(e) = recycle_deleted(base_path) {
+                log::warn!("Failed to move deleted files to the Recycle Bin: {}", e);
+            }
+        }
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            log::warn!("Failed to remove {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Moves the originals of files the update deleted back in place and on to the Recycle Bin, so
+/// restoring them from there puts them where they were.
+fn recycle_deleted(base_path: &Path) -> io::Result<()> {
+    let files_dir = backup_dir(base_path).join("files");
+    if !files_dir.exists() {
+        return Ok(());
+    }
+    for name in collect_files(&files_dir)? {
+        let path = base_path.join(&name);
+        // Files still in the install were replaced rather than deleted.
+        if fs::symlink_metadata(&path).is_ok() {
+            continue;
+        }
+        create_parent(&path)?;
+        fs::rename(files_dir.join(&name), &path)?;
+        match platform::move_to_trash(&path) {
+            Ok(()) => log::info!("Moved deleted {} to the Recycle Bin", name),
+            Err(e) => {
+                log::warn!("Failed to move deleted {} to the Recycle Bin: {}", name, e);
+                fs::remove_file(&path)?;
+            }
+        }
+        remove_empty_parents(base_path, &path);
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Folders the update created for files it added are removed along with them.
+fn remove_empty_parents(base_path: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == base_path || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
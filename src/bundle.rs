@@ -0,0 +1,225 @@
+//! Single-file installers for distribution partners. `make-installer --version <v> -o <file>`
+//! appends a pinned manifest to a copy of the updater: the channel, the version and its full
+//! archive with size and hash. Run on a machine without the game, the copy installs like the
+//! updater does, but downloads exactly that version; afterwards it updates from the channel as
+//! usual. When the server no longer has the pinned archive, the latest version is installed
+//! instead.
+//!
+//! The manifest is stored as JSON, followed by its length as 8 little-endian bytes and `MAGIC`.
+//! Signing the installer afterwards appends a signature after it, so the end of the file is
+//! searched for `MAGIC` rather than read at a fixed offset.
+
+use crossbeam_channel::Sender;
+use serde_json::{json, Value};
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::UpdaterError;
+use crate::manifest::{self, Artifact};
+use crate::{
+    apply_update, channel_url, cleanup, download_update, format_bytes, http, load_options, ApplySummary,
+    UpdateMessage, DEFAULT_CHANNEL,
+};
+
+const MAGIC: &[u8; 16] = b"DREAMIO-BUNDLE\0\0";
+/// How far from the end of the executable the manifest is looked for, leaving room for a signature.
+const SEARCH_WINDOW: u64 = 256 * 1024;
+const MAX_MANIFEST_LEN: u64 = 64 * 1024;
+
+pub struct Bundle {
+    pub channel: String,
+    pub version: String,
+    pub full: Artifact,
+}
+
+/// The manifest pinned into this executable, when it is an installer made by `make-installer`.
+pub fn embedded() -> Option<&'static Bundle> {
+    static BUNDLE: OnceLock<Option<Bundle>> = OnceLock::new();
+    BUNDLE
+        .get_or_init(|| {
+            let exe = env::current_exe().ok()?;
+            match read(&exe) {
+                Ok(bundle) => bundle.map(|(bundle, _)| bundle),
+                Err(e) => {
+                    log::warn!("Failed to look for a pinned manifest in {}: {}", exe.display(), e);
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+/// The bundle appended to `path` and where it starts, or `None` for a plain updater.
+fn read(path: &Path) -> io::Result<Option<(Bundle, u64)>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let window = len.min(SEARCH_WINDOW);
+    file.seek(SeekFrom::Start(len - window))?;
+    let mut tail = vec![0u8; window as usize];
+    file.read_exact(&mut tail)?;
+    // `MAGIC` also appears in the updater's own code, where no valid manifest precedes it.
+    let mut end = tail.len();
+    while let Some(magic_at) = tail[..end].windows(MAGIC.len()).rposition(|bytes| bytes == MAGIC) {
+        end = magic_at;
+        if let Some((bundle, manifest_at)) = parse(&tail, magic_at) {
+            return Ok(Some((bundle, len - window + manifest_at as u64)));
+        }
+    }
+    Ok(None)
+}
+
+/// The bundle whose trailer ends with `MAGIC` at `magic_at`, and where its manifest starts.
+fn parse(tail: &[u8], magic_at: usize) -> Option<(Bundle, usize)> {
+    let length_at = magic_at.checked_sub(8)?;
+    let manifest_len = u64::from_le_bytes(tail[length_at..magic_at].try_into().ok()?);
+    if manifest_len > MAX_MANIFEST_LEN || manifest_len > length_at as u64 {
+        return None;
+    }
+    let manifest_at = length_at - manifest_len as usize;
+    let json: Value = serde_json::from_slice(&tail[manifest_at..length_at]).ok()?;
+    let full = &json["full"];
+    let bundle = Bundle {
+        channel: json["channel"].as_str()?.to_string(),
+        version: json["version"].as_str()?.to_string(),
+        full: Artifact {
+            url: full["url"].as_str()?.to_string(),
+            size: full["size"].as_u64().unwrap_or(0),
+            sha256: Some(full["sha256"].as_str()?.to_lowercase()),
+            key: None,
+        },
+    };
+    Some((bundle, manifest_at))
+}
+
+impl Bundle {
+    /// Downloads and applies the pinned version into `target_path`. Returns `Ok(None)` when the
+    /// server no longer has it, so the latest version is installed instead.
+    pub fn install(
+        &self,
+        update_zip_path: &Path,
+        target_path: &Path,
+        sender: &Sender<UpdateMessage>,
+    ) -> Result<Option<ApplySummary>, Box<dyn Error>> {
+        sender
+            .send(UpdateMessage::Log(format!(
+                "Installing version {} ({}) pinned by this installer...",
+                self.version,
+                format_bytes(self.full.size)
+            )))
+            .unwrap();
+        let replaced = match http::block_on(download_update(&self.full.url, update_zip_path, sender)) {
+            Ok(sha256) => self.full.sha256.as_deref() != Some(sha256.as_str()),
+            Err(e) if UpdaterError::is_not_found(e.as_ref()) => true,
+            Err(e) => return Err(e),
+        };
+        if replaced {
+            fs::remove_file(update_zip_path).ok();
+            sender
+                .send(UpdateMessage::Log(format!(
+                    "The server no longer has version {}. Installing the latest version instead.",
+                    self.version
+                )))
+                .unwrap();
+            return Ok(None);
+        }
+        let summary = apply_update(update_zip_path, target_path, sender)?;
+        cleanup(target_path);
+        Ok(Some(summary))
+    }
+}
+
+/// `make-installer --version <version> -o <installer>` pins the full archive of `version`, the
+/// latest of the channel, into a copy of this updater. Other options, like `--channel` and
+/// `--server`, select the manifest.
+pub fn run_make_installer(args: &[String]) -> i32 {
+    let mut version = None;
+    let mut output = None;
+    let mut update_args = vec![String::new()];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--version" => version = args.next().cloned(),
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            _ => update_args.push(arg.clone()),
+        }
+    }
+    let (Some(version), Some(output)) = (version, output) else {
+        eprintln!("Usage: DreamioUpdater make-installer --version <version> -o <installer.exe> [--channel <channel>] [--server <url>]");
+        return 2;
+    };
+
+    let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let options = load_options(&update_args, &base_path);
+    let channel = options.channel.unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+    let remote_manifest = match manifest::fetch_manifest_from(&format!("{}/manifest.json", channel_url(&channel))) {
+        Ok(Some(remote_manifest)) => remote_manifest,
+        Ok(None) => {
+            eprintln!("The update server does not publish a manifest for the {} channel.", channel);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Failed to get update manifest: {}", e);
+            return 1;
+        }
+    };
+    // Only the latest version has a full archive in the manifest.
+    if remote_manifest.latest != version {
+        eprintln!(
+            "The {} channel publishes version {}, not {}. Installers can only pin the latest version.",
+            channel, remote_manifest.latest, version
+        );
+        return 1;
+    }
+    let Some(full) = remote_manifest.full else {
+        eprintln!("The manifest has no full archive to install from.");
+        return 1;
+    };
+    let Some(sha256) = &full.sha256 else {
+        eprintln!("The manifest gives no sha256 for the full archive, so the installer could not verify it.");
+        return 1;
+    };
+    let pinned = json!({
+        "channel": channel,
+        "version": version,
+        "full": { "url": full.url, "size": full.size, "sha256": sha256 },
+    });
+
+    match write_installer(&output, &pinned) {
+        Ok(()) => {
+            println!(
+                "Wrote {}, installing version {} of the {} channel from {}.",
+                output.display(),
+                version,
+                channel,
+                full.url
+            );
+            println!("Sign it after this step; signing first would be undone by appending the manifest.");
+            0
+        }
+        Err(e) => {
+            fs::remove_file(&output).ok();
+            eprintln!("Failed to write {}: {}", output.display(), e);
+            1
+        }
+    }
+}
+
+/// Copies this executable to `output`, without a bundle it may carry itself, and appends `pinned`.
+fn write_installer(output: &Path, pinned: &Value) -> io::Result<()> {
+    let exe = env::current_exe()?;
+    let updater_len = match read(&exe)? {
+        Some((_, start)) => start,
+        None => fs::metadata(&exe)?.len(),
+    };
+    let manifest = serde_json::to_vec(pinned)?;
+    let mut out = File::create(output)?;
+    io::copy(&mut File::open(&exe)?.take(updater_len), &mut out)?;
+    out.write_all(&manifest)?;
+    out.write_all(&(manifest.len() as u64).to_le_bytes())?;
+    out.write_all(MAGIC)?;
+    out.sync_all()
+}
@@ -0,0 +1,244 @@
+//! Optional `updater.json` next to the game for settings players and admins can change:
+//!
+//! ```json
+//! {
+//!   "launchAnywayAboveMb": 500,
+//!   "telemetryEndpoint": "https://example.com/updater-failures",
+//!   "server": "https://staging.example.com/Builds/Windows",
+//!   "channel": "beta",
+//!   "proxy": "http://proxy.local:8080",
+//!   "bandwidthLimitKbps": 2048,
+//!   "retries": 3,
+//!   "connectTimeoutSeconds": 15,
+//!   "stallTimeoutSeconds": 30,
+//!   "autoLaunch": false,
+//!   "afterUpdate": "changelog",
+//!   "changelogUrl": "https://dreamio.xyz/changelog/{version}",
+//!   "saveSyncCommand": ["CloudSave.exe", "--sync"],
+//!   "maintenanceWindows": [{ "days": ["sat", "sun"], "start": "02:00", "end": "05:00" }],
+//!   "maintenanceCountdownMinutes": 10,
+//!   "recycleDeletedFiles": true,
+//!   "inPlacePatching": true,
+//!   "peerCache": "http://192.168.1.10:8750",
+//!   "shareDownloads": true,
+//!   "historyVersions": 20,
+//!   "historyMaxMb": 10,
+//!   "applyWorkers": 2,
+//!   "applyWorkerPriority": "low",
+//!   "applyWorkerCores": [2, 3],
+//!   "s3": { "endpoint": "https://minio.example.com", "region": "us-east-1", "accessKeyId": "...", "secretAccessKey": "..." }
+//! }
+//! ```
+//!
+//! Command line flags override these settings. `config show` prints the settings in effect.
+
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::maintenance::MaintenanceWindow;
+use crate::postupdate::{self, PostUpdateAction};
+use crate::source::S3Settings;
+use crate::state::{self, ROLLOUT_BUCKETS};
+use crate::workers::{self, WorkerSettings};
+use crate::{downloads_url, format_bytes, get_version_info, load_options};
+
+const CONFIG_FILE_NAME: &str = "updater.json";
+
+#[derive(Default)]
+pub struct Config {
+    /// Optional updates larger than this many bytes can be postponed to play right away.
+    pub launch_anyway_above: Option<u64>,
+    /// Failure reports are only sent when an endpoint is configured.
+    pub telemetry_endpoint: Option<String>,
+    /// Replaces the official download server, e.g. for test environments.
+    pub base_url: Option<String>,
+    pub channel: Option<String>,
+    pub proxy: Option<String>,
+    /// Maximum download speed in bytes per second.
+    pub bandwidth_limit: Option<u64>,
+    pub retries: Option<u32>,
+    pub connect_timeout: Option<Duration>,
+    pub stall_timeout: Option<Duration>,
+    /// Whether the game is started once it is up to date.
+    pub auto_launch: Option<bool>,
+    /// What happens once the game is up to date, instead of starting it.
+    pub after_update: Option<PostUpdateAction>,
+    pub changelog_url: Option<String>,
+    /// Run before the game is closed and after the update is applied, so updates never race with
+    /// a save upload. The first element is the program, relative to the game folder.
+    pub save_sync_command: Option<Vec<String>>,
+    /// Endpoint and credentials for `s3://` artifact URLs.
+    pub s3: S3Settings,
+    /// When the updater updates on its own, whoever is logged in.
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    /// How long players are warned before the game is closed for a maintenance update.
+    pub maintenance_countdown: Option<Duration>,
+    /// Files updates delete go to the Recycle Bin instead of being removed for good.
+    pub recycle_deleted_files: bool,
+    /// Files too large to patch next to the original on a full drive are patched in place.
+    pub in_place_patching: bool,
+    /// Another computer's shared downloads, looked in before the server.
+    pub peer_cache: Option<String>,
+    /// Downloaded archives are kept in `peer_cache/` for `serve-cache`.
+    pub share_downloads: bool,
+    /// How many installed versions `history` keeps, and how many bytes they may take.
+    pub history_versions: Option<usize>,
+    pub history_max_size: Option<u64>,
+    /// How many threads apply an update, at what priority and on which cores.
+    pub workers: WorkerSettings,
+}
+
+impl Config {
+    pub fn load(base_path: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(base_path.join(CONFIG_FILE_NAME)) else {
+            return Self::default();
+        };
+        let Ok(json) = serde_json::from_str::<Value>(&content) else {
+            return Self::default();
+        };
+        Self {
+            launch_anyway_above: json["launchAnywayAboveMb"]
+                .as_u64()
+                .map(|mb| mb * 1024 * 1024),
+            telemetry_endpoint: json["telemetryEndpoint"].as_str().map(|s| s.to_string()),
+            base_url: json["server"]
+                .as_str()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            channel: json["channel"].as_str().map(|c| c.to_lowercase()),
+            proxy: json["proxy"].as_str().map(|s| s.to_string()),
+            bandwidth_limit: json["bandwidthLimitKbps"].as_u64().map(|kib| kib * 1024),
+            retries: json["retries"].as_u64().map(|n| n as u32),
+            connect_timeout: json["connectTimeoutSeconds"].as_u64().map(Duration::from_secs),
+            stall_timeout: json["stallTimeoutSeconds"].as_u64().map(Duration::from_secs),
+            auto_launch: json["autoLaunch"].as_bool(),
+            after_update: PostUpdateAction::from_json(&json["afterUpdate"]),
+            changelog_url: json["changelogUrl"].as_str().map(|s| s.to_string()),
+            save_sync_command: json["saveSyncCommand"]
+                .as_array()
+                .map(|args| {
+                    args.iter()
+                        .filter_map(|arg| arg.as_str().map(|s| s.to_string()))
+                        .collect::<Vec<_>>()
+                })
+                .filter(|args| !args.is_empty()),
+            s3: S3Settings {
+                endpoint: json["s3"]["endpoint"].as_str().map(|s| s.to_string()),
+                region: json["s3"]["region"].as_str().map(|s| s.to_string()),
+                access_key_id: json["s3"]["accessKeyId"].as_str().map(|s| s.to_string()),
+                secret_access_key: json["s3"]["secretAccessKey"].as_str().map(|s| s.to_string()),
+            },
+            maintenance_windows: json["maintenanceWindows"]
+                .as_array()
+                .map(|windows| windows.iter().filter_map(MaintenanceWindow::parse).collect())
+                .unwrap_or_default(),
+            maintenance_countdown: json["maintenanceCountdownMinutes"]
+                .as_u64()
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+            recycle_deleted_files: json["recycleDeletedFiles"].as_bool().unwrap_or(false),
+            in_place_patching: json["inPlacePatching"].as_bool().unwrap_or(false),
+            peer_cache: json["peerCache"].as_str().map(|s| s.to_string()),
+            share_downloads: json["shareDownloads"].as_bool().unwrap_or(false),
+            history_versions: json["historyVersions"].as_u64().map(|n| n as usize),
+            history_max_size: json["historyMaxMb"].as_u64().map(|mb| mb * 1024 * 1024),
+            workers: WorkerSettings::parse(&json),
+        }
+    }
+}
+
+/// `config show [<install dir>] [<flags>]` prints the settings the updater would use there, after
+/// flags, `DREAMIO_UPDATE_URL` and `updater.json`, and the install's rollout bucket.
+pub fn run_config(args: &[String]) -> i32 {
+    let [command, rest @ ..] = args else {
+        eprintln!("Usage: DreamioUpdater config show [<install dir>] [<flags>]");
+        return 2;
+    };
+    if command != "show" {
+        eprintln!("Usage: DreamioUpdater config show [<install dir>] [<flags>]");
+        return 2;
+    }
+    let base_path = match rest.first().filter(|arg| !arg.starts_with('-')) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    };
+    let options = load_options(args, &base_path);
+    let config = Config::load(&base_path);
+    let info = get_version_info(&base_path).ok();
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+    let or_default = |value: Option<String>| value.unwrap_or_else(|| "default".to_string());
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+
+    println!("Install: {}", base_path.display());
+    let config_path = base_path.join(CONFIG_FILE_NAME);
+    println!(
+        "{}: {}",
+        CONFIG_FILE_NAME,
+        if config_path.exists() { "found" } else { "not found" }
+    );
+    if let Some(info) = &info {
+        println!("Installed version: {} ({})", info.version_string, info.version_code);
+    }
+    let channel = options.channel.clone().or(info.map(|info| info.channel));
+    println!("Channel: {}", or_default(channel));
+    println!("Server: {}", downloads_url());
+    println!("Proxy: {}", or_none(options.proxy.clone()));
+    println!(
+        "Bandwidth limit: {}",
+        or_none(options.bandwidth_limit.map(|limit| format!("{}/s", format_bytes(limit))))
+    );
+    println!("Retries: {}", or_default(options.retries.map(|n| n.to_string())));
+    println!(
+        "Connect timeout: {}",
+        or_default(options.connect_timeout.map(|t| format!("{} s", t.as_secs())))
+    );
+    println!(
+        "Stall timeout: {}",
+        or_default(options.stall_timeout.map(|t| format!("{} s", t.as_secs())))
+    );
+    println!("After updating: {}", postupdate::action(&options).describe());
+    let telemetry = options.telemetry_endpoint.clone().filter(|_| !options.no_telemetry);
+    println!("Failure reports: {}", telemetry.unwrap_or_else(|| "off".to_string()));
+    println!(
+        "Save sync command: {}",
+        or_none(config.save_sync_command.map(|command| command.join(" ")))
+    );
+    println!("Maintenance windows: {}", config.maintenance_windows.len());
+    println!("Recycle deleted files: {}", yes_no(config.recycle_deleted_files));
+    println!("In-place patching: {}", yes_no(config.in_place_patching));
+    println!("Peer cache: {}", or_none(options.peer_cache.clone()));
+    println!("Share downloads: {}", yes_no(config.share_downloads));
+    println!(
+        "History: {} version(s), {}",
+        or_default(config.history_versions.map(|n| n.to_string())),
+        or_default(config.history_max_size.map(format_bytes))
+    );
+    println!(
+        "Apply workers: {}{}",
+        config.workers.count(),
+        if config.workers.count.is_some() { "" } else { " (auto)" }
+    );
+    println!(
+        "Apply worker priority: {}",
+        config
+            .workers
+            .priority
+            .map(workers::priority_name)
+            .unwrap_or("auto, low while the game is running")
+    );
+    println!(
+        "Apply worker cores: {}",
+        if config.workers.cores.is_empty() {
+            "any".to_string()
+        } else {
+            config.workers.cores.iter().map(|core| core.to_string()).collect::<Vec<_>>().join(", ")
+        }
+    );
+    println!(
+        "Rollout bucket: {} of {}",
+        state::rollout_bucket(&base_path),
+        ROLLOUT_BUCKETS
+    );
+    0
+}
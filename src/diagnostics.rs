@@ -0,0 +1,96 @@
+//! Diagnostics bundle players can attach to support requests after a failed update.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sysinfo::{System, SystemExt};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::{format_bytes, format_timestamp, logging, platform};
+
+/// Files from the install directory that are copied into the bundle when present.
+const INSTALL_FILES: [&str; 4] = [
+    "version.json",
+    "updater_state.json",
+    "manifest_cache.json",
+    "updater.json",
+];
+
+static LAST_REQUEST: Mutex<Option<String>> = Mutex::new(None);
+
+/// Remembers the most recent HTTP request and its outcome for the next diagnostics bundle.
+pub fn record_request(url: &str, outcome: &str) {
+    if let Ok(mut last_request) = LAST_REQUEST.lock() {
+        *last_request = Some(format!("{} -> {}", url, outcome));
+    }
+}
+
+/// Writes a zip with the updater logs, the local version files and a summary of the system to the
+/// temp directory and returns its path.
+pub fn create_bundle(base_path: &Path, error: Option<&str>) -> io::Result<PathBuf> {
+    log::logger().flush();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = env::temp_dir().join(format!("dreamio-updater-diagnostics-{}.zip", now));
+    let mut zip = ZipWriter::new(File::create(&path)?);
+    let options = FileOptions::default();
+
+    zip.start_file("system.txt", options)?;
+    zip.write_all(system_summary(base_path, error, now).as_bytes())?;
+
+    for log_file in logging::log_files() {
+        add_file(&mut zip, &log_file, options)?;
+    }
+    for name in INSTALL_FILES {
+        add_file(&mut zip, &base_path.join(name), options)?;
+    }
+
+    zip.finish()?;
+    Ok(path)
+}
+
+fn add_file(zip: &mut ZipWriter<File>, path: &Path, options: FileOptions) -> io::Result<()> {
+    let (Ok(mut file), Some(name)) = (File::open(path), path.file_name()) else {
+        return Ok(());
+    };
+    zip.start_file(name.to_string_lossy(), options)?;
+    io::copy(&mut file, zip)?;
+    Ok(())
+}
+
+fn system_summary(base_path: &Path, error: Option<&str>, now: u64) -> String {
+    let mut system = System::new();
+    system.refresh_memory();
+    let last_request = LAST_REQUEST.lock().ok().and_then(|r| r.clone());
+
+    let mut summary = String::new();
+    writeln!(summary, "Created: {}", format_timestamp(now)).ok();
+    writeln!(summary, "Updater version: {}", env!("CARGO_PKG_VERSION")).ok();
+    writeln!(
+        summary,
+        "OS: {}",
+        system.long_os_version().unwrap_or_else(|| "unknown".to_string())
+    )
+    .ok();
+    writeln!(summary, "Memory: {}", format_bytes(system.total_memory())).ok();
+    writeln!(summary, "Install path: {}", base_path.display()).ok();
+    writeln!(
+        summary,
+        "Free disk space: {}",
+        platform::free_disk_space(base_path)
+            .map(format_bytes)
+            .unwrap_or_else(|| "unknown".to_string())
+    )
+    .ok();
+    writeln!(summary, "Last request: {}", last_request.as_deref().unwrap_or("none")).ok();
+    writeln!(summary, "Error: {}", error.unwrap_or("none")).ok();
+    summary
+}
@@ -0,0 +1,152 @@
+//! `--download-only --out <dir>` downloads the archives updating would apply into a folder, without
+//! changing the install, so an admin can stage an update once and install it on several machines,
+//! or share the folder on the LAN. Each archive is checked against the manifest's hash.
+//!
+//! The folder gets the channel's `manifest.json` and the archives laid out as on the server, e.g.
+//! `patches/123.zip`, which is what `--from-file <dir>` reads. The patch chain starts from the
+//! installed version, or `--from-version <version>` for machines on another one; without either,
+//! the full archive is downloaded.
+
+use crossbeam_channel::{Receiver, Sender};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ErrorCategory, UpdaterError};
+use crate::exit;
+use crate::hashing::sha256_file;
+use crate::manifest::{self, Artifact, UpdatePlan};
+use crate::{
+    channel_url, download_file, format_bytes, get_version_info, Options, UpdateMessage, DEFAULT_CHANNEL,
+};
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+pub fn run_download_only(options: &Options, base_path: &Path) -> i32 {
+    let Some(out) = &options.out else {
+        eprintln!("Usage: DreamioUpdater --download-only --out <dir> [--from-version <version>] [--channel <channel>]");
+        return 2;
+    };
+    let installed = get_version_info(base_path).ok();
+    let from_version = options
+        .from_version
+        .clone()
+        .or_else(|| installed.as_ref().map(|info| info.version_code.clone()));
+    let channel = options
+        .channel
+        .clone()
+        .or(installed.map(|info| info.channel))
+        .unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+
+    // Fetched from its URL rather than with `fetch_manifest`, which would update the install's cache.
+    let channel_url = channel_url(&channel);
+    let (remote_manifest, manifest_json) =
+        match manifest::fetch_manifest_document(&format!("{}/{}", channel_url, MANIFEST_FILE_NAME)) {
+            Ok(Some(document)) => document,
+            Ok(None) => {
+                eprintln!("The update server does not publish a manifest for the {} channel.", channel);
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("Failed to get update manifest: {}", e);
+                return exit::for_failure(Some(ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version)));
+            }
+        };
+    let plan = match &from_version {
+        Some(version) => manifest::plan_update(&remote_manifest, version, options.prefer_full),
+        None => remote_manifest
+            .full
+            .clone()
+            .map(UpdatePlan::Full)
+            .ok_or_else(|| "the manifest has no full archive".into()),
+    };
+    let steps: Vec<(String, Artifact)> = match plan {
+        Ok(UpdatePlan::UpToDate) => {
+            println!(
+                "Version {} is the latest of the {} channel, there is nothing to download.",
+                remote_manifest.latest, channel
+            );
+            return exit::UP_TO_DATE;
+        }
+        Ok(UpdatePlan::Patches(edges)) => edges.into_iter().map(|edge| (edge.to, edge.artifact)).collect(),
+        Ok(UpdatePlan::Full(artifact)) => vec![(remote_manifest.latest.clone(), artifact)],
+        Err(e) => {
+            eprintln!("Failed to plan update: {}", e);
+            return 1;
+        }
+    };
+
+    println!(
+        "Downloading the update from version {} to {} on the {} channel into {}.",
+        from_version.as_deref().unwrap_or("none"),
+        remote_manifest.latest,
+        channel,
+        out.display()
+    );
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    for (version, artifact) in &steps {
+        // Archives of the channel's folder keep their place, others go to the top of the folder.
+        let relative = artifact
+            .url
+            .strip_prefix(&format!("{}/", channel_url))
+            .unwrap_or_else(|| artifact.url.rsplit(['/', '\\']).next().unwrap_or(&artifact.url));
+        let path = out.join(relative);
+        println!("  {} to version {} ({})", relative, version, format_bytes(artifact.size));
+        let result = download_verified(artifact, &path, &sender);
+        print_logs(&receiver);
+        if let Err(e) = result {
+            eprintln!("Failed to download {}: {}", artifact.url, e);
+            return exit::for_failure(Some(ErrorCategory::of(e.as_ref())));
+        }
+    }
+    let written = serde_json::to_string_pretty(&manifest_json)
+        .map_err(|e| e.to_string())
+        .and_then(|content| fs::write(out.join(MANIFEST_FILE_NAME), content).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("Failed to write {}: {}", out.join(MANIFEST_FILE_NAME).display(), e);
+        return 1;
+    }
+    println!(
+        "Done. Install it with `DreamioUpdater --from-file {}` in the game folder.",
+        out.display()
+    );
+    0
+}
+
+/// Downloads `artifact` to `path` unless it is already there, as when staging is run again.
+fn download_verified(
+    artifact: &Artifact,
+    path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<(), Box<dyn Error>> {
+    if let (Some(expected), true) = (&artifact.sha256, path.exists()) {
+        if sha256_file(path)? == *expected {
+            println!("    Already downloaded.");
+            return Ok(());
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let sha256 = download_file(&artifact.url, &partial, sender)?;
+    if artifact.sha256.as_ref().is_some_and(|expected| *expected != sha256) {
+        fs::remove_file(&partial).ok();
+        return Err(
+            UpdaterError::Verification("the download does not match the hash published in the manifest".to_string()).into(),
+        );
+    }
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Retries and other notes from the download; progress is not shown.
+fn print_logs(receiver: &Receiver<UpdateMessage>) {
+    for message in receiver.try_iter() {
+        if let UpdateMessage::Log(message) = message {
+            println!("    {}", message);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! `--dry-run` shows what updating would change without changing anything. Only the manifest and
+//! the central directory at the end of each archive are downloaded, using range requests, so the
+//! list of added, replaced, patched and deleted files is known before committing to a download.
+//! Nothing is written to the install folder, not even the manifest cache or the log.
+//!
+//! `--diff <file>` also writes the changes in a canonical form for release review, one line per
+//! file sorted by path, with the hashes before and after. The fields of a file's line are
+//! separated by tabs, shown as spaces here:
+//!
+//! ```text
+//! dreamio-update-diff 1
+//! channel stable
+//! from 123
+//! to 124
+//! add     Dreamio_Data/new.assets  -               crc32:1c291ca3
+//! patch   Dreamio_Data/level0      sha256:9f2c...  sha256:41d7...
+//! delete  Dreamio_Data/old.assets  sha256:e3b0...  -
+//! ```
+//!
+//! Run against a pristine install of the previous version, the output only depends on the release,
+//! so it can be archived with it and compared with the intended change list. Hashes before are of
+//! the installed files; after, patched files have the SHA-256 from the archive's metadata and added
+//! or replaced ones the CRC-32 of the archive entry, which costs a request per file to read.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use zip::ZipArchive;
+
+use crate::manifest::{self, Artifact, UpdatePlan};
+use crate::hashing::sha256_file;
+use crate::metadata::{UpdateMetadata, METADATA_FILE_NAME};
+use crate::protect::ProtectedPaths;
+use crate::{channel_url, diagnostics, format_bytes, get_version_info, http, prefetch_path, source, Options};
+
+const DIFF_HEADER: &str = "dreamio-update-diff 1";
+
+/// Archives are read in blocks of this size, so the central directory takes one or two requests.
+const BLOCK_SIZE: u64 = 256 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Change {
+    Add,
+    Replace,
+    Patch,
+    Delete,
+}
+
+impl Change {
+    fn label(self) -> &'static str {
+        match self {
+            Change::Add => "add",
+            Change::Replace => "replace",
+            Change::Patch => "patch",
+            Change::Delete => "delete",
+        }
+    }
+}
+
+pub fn run_dry_run(options: &Options, base_path: &Path) -> i32 {
+    let info = match get_version_info(base_path) {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!("Failed to read version info: {}", e);
+            return 1;
+        }
+    };
+    let channel = options.channel.clone().unwrap_or(info.channel);
+
+    // Fetched from its URL rather than with `fetch_manifest`, which would update the cache.
+    let remote_manifest = match manifest::fetch_manifest_from(&format!("{}/manifest.json", channel_url(&channel))) {
+        Ok(Some(remote_manifest)) => manifest::with_unlocked_preload(remote_manifest),
+        Ok(None) => {
+            println!("The update server does not publish a manifest for the {} channel.", channel);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Failed to get update manifest: {}", e);
+            return 1;
+        }
+    };
+    let steps: Vec<(String, Artifact)> =
+        match manifest::plan_update(&remote_manifest, &info.version_code, options.prefer_full) {
+            Ok(UpdatePlan::UpToDate) => {
+                println!("Up to date (version {}), nothing would change.", info.version_code);
+                return 0;
+            }
+            Ok(UpdatePlan::Patches(edges)) => edges.into_iter().map(|edge| (edge.to, edge.artifact)).collect(),
+            Ok(UpdatePlan::Full(artifact)) => vec![(remote_manifest.latest.clone(), artifact)],
+            Err(e) => {
+                eprintln!("Failed to plan update: {}", e);
+                return 1;
+            }
+        };
+
+    println!(
+        "Dry run of the update from version {} to {} on the {} channel. Nothing is changed.",
+        info.version_code, remote_manifest.latest, channel
+    );
+    let protected = ProtectedPaths::load(base_path);
+    let with_hashes = options.diff.is_some();
+    let mut changes = Changes::default();
+    for (version, artifact) in &steps {
+        println!("  {} to version {} ({})", artifact.url, version, format_bytes(artifact.size));
+        if artifact.key.is_some() {
+            println!("    Encrypted preload, its files are only known once it is downloaded.");
+            changes.encrypted.push(version.clone());
+            continue;
+        }
+        let listing = match list_archive(base_path, version, artifact, with_hashes) {
+            Ok(listing) => listing,
+            Err(e) => {
+                eprintln!("    Failed to read the archive's file list: {}", e);
+                return 1;
+            }
+        };
+        for name in &listing.names {
+            if name == METADATA_FILE_NAME || name.ends_with('/') {
+                continue;
+            }
+            if let Some(touch) = protected.touched_by(base_path, name) {
+                let target = name.trim_end_matches(".delete").trim_end_matches(".patch").to_string();
+                changes.kept.insert(target, touch.describe());
+                continue;
+            }
+            record(&mut changes.files, base_path, name);
+            if with_hashes {
+                record_hash(&mut changes.new_hashes, &listing, name);
+            }
+        }
+    }
+
+    println!();
+    for (name, change) in &changes.files {
+        println!("  {:<8} {}", change.label(), name);
+    }
+    for (name, touch) in &changes.kept {
+        println!("  {:<8} {} (protected, {})", "keep", name, touch);
+    }
+    let count = |wanted: Change| changes.files.values().filter(|&&change| change == wanted).count();
+    println!();
+    println!(
+        "{} to add, {} to replace, {} to patch, {} to delete, {} protected file(s) kept.",
+        count(Change::Add),
+        count(Change::Replace),
+        count(Change::Patch),
+        count(Change::Delete),
+        changes.kept.len()
+    );
+    if !changes.encrypted.is_empty() {
+        println!("The files of {} encrypted archive(s) are not included.", changes.encrypted.len());
+    }
+    let download_size: u64 = steps.iter().map(|(_, artifact)| artifact.size).sum();
+    println!("Total download: {}", format_bytes(download_size));
+
+    let Some(diff_path) = &options.diff else {
+        return 0;
+    };
+    let diff = diff_text(base_path, &channel, &info.version_code, &remote_manifest.latest, &changes);
+    match fs::write(diff_path, diff) {
+        Ok(()) => {
+            println!("Wrote the diff to {}.", diff_path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", diff_path.display(), e);
+            1
+        }
+    }
+}
+
+/// The changes of every archive of the update, folded together.
+#[derive(Default)]
+struct Changes {
+    /// By path, so they are listed sorted.
+    files: BTreeMap<String, Change>,
+    /// The hashes files end up with, only recorded for `--diff`.
+    new_hashes: HashMap<String, String>,
+    /// Protected files the update leaves alone, with what it would have done to them.
+    kept: BTreeMap<String, &'static str>,
+    /// Versions whose archives are encrypted, so their files are unknown.
+    encrypted: Vec<String>,
+}
+
+/// The `--diff` file for updating the install in `base_path` from version `from` to `to`.
+fn diff_text(base_path: &Path, channel: &str, from: &str, to: &str, changes: &Changes) -> String {
+    let mut lines = vec![
+        DIFF_HEADER.to_string(),
+        format!("channel {}", channel),
+        format!("from {}", from),
+        format!("to {}", to),
+    ];
+    lines.extend(changes.encrypted.iter().map(|version| format!("encrypted {}", version)));
+    for (name, change) in &changes.files {
+        let old = match change {
+            Change::Add => "-".to_string(),
+            _ => installed_hash(base_path, name),
+        };
+        let new = match change {
+            Change::Delete => "-",
+            _ => changes.new_hashes.get(name).map(|hash| hash.as_str()).unwrap_or("unknown"),
+        };
+        lines.push(format!("{}\t{}\t{}\t{}", change.label(), name, old, new));
+    }
+    for (name, touch) in &changes.kept {
+        lines.push(format!("keep\t{}\t{}", name, touch));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Hash of the installed file `name`, or `dir` for a folder.
+fn installed_hash(base_path: &Path, name: &str) -> String {
+    let path = base_path.join(name);
+    if path.is_dir() {
+        return "dir".to_string();
+    }
+    match sha256_file(&path) {
+        Ok(hash) => format!("sha256:{}", hash),
+        Err(_) => "missing".to_string(),
+    }
+}
+
+/// Folds the hash archive entry `name` leaves its file with into those of the archives before it.
+fn record_hash(hashes: &mut HashMap<String, String>, listing: &Listing, name: &str) {
+    if let Some(target) = name.strip_suffix(".delete") {
+        hashes.remove(target);
+    } else if let Some(target) = name.strip_suffix(".patch") {
+        let hash = listing
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.files.get(target))
+            .map(|hashes| format!("sha256:{}", hashes.new));
+        match hash {
+            Some(hash) => hashes.insert(target.to_string(), hash),
+            None => hashes.remove(target),
+        };
+    } else if let Some(crc) = listing.crcs.get(name) {
+        hashes.insert(name.to_string(), format!("crc32:{:08x}", crc));
+    }
+}
+
+/// Folds archive entry `name` into the changes of the archives before it in the chain.
+fn record(changes: &mut BTreeMap<String, Change>, base_path: &Path, name: &str) {
+    let exists = |target: &str| fs::symlink_metadata(base_path.join(target)).is_ok();
+    if let Some(target) = name.strip_suffix(".delete") {
+        match changes.get(target) {
+            // Added by an earlier patch of the chain and gone again.
+            Some(Change::Add) => {
+                changes.remove(target);
+            }
+            Some(_) => {
+                changes.insert(target.to_string(), Change::Delete);
+            }
+            None if exists(target) => {
+                changes.insert(target.to_string(), Change::Delete);
+            }
+            None => {}
+        }
+    } else if let Some(target) = name.strip_suffix(".patch") {
+        if !matches!(changes.get(target), Some(Change::Add | Change::Replace)) {
+            changes.insert(target.to_string(), Change::Patch);
+        }
+    } else {
+        let change = match changes.get(name) {
+            Some(Change::Add) => Change::Add,
+            Some(_) => Change::Replace,
+            None if exists(name) => Change::Replace,
+            None => Change::Add,
+        };
+        changes.insert(name.to_string(), change);
+    }
+}
+
+/// What the dry run needs to know about an archive.
+struct Listing {
+    /// Sorted.
+    names: Vec<String>,
+    /// Only read for `--diff`.
+    crcs: HashMap<String, u32>,
+    metadata: Option<UpdateMetadata>,
+}
+
+/// Lists the archive, read from an earlier prefetch when there is one. `with_hashes` also reads
+/// the checksum of every entry and the update metadata.
+fn list_archive(
+    base_path: &Path,
+    version: &str,
+    artifact: &Artifact,
+    with_hashes: bool,
+) -> Result<Listing, Box<dyn Error>> {
+    let prefetched = prefetch_path(base_path, version, artifact);
+    if prefetched.exists() {
+        return read_listing(ZipArchive::new(File::open(prefetched)?)?, with_hashes);
+    }
+    match source::http_url(&artifact.url) {
+        Some(url) => read_listing(ZipArchive::new(RemoteArchive::open(&url, artifact.size)?)?, with_hashes),
+        None => {
+            let path = source::local_path(&artifact.url).unwrap_or_default();
+            read_listing(ZipArchive::new(File::open(path)?)?, with_hashes)
+        }
+    }
+}
+
+fn read_listing<R: Read + Seek>(mut archive: ZipArchive<R>, with_hashes: bool) -> Result<Listing, Box<dyn Error>> {
+    let mut names: Vec<String> = archive.file_names().map(|name| name.to_string()).collect();
+    names.sort();
+    let mut listing = Listing {
+        names,
+        crcs: HashMap::new(),
+        metadata: None,
+    };
+    if !with_hashes {
+        return Ok(listing);
+    }
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)?;
+        listing.crcs.insert(file.name().to_string(), file.crc32());
+    }
+    if let Ok(mut file) = archive.by_name(METADATA_FILE_NAME) {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        listing.metadata = Some(UpdateMetadata::parse(&content)?);
+    }
+    Ok(listing)
+}
+
+/// An archive on a web server, read with range requests as far as the zip reader seeks.
+struct RemoteArchive {
+    client: reqwest::blocking::Client,
+    url: String,
+    len: u64,
+    position: u64,
+    block: Option<(u64, Vec<u8>)>,
+}
+
+impl RemoteArchive {
+    fn open(url: &str, size: u64) -> Result<Self, Box<dyn Error>> {
+        let client = http::client(Duration::from_secs(30))?;
+        let len = if size > 0 {
+            size
+        } else {
+            let response = client.head(url).send()?;
+            response
+                .content_length()
+                .filter(|&len| len > 0)
+                .ok_or("the server does not report the archive's size")?
+        };
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            len,
+            position: 0,
+            block: None,
+        })
+    }
+
+    /// Fetches the block holding `position`. Blocks are aligned, so the zip reader scanning
+    /// backwards for the end of the archive stays within one.
+    fn fetch(&mut self, position: u64) -> io::Result<()> {
+        let start = position / BLOCK_SIZE * BLOCK_SIZE;
+        let end = (start + BLOCK_SIZE).min(self.len) - 1;
+        // Signed URLs carry their credentials in the query.
+        let description = self.url.split('?').next().unwrap_or(&self.url).to_string();
+        let response = self
+            .client
+            .get(&self.url)
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end))
+            .send()
+            .map_err(|e| {
+                diagnostics::record_request(&description, &e.to_string());
+                io::Error::other(e)
+            })?;
+        diagnostics::record_request(&description, response.status().as_str());
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(io::Error::other(format!(
+                "the server does not support partial downloads (HTTP {})",
+                response.status()
+            )));
+        }
+        let data = response
+            .bytes()
+            .map_err(io::Error::other)?;
+        self.block = Some((start, data.to_vec()));
+        Ok(())
+    }
+}
+
+impl Read for RemoteArchive {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let cached = matches!(
+            &self.block,
+            Some((start, data)) if self.position >= *start && self.position < start + data.len() as u64
+        );
+        if !cached {
+            self.fetch(self.position)?;
+        }
+        let Some((start, data)) = &self.block else {
+            return Ok(0);
+        };
+        let offset = (self.position - start) as usize;
+        if offset >= data.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(data.len() - offset);
+        buf[..n].copy_from_slice(&data[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RemoteArchive {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.len as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+        };
+        if position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the archive",
+            ));
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::FileHashes;
+
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn diff_is_sorted_tab_separated_and_stable() {
+        let install = tempfile::tempdir().unwrap();
+        let base_path = install.path();
+        fs::create_dir(base_path.join("Dreamio_Data")).unwrap();
+        fs::write(base_path.join("Dreamio.exe"), "").unwrap();
+        fs::write(base_path.join("Dreamio_Data/level0"), "abc").unwrap();
+        fs::write(base_path.join("Dreamio_Data/old.assets"), "").unwrap();
+        fs::write(base_path.join("Dreamio_Data/unknown.bin"), "abc").unwrap();
+
+        let mut metadata = UpdateMetadata::default();
+        metadata.files.insert(
+            "Dreamio_Data/level0".to_string(),
+            FileHashes {
+                old: ABC_SHA256.to_string(),
+                new: EMPTY_SHA256.to_string(),
+            },
+        );
+        let listing = Listing {
+            names: Vec::new(),
+            crcs: HashMap::from([
+                ("Dreamio.exe".to_string(), 0xabcd),
+                ("Dreamio_Data/new.assets".to_string(), 0x1c291ca3),
+            ]),
+            metadata: Some(metadata),
+        };
+        let mut changes = Changes::default();
+        // Out of order, the diff sorts them by path.
+        for name in [
+            "Dreamio_Data/unknown.bin.patch",
+            "Dreamio_Data/old.assets.delete",
+            "Dreamio_Data/new.assets",
+            "Dreamio_Data/level0.patch",
+            "Dreamio.exe",
+        ] {
+            record(&mut changes.files, base_path, name);
+            record_hash(&mut changes.new_hashes, &listing, name);
+        }
+        changes.kept.insert("saves/options.cfg".to_string(), "would be overwritten");
+        changes.encrypted.push("125".to_string());
+
+        let expected = format!(
+            "dreamio-update-diff 1\n\
+             channel beta\n\
+             from 123\n\
+             to 125\n\
+             encrypted 125\n\
+             replace\tDreamio.exe\tsha256:{empty}\tcrc32:0000abcd\n\
+             patch\tDreamio_Data/level0\tsha256:{abc}\tsha256:{empty}\n\
+             add\tDreamio_Data/new.assets\t-\tcrc32:1c291ca3\n\
+             delete\tDreamio_Data/old.assets\tsha256:{empty}\t-\n\
+             patch\tDreamio_Data/unknown.bin\tsha256:{abc}\tunknown\n\
+             keep\tsaves/options.cfg\twould be overwritten\n",
+            empty = EMPTY_SHA256,
+            abc = ABC_SHA256,
+        );
+        assert_eq!(diff_text(base_path, "beta", "123", "125", &changes), expected);
+    }
+}
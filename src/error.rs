@@ -0,0 +1,217 @@
+//! Errors the updater has to tell apart to decide what to do next: whether a missing patch means
+//! there are no more updates, whether a download is worth retrying, whether the server could be
+//! reached at all. They travel as `Box<dyn Error>`, or inside an `io::Error`, like every other
+//! error, and are recognized with `UpdaterError::find` instead of by their message.
+//!
+//! `ErrorCategory` sorts failures for failure reports, help links, support codes and exit codes,
+//! by the error behind them rather than by what the message says.
+
+use reqwest::StatusCode;
+use std::error::Error;
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum UpdaterError {
+    /// The server answered, but not with the file.
+    #[error("HTTP error: {0}")]
+    HttpStatus(StatusCode),
+    /// A web page came back where JSON was expected, usually from a firewall in between. Holds
+    /// the page, which often names what blocked the request.
+    #[error("Received an HTML response instead of JSON. A security appliance or firewall might be blocking the request. Please check your firewall software, for instance Xfinity Advanced Security.")]
+    HtmlResponse(String),
+    /// The server could not be reached or the connection broke.
+    #[error(transparent)]
+    Network(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A patch could not be applied and there was no full file to fall back to.
+    #[error("{0}")]
+    Patch(String),
+    /// The manifest is missing, malformed or in a format this updater cannot read.
+    #[error("{0}")]
+    Manifest(String),
+    /// A download does not match its published hash.
+    #[error("{0}")]
+    Verification(String),
+    /// The drive cannot hold what the update has to write.
+    #[error("{0}")]
+    DiskSpace(String),
+    /// Files of an update could not be applied, sorted by what went wrong with the first of them.
+    #[error("{1}")]
+    FilesFailed(ErrorCategory, String),
+}
+
+impl UpdaterError {
+    /// The `UpdaterError` behind `error`, looking through `io::Error`s and error sources.
+    pub fn find<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a UpdaterError> {
+        chain(error).find_map(|error| error.downcast_ref::<UpdaterError>())
+    }
+
+    /// Whether the server said the file does not exist.
+    pub fn is_not_found(error: &(dyn Error + 'static)) -> bool {
+        matches!(Self::find(error), Some(UpdaterError::HttpStatus(StatusCode::NOT_FOUND)))
+    }
+
+    /// Whether the server could not be reached at all, as opposed to a bad response.
+    pub fn is_network(error: &(dyn Error + 'static)) -> bool {
+        let reqwest_error = match Self::find(error) {
+            Some(UpdaterError::Network(e)) => Some(e),
+            _ => error.downcast_ref::<reqwest::Error>(),
+        };
+        reqwest_error.map(|e| e.is_connect() || e.is_timeout()).unwrap_or(false)
+    }
+
+    /// Whether trying again cannot help: the server refused the request or does not have the
+    /// file, or what it sent is wrong. Timeouts, rate limits and server errors are worth a retry.
+    pub fn is_permanent(error: &(dyn Error + 'static)) -> bool {
+        match Self::find(error) {
+            Some(UpdaterError::HttpStatus(status)) => {
+                status.is_client_error()
+                    && *status != StatusCode::REQUEST_TIMEOUT
+                    && *status != StatusCode::TOO_MANY_REQUESTS
+            }
+            Some(UpdaterError::Manifest(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// `error` and the errors behind it, looking into `io::Error`s that wrap another error.
+fn chain<'a>(error: &'a (dyn Error + 'static)) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(Some(error), |&error| {
+        match error.downcast_ref::<io::Error>().and_then(|e| e.get_ref()) {
+            Some(inner) => Some(inner as &(dyn Error + 'static)),
+            None => error.source(),
+        }
+    })
+}
+
+/// The coarse kind of a failure. Failure reports send only this, and it picks the help link, the
+/// prefix of support codes and the exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The update server could not be reached.
+    Network,
+    /// The server answered, but not as expected.
+    Http,
+    /// A patch or download was wrong.
+    Patch,
+    /// Antivirus software blocked a file.
+    Antivirus,
+    /// The updater may not change the game files.
+    Permissions,
+    /// The drive is full.
+    Disk,
+    /// The installed or published version could not be read.
+    Version,
+    Other,
+}
+
+impl ErrorCategory {
+    /// The name failure reports, help links and result records use.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::Network => "network",
+            ErrorCategory::Http => "http",
+            ErrorCategory::Patch => "patch",
+            ErrorCategory::Antivirus => "antivirus",
+            ErrorCategory::Permissions => "permissions",
+            ErrorCategory::Disk => "disk",
+            ErrorCategory::Version => "version",
+            ErrorCategory::Other => "other",
+        }
+    }
+
+    /// The category of `error`, by the `UpdaterError`, `io::Error` or `reqwest::Error` behind it.
+    pub fn of(error: &(dyn Error + 'static)) -> Self {
+        for error in chain(error) {
+            let category = match error.downcast_ref::<UpdaterError>() {
+                Some(UpdaterError::HttpStatus(_) | UpdaterError::HtmlResponse(_)) => Some(ErrorCategory::Http),
+                Some(UpdaterError::Network(e)) => Some(Self::of_request(e)),
+                Some(UpdaterError::Patch(_) | UpdaterError::Verification(_)) => Some(ErrorCategory::Patch),
+                Some(UpdaterError::Manifest(_)) => Some(ErrorCategory::Version),
+                Some(UpdaterError::DiskSpace(_)) => Some(ErrorCategory::Disk),
+                Some(UpdaterError::FilesFailed(category, _)) => Some(*category),
+                Some(UpdaterError::Io(e)) => Self::of_io(e),
+                None => error
+                    .downcast_ref::<io::Error>()
+                    .and_then(Self::of_io)
+                    .or_else(|| error.downcast_ref::<reqwest::Error>().map(Self::of_request)),
+            };
+            if let Some(category) = category {
+                return category;
+            }
+        }
+        ErrorCategory::Other
+    }
+
+    /// `fallback` when the error itself does not tell, for failures whose step already does.
+    pub fn or(self, fallback: Self) -> Self {
+        match self {
+            ErrorCategory::Other => fallback,
+            category => category,
+        }
+    }
+
+    fn of_request(error: &reqwest::Error) -> Self {
+        if error.is_status() {
+            ErrorCategory::Http
+        } else {
+            ErrorCategory::Network
+        }
+    }
+
+    fn of_io(error: &io::Error) -> Option<Self> {
+        // ERROR_VIRUS_INFECTED and ERROR_VIRUS_DELETED, when Windows Defender or another scanner
+        // refuses a file.
+        if cfg!(windows) && matches!(error.raw_os_error(), Some(225 | 226)) {
+            return Some(ErrorCategory::Antivirus);
+        }
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Some(ErrorCategory::Permissions),
+            io::ErrorKind::StorageFull => Some(ErrorCategory::Disk),
+            io::ErrorKind::TimedOut => Some(ErrorCategory::Network),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categorizes_by_the_error_behind_the_message() {
+        let html = UpdaterError::HtmlResponse("<html>Blocked</html>".to_string());
+        assert_eq!(ErrorCategory::of(&html), ErrorCategory::Http);
+        let wrapped = io::Error::other(UpdaterError::Verification("the download does not match".to_string()));
+        assert_eq!(ErrorCategory::of(&wrapped), ErrorCategory::Patch);
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "Access is denied.");
+        assert_eq!(ErrorCategory::of(&denied), ErrorCategory::Permissions);
+        let full = io::Error::from(io::ErrorKind::StorageFull);
+        assert_eq!(ErrorCategory::of(&UpdaterError::Io(full)), ErrorCategory::Disk);
+        // What in-place patching fails with, though it happens while patching.
+        let no_room = io::Error::other(UpdaterError::DiskSpace("Another 12 MB are needed.".to_string()));
+        assert_eq!(ErrorCategory::of(&no_room).or(ErrorCategory::Patch), ErrorCategory::Disk);
+    }
+
+    #[test]
+    fn failed_files_keep_the_category_of_the_first() {
+        let error = io::Error::other(UpdaterError::FilesFailed(
+            ErrorCategory::Antivirus,
+            "1 file(s) could not be updated: Dreamio.exe".to_string(),
+        ));
+        assert_eq!(ErrorCategory::of(&error), ErrorCategory::Antivirus);
+        assert_eq!(error.to_string(), "1 file(s) could not be updated: Dreamio.exe");
+    }
+
+    #[test]
+    fn messages_alone_do_not_pick_a_category() {
+        // Mentioning a patch or a version does not make an error one.
+        let error = io::Error::other("Error applying patch to version.json");
+        assert_eq!(ErrorCategory::of(&error), ErrorCategory::Other);
+        assert_eq!(ErrorCategory::of(&error).or(ErrorCategory::Version), ErrorCategory::Version);
+        assert_eq!(ErrorCategory::Disk.or(ErrorCategory::Patch), ErrorCategory::Disk);
+    }
+}
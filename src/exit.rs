@@ -0,0 +1,44 @@
+//! Exit codes, so the game or launcher that starts the updater can react to how it ended without
+//! parsing its output:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0    | The game was already up to date. |
+//! | 1    | The update failed for another reason. |
+//! | 2    | An update was installed, whether the game was started after it or not. |
+//! | 3    | The update server could not be reached or did not respond as expected. |
+//! | 4    | A download or patched file did not match its published hash, or a patch failed. |
+//! | 5    | There is not enough free disk space. |
+//! | 6    | The install folder needs administrator rights. |
+//! | 130  | The player cancelled the update; the next start continues it. |
+//!
+//! Codes are never reused for another meaning; new failures get new codes.
+
+use crate::error::ErrorCategory;
+
+pub const UP_TO_DATE: i32 = 0;
+pub const FAILED: i32 = 1;
+/// Also when the game was started after updating, so launchers can tell an update happened.
+pub const UPDATED: i32 = 2;
+pub const NETWORK: i32 = 3;
+pub const VERIFICATION: i32 = 4;
+pub const DISK_SPACE: i32 = 5;
+pub const NEEDS_ELEVATION: i32 = 6;
+/// What shells report for a process stopped by Ctrl+C.
+pub const CANCELLED: i32 = 130;
+
+/// The exit code of an update that failed with an error of `category`.
+pub fn for_failure(category: Option<ErrorCategory>) -> i32 {
+    match category {
+        Some(ErrorCategory::Network | ErrorCategory::Http) => NETWORK,
+        Some(ErrorCategory::Patch) => VERIFICATION,
+        Some(ErrorCategory::Disk) => DISK_SPACE,
+        Some(ErrorCategory::Permissions) => NEEDS_ELEVATION,
+        _ => FAILED,
+    }
+}
+
+/// Whether `code` means the update did not end in an up-to-date game.
+pub fn is_failure(code: i32) -> bool {
+    code != UP_TO_DATE && code != UPDATED
+}
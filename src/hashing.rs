@@ -0,0 +1,144 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// Hashes of files read or written while updating, so a file is not read again just to be hashed.
+/// Finding modified files, checking a patch's source and checking its result each read the whole
+/// file, and a patch chain patches the same files archive after archive; on hard disks that
+/// reading took longer than the patching. A hash only counts while the file has the size and
+/// modification time it had when it was taken.
+static KNOWN_HASHES: OnceLock<Mutex<HashMap<PathBuf, KnownHash>>> = OnceLock::new();
+
+struct KnownHash {
+    len: u64,
+    modified: SystemTime,
+    sha256: String,
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    sha256_reader(&mut File::open(path)?)
+}
+
+/// `sha256_file`, but only reads the file when its hash is not known yet.
+pub fn sha256_file_cached(path: &Path) -> io::Result<String> {
+    if let Some(sha256) = known_sha256(path) {
+        return Ok(sha256);
+    }
+    let stamp = stamp(path);
+    let sha256 = sha256_file(path)?;
+    // Taken before reading, so a file changed meanwhile is not matched to this hash.
+    if let Some((len, modified)) = stamp {
+        store(path, len, modified, &sha256);
+    }
+    Ok(sha256)
+}
+
+/// The hash of `path` if it was taken before and the file has not changed since.
+pub fn known_sha256(path: &Path) -> Option<String> {
+    let (len, modified) = stamp(path)?;
+    let known = KNOWN_HASHES.get()?.lock().ok()?;
+    let hash = known.get(path)?;
+    (hash.len == len && hash.modified == modified).then(|| hash.sha256.clone())
+}
+
+/// Records `sha256` as the hash of what `path` holds now, e.g. after writing it.
+pub fn remember(path: &Path, sha256: &str) {
+    if let Some((len, modified)) = stamp(path) {
+        store(path, len, modified, sha256);
+    }
+}
+
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+fn store(path: &Path, len: u64, modified: SystemTime, sha256: &str) {
+    let known = KNOWN_HASHES.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut known) = known.lock() {
+        let sha256 = sha256.to_string();
+        known.insert(path.to_path_buf(), KnownHash { len, modified, sha256 });
+    }
+}
+
+pub fn sha256_reader<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    update_from_reader(&mut hasher, reader)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub fn update_from_reader<R: Read>(hasher: &mut Sha256, reader: &mut R) -> io::Result<()> {
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..n]);
+    }
+}
+
+/// Hashes everything written through it on the way to `inner`.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn sha256(&self) -> String {
+        hex_digest(&self.hasher)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hash of everything fed to `hasher` so far, without consuming it.
+pub fn hex_digest(hasher: &Sha256) -> String {
+    to_hex(&hasher.clone().finalize())
+}
+
+pub fn sha256_bytes(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// HMAC-SHA256 of `data` with `key`, as used for signing S3 requests.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,48 @@
+//! Help shown when an update fails, picked by error category and the player's language. The
+//! manifest's `helpLinks` map categories to links per language, so guidance can change without
+//! shipping a new updater:
+//!
+//! ```json
+//! "helpLinks": { "antivirus": { "en": "https://...", "de-AT": "https://..." }, "other": { "en": "https://..." } }
+//! ```
+//!
+//! A link for the exact locale wins over one for the language, then English, then any language.
+//! Categories without a link fall back to `other`, and then to downloading the game manually.
+
+use serde_json::Value;
+use std::path::Path;
+
+use crate::error::ErrorCategory;
+use crate::{downloads_url, manifest, platform};
+
+pub struct HelpLink {
+    pub text: &'static str,
+    pub url: String,
+}
+
+pub fn for_error(base_path: &Path, category: ErrorCategory) -> HelpLink {
+    let links = manifest::cached_manifest_json(base_path)["helpLinks"].clone();
+    let locale = platform::user_locale().unwrap_or_else(|| "en".to_string());
+    match localized(&links[category.name()], &locale).or_else(|| localized(&links["other"], &locale)) {
+        Some(url) => HelpLink {
+            text: "Please try again. If the issue persists, this guide may help:",
+            url,
+        },
+        None => HelpLink {
+            text: "Please try again. If the issue persists, you can download the latest version of the game manually:",
+            url: format!("{}/latest.zip", downloads_url()),
+        },
+    }
+}
+
+/// Picks the link for `locale`, such as `de-AT`, from links keyed by locale or language.
+fn localized(links: &Value, locale: &str) -> Option<String> {
+    let links = links.as_object()?;
+    let language = locale.split('-').next().unwrap_or(locale);
+    [locale, language, "en"]
+        .iter()
+        .find_map(|key| links.get(*key))
+        .or_else(|| links.values().next())
+        .and_then(|url| url.as_str())
+        .map(|url| url.to_string())
+}
@@ -0,0 +1,334 @@
+//! The updater keeps the manifest of each version it installs in `history/<version>.json`, along
+//! with the files the update to it added, replaced, patched and deleted. That answers questions
+//! about earlier versions without the server: `history` lists them and which reverse patches lead
+//! back to each, and `history file <path>` tells which version added or last changed a file.
+//!
+//! Only the newest versions are kept, `historyVersions` in `updater.json` (20 by default) and no
+//! more than `historyMaxMb` (10 MB by default) together; `history prune` trims them further.
+//! Versions installed from the full archive record no files, since it writes every one of them.
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::ZipArchive;
+
+use crate::config::Config;
+use crate::manifest;
+use crate::metadata::METADATA_FILE_NAME;
+use crate::{format_bytes, format_timestamp, get_version_info};
+
+const HISTORY_DIR_NAME: &str = "history";
+const DEFAULT_VERSIONS: usize = 20;
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// What an archive does to the install, read before it is applied.
+#[derive(Default)]
+pub struct Changes {
+    full: bool,
+    added: Vec<String>,
+    replaced: Vec<String>,
+    patched: Vec<String>,
+    deleted: Vec<String>,
+}
+
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    version: String,
+    channel: String,
+    installed_at: u64,
+    full: bool,
+    files: HashMap<String, &'static str>,
+    manifest: Value,
+}
+
+/// Lists the changes of the archive at `zip_path` to the install in `base_path`. Files already
+/// there are replaced rather than added.
+pub fn archive_changes(zip_path: &Path, base_path: &Path, full: bool) -> io::Result<Changes> {
+    let mut changes = Changes {
+        full,
+        ..Changes::default()
+    };
+    if full {
+        return Ok(changes);
+    }
+    let archive = ZipArchive::new(io::BufReader::new(File::open(zip_path)?))?;
+    for name in archive.file_names() {
+        if name == METADATA_FILE_NAME || name.ends_with('/') {
+            continue;
+        }
+        if let Some(file) = name.strip_suffix(".patch") {
+            changes.patched.push(file.to_string());
+        } else if let Some(file) = name.strip_suffix(".delete") {
+            changes.deleted.push(file.to_string());
+        } else if base_path.join(name).exists() {
+            changes.replaced.push(name.to_string());
+        } else {
+            changes.added.push(name.to_string());
+        }
+    }
+    Ok(changes)
+}
+
+/// Records that `version` of `channel` was just installed with `changes`, keeping the manifest it
+/// was installed from, and drops the oldest versions beyond the configured limits.
+pub fn record(base_path: &Path, version: &str, channel: &str, changes: Changes) {
+    let installed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let record = json!({
+        "version": version,
+        "channel": channel,
+        "installedAt": installed_at,
+        "full": changes.full,
+        "files": {
+            "added": changes.added,
+            "replaced": changes.replaced,
+            "patched": changes.patched,
+            "deleted": changes.deleted,
+        },
+        "manifest": manifest::cached_manifest_json(base_path),
+    });
+    let dir = base_path.join(HISTORY_DIR_NAME);
+    let written = fs::create_dir_all(&dir)
+        .and_then(|_| serde_json::to_string(&record).map_err(io::Error::from))
+        .and_then(|content| fs::write(dir.join(file_name(version)), content));
+    if let Err(e) = written {
+        log::warn!("Failed to record version {} in the history: {}", version, e);
+        return;
+    }
+    let config = Config::load(base_path);
+    prune(
+        base_path,
+        config.history_versions.unwrap_or(DEFAULT_VERSIONS),
+        config.history_max_size.unwrap_or(DEFAULT_MAX_SIZE),
+    );
+}
+
+/// Version codes are used as file names, so characters Windows does not allow are replaced.
+fn file_name(version: &str) -> String {
+    let name: String = version
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect();
+    format!("{}.json", name)
+}
+
+/// The recorded versions, oldest first.
+fn entries(base_path: &Path) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = fs::read_dir(base_path.join(HISTORY_DIR_NAME))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|dir_entry| {
+            let path = dir_entry.path();
+            let content = fs::read_to_string(&path).ok()?;
+            let record: Value = serde_json::from_str(&content).ok()?;
+            let mut files = HashMap::new();
+            for kind in ["added", "replaced", "patched", "deleted"] {
+                for file in record["files"][kind].as_array().into_iter().flatten() {
+                    if let Some(file) = file.as_str() {
+                        files.insert(file.to_string(), kind);
+                    }
+                }
+            }
+            Some(Entry {
+                size: content.len() as u64,
+                version: record["version"].as_str()?.to_string(),
+                channel: record["channel"].as_str().unwrap_or_default().to_string(),
+                installed_at: record["installedAt"].as_u64().unwrap_or(0),
+                full: record["full"].as_bool().unwrap_or(false),
+                files,
+                manifest: record["manifest"].clone(),
+                path,
+            })
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.installed_at);
+    entries
+}
+
+/// Removes the oldest versions until at most `keep` remain and they take at most `max_size`
+/// bytes. The newest version is always kept. Returns how many were removed and their size.
+fn prune(base_path: &Path, keep: usize, max_size: u64) -> (usize, u64) {
+    let entries = entries(base_path);
+    let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let mut count = entries.len();
+    let mut removed = (0, 0);
+    for entry in &entries[..entries.len().saturating_sub(1)] {
+        if count <= keep && total <= max_size {
+            break;
+        }
+        if let Err(e) = fs::remove_file(&entry.path) {
+            log::warn!("Failed to remove {}: {}", entry.path.display(), e);
+            continue;
+        }
+        count -= 1;
+        total -= entry.size;
+        removed.0 += 1;
+        removed.1 += entry.size;
+    }
+    removed
+}
+
+/// The reverse patches the kept manifests list, by the version they apply to: the version they
+/// lead back to and their size.
+fn reverse_edges(entries: &[Entry]) -> HashMap<String, Vec<(String, u64)>> {
+    let mut edges: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+    let mut seen = HashSet::new();
+    for patch in entries
+        .iter()
+        .filter_map(|entry| entry.manifest["patches"].as_array())
+        .flatten()
+    {
+        let (Some(from), Some(to), Some(reverse)) = (
+            patch["from"].as_str(),
+            patch["to"].as_str(),
+            patch["reverse"].as_object(),
+        ) else {
+            continue;
+        };
+        if seen.insert((from.to_string(), to.to_string())) {
+            let size = reverse.get("size").and_then(Value::as_u64).unwrap_or(0);
+            edges.entry(to.to_string()).or_default().push((from.to_string(), size));
+        }
+    }
+    edges
+}
+
+/// Versions reverse patches lead back to from `current`, with how many are needed and their size.
+fn reachable_by_reverse(entries: &[Entry], current: &str) -> HashMap<String, (usize, u64)> {
+    let edges = reverse_edges(entries);
+    let mut reached = HashMap::from([(current.to_string(), (0, 0))]);
+    let mut queue = VecDeque::from([current.to_string()]);
+    while let Some(version) = queue.pop_front() {
+        let (steps, size) = reached[&version];
+        for (to, reverse_size) in edges.get(&version).into_iter().flatten() {
+            if !reached.contains_key(to) {
+                reached.insert(to.clone(), (steps + 1, size + reverse_size));
+                queue.push_back(to.clone());
+            }
+        }
+    }
+    reached
+}
+
+pub fn run_history(args: &[String]) -> i32 {
+    let current_dir = || env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    match args {
+        [] => list(&current_dir()),
+        [dir] if !dir.starts_with('-') && dir != "file" && dir != "prune" => list(Path::new(dir)),
+        [command, file] if command == "file" => query_file(&current_dir(), file),
+        [command, file, dir] if command == "file" => query_file(Path::new(dir), file),
+        [command, rest @ ..] if command == "prune" => run_prune(rest),
+        _ => {
+            eprintln!("Usage: DreamioUpdater history [<install dir>]");
+            eprintln!("       DreamioUpdater history file <path> [<install dir>]");
+            eprintln!("       DreamioUpdater history prune [--keep <versions>] [--max-mb <size>] [<install dir>]");
+            2
+        }
+    }
+}
+
+fn list(base_path: &Path) -> i32 {
+    let entries = entries(base_path);
+    if entries.is_empty() {
+        println!("No versions are recorded in {} yet. They are recorded as updates install them.", base_path.display());
+        return 0;
+    }
+    let installed = get_version_info(base_path).ok().map(|info| info.version_code);
+    let reachable = installed
+        .as_deref()
+        .map(|version| reachable_by_reverse(&entries, version))
+        .unwrap_or_default();
+    for entry in entries.iter().rev() {
+        let changes = if entry.full {
+            "full archive".to_string()
+        } else {
+            format!("{} file(s) changed", entry.files.len())
+        };
+        let back = match reachable.get(&entry.version) {
+            _ if installed.as_deref() == Some(entry.version.as_str()) => ", installed".to_string(),
+            Some((steps, size)) => format!(", {} reverse patch(es) back, {}", steps, format_bytes(*size)),
+            None => String::new(),
+        };
+        println!(
+            "{}  {}  {}  {}{}",
+            entry.version,
+            entry.channel,
+            format_timestamp(entry.installed_at),
+            changes,
+            back
+        );
+    }
+    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+    println!("{} version(s), {}.", entries.len(), format_bytes(size));
+    0
+}
+
+fn query_file(base_path: &Path, file: &str) -> i32 {
+    let file = file.replace('\\', "/");
+    let file = file.trim_start_matches("./");
+    let entries = entries(base_path);
+    let Some(oldest) = entries.first() else {
+        println!("No versions are recorded in {} yet.", base_path.display());
+        return 1;
+    };
+    let mut added_in = None;
+    let mut last_full = None;
+    for entry in &entries {
+        if entry.full {
+            last_full = Some(&entry.version);
+            continue;
+        }
+        let Some(kind) = entry.files.get(file) else {
+            continue;
+        };
+        println!("{}: {}", entry.version, kind);
+        if *kind == "added" && added_in.is_none() {
+            added_in = Some(&entry.version);
+        }
+    }
+    match (added_in, last_full) {
+        (Some(version), _) => println!("{} was added in version {}.", file, version),
+        (None, Some(version)) => println!(
+            "{} was not added by an update since version {}, which was installed from the full archive.",
+            file, version
+        ),
+        (None, None) => println!(
+            "{} was not added by an update since version {}, the oldest recorded.",
+            file, oldest.version
+        ),
+    }
+    0
+}
+
+fn run_prune(args: &[String]) -> i32 {
+    let mut keep = None;
+    let mut max_size = None;
+    let mut base_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keep" => keep = args.next().and_then(|n| n.parse().ok()),
+            "--max-mb" => max_size = args.next().and_then(|mb| mb.parse::<u64>().ok()).map(|mb| mb * 1024 * 1024),
+            _ if base_path.is_none() && !arg.starts_with('-') => base_path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("Usage: DreamioUpdater history prune [--keep <versions>] [--max-mb <size>] [<install dir>]");
+                return 2;
+            }
+        }
+    }
+    let base_path = base_path.unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    let config = Config::load(&base_path);
+    let keep = keep.or(config.history_versions).unwrap_or(DEFAULT_VERSIONS);
+    let max_size = max_size.or(config.history_max_size).unwrap_or(DEFAULT_MAX_SIZE);
+    let (count, size) = prune(&base_path, keep, max_size);
+    println!("Removed {} version(s) from the history, {}.", count, format_bytes(size));
+    0
+}
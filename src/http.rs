@@ -0,0 +1,85 @@
+//! HTTP settings shared by every request the updater makes, configured once at startup from
+//! `updater.json` and the command line, and the runtime the async downloads run on.
+
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+const USER_AGENT: &str = "DreamioUpdater/1.0";
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct HttpSettings {
+    pub proxy: Option<String>,
+    /// Maximum download speed in bytes per second.
+    pub bandwidth_limit: Option<u64>,
+    /// How often a failed download is retried before giving up.
+    pub retries: u32,
+    pub connect_timeout: Duration,
+    /// A download that receives no data for this long is treated as failed and retried.
+    pub stall_timeout: Duration,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            bandwidth_limit: None,
+            retries: DEFAULT_RETRIES,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+        }
+    }
+}
+
+static SETTINGS: OnceLock<HttpSettings> = OnceLock::new();
+static RUNTIME: OnceLock<io::Result<Runtime>> = OnceLock::new();
+
+pub fn configure(settings: HttpSettings) {
+    SETTINGS.set(settings).ok();
+}
+
+pub fn settings() -> &'static HttpSettings {
+    SETTINGS.get_or_init(HttpSettings::default)
+}
+
+pub fn client(timeout: Duration) -> reqwest::Result<reqwest::blocking::Client> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(settings().connect_timeout)
+        .timeout(timeout);
+    if let Some(proxy) = &settings().proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    builder.build()
+}
+
+/// Client for the async network layer. It has no overall timeout, since a download can take
+/// hours; callers time out each read after [`HttpSettings::stall_timeout`] instead.
+pub fn async_client() -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(settings().connect_timeout);
+    if let Some(proxy) = &settings().proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    builder.build()
+}
+
+/// Runs `future` on the runtime shared by all downloads. Must not be called from a task already
+/// running on it.
+pub fn block_on<T>(future: impl Future<Output = Result<T, Box<dyn Error>>>) -> Result<T, Box<dyn Error>> {
+    let runtime = RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+    });
+    match runtime {
+        Ok(runtime) => runtime.block_on(future),
+        Err(e) => Err(format!("Failed to start the network runtime: {}", e).into()),
+    }
+}
@@ -0,0 +1,784 @@
+//! Patching a file in place, for drives too full to hold the patched copy next to the original.
+//! Opt in with `inPlacePatching` in `updater.json`; it is only used for files whose patched copy
+//! does not fit.
+//!
+//! The new file is produced and written over the old one a window at a time. bsdiff reads the old
+//! file at arbitrary offsets, so the old bytes a later window still reads are saved to a spill
+//! file before their own window is overwritten; the patch's control block tells which ones those
+//! are before anything is written. Before each window is written, its old content goes to an
+//! undo slot and `<file>.inplace.json` records the window, so an interrupted patch resumes at that
+//! window on the next start, or is undone when the update moved on to another patch.
+//!
+//! Rolling back cannot restore a kept original, since there is none. Instead, a reverse delta of
+//! every window, from its new content back to the old, is stored in the update backup, and rolling
+//! back applies them window by window. That is slower than restoring a file, but needs no more
+//! space than the file itself.
+
+use bzip2::read::BzDecoder;
+use qbsdiff::{Bspatch, Bsdiff};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::UpdaterError;
+use crate::{backup, hashing, patch_output_size, platform};
+
+/// Bytes produced and written at a time. Reverse deltas are computed per window, which takes
+/// about ten times this in memory.
+#[cfg(not(test))]
+const WINDOW: u64 = 16 * 1024 * 1024;
+/// Small enough that test files span several windows.
+#[cfg(test)]
+const WINDOW: u64 = 64 * 1024;
+/// Room left on the drive for everything else, like the journal and the log.
+const SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+const PATCH_MAGIC: &[u8] = b"BSDIFF40";
+const REVERSE_MAGIC: &[u8] = b"DRMREV01";
+const REVERSE_HEADER_LEN: u64 = 32;
+
+/// Whether `path` has to be patched in place, when that is allowed: the drive cannot hold the
+/// patched copy next to the original.
+pub fn needed(base_path: &Path, path: &Path, patch_data: &[u8]) -> bool {
+    let folder = path.parent().unwrap_or(base_path);
+    match platform::free_disk_space(folder) {
+        Some(free) => free < patch_output_size(patch_data) + SPACE_MARGIN,
+        None => false,
+    }
+}
+
+/// Whether an in-place patch of `path` was interrupted.
+pub fn in_progress(path: &Path) -> bool {
+    work_path(path, "json").exists()
+}
+
+/// Patches `path` in place and returns the hash of the result. The reverse delta goes to the
+/// update backup unless the backup already keeps the original.
+pub fn apply(
+    base_path: &Path,
+    path: &Path,
+    patch_data: &[u8],
+    on_progress: &mut dyn FnMut(u64),
+) -> io::Result<String> {
+    let patch = ParsedPatch::parse(patch_data)?;
+    let old_len = fs::metadata(path)?.len();
+    let spill = spill_ranges(&patch, old_len);
+    let spill_len = spill.last().map(|range| range.offset + range.end - range.start).unwrap_or(0);
+    let needed = spill_len + patch.new_len.saturating_sub(old_len) + WINDOW + SPACE_MARGIN;
+    let folder = path.parent().unwrap_or(base_path);
+    if let Some(free) = platform::free_disk_space(folder) {
+        if free < needed {
+            return Err(io::Error::other(UpdaterError::DiskSpace(format!(
+                "There is not enough disk space to patch {} even in place. Another {} MB are needed.",
+                path.display(),
+                (needed - free).div_ceil(1024 * 1024)
+            ))));
+        }
+    }
+
+    let (reverse, keep_reverse) = match backup::reverse_patch_path(base_path, path)? {
+        Some(reverse) => (reverse, true),
+        None => (work_path(path, "reverse"), false),
+    };
+    let mut header = Vec::with_capacity(REVERSE_HEADER_LEN as usize);
+    header.extend_from_slice(REVERSE_MAGIC);
+    header.extend_from_slice(&old_len.to_le_bytes());
+    header.extend_from_slice(&patch.new_len.to_le_bytes());
+    header.extend_from_slice(&WINDOW.to_le_bytes());
+    write_synced(&reverse, &header)?;
+    File::create(work_path(path, "spill"))?;
+    let journal = Journal {
+        patch_sha256: hashing::sha256_bytes(patch_data),
+        old_len,
+        reverse,
+        keep_reverse,
+        window: 0,
+        writing: false,
+        reverse_len: REVERSE_HEADER_LEN,
+    };
+    journal.save(path)?;
+    run(path, &patch, journal, &spill, on_progress)
+}
+
+/// Finishes an interrupted in-place patch of `path` if it was applying `patch_data`, and undoes it
+/// otherwise, so the file is either patched or back to the original. Returns whether it was
+/// patched.
+pub fn recover(path: &Path, patch_data: &[u8], on_progress: &mut dyn FnMut(u64)) -> io::Result<bool> {
+    let journal = Journal::load(path)?;
+    if journal.patch_sha256 == hashing::sha256_bytes(patch_data) {
+        log::info!("Resuming the in-place patch of {} at window {}", path.display(), journal.window);
+        let patch = ParsedPatch::parse(patch_data)?;
+        let spill = spill_ranges(&patch, journal.old_len);
+        let sha256 = run(path, &patch, journal, &spill, on_progress)?;
+        hashing::remember(path, &sha256);
+        return Ok(true);
+    }
+    log::info!("Undoing the unfinished in-place patch of {}", path.display());
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    if journal.writing {
+        restore_undo_slot(&file, path, journal.window)?;
+    }
+    OpenOptions::new().write(true).open(&journal.reverse)?.set_len(journal.reverse_len)?;
+    undo_windows(&file, &journal.reverse)?;
+    drop(file);
+    fs::remove_file(&journal.reverse)?;
+    remove_work_files(path);
+    Ok(false)
+}
+
+/// Rolls `path` back with the reverse delta an in-place patch stored at `reverse`.
+pub fn undo(path: &Path, reverse: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    undo_windows(&file, reverse)
+}
+
+fn run(
+    path: &Path,
+    patch: &ParsedPatch,
+    mut journal: Journal,
+    spill: &[SpillRange],
+    on_progress: &mut dyn FnMut(u64),
+) -> io::Result<String> {
+    let file = platform::retry_while_in_use(path, || OpenOptions::new().read(true).write(true).open(path))?;
+    let spill_path = work_path(path, "spill");
+    let spill_file = OpenOptions::new().read(true).write(true).open(&spill_path)?;
+    let mut reverse = OpenOptions::new().read(true).write(true).open(&journal.reverse)?;
+    if journal.writing {
+        restore_undo_slot(&file, path, journal.window)?;
+    }
+    // Anything past the recorded length belongs to a window that was not finished.
+    reverse.set_len(journal.reverse_len)?;
+
+    let start = journal.window * WINDOW;
+    let mut hasher = Sha256::new();
+    (&file).seek(SeekFrom::Start(0))?;
+    hashing::update_from_reader(&mut hasher, &mut (&file).take(start.min(patch.new_len)))?;
+    let mut output = Output::new(patch);
+    // Windows past the end of a file that shrinks produce nothing.
+    output.skip(start.min(patch.new_len))?;
+
+    let windows = journal.old_len.max(patch.new_len).div_ceil(WINDOW);
+    for window in journal.window..windows {
+        let window_start = window * WINDOW;
+        let window_end = window_start + WINDOW;
+        let mut old = OldReader {
+            file: &file,
+            old_len: journal.old_len,
+            overwritten: window_start,
+            spill_file: &spill_file,
+            spill,
+        };
+        let new_window = output.produce(patch.new_len.min(window_end).saturating_sub(window_start), &mut old)?;
+        let old_window = read_at(&file, window_start, journal.old_len.min(window_end).saturating_sub(window_start))?;
+
+        // Old bytes later windows still read, saved before this window overwrites them.
+        for range in spill.iter().filter(|range| range.start < window_end && range.end > window_start) {
+            let from = range.start.max(window_start);
+            let to = range.end.min(window_end);
+            let bytes = &old_window[(from - window_start) as usize..(to - window_start) as usize];
+            write_at(&spill_file, range.offset + from - range.start, bytes)?;
+        }
+        spill_file.sync_data()?;
+
+        let mut delta = Vec::new();
+        Bsdiff::new(&new_window, &old_window).compare(Cursor::new(&mut delta))?;
+        reverse.seek(SeekFrom::Start(journal.reverse_len))?;
+        reverse.write_all(&(delta.len() as u64).to_le_bytes())?;
+        reverse.write_all(&delta)?;
+        reverse.sync_data()?;
+
+        write_synced(&work_path(path, "undo"), &old_window)?;
+        journal.window = window;
+        journal.writing = true;
+        journal.save(path)?;
+        write_at(&file, window_start, &new_window)?;
+        file.sync_data()?;
+        hasher.update(&new_window);
+        on_progress(window_start + new_window.len() as u64);
+
+        journal.window = window + 1;
+        journal.writing = false;
+        journal.reverse_len += 8 + delta.len() as u64;
+        journal.save(path)?;
+    }
+
+    file.set_len(patch.new_len)?;
+    file.sync_all()?;
+    drop(file);
+    if !journal.keep_reverse {
+        fs::remove_file(&journal.reverse).ok();
+    }
+    remove_work_files(path);
+    Ok(hashing::to_hex(&hasher.finalize()))
+}
+
+/// Puts back the old content of `window`, which was being overwritten when the patch stopped.
+fn restore_undo_slot(file: &File, path: &Path, window: u64) -> io::Result<()> {
+    let old_window = fs::read(work_path(path, "undo"))?;
+    write_at(file, window * WINDOW, &old_window)?;
+    file.sync_data()
+}
+
+/// Applies the window deltas in `reverse`. A reverse delta of an unfinished patch only has the
+/// windows that were written; the others still hold the old content.
+fn undo_windows(file: &File, reverse: &Path) -> io::Result<()> {
+    let data = fs::read(reverse)?;
+    if data.len() < REVERSE_HEADER_LEN as usize || &data[..8] != REVERSE_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a reverse delta"));
+    }
+    let field = |index: usize| u64::from_le_bytes(data[8 * index..8 * index + 8].try_into().unwrap());
+    let (old_len, new_len, window_size) = (field(1), field(2), field(3));
+
+    let mut offset = REVERSE_HEADER_LEN as usize;
+    let mut window_start = 0;
+    while offset + 8 <= data.len() {
+        let len = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize;
+        let delta = data
+            .get(offset + 8..offset + 8 + len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated reverse delta"))?;
+        let new_window = read_at(file, window_start, new_len.min(window_start + window_size).saturating_sub(window_start))?;
+        let mut old_window = Vec::new();
+        Bspatch::new(delta)?.apply(&new_window, Cursor::new(&mut old_window))?;
+        write_at(file, window_start, &old_window)?;
+        offset += 8 + len;
+        window_start += window_size;
+    }
+    file.set_len(old_len)?;
+    file.sync_all()
+}
+
+/// What the patch does, in the order it does it: add `add` bytes of the diff block to the old file
+/// at the current position, append `copy` bytes of the extra block, then move in the old file by
+/// `seek`.
+struct Control {
+    add: u64,
+    copy: u64,
+    seek: i64,
+}
+
+struct ParsedPatch<'a> {
+    controls: Vec<Control>,
+    diff: &'a [u8],
+    extra: &'a [u8],
+    new_len: u64,
+}
+
+impl<'a> ParsedPatch<'a> {
+    /// Reads the control block of a bsdiff 4 patch, as `publish` writes them.
+    fn parse(data: &'a [u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a bsdiff patch");
+        if data.len() < 32 || &data[..8] != PATCH_MAGIC {
+            return Err(invalid());
+        }
+        let control_len = u64::try_from(offtin(&data[8..16])).map_err(|_| invalid())? as usize;
+        let diff_len = u64::try_from(offtin(&data[16..24])).map_err(|_| invalid())? as usize;
+        let new_len = u64::try_from(offtin(&data[24..32])).map_err(|_| invalid())?;
+        let control_block = data.get(32..32 + control_len).ok_or_else(invalid)?;
+        let diff = data.get(32 + control_len..32 + control_len + diff_len).ok_or_else(invalid)?;
+        let extra = &data[32 + control_len + diff_len..];
+
+        let mut decoder = BzDecoder::new(control_block);
+        let mut controls = Vec::new();
+        let mut triple = [0u8; 24];
+        loop {
+            match decoder.read_exact(&mut triple) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            controls.push(Control {
+                add: u64::try_from(offtin(&triple[..8])).map_err(|_| invalid())?,
+                copy: u64::try_from(offtin(&triple[8..16])).map_err(|_| invalid())?,
+                seek: offtin(&triple[16..24]),
+            });
+        }
+        Ok(Self {
+            controls,
+            diff,
+            extra,
+            new_len,
+        })
+    }
+}
+
+/// bsdiff's signed integers: little endian magnitude, sign in the top bit.
+fn offtin(bytes: &[u8]) -> i64 {
+    let value = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let magnitude = (value & !(1 << 63)) as i64;
+    if value & (1 << 63) != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Old bytes `start..end` saved at `offset` in the spill file.
+struct SpillRange {
+    start: u64,
+    end: u64,
+    offset: u64,
+}
+
+/// The old bytes the patch reads only after the window holding them was overwritten. Windows are
+/// written in order, so an old byte is gone once the new file is produced past its window's end.
+fn spill_ranges(patch: &ParsedPatch, old_len: u64) -> Vec<SpillRange> {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    let mut new_pos: u64 = 0;
+    let mut old_pos: i64 = 0;
+    for control in &patch.controls {
+        let read_start = old_pos.max(0) as u64;
+        let read_end = (old_pos + control.add as i64).clamp(0, old_len as i64) as u64;
+        let mut start = read_start;
+        while start < read_end {
+            let window_end = (start / WINDOW + 1) * WINDOW;
+            let end = read_end.min(window_end);
+            // Old byte `b` is read while producing new byte `new_pos + b - old_pos`.
+            let first_late = (window_end as i64 - new_pos as i64 + old_pos).max(start as i64) as u64;
+            if first_late < end {
+                ranges.push((first_late, end));
+            }
+            start = end;
+        }
+        new_pos += control.add + control.copy;
+        old_pos += control.add as i64 + control.seek;
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<SpillRange> = Vec::new();
+    let mut offset = 0;
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.end => {
+                if end > last.end {
+                    offset += end - last.end;
+                    last.end = end;
+                }
+            }
+            _ => {
+                merged.push(SpillRange { start, end, offset });
+                offset += end - start;
+            }
+        }
+    }
+    merged
+}
+
+/// Reads the old file while it is being overwritten: from the spill file below `overwritten`, from
+/// the file itself above it, and zeros outside the old file, as bspatch does.
+struct OldReader<'a> {
+    file: &'a File,
+    old_len: u64,
+    overwritten: u64,
+    spill_file: &'a File,
+    spill: &'a [SpillRange],
+}
+
+impl OldReader<'_> {
+    fn read(&mut self, position: i64, buf: &mut [u8]) -> io::Result<()> {
+        buf.fill(0);
+        let mut done = 0;
+        while done < buf.len() {
+            let at = position + done as i64;
+            if at < 0 {
+                done += ((-at) as usize).min(buf.len() - done);
+                continue;
+            }
+            let at = at as u64;
+            if at >= self.old_len {
+                break;
+            }
+            let rest = &mut buf[done..];
+            let n = if at < self.overwritten {
+                let index = self.spill.partition_point(|range| range.end <= at);
+                let range = self
+                    .spill
+                    .get(index)
+                    .filter(|range| range.start <= at)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "old data missing from the spill file"))?;
+                let n = rest.len().min((range.end - at) as usize);
+                read_exact_at(self.spill_file, range.offset + at - range.start, &mut rest[..n])?;
+                n
+            } else {
+                let n = rest.len().min((self.old_len - at) as usize);
+                read_exact_at(self.file, at, &mut rest[..n])?;
+                n
+            };
+            done += n;
+        }
+        Ok(())
+    }
+}
+
+/// The new file as the patch produces it, from the start.
+struct Output<'a> {
+    controls: std::slice::Iter<'a, Control>,
+    diff: BzDecoder<&'a [u8]>,
+    extra: BzDecoder<&'a [u8]>,
+    old_pos: i64,
+    add_left: u64,
+    copy_left: u64,
+    seek: i64,
+}
+
+impl<'a> Output<'a> {
+    fn new(patch: &'a ParsedPatch<'_>) -> Self {
+        Self {
+            controls: patch.controls.iter(),
+            diff: BzDecoder::new(patch.diff),
+            extra: BzDecoder::new(patch.extra),
+            old_pos: 0,
+            add_left: 0,
+            copy_left: 0,
+            seek: 0,
+        }
+    }
+
+    /// Moves past the first `len` bytes, which are already written, without reading the old file.
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let mut left = len;
+        let mut scratch = vec![0u8; 1024 * 1024];
+        while left > 0 {
+            let (is_add, n) = self.next_run(left)?;
+            let buf = &mut scratch[..n as usize];
+            if is_add {
+                self.diff.read_exact(buf)?;
+                self.old_pos += n as i64;
+                self.add_left -= n;
+            } else {
+                self.extra.read_exact(buf)?;
+                self.copy_left -= n;
+            }
+            left -= n;
+        }
+        Ok(())
+    }
+
+    /// Produces the next `len` bytes.
+    fn produce(&mut self, len: u64, old: &mut OldReader) -> io::Result<Vec<u8>> {
+        let mut out = vec![0u8; len as usize];
+        let mut done = 0;
+        while (done as u64) < len {
+            let (is_add, n) = self.next_run(len - done as u64)?;
+            let buf = &mut out[done..done + n as usize];
+            if is_add {
+                self.diff.read_exact(buf)?;
+                let mut old_bytes = vec![0u8; n as usize];
+                old.read(self.old_pos, &mut old_bytes)?;
+                for (byte, old_byte) in buf.iter_mut().zip(old_bytes) {
+                    *byte = byte.wrapping_add(old_byte);
+                }
+                self.old_pos += n as i64;
+                self.add_left -= n;
+            } else {
+                self.extra.read_exact(buf)?;
+                self.copy_left -= n;
+            }
+            done += n as usize;
+        }
+        Ok(out)
+    }
+
+    /// The kind, adding to old bytes or copying extra bytes, and length of the next run of at most
+    /// `max` bytes, capped at 1 MB.
+    fn next_run(&mut self, max: u64) -> io::Result<(bool, u64)> {
+        while self.add_left == 0 && self.copy_left == 0 {
+            self.old_pos += self.seek;
+            let control = self
+                .controls
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the patch ends early"))?;
+            self.add_left = control.add;
+            self.copy_left = control.copy;
+            self.seek = control.seek;
+        }
+        let max = max.min(1024 * 1024);
+        Ok(if self.add_left > 0 {
+            (true, self.add_left.min(max))
+        } else {
+            (false, self.copy_left.min(max))
+        })
+    }
+}
+
+struct Journal {
+    patch_sha256: String,
+    old_len: u64,
+    reverse: PathBuf,
+    /// Whether the reverse delta is kept for rolling back, or only for undoing an unfinished patch.
+    keep_reverse: bool,
+    /// The next window to write, or the one being written.
+    window: u64,
+    writing: bool,
+    /// Length of the reverse delta before `window`.
+    reverse_len: u64,
+}
+
+impl Journal {
+    fn load(path: &Path) -> io::Result<Self> {
+        let json: Value = serde_json::from_str(&fs::read_to_string(work_path(path, "json"))?)?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "unreadable in-place patch journal");
+        Ok(Self {
+            patch_sha256: json["patchSha256"].as_str().ok_or_else(invalid)?.to_string(),
+            old_len: json["oldLen"].as_u64().ok_or_else(invalid)?,
+            reverse: PathBuf::from(json["reverse"].as_str().ok_or_else(invalid)?),
+            keep_reverse: json["keepReverse"].as_bool().unwrap_or(false),
+            window: json["window"].as_u64().ok_or_else(invalid)?,
+            writing: json["writing"].as_bool().unwrap_or(false),
+            reverse_len: json["reverseLen"].as_u64().ok_or_else(invalid)?,
+        })
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let json = json!({
+            "patchSha256": self.patch_sha256,
+            "oldLen": self.old_len,
+            "reverse": self.reverse.to_string_lossy(),
+            "keepReverse": self.keep_reverse,
+            "window": self.window,
+            "writing": self.writing,
+            "reverseLen": self.reverse_len,
+        });
+        // Written to a temporary file first so a crash never leaves a half-written journal.
+        let journal_path = work_path(path, "json");
+        let temp_path = work_path(path, "json.tmp");
+        write_synced(&temp_path, json.to_string().as_bytes())?;
+        fs::rename(&temp_path, &journal_path)
+    }
+}
+
+/// `<file>.inplace.<extension>`, next to the file so it stays on the same drive.
+fn work_path(path: &Path, extension: &str) -> PathBuf {
+    let mut work_path = path.as_os_str().to_owned();
+    work_path.push(format!(".inplace.{}", extension));
+    PathBuf::from(work_path)
+}
+
+fn remove_work_files(path: &Path) {
+    for extension in ["spill", "undo", "json"] {
+        fs::remove_file(work_path(path, extension)).ok();
+    }
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+fn read_at(file: &File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len as usize];
+    read_exact_at(file, offset, &mut buf)?;
+    Ok(buf)
+}
+
+fn read_exact_at(mut file: &File, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+fn write_at(mut file: &File, offset: u64, content: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    const OLD_LEN: usize = 5 * WINDOW as usize / 2;
+    const NEW_LEN: usize = 4 * WINDOW as usize;
+
+    fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// `old` with its blocks in reverse order, each slightly changed, cut or padded to `len`. The
+    /// patch then reads old bytes after the window holding them was overwritten.
+    fn next_version(old: &[u8], len: usize) -> Vec<u8> {
+        let mut new: Vec<u8> = old
+            .chunks(WINDOW as usize / 3)
+            .rev()
+            .flat_map(|block| {
+                let mut block = block.to_vec();
+                block[0] ^= 0xff;
+                block
+            })
+            .collect();
+        new.extend(random_bytes(len.saturating_sub(new.len()), 7));
+        new.truncate(len);
+        new
+    }
+
+    struct Case {
+        _root: tempfile::TempDir,
+        base_path: PathBuf,
+        path: PathBuf,
+        old: Vec<u8>,
+        new: Vec<u8>,
+        patch: Vec<u8>,
+    }
+
+    fn case(old_len: usize, new_len: usize) -> Case {
+        let root = tempfile::tempdir().unwrap();
+        let base_path = root.path().to_path_buf();
+        let path = base_path.join("data.bin");
+        let old = random_bytes(old_len, 3);
+        let new = next_version(&old, new_len);
+        let mut patch = Vec::new();
+        Bsdiff::new(&old, &new).compare(Cursor::new(&mut patch)).unwrap();
+        fs::write(&path, &old).unwrap();
+        Case {
+            _root: root,
+            base_path,
+            path,
+            old,
+            new,
+            patch,
+        }
+    }
+
+    fn windows(case: &Case) -> u64 {
+        (case.old.len().max(case.new.len()) as u64).div_ceil(WINDOW)
+    }
+
+    fn assert_finished(case: &Case) {
+        assert!(fs::read(&case.path).unwrap() == case.new, "the file is not patched");
+        assert!(!in_progress(&case.path));
+        assert!(!work_path(&case.path, "spill").exists());
+        assert!(!work_path(&case.path, "undo").exists());
+    }
+
+    fn assert_original(case: &Case) {
+        assert!(fs::read(&case.path).unwrap() == case.old, "the file is not the original");
+        assert!(!in_progress(&case.path));
+    }
+
+    fn patch_and_undo(old_len: usize, new_len: usize) {
+        let case = case(old_len, new_len);
+        assert!(windows(&case) >= 3);
+        let mut progress = Vec::new();
+        let sha256 = apply(&case.base_path, &case.path, &case.patch, &mut |written| progress.push(written)).unwrap();
+        assert_eq!(sha256, hashing::sha256_bytes(&case.new));
+        assert_eq!(progress.len() as u64, windows(&case));
+        assert_finished(&case);
+
+        // Kept in the update backup for rolling back.
+        let reverse = case.base_path.join("update_backup").join("inplace").join("data.bin").join("0");
+        undo(&case.path, &reverse).unwrap();
+        assert_original(&case);
+    }
+
+    #[test]
+    fn patches_a_file_growing_across_windows() {
+        patch_and_undo(OLD_LEN, NEW_LEN);
+    }
+
+    #[test]
+    fn patches_a_file_shrinking_across_windows() {
+        patch_and_undo(NEW_LEN, OLD_LEN);
+    }
+
+    /// Patches until window `window` was written over the old content, then stops as if the
+    /// computer turned off before the journal recorded it.
+    fn crash_while_writing(case: &Case, window: u64) {
+        let mut written = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            apply(&case.base_path, &case.path, &case.patch, &mut |_| {
+                written += 1;
+                if written > window {
+                    panic!("simulated crash in window {}", window);
+                }
+            })
+        }));
+        assert!(result.is_err());
+    }
+
+    fn edit_journal(path: &Path, edit: impl FnOnce(&mut Journal)) {
+        let mut journal = Journal::load(path).unwrap();
+        edit(&mut journal);
+        journal.save(path).unwrap();
+    }
+
+    /// The journal states a crash can leave behind in `window`.
+    #[derive(Clone, Copy, Debug)]
+    enum Crash {
+        /// The reverse delta of the window is stored, nothing of it was written yet.
+        DeltaStored,
+        /// The window was only partly written over.
+        Torn,
+        /// The window was written over, the journal still names it.
+        Written,
+        /// The journal moved on to the next window.
+        Recorded,
+    }
+
+    fn crash(case: &Case, window: u64, crash: Crash) {
+        crash_while_writing(case, window);
+        let file = OpenOptions::new().read(true).write(true).open(&case.path).unwrap();
+        let old_window = fs::read(work_path(&case.path, "undo")).unwrap();
+        let window_start = window * WINDOW;
+        match crash {
+            Crash::DeltaStored => {
+                write_at(&file, window_start, &old_window).unwrap();
+                let len = (case.old.len() as u64).max((case.new.len() as u64).min(window_start));
+                file.set_len(len).unwrap();
+                edit_journal(&case.path, |journal| journal.writing = false);
+            }
+            Crash::Torn => {
+                let half = old_window.len() / 2;
+                write_at(&file, window_start + half as u64, &old_window[half..]).unwrap();
+            }
+            Crash::Written => {}
+            Crash::Recorded => {
+                let journal = Journal::load(&case.path).unwrap();
+                let reverse_len = fs::metadata(&journal.reverse).unwrap().len();
+                edit_journal(&case.path, |journal| {
+                    journal.window = window + 1;
+                    journal.writing = false;
+                    journal.reverse_len = reverse_len;
+                });
+            }
+        }
+    }
+
+    fn recover_after_every_crash(old_len: usize, new_len: usize) {
+        let crashes = [Crash::DeltaStored, Crash::Torn, Crash::Written, Crash::Recorded];
+        let windows = windows(&case(old_len, new_len));
+        for window in 0..windows {
+            for kind in crashes {
+                let finished = case(old_len, new_len);
+                crash(&finished, window, kind);
+                assert!(in_progress(&finished.path));
+                let patched = recover(&finished.path, &finished.patch, &mut |_| {}).unwrap();
+                assert!(patched, "{:?} in window {}", kind, window);
+                assert_finished(&finished);
+
+                let undone = case(old_len, new_len);
+                crash(&undone, window, kind);
+                let patched = recover(&undone.path, b"another patch", &mut |_| {}).unwrap();
+                assert!(!patched, "{:?} in window {}", kind, window);
+                assert_original(&undone);
+            }
+        }
+    }
+
+    #[test]
+    fn recovers_a_growing_file_from_every_crash() {
+        recover_after_every_crash(OLD_LEN, NEW_LEN);
+    }
+
+    #[test]
+    fn recovers_a_shrinking_file_from_every_crash() {
+        recover_after_every_crash(NEW_LEN, OLD_LEN);
+    }
+}
@@ -0,0 +1,145 @@
+//! `install <dir>` sets up the game in a fresh folder from the console, for scripted setups and
+//! players who prefer it to the installer window. The folder must be empty or not exist yet, so an
+//! install never mixes with other files or overwrites an existing one.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::mods::ModChoice;
+use crate::{
+    copy_updater_to_install_dir, exit, get_version_info, load_options, platform, spawn_update_task, telemetry,
+    UpdateMessage,
+};
+
+pub fn run_install(args: &[String]) -> i32 {
+    let mut target_path = None;
+    let mut create_desktop = false;
+    let mut create_startmenu = false;
+    let mut register = true;
+    let mut force = false;
+    // Everything else is an updater option, e.g. `--channel`, parsed as if after the executable.
+    let mut update_args = vec![String::new()];
+    for arg in args {
+        match arg.as_str() {
+            "--desktop-shortcut" => create_desktop = true,
+            "--start-menu-shortcut" => create_startmenu = true,
+            "--no-uninstall-entry" => register = false,
+            "--force" => force = true,
+            _ if target_path.is_none() && !arg.starts_with('-') => target_path = Some(PathBuf::from(arg)),
+            _ => update_args.push(arg.clone()),
+        }
+    }
+
+    let Some(target_path) = target_path else {
+        eprintln!(
+            "Usage: DreamioUpdater install <dir> [--desktop-shortcut] [--start-menu-shortcut] [--no-uninstall-entry] [--force] [updater options]"
+        );
+        return 2;
+    };
+    if let Err(problem) = check_target(&target_path, force) {
+        eprintln!("Cannot install to {}: {}", target_path.display(), problem);
+        return 1;
+    }
+    if let Err(e) = fs::create_dir_all(&target_path) {
+        eprintln!("Failed to create {}: {}", target_path.display(), e);
+        return 1;
+    }
+    // Shortcuts and the uninstall entry need an absolute path. Canonicalizing would give Windows'
+    // `\\?\` paths, which some programs reading them do not understand.
+    let target_path = match env::current_dir() {
+        Ok(dir) if target_path.is_relative() => dir.join(target_path),
+        _ => target_path,
+    };
+
+    let mut options = load_options(&update_args, &target_path);
+    options.no_launch = true;
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    spawn_update_task(sender, target_path.clone(), options);
+
+    let mut failed = true;
+    let mut failure = None;
+    for message in receiver {
+        match message {
+            UpdateMessage::Status(message) | UpdateMessage::Log(message) => println!("{}", message),
+            UpdateMessage::Error(message, category, _) => {
+                log::error!("{}", message);
+                eprintln!("{}", telemetry::summarize(&message, category));
+            }
+            UpdateMessage::OfferLaunchAnyway(_, _, reply) => {
+                reply.send(false).ok();
+            }
+            UpdateMessage::OfferElevation(_, reply) => {
+                reply.send(false).ok();
+            }
+            UpdateMessage::ReleaseCountdown(_, _, reply) => {
+                reply.send(true).ok();
+            }
+            UpdateMessage::ModifiedFiles(files, reply) => {
+                reply.send(vec![ModChoice::Replace; files.len()]).ok();
+            }
+            UpdateMessage::RestartUpdater | UpdateMessage::RelaunchedElevated => {
+                eprintln!(
+                    "The updater was replaced while installing. Run it in {} to finish.",
+                    target_path.display()
+                );
+                break;
+            }
+            UpdateMessage::UpdateComplete => {
+                failed = false;
+                break;
+            }
+            UpdateMessage::UpdateFailed(category) => {
+                failure = Some(category);
+                break;
+            }
+            UpdateMessage::Interrupted => {
+                eprintln!("Run the same command with --force to finish installing.");
+                return exit::CANCELLED;
+            }
+            _ => {}
+        }
+    }
+    if failed {
+        return exit::for_failure(failure);
+    }
+
+    let version = match get_version_info(&target_path) {
+        Ok(info) => info.version_string,
+        Err(e) => {
+            eprintln!("The downloaded build has no usable version.json: {}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = copy_updater_to_install_dir(&target_path) {
+        eprintln!("Failed to copy the updater to {}: {}", target_path.display(), e);
+        return 1;
+    }
+    platform::create_shortcuts(&target_path, create_desktop, create_startmenu);
+    if register {
+        if let Err(e) = platform::register_uninstaller(&target_path) {
+            log::warn!("Failed to register the uninstaller: {}", e);
+        }
+    }
+    println!("Installed version {} to {}", version, target_path.display());
+    0
+}
+
+/// An install goes into a new or empty folder. `--force` allows a non-empty one, but never an
+/// existing install, which is updated instead.
+fn check_target(path: &Path, force: bool) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    if !path.is_dir() {
+        return Err("it is not a folder".to_string());
+    }
+    if path.join("version.json").exists() {
+        return Err("the game is already installed there. Run the updater in that folder to update it".to_string());
+    }
+    let mut entries = fs::read_dir(path).map_err(|e| e.to_string())?;
+    if entries.next().is_some() && !force {
+        return Err("the folder is not empty. Choose an empty folder or pass --force".to_string());
+    }
+    Ok(())
+}
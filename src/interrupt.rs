@@ -0,0 +1,85 @@
+//! Ctrl+C, Ctrl+Break and closing the console while an update runs from the console, with
+//! `--windowless` or `install`. Instead of dying mid-file, the update stops at the next safe point:
+//! between two chunks of a download or two files being applied. The download checkpoint, the apply
+//! journal and `update_state.json` are flushed and `update.zip` is kept, so the next start
+//! continues where this one stopped. A second Ctrl+C exits right away.
+//!
+//! Windows ends the process a few seconds after the console window is closed, so the handler holds
+//! it until the safe point is reached, for at most `CLOSE_GRACE`.
+//!
+//! The window's Cancel button stops the update the same way.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{exit, platform};
+
+/// Windows allows five seconds before killing the process.
+const CLOSE_GRACE: Duration = Duration::from_secs(4);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// The error a step returns when it stopped at a safe point because of an interruption.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the update was interrupted")
+    }
+}
+
+impl Error for Interrupted {}
+
+pub fn install() {
+    platform::on_console_interrupt(handle);
+}
+
+/// `ends_process`: Windows ends the process as soon as this returns.
+fn handle(ends_process: bool) {
+    if REQUESTED.swap(true, Ordering::SeqCst) && !ends_process {
+        eprintln!("Stopped immediately. The update resumes from its last checkpoint on the next start.");
+        std::process::exit(exit::CANCELLED);
+    }
+    eprintln!("Stopping the update at a safe point... Press Ctrl+C again to stop immediately.");
+    if ends_process {
+        let started = Instant::now();
+        while !STOPPED.load(Ordering::SeqCst) && started.elapsed() < CLOSE_GRACE {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+/// Asks the update to stop at the next safe point, as Ctrl+C does.
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether the update was asked to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fails with `Interrupted` once the update was asked to stop. Called at safe points.
+pub fn check() -> io::Result<()> {
+    if requested() {
+        return Err(io::Error::other(Interrupted));
+    }
+    Ok(())
+}
+
+/// Records that the update reached its safe point and everything needed to resume is on disk.
+/// Returns the message telling the player how to continue.
+pub fn stopped(base_path: &Path) -> String {
+    STOPPED.store(true, Ordering::SeqCst);
+    format!(
+        "Update paused. Run the updater in {} again to continue where it stopped.",
+        base_path.display()
+    )
+}
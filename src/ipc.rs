@@ -0,0 +1,73 @@
+//! `--ipc <target>` streams the `--windowless` events to the game's launcher, so it can show an
+//! update screen of its own while the updater works without a window. The launcher listens and
+//! the updater connects to it when it starts:
+//!
+//! - `pipe:<name>`: a named pipe, `\\.\pipe\<name>` on Windows. On Linux and macOS, a Unix domain
+//!   socket at `<name>`, in the temporary folder unless it is a path.
+//! - `tcp:<port>` or `tcp:<address>:<port>`: a TCP connection to this computer. Other hosts are
+//!   refused, since events include file names and error details.
+//!
+//! Each event is one JSON object per line, the same as on stdout, which keeps receiving them. When
+//! the launcher cannot be reached or goes away, the update carries on without it. While it is
+//! connected, a failed update is left to the launcher to show instead of an error dialog.
+
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::platform;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub enum IpcTarget {
+    Pipe(String),
+    Tcp(SocketAddr),
+}
+
+impl IpcTarget {
+    pub fn parse(spec: &str) -> Option<Self> {
+        if let Some(name) = spec.strip_prefix("pipe:") {
+            return (!name.is_empty()).then(|| IpcTarget::Pipe(name.to_string()));
+        }
+        let address = spec.strip_prefix("tcp:")?;
+        let address = match address.parse::<u16>() {
+            Ok(port) => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
+            Err(_) => address.parse().ok()?,
+        };
+        address.ip().is_loopback().then_some(IpcTarget::Tcp(address))
+    }
+}
+
+static CONNECTION: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Connects to the launcher listening at `target`.
+pub fn connect(target: &IpcTarget) -> io::Result<()> {
+    let connection: Box<dyn Write + Send> = match target {
+        IpcTarget::Pipe(name) => platform::connect_pipe(name)?,
+        IpcTarget::Tcp(address) => {
+            let stream = TcpStream::connect_timeout(address, CONNECT_TIMEOUT)?;
+            stream.set_nodelay(true).ok();
+            Box::new(stream)
+        }
+    };
+    *CONNECTION.lock().unwrap() = Some(connection);
+    Ok(())
+}
+
+pub fn is_connected() -> bool {
+    CONNECTION.lock().unwrap().is_some()
+}
+
+/// Sends an event line to the launcher, if one is connected.
+pub fn send(line: &str) {
+    let mut connection = CONNECTION.lock().unwrap();
+    let Some(stream) = connection.as_mut() else {
+        return;
+    };
+    if let Err(e) = writeln!(stream, "{}", line).and_then(|_| stream.flush()) {
+        log::warn!("The launcher stopped receiving progress: {}", e);
+        *connection = None;
+    }
+}
@@ -0,0 +1,125 @@
+//! Records how far applying `update.zip` got, so an update interrupted by a crash or power loss
+//! resumes where it stopped instead of redoing every entry. Large entries are checkpointed with
+//! the hash of the bytes written so far, so they resume mid-file once that prefix is verified.
+
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const JOURNAL_FILE_NAME: &str = "update_journal.json";
+
+pub struct PartialEntry {
+    pub index: usize,
+    pub offset: u64,
+    pub sha256: String,
+}
+
+pub struct ApplyJournal {
+    path: PathBuf,
+    archive_id: String,
+    /// Entries before this index have been applied.
+    pub completed: usize,
+    pub partial: Option<PartialEntry>,
+}
+
+impl ApplyJournal {
+    /// Loads the journal for `archive`, or starts a new one when the journal belongs to another
+    /// archive or does not exist.
+    pub fn open(base_path: &Path, archive: &Path) -> io::Result<Self> {
+        let path = base_path.join(JOURNAL_FILE_NAME);
+        let archive_id = archive_id(archive)?;
+        let saved = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+            .filter(|json| json["archive"].as_str() == Some(archive_id.as_str()));
+
+        let Some(saved) = saved else {
+            return Ok(Self {
+                path,
+                archive_id,
+                completed: 0,
+                partial: None,
+            });
+        };
+        let partial = match (
+            saved["partial"]["index"].as_u64(),
+            saved["partial"]["offset"].as_u64(),
+            saved["partial"]["sha256"].as_str(),
+        ) {
+            (Some(index), Some(offset), Some(sha256)) => Some(PartialEntry {
+                index: index as usize,
+                offset,
+                sha256: sha256.to_string(),
+            }),
+            _ => None,
+        };
+        Ok(Self {
+            path,
+            archive_id,
+            completed: saved["completed"].as_u64().unwrap_or(0) as usize,
+            partial,
+        })
+    }
+
+    /// Records that every entry before `index` has been applied.
+    pub fn advance_to(&mut self, index: usize) -> io::Result<()> {
+        self.completed = index;
+        if self.partial.as_ref().map(|p| p.index < index).unwrap_or(false) {
+            self.partial = None;
+        }
+        self.save()
+    }
+
+    /// Records that the first `offset` bytes of entry `index` are on disk and hash to `sha256`.
+    pub fn checkpoint(&mut self, index: usize, offset: u64, sha256: String) -> io::Result<()> {
+        self.partial = Some(PartialEntry {
+            index,
+            offset,
+            sha256,
+        });
+        self.save()
+    }
+
+    pub fn remove(self) {
+        fs::remove_file(&self.path).ok();
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let partial = match &self.partial {
+            Some(partial) => json!({
+                "index": partial.index,
+                "offset": partial.offset,
+                "sha256": partial.sha256,
+            }),
+            None => Value::Null,
+        };
+        let journal = json!({
+            "archive": self.archive_id,
+            "completed": self.completed,
+            "partial": partial,
+        });
+
+        // Written to a temporary file first so a crash never leaves a half-written journal.
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, journal.to_string())?;
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+pub fn remove_journal(base_path: &Path) {
+    fs::remove_file(base_path.join(JOURNAL_FILE_NAME)).ok();
+}
+
+/// Identifies an archive by its size and modification time, which is enough to tell successive
+/// `update.zip` downloads apart.
+fn archive_id(archive: &Path) -> io::Result<String> {
+    let metadata = fs::metadata(archive)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Ok(format!("{}-{}", metadata.len(), modified))
+}
@@ -0,0 +1,3560 @@
+//! The DREAMIO updater. `main.rs` only starts [`run`]; the rest lives here so benchmarks can
+//! reach the extraction, patching, hashing and manifest code.
+
+mod analyze;
+mod background;
+mod backup;
+mod bundle;
+mod config;
+mod diagnostics;
+mod downloadonly;
+mod dryrun;
+mod error;
+mod exit;
+pub mod hashing;
+mod help;
+mod history;
+mod http;
+mod inplace;
+mod install;
+mod interrupt;
+mod ipc;
+mod journal;
+mod lock;
+mod logging;
+mod maintenance;
+pub mod manifest;
+mod metadata;
+mod mods;
+mod nettest;
+mod notes;
+mod offline;
+mod peercache;
+mod pin;
+pub mod pipeline;
+mod platform;
+mod plugins;
+mod postupdate;
+mod preload;
+mod protect;
+mod publish;
+mod report;
+mod resume;
+mod rollback;
+mod schedule;
+mod session;
+mod soak;
+mod source;
+mod sparse;
+mod state;
+mod stats;
+mod telemetry;
+mod uninstall;
+mod windowless;
+mod workers;
+
+use bytes::Bytes;
+use crossbeam_channel::{Receiver, Sender};
+use eframe::{egui, App, Frame};
+use egui::ColorImage;
+use memmap2::Mmap;
+use qbsdiff::Bspatch;
+use sha2::{Digest, Sha256};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use sysinfo::{System, SystemExt};
+use rfd::FileDialog;
+use zip::ZipArchive;
+
+use background::{BackgroundScope, LinkMonitor};
+use config::Config;
+use error::{ErrorCategory, UpdaterError};
+use journal::ApplyJournal;
+use manifest::{Artifact, CachedManifest, RemoteManifest, UpdatePlan};
+use metadata::{UpdateMetadata, METADATA_FILE_NAME};
+use mods::ModChoice;
+use notes::ReleaseNotes;
+use pipeline::{UpdateContext, UpdatePipeline};
+use postupdate::PostUpdateAction;
+use protect::ProtectedPaths;
+use report::ReportTarget;
+use platform::{ProgressState, Taskbar, WindowHandle, GAME_EXECUTABLE};
+use stats::TransferStats;
+
+pub struct SharedState {
+    pub update_complete: bool,
+    /// How the updater exits if the update did not complete. Closing the window pauses the update.
+    pub exit_code: i32,
+    /// Updates the player postponed, downloaded in the background once the window is closed.
+    pub deferred_downloads: Vec<(String, Artifact)>,
+}
+
+#[cfg(windows)]
+const DEFAULT_DOWNLOADS_URL: &str = "https://storage.googleapis.com/dreamio/downloads/Builds/Windows";
+#[cfg(target_os = "linux")]
+const DEFAULT_DOWNLOADS_URL: &str = "https://storage.googleapis.com/dreamio/downloads/Builds/Linux";
+#[cfg(target_os = "macos")]
+const DEFAULT_DOWNLOADS_URL: &str = "https://storage.googleapis.com/dreamio/downloads/Builds/macOS";
+/// Set at startup when `--server`, `DREAMIO_UPDATE_URL` or `updater.json` points at another server, e.g. for testing.
+static DOWNLOADS_URL: OnceLock<String> = OnceLock::new();
+/// Set by `--best-effort`: files that fail to update are skipped instead of failing the update.
+static BEST_EFFORT: AtomicBool = AtomicBool::new(false);
+/// Files left to Windows to replace at the next restart, as the staged file and the file it
+/// replaces. Deleted files have nothing to replace.
+static REBOOT_DEFERRED: Mutex<Vec<(PathBuf, Option<PathBuf>)>> = Mutex::new(Vec::new());
+/// Environment variable that points the updater at another server, between the flag and `updater.json` in precedence.
+const SERVER_URL_VARIABLE: &str = "DREAMIO_UPDATE_URL";
+const CHANNELS: [&str; 3] = ["stable", "beta", "nightly"];
+const DEFAULT_CHANNEL: &str = "stable";
+
+/// How much the updater window shows. The log file always records everything.
+#[derive(Clone, Copy, Default, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// Only the overall progress and the final result.
+    Quiet,
+    #[default]
+    Normal,
+    /// Also every HTTP request, extracted file and step timing.
+    Verbose,
+    /// Also status changes and hash checks.
+    Debug,
+}
+
+#[derive(Clone, Default)]
+pub struct Options {
+    pub assume_yes: bool,
+    pub channel: Option<String>,
+    pub prefer_full: bool,
+    pub verbosity: Verbosity,
+    pub telemetry_endpoint: Option<String>,
+    pub no_telemetry: bool,
+    pub base_url: Option<String>,
+    pub proxy: Option<String>,
+    pub bandwidth_limit: Option<u64>,
+    pub retries: Option<u32>,
+    pub connect_timeout: Option<Duration>,
+    pub stall_timeout: Option<Duration>,
+    pub no_launch: bool,
+    pub best_effort: bool,
+    pub windowless: bool,
+    /// Shows the minimal progress window: the banner, the overall progress, the current step and
+    /// Cancel, without the log.
+    pub gui: bool,
+    /// Updating in a maintenance window, after players were warned and given time to save.
+    pub maintenance: bool,
+    pub report: Option<ReportTarget>,
+    /// Where the launcher listens for the `--windowless` events, e.g. `pipe:dreamio`.
+    pub ipc: Option<String>,
+    /// Where `--dry-run` writes its diff for release review.
+    pub diff: Option<PathBuf>,
+    /// An archive or folder of archives to update from instead of the server.
+    pub from_file: Option<PathBuf>,
+    /// Where `--download-only` puts the archives.
+    pub out: Option<PathBuf>,
+    /// The version `--download-only` downloads the update for, instead of the installed one.
+    pub from_version: Option<String>,
+    /// Where archives are looked for before the server, see `peercache`.
+    pub peer_cache: Option<String>,
+    /// What happens once the game is up to date, see `postupdate::action`.
+    pub after_update: Option<PostUpdateAction>,
+    /// Passed on to the game when it is started after updating.
+    pub launch_args: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Options {
+    let mut options = Options::default();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--yes" | "-y" => options.assume_yes = true,
+            "--channel" => options.channel = args.next().map(|c| c.to_lowercase()),
+            "--prefer-full" => options.prefer_full = true,
+            "--quiet" | "-q" => options.verbosity = Verbosity::Quiet,
+            "--verbose" | "-v" if options.verbosity >= Verbosity::Verbose => {
+                options.verbosity = Verbosity::Debug
+            }
+            "--verbose" | "-v" => options.verbosity = Verbosity::Verbose,
+            "-vv" => options.verbosity = Verbosity::Debug,
+            "--telemetry-endpoint" => options.telemetry_endpoint = args.next().cloned(),
+            "--no-telemetry" => options.no_telemetry = true,
+            "--server" => options.base_url = args.next().map(|url| url.trim_end_matches('/').to_string()),
+            "--proxy" => options.proxy = args.next().cloned(),
+            "--limit-rate" => {
+                options.bandwidth_limit = args.next().and_then(|kib| kib.parse::<u64>().ok()).map(|kib| kib * 1024)
+            }
+            "--retries" => options.retries = args.next().and_then(|n| n.parse().ok()),
+            "--connect-timeout" => {
+                options.connect_timeout = args.next().and_then(|secs| secs.parse().ok()).map(Duration::from_secs)
+            }
+            "--stall-timeout" => {
+                options.stall_timeout = args.next().and_then(|secs| secs.parse().ok()).map(Duration::from_secs)
+            }
+            "--no-launch" => options.no_launch = true,
+            "--after-update" => options.after_update = args.next().and_then(|name| PostUpdateAction::parse(name)),
+            "--after-update-command" => {
+                options.after_update = args
+                    .next()
+                    .map(|command| split_arguments(command))
+                    .filter(|command| !command.is_empty())
+                    .map(PostUpdateAction::Command)
+            }
+            "--best-effort" => options.best_effort = true,
+            "--report-file" => options.report = args.next().map(|path| ReportTarget::File(PathBuf::from(path))),
+            "--report-fd" => options.report = args.next().and_then(|fd| fd.parse().ok()).map(ReportTarget::Descriptor),
+            "--windowless" => {
+                options.windowless = true;
+                options.assume_yes = true;
+            }
+            "--ipc" => {
+                options.ipc = args.next().cloned();
+                options.windowless = true;
+                options.assume_yes = true;
+            }
+            "--gui" => options.gui = true,
+            "--diff" => options.diff = args.next().map(PathBuf::from),
+            "--from-file" => options.from_file = args.next().map(PathBuf::from),
+            "--peer-cache" => options.peer_cache = args.next().cloned(),
+            "--out" => options.out = args.next().map(PathBuf::from),
+            "--from-version" => options.from_version = args.next().cloned(),
+            "--launch-args" => {
+                if let Some(launch_args) = args.next() {
+                    options.launch_args.extend(split_arguments(launch_args));
+                }
+            }
+            // Everything after `--` belongs to the game.
+            "--" => {
+                options.launch_args.extend(args.by_ref().cloned());
+            }
+            _ => {}
+        }
+    }
+    options
+}
+
+/// Splits `--launch-args` at spaces outside double quotes, the way shortcuts and launchers pass
+/// a whole command line as one argument.
+fn split_arguments(command_line: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut started = false;
+    for c in command_line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                started = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if started {
+                    arguments.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        arguments.push(current);
+    }
+    arguments
+}
+
+/// Parses the command line and fills in settings it does not override from `updater.json`, then
+/// applies the server and network settings for the rest of the run.
+fn load_options(args: &[String], base_path: &Path) -> Options {
+    let mut options = parse_options(args);
+    let config = Config::load(base_path);
+    options.channel = options.channel.or(config.channel);
+    options.telemetry_endpoint = options.telemetry_endpoint.or(config.telemetry_endpoint);
+    options.base_url = options
+        .base_url
+        .or_else(|| {
+            env::var(SERVER_URL_VARIABLE)
+                .ok()
+                .filter(|url| !url.is_empty())
+                .map(|url| url.trim_end_matches('/').to_string())
+        })
+        .or(config.base_url);
+    options.proxy = options.proxy.or(config.proxy);
+    options.bandwidth_limit = options.bandwidth_limit.or(config.bandwidth_limit);
+    options.retries = options.retries.or(config.retries);
+    options.connect_timeout = options.connect_timeout.or(config.connect_timeout);
+    options.stall_timeout = options.stall_timeout.or(config.stall_timeout);
+    options.peer_cache = options.peer_cache.or(config.peer_cache);
+    options.no_launch = options.no_launch || config.auto_launch == Some(false);
+    options.after_update = options
+        .after_update
+        .or(config.after_update)
+        .or(config.auto_launch.filter(|launch| *launch).map(|_| PostUpdateAction::Launch));
+
+    if let Some(base_url) = &options.base_url {
+        DOWNLOADS_URL.set(base_url.clone()).ok();
+    }
+    BEST_EFFORT.store(options.best_effort, Ordering::Relaxed);
+    peercache::configure(options.peer_cache.clone());
+    let variable = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    source::configure_s3(source::S3Settings {
+        endpoint: config.s3.endpoint.or_else(|| variable("AWS_ENDPOINT_URL")),
+        region: config.s3.region.or_else(|| variable("AWS_REGION")),
+        access_key_id: config.s3.access_key_id.or_else(|| variable("AWS_ACCESS_KEY_ID")),
+        secret_access_key: config.s3.secret_access_key.or_else(|| variable("AWS_SECRET_ACCESS_KEY")),
+    });
+    let defaults = http::HttpSettings::default();
+    http::configure(http::HttpSettings {
+        proxy: options.proxy.clone(),
+        bandwidth_limit: options.bandwidth_limit,
+        retries: options.retries.unwrap_or(defaults.retries),
+        connect_timeout: options.connect_timeout.unwrap_or(defaults.connect_timeout),
+        stall_timeout: options.stall_timeout.unwrap_or(defaults.stall_timeout),
+    });
+    options
+}
+
+fn downloads_url() -> &'static str {
+    DOWNLOADS_URL.get().map(|url| url.as_str()).unwrap_or(DEFAULT_DOWNLOADS_URL)
+}
+
+/// Stable builds live at the root of the downloads folder, other channels in a subfolder named after them.
+fn channel_url(channel: &str) -> String {
+    if channel == DEFAULT_CHANNEL {
+        downloads_url().to_string()
+    } else {
+        format!("{}/{}", downloads_url(), channel)
+    }
+}
+
+pub struct ProgressUpdate {
+    pub downloaded: u64,
+    pub total: u64,
+    pub bytes_per_sec: f64,
+    pub eta: Duration,
+    pub elapsed: Duration,
+}
+
+pub struct FileProgress {
+    pub name: String,
+    pub processed: u64,
+    pub total: u64,
+}
+
+pub enum UpdateMessage {
+    Log(String),
+    /// Diagnostic details shown only at the given verbosity or above.
+    Detail(Verbosity, String),
+    /// An error for the player, with its category and the page a server sent where JSON was
+    /// expected, if that was the problem.
+    Error(String, ErrorCategory, Option<String>),
+    Status(String),
+    Progress(f32),
+    ProgressUpdate(ProgressUpdate),
+    ApplyingProgress(String),
+    FileProgress(FileProgress),
+    Step(usize, usize, String),
+    OfferLaunchAnyway(String, u64, Sender<bool>),
+    /// The install folder needs administrator rights. Replies whether to relaunch elevated.
+    OfferElevation(PathBuf, Sender<bool>),
+    DeferUpdate(Vec<(String, Artifact)>),
+    /// A preloaded version unlocks at the given time. Replies when the player stops waiting to
+    /// play the installed version.
+    ReleaseCountdown(String, SystemTime, Sender<bool>),
+    /// Files the player changed that the update patches. Replies with a choice for each.
+    ModifiedFiles(Vec<String>, Sender<Vec<ModChoice>>),
+    /// What's new in the versions the update installs.
+    ReleaseNotes(ReleaseNotes),
+    RestartUpdater,
+    /// An elevated updater took over the update.
+    RelaunchedElevated,
+    UpdateComplete,
+    /// Follows the error that ended the update, with its category.
+    UpdateFailed(ErrorCategory),
+    /// Stopped at a safe point after Ctrl+C or closing the console. The next start resumes.
+    Interrupted,
+}
+
+pub struct LogEntry {
+    message: String,
+    /// The category of an error, `None` for other messages.
+    error: Option<ErrorCategory>,
+}
+
+#[derive(Default)]
+pub struct ApplySummary {
+    pub updater_replaced: bool,
+}
+
+pub struct VersionInfo {
+    pub version_code: String,
+    pub update_url: String,
+    pub version_string: String,
+    pub channel: String,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum InstallerState {
+    ConfirmClose,
+    Updater,
+    Terms,
+    Location,
+    Installing,
+    Finished,
+}
+
+/// Modified files with the choice selected for each, and where to send the choices.
+type ModifiedFiles = (Vec<(String, ModChoice)>, Sender<Vec<ModChoice>>);
+
+pub struct UpdateGUI {
+    logs: Vec<LogEntry>,
+    progress: f32,
+    status: String,
+    update_receiver: Receiver<UpdateMessage>,
+    update_sender: Sender<UpdateMessage>,
+    update_complete: bool,
+    bytes_downloaded: u64,
+    total_bytes: u64,
+    bytes_per_sec: f64,
+    eta: Duration,
+    elapsed: Duration,
+    applying_progress: String,
+    file_progress: Option<FileProgress>,
+    launch_anyway_offer: Option<(String, u64, Sender<bool>)>,
+    elevation_offer: Option<(PathBuf, Sender<bool>)>,
+    release_countdown: Option<(String, SystemTime, Sender<bool>)>,
+    /// Modified files with the choice selected for each, until the player confirms.
+    modified_files: Option<ModifiedFiles>,
+    step: Option<(usize, usize, String)>,
+    release_notes: Option<ReleaseNotes>,
+    /// Shown after updating instead of closing, with `afterUpdate` set to `summary`.
+    summary: Option<String>,
+    /// Cancel was pressed and the update is stopping at its next safe point.
+    cancelling: bool,
+    update_failed: bool,
+    last_error_response: Option<String>,
+    show_error_details: bool,
+    shared_state: Arc<Mutex<SharedState>>,
+    image: Option<ColorImage>,
+    texture: Option<egui::TextureHandle>,
+    taskbar: Option<Taskbar>,
+    window_handle: Option<WindowHandle>,
+    flashing: bool,
+    installer_state: InstallerState,
+    terms_accepted: bool,
+    install_path: String,
+    create_desktop_shortcut: bool,
+    create_startmenu_shortcut: bool,
+    register_uninstall_entry: bool,
+    options: Options,
+}
+
+impl UpdateGUI {
+    pub fn new(shared_state: Arc<Mutex<SharedState>>, options: Options) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        let image = image::load_from_memory(include_bytes!("../assets/logo.png")).unwrap();
+        let size = [image.width() as _, image.height() as _];
+        let image_buffer = image.to_rgba8();
+        let pixels = image_buffer.as_flat_samples();
+        let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
+
+        let version_exists = Path::new("version.json").exists();
+        let installer_state = if version_exists {
+            if !options.assume_yes && is_game_running(Path::new(".")) {
+                InstallerState::ConfirmClose
+            } else {
+                InstallerState::Updater
+            }
+        } else {
+            InstallerState::Terms
+        };
+
+        let install_path = platform::default_install_path();
+
+        let app = Self {
+            logs: vec![],
+            progress: 0.0,
+            status: "Ready to update.".to_string(),
+            update_receiver: receiver,
+            update_sender: sender,
+            update_complete: false,
+            bytes_downloaded: 0,
+            total_bytes: 0,
+            bytes_per_sec: 0.0,
+            eta: Duration::from_secs(0),
+            elapsed: Duration::from_secs(0),
+            applying_progress: "".to_string(),
+            file_progress: None,
+            launch_anyway_offer: None,
+            elevation_offer: None,
+            release_countdown: None,
+            modified_files: None,
+            step: None,
+            release_notes: None,
+            summary: None,
+            cancelling: false,
+            update_failed: false,
+            last_error_response: None,
+            show_error_details: false,
+            shared_state,
+            image: Some(color_image),
+            texture: None,
+            taskbar: Taskbar::new(),
+            window_handle: None,
+            flashing: false,
+            installer_state,
+            terms_accepted: false,
+            install_path,
+            create_desktop_shortcut: true,
+            create_startmenu_shortcut: true,
+            register_uninstall_entry: true,
+            options,
+        };
+        if installer_state == InstallerState::Updater {
+            app.start_update_thread();
+        }
+        app
+    }
+
+    #[cfg(not(test))]
+    fn start_update_thread(&self) {
+        let target_path = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        spawn_update_task(self.update_sender.clone(), target_path, self.options.clone());
+    }
+
+    #[cfg(test)]
+    fn start_update_thread(&self) {
+        // Do not start the thread in tests
+    }
+
+    fn start_install_thread(&self, target_path: PathBuf) {
+        spawn_update_task(self.update_sender.clone(), target_path, self.options.clone());
+    }
+
+    fn target_path(&self) -> PathBuf {
+        if self.installer_state == InstallerState::Installing {
+            PathBuf::from(&self.install_path)
+        } else {
+            env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
+        }
+    }
+
+    fn create_diagnostics_bundle(&mut self) {
+        let error = self
+            .logs
+            .iter()
+            .rev()
+            .find(|log| log.error.is_some())
+            .map(|log| log.message.clone());
+        match diagnostics::create_bundle(&self.target_path(), error.as_deref()) {
+            Ok(path) => {
+                self.logs.push(LogEntry {
+                    message: format!(
+                        "Diagnostics report saved to {}. Please attach it to your support request.",
+                        path.display()
+                    ),
+                    error: None,
+                });
+                opener::reveal(path).ok();
+            }
+            Err(e) => self.logs.push(LogEntry {
+                message: format!("Failed to create diagnostics report: {}", e),
+                error: Some(ErrorCategory::of(&e)),
+            }),
+        }
+    }
+
+    fn send_failure_report(&self) {
+        if self.options.no_telemetry {
+            return;
+        }
+        let error = self.logs.iter().rev().find_map(|log| log.error);
+        if let (Some(endpoint), Some(category)) = (&self.options.telemetry_endpoint, error) {
+            telemetry::send_failure_report(endpoint, category, state::rollout_bucket(&self.target_path()));
+        }
+    }
+
+    fn retry(&mut self) {
+        self.flash_window(false);
+        self.flashing = false;
+        if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+            taskbar.set_progress_state(hwnd, ProgressState::NoProgress);
+        }
+        let shared_state = Arc::clone(&self.shared_state);
+        shared_state.lock().unwrap().exit_code = exit::CANCELLED;
+        let options = self.options.clone();
+        *self = Self::new(shared_state, options);
+    }
+
+    fn flash_window(&self, start: bool) {
+        if let Some(window) = self.window_handle {
+            platform::flash_window(window, start);
+        }
+    }
+}
+
+impl App for UpdateGUI {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut Frame) {
+        if self.window_handle.is_none() {
+            self.window_handle = platform::window_handle(frame);
+        }
+
+        while let Ok(msg) = self.update_receiver.try_recv() {
+            match msg {
+                UpdateMessage::Log(log) => {
+                    log::info!("{}", log);
+                    if self.options.verbosity > Verbosity::Quiet {
+                        self.logs.push(LogEntry {
+                            message: log,
+                            error: None,
+                        })
+                    }
+                }
+                UpdateMessage::Detail(level, detail) => {
+                    if self.options.verbosity >= level {
+                        self.logs.push(LogEntry {
+                            message: detail,
+                            error: None,
+                        })
+                    }
+                }
+                UpdateMessage::Error(log, category, response) => {
+                    log::error!("[{}] {}", telemetry::summarize(&log, category).code, log);
+                    report::record_error(&log, category);
+                    if let Some(response) = &response {
+                        log::error!("Server response: {}", response);
+                    }
+                    self.logs.push(LogEntry {
+                        message: log,
+                        error: Some(category),
+                    });
+                    self.last_error_response = response;
+                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+                        taskbar.set_progress_state(hwnd, ProgressState::Error);
+                    }
+                    self.flashing = true;
+                    self.flash_window(true);
+                }
+                UpdateMessage::Status(status) => {
+                    log::debug!("Status: {}", status);
+                    if self.options.verbosity >= Verbosity::Debug {
+                        self.logs.push(LogEntry {
+                            message: format!("Status: {}", status),
+                            error: None,
+                        });
+                    }
+                    self.status = status;
+                }
+                UpdateMessage::Progress(progress) => {
+                    self.progress = progress;
+                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+                        taskbar.set_progress_state(hwnd, ProgressState::Normal);
+                        taskbar.set_progress_value(hwnd, (progress * 100.0) as u64, 100);
+                    }
+                }
+                UpdateMessage::ProgressUpdate(update) => {
+                    self.bytes_downloaded = update.downloaded;
+                    self.total_bytes = update.total;
+                    self.bytes_per_sec = update.bytes_per_sec;
+                    self.eta = update.eta;
+                    self.elapsed = update.elapsed;
+                    self.progress = if update.total > 0 {
+                        update.downloaded as f32 / update.total as f32
+                    } else {
+                        0.0
+                    };
+                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+                        taskbar.set_progress_state(hwnd, ProgressState::Normal);
+                        taskbar.set_progress_value(hwnd, update.downloaded, update.total);
+                    }
+                }
+                UpdateMessage::ApplyingProgress(progress_text) => {
+                    self.applying_progress = progress_text;
+                    self.file_progress = None;
+                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+                        taskbar.set_progress_state(hwnd, ProgressState::Indeterminate);
+                    }
+                }
+                UpdateMessage::FileProgress(file_progress) => {
+                    self.file_progress = Some(file_progress);
+                }
+                UpdateMessage::Step(index, count, description) => {
+                    self.step = Some((index, count, description));
+                }
+                UpdateMessage::OfferLaunchAnyway(version, size, reply) => {
+                    self.launch_anyway_offer = Some((version, size, reply));
+                    self.flash_window(true);
+                }
+                UpdateMessage::OfferElevation(path, reply) => {
+                    self.elevation_offer = Some((path, reply));
+                    self.flash_window(true);
+                }
+                UpdateMessage::DeferUpdate(downloads) => {
+                    let mut state = self.shared_state.lock().unwrap();
+                    state.deferred_downloads = downloads;
+                }
+                UpdateMessage::ReleaseCountdown(version, release_at, reply) => {
+                    self.release_countdown = Some((version, release_at, reply));
+                    self.flash_window(true);
+                }
+                UpdateMessage::ModifiedFiles(files, reply) => {
+                    let files = files.into_iter().map(|file| (file, ModChoice::BackUpAndReplace)).collect();
+                    self.modified_files = Some((files, reply));
+                    self.flash_window(true);
+                }
+                UpdateMessage::ReleaseNotes(notes) => self.release_notes = Some(notes),
+                UpdateMessage::RestartUpdater => {
+                    let mut args = successor_args();
+                    if !args.iter().any(|arg| arg == "--yes" || arg == "-y") {
+                        args.push("--yes".to_string());
+                    }
+                    match env::current_exe().and_then(|exe| Command::new(exe).args(&args).spawn()) {
+                        Ok(_) => {
+                            report::record_restart();
+                            let mut state = self.shared_state.lock().unwrap();
+                            state.update_complete = true;
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                        Err(e) => {
+                            self.logs.push(LogEntry {
+                                message: format!("Failed to restart the updater: {}", e),
+                                error: Some(ErrorCategory::of(&e)),
+                            });
+                            self.shared_state.lock().unwrap().exit_code = exit::FAILED;
+                            self.update_complete = true;
+                            self.update_failed = true;
+                        }
+                    }
+                }
+                // Cancelled; the next start continues where the update stopped.
+                UpdateMessage::Interrupted => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                UpdateMessage::RelaunchedElevated => {
+                    report::record_restart();
+                    let mut state = self.shared_state.lock().unwrap();
+                    state.update_complete = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                UpdateMessage::UpdateComplete => {
+                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+                        taskbar.set_progress_state(hwnd, ProgressState::NoProgress);
+                    }
+                    self.update_complete = true;
+
+                    if self.installer_state == InstallerState::Installing {
+                        let path = PathBuf::from(&self.install_path);
+                        copy_updater_to_install_dir(&path).ok();
+                        platform::create_shortcuts(
+                            &path,
+                            self.create_desktop_shortcut,
+                            self.create_startmenu_shortcut,
+                        );
+                        if self.register_uninstall_entry {
+                            platform::register_uninstaller(&path).ok();
+                        }
+                        self.installer_state = InstallerState::Finished;
+                    } else {
+                        match postupdate::action(&self.options) {
+                            PostUpdateAction::Launch => {
+                                self.logs.push(LogEntry {
+                                    message: "Launching game...".to_string(),
+                                    error: None,
+                                });
+                                match launch_game(&self.target_path(), &self.options.launch_args) {
+                                    Ok(_) => {
+                                        self.logs.push(LogEntry {
+                                            message: "Game launched successfully.".to_string(),
+                                            error: None,
+                                        });
+                                        let mut state = self.shared_state.lock().unwrap();
+                                        state.update_complete = true;
+                                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                                    }
+                                    Err(e) => {
+                                        self.logs.push(LogEntry {
+                                            message: format!("Failed to launch game: {}", e),
+                                            error: Some(ErrorCategory::of(&e)),
+                                        });
+                                        let mut state = self.shared_state.lock().unwrap();
+                                        state.update_complete = true;
+                                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                                    }
+                                }
+                            }
+                            PostUpdateAction::Summary => {
+                                self.summary = Some(postupdate::summary(&self.target_path()));
+                                self.shared_state.lock().unwrap().update_complete = true;
+                                self.flash_window(true);
+                            }
+                            action => {
+                                self.shared_state.lock().unwrap().update_complete = true;
+                                match postupdate::run(&self.target_path(), &action, &self.options.launch_args) {
+                                    Ok(()) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                                    // Left open so the player sees why nothing happened.
+                                    Err(message) => self.logs.push(LogEntry {
+                                        message,
+                                        error: Some(ErrorCategory::Other),
+                                    }),
+                                }
+                            }
+                        }
+                    }
+                }
+                UpdateMessage::UpdateFailed(category) => {
+                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
+                        taskbar.set_progress_state(hwnd, ProgressState::Error);
+                    }
+                    self.send_failure_report();
+                    let exit_code = exit::for_failure(Some(category));
+                    self.shared_state.lock().unwrap().exit_code = exit_code;
+                    self.update_complete = true;
+                    self.update_failed = true;
+                    self.flashing = true;
+                    self.flash_window(true);
+                }
+            }
+        }
+
+        egui::TopBottomPanel::top("header").show(ctx, |ui| {
+            let texture: &egui::TextureHandle = self.texture.get_or_insert_with(|| {
+                ui.ctx()
+                    .load_texture("icon", self.image.take().unwrap(), Default::default())
+            });
+
+            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                ui.image(texture);
+            });
+
+            ui.separator();
+
+            match self.installer_state {
+                InstallerState::ConfirmClose => {
+                    ui.heading("DREAMIO is running");
+                    ui.label("The game must be closed to update.");
+                }
+                InstallerState::Terms => {
+                    ui.heading("Welcome to DREAMIO Setup");
+                    ui.label("Please review the terms below.");
+                }
+                InstallerState::Location => {
+                    ui.heading("Installation Options");
+                    ui.label("Choose where to install DREAMIO.");
+                }
+                InstallerState::Finished => {
+                    ui.heading("Installation Complete");
+                    ui.label("DREAMIO has been successfully installed.");
+                }
+                InstallerState::Updater | InstallerState::Installing => {
+                    ui.label(&self.status);
+
+                    let mut launch_anyway_choice = None;
+                    if let Some((version, size, _)) = &self.launch_anyway_offer {
+                        ui.label(format!(
+                            "Version {} ({}) is available. This update is optional.",
+                            version,
+                            format_bytes(*size)
+                        ));
+                        ui.horizontal(|ui| {
+                            if ui.button("Update now").clicked() {
+                                launch_anyway_choice = Some(false);
+                            }
+                            if ui.button("Play now, update later").clicked() {
+                                launch_anyway_choice = Some(true);
+                            }
+                        });
+                    }
+                    if let Some(play_now) = launch_anyway_choice {
+                        if let Some((_, _, reply)) = self.launch_anyway_offer.take() {
+                            reply.send(play_now).ok();
+                        }
+                        self.flash_window(false);
+                    }
+
+                    let mut elevation_choice = None;
+                    if let Some((path, _)) = &self.elevation_offer {
+                        ui.label(format!(
+                            "DREAMIO is installed in {}, which needs administrator rights to update.",
+                            path.display()
+                        ));
+                        ui.horizontal(|ui| {
+                            if ui.button("Restart as administrator").clicked() {
+                                elevation_choice = Some(true);
+                            }
+                            if ui.button("Cancel").clicked() {
+                                elevation_choice = Some(false);
+                            }
+                        });
+                    }
+                    if let Some(elevate) = elevation_choice {
+                        if let Some((_, reply)) = self.elevation_offer.take() {
+                            reply.send(elevate).ok();
+                        }
+                        self.flash_window(false);
+                    }
+
+                    let mut stop_waiting = false;
+                    if let Some((version, release_at, _)) = &self.release_countdown {
+                        let remaining = release_at.duration_since(SystemTime::now()).unwrap_or_default();
+                        if remaining.is_zero() {
+                            self.release_countdown = None;
+                        } else {
+                            ui.label(format!(
+                                "Version {} is preloaded and unlocks in {}. Keep this window open to play it right away.",
+                                version,
+                                format_duration(remaining)
+                            ));
+                            if ui.button("Play the current version").clicked() {
+                                stop_waiting = true;
+                            }
+                        }
+                    }
+                    if stop_waiting {
+                        if let Some((_, _, reply)) = self.release_countdown.take() {
+                            reply.send(true).ok();
+                        }
+                        self.flash_window(false);
+                    }
+
+                    let mut confirm_choices = false;
+                    if let Some((files, _)) = &mut self.modified_files {
+                        ui.label(format!(
+                            "{} file(s) the update changes were modified, e.g. by a mod. Patching them would break them. Choose what to do with each:",
+                            files.len()
+                        ));
+                        egui::ScrollArea::vertical()
+                            .id_salt("modified_files")
+                            .max_height(150.0)
+                            .show(ui, |ui| {
+                                for (file, choice) in files.iter_mut() {
+                                    ui.label(file.as_str());
+                                    ui.horizontal(|ui| {
+                                        for option in ModChoice::ALL {
+                                            ui.radio_value(choice, option, option.label());
+                                        }
+                                    });
+                                }
+                            });
+                        if ui.button("Continue").clicked() {
+                            confirm_choices = true;
+                        }
+                    }
+                    if confirm_choices {
+                        if let Some((files, reply)) = self.modified_files.take() {
+                            reply.send(files.into_iter().map(|(_, choice)| choice).collect()).ok();
+                        }
+                        self.flash_window(false);
+                    }
+
+                    let progress_text = if self.status == "Applying update..." {
+                        self.applying_progress.clone()
+                    } else {
+                        format!(
+                            "[{}] {}/{} ({}/s, ETA: {})",
+                            format_duration(self.elapsed),
+                            format_bytes(self.bytes_downloaded),
+                            format_bytes(self.total_bytes),
+                            format_bytes(self.bytes_per_sec as u64),
+                            format_duration(self.eta)
+                        )
+                    };
+                    let quiet = self.options.verbosity == Verbosity::Quiet;
+                    let minimal = self.options.gui;
+                    if let Some((index, count, description)) = &self.step {
+                        let overall = ((*index - 1) as f32 + self.progress) / *count as f32;
+                        if !quiet {
+                            ui.label(format!("Step {}/{}: {}", index, count, description));
+                        }
+                        ui.add(
+                            egui::ProgressBar::new(overall)
+                                .fill(egui::Color32::from_hex("#ddb99b").unwrap()),
+                        );
+                    }
+                    // In quiet mode and the minimal window the step bar alone shows the overall
+                    // progress.
+                    if !(quiet || minimal) || self.step.is_none() {
+                        ui.add(
+                            egui::ProgressBar::new(self.progress)
+                                .fill(egui::Color32::from_hex("#ddb99b").unwrap()),
+                        );
+                    }
+                    if !(quiet || minimal) {
+                        ui.label(progress_text);
+                    }
+
+                    if let Some(file_progress) = &self.file_progress {
+                        if !(quiet || minimal) && self.status == "Applying update..." && file_progress.total > 0 {
+                            ui.add(
+                                egui::ProgressBar::new(
+                                    file_progress.processed as f32 / file_progress.total as f32,
+                                )
+                                .fill(egui::Color32::from_hex("#ddb99b").unwrap()),
+                            );
+                            ui.label(format!(
+                                "{}: {}/{}",
+                                file_progress.name,
+                                format_bytes(file_progress.processed),
+                                format_bytes(file_progress.total)
+                            ));
+                        }
+                    }
+
+                    // A first install has nothing to fall back to, so it is not cancelled halfway.
+                    let can_cancel = self.installer_state == InstallerState::Updater && !self.update_complete;
+                    if can_cancel && !self.cancelling && ui.button("Cancel").clicked() {
+                        interrupt::request();
+                        self.cancelling = true;
+                        self.status = "Stopping at a safe point...".to_string();
+                    }
+
+                    if let Some(summary) = &self.summary {
+                        ui.separator();
+                        ui.heading(summary);
+                        ui.horizontal(|ui| {
+                            if ui.button("Play").clicked() {
+                                match launch_game(&self.target_path(), &self.options.launch_args) {
+                                    Ok(_) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                                    Err(e) => {
+                                        ui.colored_label(egui::Color32::RED, format!("Failed to launch: {}", e));
+                                    }
+                                }
+                            }
+                            if ui.button("Exit").clicked() {
+                                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                            }
+                        });
+                    }
+
+                    if self.update_failed {
+                        ui.separator();
+
+                        if self.last_error_response.is_some() {
+                            ui.heading("A security appliance or firewall might be blocking the request. Please check your firewall software, for instance Xfinity Advanced Security.");
+                            ui.horizontal(|ui| {
+                                if ui.button("Retry").clicked() {
+                                    self.retry();
+                                }
+                                if ui.button("More details").clicked() {
+                                    if let Some(response) = &self.last_error_response {
+                                        let path = std::env::temp_dir().join("dreamio-updater-error.html");
+                                        if let Ok(mut file) = std::fs::File::create(&path) {
+                                            if file.write_all(response.as_bytes()).is_ok() {
+                                                opener::open(path).ok();
+                                            }
+                                        }
+                                    }
+                                }
+                                if ui.button("Create diagnostics report").clicked() {
+                                    self.create_diagnostics_bundle();
+                                }
+                            });
+                        } else {
+                            let error = self.logs.iter().rev().find_map(|log| log.error);
+                            let help = help::for_error(&self.target_path(), error.unwrap_or(ErrorCategory::Other));
+                            ui.heading(help.text);
+                            ui.hyperlink(help.url);
+                            ui.horizontal(|ui| {
+                                if ui.button("Retry").clicked() {
+                                    self.retry();
+                                }
+                                if ui.button("Create diagnostics report").clicked() {
+                                    self.create_diagnostics_bundle();
+                                }
+                            });
+                        }
+                    }
+                }
+            }
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            match self.installer_state {
+                InstallerState::ConfirmClose => {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.label("Save your progress in the game before continuing. Any unsaved progress will be lost when the game is closed.");
+                        if session::is_session_active(&self.target_path()) {
+                            ui.add_space(10.0);
+                            ui.colored_label(egui::Color32::YELLOW, "A story is in progress right now.");
+                        }
+                        ui.add_space(20.0);
+                        if ui.button("Close game and update").clicked() {
+                            self.installer_state = InstallerState::Updater;
+                            self.start_update_thread();
+                        }
+                        if ui.button("Cancel").clicked() {
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                    });
+                }
+                InstallerState::Terms => {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.label("Before installing, please review Privacy Policy and Terms and Conditions.");
+                        ui.hyperlink("https://dreamio.xyz/privacy-policy/");
+                        ui.hyperlink("https://dreamio.xyz/terms-and-conditions/");
+                        ui.add_space(20.0);
+                        ui.checkbox(&mut self.terms_accepted, "I accept the Privacy Policy and Terms and Conditions");
+                        ui.add_space(20.0);
+                        if ui.add_enabled(self.terms_accepted, egui::Button::new("Next")).clicked() {
+                            self.installer_state = InstallerState::Location;
+                        }
+                    });
+                }
+                InstallerState::Location => {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.label("Install Location:");
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut self.install_path);
+                            if ui.button("Browse...").clicked() {
+                                if let Some(path) = FileDialog::new().pick_folder() {
+                                    let mut new_path = path;
+                                    let path_str = new_path.to_string_lossy().to_string();
+                                    if !path_str.to_lowercase().contains("dreamio") {
+                                        new_path = new_path.join(platform::DISPLAY_NAME);
+                                    }
+                                    self.install_path = new_path.display().to_string();
+                                }
+                            }
+                            if ui.button("Reset").clicked() {
+                                self.install_path = platform::default_install_path();
+                            }
+                        });
+                        ui.add_space(10.0);
+                        ui.checkbox(&mut self.create_desktop_shortcut, "Create Desktop Shortcut");
+                        ui.checkbox(&mut self.create_startmenu_shortcut, "Create Start Menu Shortcut");
+                        if cfg!(windows) {
+                            ui.checkbox(&mut self.register_uninstall_entry, "Add to Installed Apps");
+                        }
+                        ui.add_space(20.0);
+                        if ui.button("Install").clicked() {
+                            let mut path = PathBuf::from(&self.install_path);
+                            let path_str = path.to_string_lossy().to_string();
+                            if !path_str.to_lowercase().contains("dreamio") {
+                                path = path.join(platform::DISPLAY_NAME);
+                                self.install_path = path.display().to_string();
+                            }
+                            match std::fs::create_dir_all(&path) {
+                                Ok(_) => {
+                                    self.installer_state = InstallerState::Installing;
+                                    self.start_install_thread(path);
+                                }
+                                Err(e) => {
+                                    let mut message = format!("Failed to create directory: {}.", path.display());
+                                    if e.kind() == std::io::ErrorKind::PermissionDenied {
+                                        message.push_str("\nAdministrator privileges might be required.");
+                                    } else {
+                                        message.push_str(&format!(" {}", e));
+                                    }
+                                    self.logs.push(LogEntry {
+                                        message,
+                                        error: Some(ErrorCategory::of(&e)),
+                                    });
+                                }
+                            }
+                        }
+                        for log in &self.logs {
+                            if log.error.is_some() {
+                                ui.colored_label(egui::Color32::RED, &log.message);
+                            }
+                        }
+                    });
+                }
+                InstallerState::Finished => {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.label("Installation has completed successfully.");
+                        ui.add_space(20.0);
+                        if ui.button("Launch DREAMIO").clicked() {
+                            match launch_game(Path::new(&self.install_path), &self.options.launch_args) {
+                                Ok(_) => {
+                                    let mut state = self.shared_state.lock().unwrap();
+                                    state.update_complete = true;
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                                }
+                                Err(e) => {
+                                    ui.colored_label(egui::Color32::RED, format!("Failed to launch: {}", e));
+                                }
+                            }
+                        }
+                        if ui.button("Exit").clicked() {
+                            let mut state = self.shared_state.lock().unwrap();
+                            state.update_complete = true;
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                    });
+                }
+                InstallerState::Updater | InstallerState::Installing => {
+                    // Errors are summarized for players, with the full message a key press away.
+                    if ctx.input(|input| input.key_pressed(egui::Key::D)) {
+                        self.show_error_details = !self.show_error_details;
+                    }
+                    let details = self.show_error_details || self.options.verbosity >= Verbosity::Verbose;
+                    let mut has_errors = false;
+                    if let Some(notes) = self.release_notes.as_ref().filter(|_| !self.options.gui) {
+                        egui::CollapsingHeader::new(notes.title())
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                egui::ScrollArea::vertical()
+                                    .id_salt("release_notes")
+                                    .max_height(160.0)
+                                    .show(ui, |ui| notes::show(ui, &notes.markdown));
+                            });
+                        ui.separator();
+                    }
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        // The minimal window only lists what went wrong.
+                        for log in self.logs.iter().filter(|log| log.error.is_some() || !self.options.gui) {
+                            let text = if let Some(category) = log.error {
+                                has_errors = true;
+                                let message = if details {
+                                    log.message.clone()
+                                } else {
+                                    telemetry::summarize(&log.message, category).to_string()
+                                };
+                                egui::RichText::new(message)
+                                    .color(egui::Color32::RED)
+                                    .monospace()
+                            } else {
+                                egui::RichText::new(&log.message).monospace()
+                            };
+                            ui.label(text);
+                        }
+                        if has_errors && !details && ui.button("Show details (D)").clicked() {
+                            self.show_error_details = true;
+                        }
+                    });
+                }
+            }
+        });
+
+        if !self.update_complete || self.flashing {
+            ctx.request_repaint();
+        }
+    }
+}
+
+
+fn is_game_running(base_path: &Path) -> bool {
+    let mut system = System::new();
+    system.refresh_processes();
+    manifest::game_executables(base_path)
+        .processes
+        .iter()
+        .any(|name| system.processes_by_name(name).next().is_some())
+}
+
+/// Starts the game the way the installed build's manifest says, with `extra_args` from the
+/// command line added.
+pub fn launch_game(base_path: &Path, extra_args: &[String]) -> io::Result<std::process::Child> {
+    platform::launch_game(base_path, &manifest::game_executables(base_path).launch, extra_args)
+}
+
+fn install_vcredist(base_path: &Path, sender: &Sender<UpdateMessage>) {
+
+    sender
+        .send(UpdateMessage::Log("Installing Microsoft Visual C++ Redistributable...".to_string()))
+        .unwrap();
+
+    let url = "https://aka.ms/vs/17/release/vc_redist.x64.exe";
+    let vcredist_path = base_path.join("vc_redist.x64.exe");
+
+    if let Err(e) = download_file(url, &vcredist_path, sender) {
+        sender
+            .send(UpdateMessage::Log(format!(
+                "Failed to download Microsoft Visual C++ Redistributable: {}",
+                e
+            )))
+            .unwrap();
+        return;
+    }
+
+    sender
+        .send(UpdateMessage::Log("Running Microsoft Visual C++ installer...".to_string()))
+        .unwrap();
+
+    match std::process::Command::new(&vcredist_path)
+        .args(["/install", "/quiet", "/norestart"])
+        .status()
+    {
+        Ok(status) => {
+            if status.success() {
+                sender
+                    .send(UpdateMessage::Log("Successfully installed Microsoft Visual C++ Redistributable.".to_string()))
+                    .unwrap();
+            } else {
+                sender
+                    .send(UpdateMessage::Log(format!("Microsoft Visual C++ installer returned non-zero status: {}", status)))
+                    .unwrap();
+            }
+        }
+        Err(e) => {
+            // Check if error is ERROR_ELEVATION_REQUIRED (740)
+            if e.raw_os_error() == Some(740) {
+                sender
+                    .send(UpdateMessage::Log("Elevation required. Prompting for administrator privileges...".to_string()))
+                    .unwrap();
+
+                // Fall back to powershell Start-Process -Verb RunAs
+                let escaped_path = vcredist_path.display().to_string().replace("'", "''");
+                let script = format!(
+                    "Start-Process -FilePath '{}' -ArgumentList '/install /quiet /norestart' -Verb RunAs -Wait",
+                    escaped_path
+                );
+
+                match std::process::Command::new("powershell")
+                    .args(["-NoProfile", "-ExecutionPolicy", "Bypass", "-Command", &script])
+                    .status()
+                {
+                    Ok(status) => {
+                        if status.success() {
+                            sender
+                                .send(UpdateMessage::Log("Successfully installed Microsoft Visual C++ Redistributable with elevated privileges.".to_string()))
+                                .unwrap();
+                        } else {
+                            sender
+                                .send(UpdateMessage::Log(format!("Elevated Microsoft Visual C++ installer returned non-zero status: {}", status)))
+                                .unwrap();
+                        }
+                    }
+                    Err(elevated_e) => {
+                        sender
+                            .send(UpdateMessage::Log(format!(
+                                "Failed to run elevated Microsoft Visual C++ installer: {}",
+                                elevated_e
+                            )))
+                            .unwrap();
+                    }
+                }
+            } else {
+                sender
+                    .send(UpdateMessage::Log(format!(
+                        "Failed to run Microsoft Visual C++ installer: {}",
+                        e
+                    )))
+                    .unwrap();
+            }
+        }
+    }
+
+    if vcredist_path.exists() {
+        let _ = std::fs::remove_file(&vcredist_path);
+    }
+}
+
+fn spawn_update_task(sender: Sender<UpdateMessage>, target_path: PathBuf, options: Options) {
+    thread::spawn(move || update_task(sender, target_path, options));
+}
+
+fn update_task(sender: Sender<UpdateMessage>, target_path: PathBuf, options: Options) {
+    sender
+        .send(UpdateMessage::Status("Checking for updates...".to_string()))
+        .unwrap();
+
+    if let Some(pin) = pin::load(&target_path) {
+        let banner = pin::banner(&pin, &target_path);
+        log::warn!("{}", banner);
+        sender
+            .send(UpdateMessage::Status("Updates are paused by a version pin.".to_string()))
+            .unwrap();
+        sender.send(UpdateMessage::Log(banner)).unwrap();
+        sender.send(UpdateMessage::UpdateComplete).unwrap();
+        return;
+    }
+
+    // Updating again would race the replacements Windows still has to make.
+    let reboot_pending = state::reboot_pending(&target_path);
+    if !reboot_pending.is_empty() {
+        sender
+            .send(UpdateMessage::Error(
+                format!(
+                    "The previous update finishes when the computer restarts ({} file(s) waiting). Restart your computer and try again.",
+                    reboot_pending.len()
+                ),
+                ErrorCategory::Other,
+                None,
+            ))
+            .unwrap();
+        sender.send(UpdateMessage::UpdateFailed(ErrorCategory::Other)).unwrap();
+        return;
+    }
+
+    // Without this, every file would be skipped one by one with "access denied".
+    if is_permission_denied(&target_path) && platform::can_relaunch_elevated() {
+        let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+        sender
+            .send(UpdateMessage::OfferElevation(target_path.clone(), reply_sender))
+            .unwrap();
+        if reply_receiver.recv().unwrap_or(false) {
+            match platform::relaunch_elevated(&successor_args()) {
+                Ok(()) => {
+                    sender.send(UpdateMessage::RelaunchedElevated).unwrap();
+                    return;
+                }
+                Err(e) => sender
+                    .send(UpdateMessage::Error(
+                        format!("Failed to restart the updater as administrator: {}", e),
+                        ErrorCategory::Permissions,
+                        None,
+                    ))
+                    .unwrap(),
+            }
+        } else {
+            sender
+                .send(UpdateMessage::Error(
+                    format!(
+                        "Updating {} needs administrator rights. Run the updater as administrator.",
+                        target_path.display()
+                    ),
+                    ErrorCategory::Permissions,
+                    None,
+                ))
+                .unwrap();
+        }
+        sender.send(UpdateMessage::UpdateFailed(ErrorCategory::Permissions)).unwrap();
+        return;
+    }
+
+    let mut context = UpdateContext::new(sender, target_path, options);
+    mods::ask_player();
+    // Steps stopping the update report why themselves.
+    let _ = UpdatePipeline::for_install(&context).build().run(&mut context);
+}
+
+/// Tries creating a file in `dir`, since neither its permissions nor UAC tell reliably whether
+/// the updater may write there. A folder that does not exist yet is left to the installer.
+fn is_permission_denied(dir: &Path) -> bool {
+    if !dir.is_dir() {
+        return false;
+    }
+    let probe = dir.join(".dreamio_write_test");
+    match File::create(&probe) {
+        Ok(_) => {
+            fs::remove_file(&probe).ok();
+            false
+        }
+        Err(e) => e.kind() == io::ErrorKind::PermissionDenied,
+    }
+}
+
+const PREFETCH_DIR: &str = "prefetch";
+
+/// Prefetched archives are keyed by URL and target version, since `latest.zip` keeps its URL
+/// across versions.
+fn prefetch_path(base_path: &Path, target_version: &str, artifact: &Artifact) -> PathBuf {
+    let key = hashing::sha256_bytes(format!("{}\n{}", artifact.url, target_version).as_bytes());
+    base_path.join(PREFETCH_DIR).join(format!("{}.zip", &key[..16]))
+}
+
+/// Whether the archive prefetched at `path` is there and matches the hash the manifest publishes.
+/// One that does not, say from an older updater or a damaged disk, is removed so it is downloaded
+/// again rather than applied.
+fn prefetched_is_valid(path: &Path, artifact: &Artifact) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    let Some(expected) = &artifact.sha256 else {
+        return true;
+    };
+    match hashing::sha256_file(path) {
+        Ok(sha256) if sha256 == *expected => true,
+        result => {
+            match result {
+                Ok(_) => log::warn!("{} does not match the hash published in the manifest", path.display()),
+                Err(e) => log::warn!("Failed to hash {}: {}", path.display(), e),
+            }
+            fs::remove_file(path).ok();
+            false
+        }
+    }
+}
+
+/// Removes what was prefetched for the update just applied, keeping the preload of the next version.
+fn remove_prefetched(base_path: &Path, preload: &[(String, Artifact)]) {
+    let dir = base_path.join(PREFETCH_DIR);
+    if preload.is_empty() {
+        fs::remove_dir_all(&dir).ok();
+        return;
+    }
+    let keep: Vec<PathBuf> = preload
+        .iter()
+        .map(|(target_version, artifact)| prefetch_path(base_path, target_version, artifact))
+        .collect();
+    for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+        if !keep.contains(&entry.path()) {
+            fs::remove_file(entry.path()).ok();
+        }
+    }
+}
+
+/// The next archive of a patch chain, downloading into the prefetch folder while the current one
+/// is applied.
+struct BackgroundDownload {
+    target_version: String,
+    progress: Receiver<UpdateMessage>,
+    handle: thread::JoinHandle<Result<(), String>>,
+}
+
+impl BackgroundDownload {
+    fn start(base_path: &Path, target_version: &str, artifact: &Artifact) -> Option<Self> {
+        let path = prefetch_path(base_path, target_version, artifact);
+        if path.exists() || fs::create_dir_all(base_path.join(PREFETCH_DIR)).is_err() {
+            return None;
+        }
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".part");
+        let partial_path = PathBuf::from(partial_path);
+
+        let (sender, progress) = crossbeam_channel::unbounded();
+        let artifact = artifact.clone();
+        let handle = thread::spawn(move || {
+            let result = peercache::fetch(&artifact, &partial_path, &sender)
+                .map(Ok)
+                .unwrap_or_else(|| download_file(&artifact.url, &partial_path, &sender))
+                .map_err(|e| e.to_string())
+                .and_then(|sha256| match &artifact.sha256 {
+                    Some(expected) if *expected != sha256 => {
+                        Err("the download does not match the hash published in the manifest".to_string())
+                    }
+                    _ => fs::rename(&partial_path, &path).map_err(|e| e.to_string()),
+                });
+            if result.is_err() {
+                fs::remove_file(&partial_path).ok();
+            }
+            result
+        });
+        Some(Self {
+            target_version: target_version.to_string(),
+            progress,
+            handle,
+        })
+    }
+
+    /// Waits for the download to finish, showing its progress once the archive before it has
+    /// been applied. On success the archive is in the prefetch folder.
+    fn finish(self, sender: &Sender<UpdateMessage>) -> Result<(), String> {
+        if !self.handle.is_finished() {
+            sender
+                .send(UpdateMessage::Log(format!(
+                    "Finishing the background download of version {}...",
+                    self.target_version
+                )))
+                .unwrap();
+        }
+        while !self.handle.is_finished() {
+            // Only the latest progress matters; the rest piled up while applying.
+            let latest = self
+                .progress
+                .recv_timeout(PROGRESS_REPORT_INTERVAL)
+                .into_iter()
+                .chain(self.progress.try_iter())
+                .filter(|message| matches!(message, UpdateMessage::ProgressUpdate(_)))
+                .last();
+            if let Some(message) = latest {
+                sender.send(message).unwrap();
+            }
+        }
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err("the download thread panicked".to_string()))
+    }
+}
+
+/// Downloads postponed updates without applying them, so the next start can install them right away.
+fn prefetch_updates(base_path: &Path, downloads: &[(String, Artifact)]) {
+    let _background = BackgroundScope::enter();
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    thread::spawn(move || for _ in receiver {});
+
+    if fs::create_dir_all(base_path.join(PREFETCH_DIR)).is_err() {
+        return;
+    }
+    for (target_version, artifact) in downloads {
+        let path = prefetch_path(base_path, target_version, artifact);
+        if prefetched_is_valid(&path, artifact) {
+            continue;
+        }
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".part");
+        let partial_path = PathBuf::from(partial_path);
+        let result = download_file(&artifact.url, &partial_path, &sender)
+            .and_then(|_| fs::rename(&partial_path, &path).map_err(|e| e.into()));
+        if let Err(e) = result {
+            log::warn!("Prefetching update to version {} failed: {}", target_version, e);
+            fs::remove_file(&partial_path).ok();
+            return;
+        }
+        if !prefetched_is_valid(&path, artifact) {
+            return;
+        }
+        log::info!("Prefetched update to version {}", target_version);
+    }
+}
+
+fn apply_patches_sequentially(
+    sender: &Sender<UpdateMessage>,
+    target_path: &Path,
+    update_zip_path: &Path,
+) -> ControlFlow<()> {
+    loop {
+        match get_version_info(target_path) {
+            Ok(info) => {
+                let version_code = info.version_code;
+                let update_url = info.update_url;
+
+                sender
+                    .send(UpdateMessage::Log(format!(
+                        "Downloading update for version {}...",
+                        version_code
+                    )))
+                    .unwrap();
+                match download_and_apply_update(&update_url, update_zip_path, target_path, sender) {
+                    Ok(summary) => {
+                        if summary.updater_replaced {
+                            request_updater_restart(sender);
+                            return ControlFlow::Break(());
+                        }
+                        match get_version_info(target_path) {
+                            Ok(new_info) => {
+                                if new_info.version_code == version_code {
+                                    sender
+                                        .send(UpdateMessage::Log(
+                                            "Update complete. No more updates available."
+                                                .to_string(),
+                                        ))
+                                        .unwrap();
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                let category = ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version);
+                                sender
+                                    .send(UpdateMessage::Error(
+                                        format!("Failed to read updated version info: {}", e),
+                                        category,
+                                        None,
+                                    ))
+                                    .unwrap();
+                                sender.send(UpdateMessage::UpdateFailed(category)).unwrap();
+                                cleanup(target_path);
+                                return ControlFlow::Break(());
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        if UpdaterError::is_not_found(e.as_ref()) {
+                            sender
+                                .send(UpdateMessage::Log(
+                                    "No more updates available.".to_string(),
+                                ))
+                                .unwrap();
+                            break;
+                        } else {
+                            report_failure(
+                                sender,
+                                target_path,
+                                ErrorCategory::of(e.as_ref()),
+                                format!("Error downloading update: {}", e),
+                            );
+                            return ControlFlow::Break(());
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let category = ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version);
+                sender
+                    .send(UpdateMessage::Error(
+                        format!("Failed to read version info: {}", e),
+                        category,
+                        None,
+                    ))
+                    .unwrap();
+                sender.send(UpdateMessage::UpdateFailed(category)).unwrap();
+                cleanup(target_path);
+                return ControlFlow::Break(());
+            }
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+fn quick_integrity_check(base_path: &Path) -> Result<(), String> {
+    get_version_info(base_path).map_err(|e| format!("version.json is unreadable: {}", e))?;
+    let game_exe = base_path.join(GAME_EXECUTABLE);
+    match fs::metadata(&game_exe) {
+        Ok(metadata) if metadata.len() > 0 => Ok(()),
+        Ok(_) => Err(format!("{} is empty", GAME_EXECUTABLE)),
+        Err(e) => Err(format!("{} is missing: {}", GAME_EXECUTABLE, e)),
+    }
+}
+
+fn offline_status(cached: &CachedManifest, current_version: &str) -> String {
+    if cached.manifest.latest == current_version {
+        format!(
+            "Could not reach the update server. You were up to date as of {}.",
+            format_timestamp(cached.fetched_at)
+        )
+    } else {
+        format!(
+            "Could not reach the update server. Version {} was available as of {} and will be installed next time.",
+            cached.manifest.latest,
+            format_timestamp(cached.fetched_at)
+        )
+    }
+}
+
+fn minimum_version_notice(remote_manifest: &RemoteManifest, current_version: &str) -> String {
+    format!(
+        "Version {} can no longer be played. Version {} or newer is required and has to be installed before the game can start.",
+        current_version,
+        remote_manifest.minimum_version.as_deref().unwrap_or_default()
+    )
+}
+
+fn report_failure(sender: &Sender<UpdateMessage>, target_path: &Path, category: ErrorCategory, message: String) {
+    // Whatever failed stopped at a safe point, and what it left behind is needed to resume.
+    if interrupt::requested() {
+        sender.send(UpdateMessage::Log(interrupt::stopped(target_path))).unwrap();
+        sender.send(UpdateMessage::Interrupted).unwrap();
+        return;
+    }
+    sender.send(UpdateMessage::Error(message, category, None)).unwrap();
+    sender.send(UpdateMessage::UpdateFailed(category)).unwrap();
+    cleanup(target_path);
+}
+
+/// Writes `message` to the log file and shows it in the window at `level` verbosity or above.
+fn report_detail(sender: &Sender<UpdateMessage>, level: Verbosity, message: String) {
+    if level >= Verbosity::Debug {
+        log::debug!("{}", message);
+    } else {
+        log::info!("{}", message);
+    }
+    sender.send(UpdateMessage::Detail(level, message)).unwrap();
+}
+
+fn request_updater_restart(sender: &Sender<UpdateMessage>) {
+    sender
+        .send(UpdateMessage::Log(
+            "The updater was updated. Restarting to continue...".to_string(),
+        ))
+        .unwrap();
+    sender.send(UpdateMessage::RestartUpdater).unwrap();
+}
+
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reports the number of bytes written so far, at most every [`PROGRESS_REPORT_INTERVAL`].
+struct ProgressWriter<'a, W: Write> {
+    inner: W,
+    processed: u64,
+    last_report: Instant,
+    on_progress: &'a mut dyn FnMut(u64),
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    fn new(inner: W, on_progress: &'a mut dyn FnMut(u64)) -> Self {
+        Self {
+            inner,
+            processed: 0,
+            last_report: Instant::now(),
+            on_progress,
+        }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.processed += n as u64;
+        if self.last_report.elapsed() >= PROGRESS_REPORT_INTERVAL {
+            (self.on_progress)(self.processed);
+            self.last_report = Instant::now();
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn report_file_progress(
+    sender: &Sender<UpdateMessage>,
+    name: &str,
+    processed: u64,
+    total: u64,
+    index: usize,
+    count: usize,
+) {
+    sender
+        .send(UpdateMessage::FileProgress(FileProgress {
+            name: name.to_string(),
+            processed,
+            total,
+        }))
+        .unwrap();
+    let fraction = if total > 0 {
+        (processed as f32 / total as f32).min(1.0)
+    } else {
+        0.0
+    };
+    sender
+        .send(UpdateMessage::Progress((index as f32 + fraction) / count as f32))
+        .unwrap();
+}
+
+/// Reads the size of the patched file from the bsdiff header.
+fn patch_output_size(patch_data: &[u8]) -> u64 {
+    patch_data
+        .get(24..32)
+        .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+        .map(|bytes| u64::from_le_bytes(bytes) & !(1 << 63))
+        .unwrap_or(0)
+}
+
+/// Large entries are checkpointed in the journal every this many bytes.
+const EXTRACT_CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Extracts `reader` to `out_path`, calling `on_checkpoint` with the offset and hash of the bytes
+/// written so far at regular intervals. When `resume` is given and the file still starts with the
+/// checkpointed bytes, extraction continues from there. Returns the offset it resumed from.
+fn extract_resumable<R: Read>(
+    reader: &mut R,
+    out_path: &Path,
+    resume: Option<(u64, String)>,
+    on_checkpoint: &mut dyn FnMut(u64, String) -> io::Result<()>,
+    on_progress: &mut dyn FnMut(u64),
+) -> io::Result<u64> {
+    if resume.is_none() {
+        platform::retry_while_in_use(out_path, || platform::prepare_replace(out_path))?;
+        // A file consolidated by `analyze` shares its data with its duplicates, which must keep it.
+        if platform::file_identity(out_path).is_some_and(|identity| identity.links > 1) {
+            platform::retry_while_in_use(out_path, || fs::remove_file(out_path))?;
+        }
+    }
+    let mut outfile = platform::retry_while_in_use(out_path, || {
+        File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(out_path)
+    })?;
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    if let Some((resume_offset, expected_hash)) = resume {
+        // Skipped zero blocks are only materialized at checkpoints, so the length is restored first.
+        outfile.set_len(resume_offset)?;
+        hashing::update_from_reader(&mut hasher, &mut (&outfile).take(resume_offset))?;
+        if hashing::hex_digest(&hasher) == expected_hash {
+            let skipped = io::copy(&mut reader.by_ref().take(resume_offset), &mut io::sink())?;
+            if skipped != resume_offset {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the archive entry is shorter than the journal",
+                ));
+            }
+            offset = resume_offset;
+        } else {
+            hasher = Sha256::new();
+        }
+    }
+    if offset == 0 {
+        outfile.set_len(0)?;
+    }
+
+    let mut writer = sparse::SparseWriter::starting_at(&mut outfile, offset)?;
+    let mut progress = ProgressWriter::new(&mut writer, on_progress);
+    progress.processed = offset;
+    let mut written = offset;
+    let mut next_checkpoint = offset + EXTRACT_CHECKPOINT_INTERVAL;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let limit = buffer.len().min((next_checkpoint - written) as usize);
+        let n = reader.read(&mut buffer[..limit])?;
+        if n == 0 {
+            break;
+        }
+        progress.write_all(&buffer[..n])?;
+        hasher.update(&buffer[..n]);
+        written += n as u64;
+        if written == next_checkpoint {
+            progress.inner.sync_data()?;
+            on_checkpoint(written, hashing::hex_digest(&hasher))?;
+            next_checkpoint += EXTRACT_CHECKPOINT_INTERVAL;
+        }
+    }
+    writer.finish()?;
+    Ok(offset)
+}
+
+/// The file a patch is applied to, mapped once. Its hash is taken from the same mapping the patch
+/// reads, so a file whose hash is not known yet is read from disk once rather than twice.
+struct PatchSource {
+    path: PathBuf,
+    // Mapping the original keeps multi-gigabyte files out of the process's memory.
+    map: Option<Mmap>,
+    _file: File,
+}
+
+impl PatchSource {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = platform::retry_while_in_use(path, || File::open(path))?;
+        let map = if file.metadata()?.len() > 0 {
+            Some(unsafe { Mmap::map(&file)? })
+        } else {
+            None
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            map,
+            _file: file,
+        })
+    }
+
+    fn contents(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+
+    fn sha256(&self) -> String {
+        hashing::known_sha256(&self.path).unwrap_or_else(|| {
+            let sha256 = hashing::sha256_bytes(self.contents());
+            hashing::remember(&self.path, &sha256);
+            sha256
+        })
+    }
+}
+
+/// Patches `source` into `new_file`, in place when that is allowed and the drive has no room for a
+/// patched copy, and returns the hash of the result.
+fn patch_file(
+    base_path: &Path,
+    in_place_patching: bool,
+    source: PatchSource,
+    patch_data: &[u8],
+    new_file: &Path,
+    on_progress: &mut dyn FnMut(u64),
+) -> io::Result<String> {
+    if source.path != new_file || !in_place_patching || !inplace::needed(base_path, new_file, patch_data) {
+        return apply_patch(source, patch_data, new_file, on_progress);
+    }
+    log::info!("Not enough disk space for a patched copy of {}; patching it in place", new_file.display());
+    drop(source);
+    let sha256 = inplace::apply(base_path, new_file, patch_data, on_progress)?;
+    hashing::remember(new_file, &sha256);
+    Ok(sha256)
+}
+
+/// Patches `source` into `new_file` and returns the hash of the result, taken while writing it.
+fn apply_patch(
+    source: PatchSource,
+    patch_data: &[u8],
+    new_file: &Path,
+    on_progress: &mut dyn FnMut(u64),
+) -> io::Result<String> {
+    let mut patched_path = new_file.as_os_str().to_owned();
+    patched_path.push(".patched");
+    let patched_path = PathBuf::from(patched_path);
+
+    let patcher = Bspatch::new(patch_data)?;
+    let result = File::create(&patched_path).and_then(|mut outfile| {
+        let mut writer = sparse::SparseWriter::new(&mut outfile);
+        let mut hashed = hashing::HashingWriter::new(&mut writer);
+        patcher.apply(source.contents(), ProgressWriter::new(&mut hashed, on_progress))?;
+        let sha256 = hashed.sha256();
+        writer.finish()?;
+        Ok(sha256)
+    });
+    let old_file = source.path.clone();
+    // Windows does not replace a file that is still mapped.
+    drop(source);
+
+    let result = result.and_then(|sha256| {
+        platform::keep_permissions(&old_file, &patched_path)?;
+        Ok(sha256)
+    });
+    let sha256 = match result {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            fs::remove_file(&patched_path).ok();
+            return Err(e);
+        }
+    };
+    platform::retry_while_in_use(new_file, || fs::rename(&patched_path, new_file))
+        .or_else(|e| defer_until_reboot(Some(&patched_path), new_file, e))?;
+    if deferred_replacement(new_file).is_none() {
+        hashing::remember(new_file, &sha256);
+    }
+    Ok(sha256)
+}
+
+/// Leaves replacing `path` with `staged`, or deleting it without a staged file, to Windows at the
+/// next restart. Only done for files other programs kept open through every retry, which shell
+/// extensions and overlays do for as long as they run. Returns `error` when that fails too.
+fn defer_until_reboot(staged: Option<&Path>, path: &Path, error: io::Error) -> io::Result<()> {
+    if !platform::is_still_in_use(&error) {
+        return Err(error);
+    }
+    let source = staged.unwrap_or(path);
+    if let Err(e) = platform::schedule_on_reboot(source, staged.map(|_| path)) {
+        log::warn!("Could not schedule {} for the next restart: {}", path.display(), e);
+        return Err(error);
+    }
+    log::info!("{} is in use and will be replaced at the next restart", path.display());
+    REBOOT_DEFERRED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((source.to_path_buf(), staged.map(|_| path.to_path_buf())));
+    Ok(())
+}
+
+/// The staged file that replaces `path` at the next restart, if it was deferred.
+fn deferred_replacement(path: &Path) -> Option<PathBuf> {
+    let deferred = REBOOT_DEFERRED.lock().unwrap_or_else(|e| e.into_inner());
+    deferred
+        .iter()
+        .rev()
+        .find(|(_, target)| target.as_deref() == Some(path))
+        .map(|(staged, _)| staged.clone())
+}
+
+/// Records the files left to the next restart in `updater_state.json`, so later runs wait for
+/// it, and tells the player to restart.
+fn finish_deferred(base_path: &Path, sender: &Sender<UpdateMessage>) {
+    let deferred: Vec<PathBuf> = REBOOT_DEFERRED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .drain(..)
+        .map(|(staged, _)| staged)
+        .collect();
+    if deferred.is_empty() {
+        return;
+    }
+    if let Err(e) = state::record_reboot_pending(base_path, &deferred) {
+        log::warn!("Failed to record the files waiting for a restart: {}", e);
+    }
+    sender
+        .send(UpdateMessage::Log(format!(
+            "{} file(s) are in use by other programs and will be replaced when the computer restarts. Restart your computer to finish the update.",
+            deferred.len()
+        )))
+        .unwrap();
+}
+
+/// Chunks buffered between the network and the disk writer.
+const WRITE_QUEUE_CHUNKS: usize = 64;
+
+/// Downloads `url` to `path` from a thread outside the network runtime and returns the SHA256
+/// hash of the contents.
+fn download_file(
+    url: &str,
+    path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    http::block_on(download_file_async(url, path, sender))
+}
+
+async fn download_file_async(
+    url: &str,
+    path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    download_resumable(url, path, None, sender).await
+}
+
+/// Streams `url` to `path` and returns the SHA256 hash of the contents. Chunks are written and
+/// hashed on a blocking thread, so the next ones download while the previous ones reach the disk.
+/// With `checkpoints`, the install folder whose `update_state.json` records the download, a
+/// partial download left at `path` continues from its last checkpoint.
+async fn download_resumable(
+    url: &str,
+    path: &Path,
+    checkpoints: Option<&Path>,
+    sender: &Sender<UpdateMessage>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let source = source::for_url(url);
+    report_detail(sender, Verbosity::Verbose, format!("Fetching {}", source.describe()));
+    let saved = checkpoints
+        .map(|base_path| resume::saved_download(base_path, url))
+        .filter(|&saved| fs::metadata(path).map(|metadata| metadata.len() >= saved).unwrap_or(false))
+        .unwrap_or(0);
+    let mut body = source.open(saved).await?;
+    let start = body.start();
+    if start > 0 {
+        report_detail(
+            sender,
+            Verbosity::Normal,
+            format!("Continuing the interrupted download from {}.", format_bytes(start)),
+        );
+    }
+
+    let total_size = body.size().map(|size| start + size).unwrap_or(0);
+    let mut downloaded: u64 = start;
+    // Truncated to the bytes kept rather than on opening, so a resumed download keeps them.
+    let mut file = File::options().read(true).write(true).create(true).truncate(false).open(path)?;
+    file.set_len(start)?;
+    let checkpoint = checkpoints.map(|base_path| (base_path.to_path_buf(), url.to_string()));
+    let (chunks, mut received) = tokio::sync::mpsc::channel::<Bytes>(WRITE_QUEUE_CHUNKS);
+    let writer = tokio::task::spawn_blocking(move || -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        // The part downloaded before is hashed again, so the hash covers the whole file.
+        io::copy(&mut (&mut file).take(start), &mut hasher)?;
+        let mut written = start;
+        let mut checkpointed = start;
+        while let Some(chunk) = received.blocking_recv() {
+            file.write_all(&chunk)?;
+            hasher.update(&chunk);
+            written += chunk.len() as u64;
+            if let Some((base_path, url)) = &checkpoint {
+                if written - checkpointed >= resume::CHECKPOINT_BYTES {
+                    file.sync_data()?;
+                    resume::record_download(base_path, url, written);
+                    checkpointed = written;
+                }
+            }
+        }
+        file.flush()?;
+        if let Some((base_path, url)) = &checkpoint {
+            file.sync_data()?;
+            resume::record_download(base_path, url, written);
+        }
+        Ok(hashing::hex_digest(&hasher))
+    });
+    let mut stats = TransferStats::new();
+    let stall_timeout = http::settings().stall_timeout;
+    let mut link_monitor = LinkMonitor::for_download();
+
+    loop {
+        let chunk = match tokio::time::timeout(stall_timeout, body.chunk()).await {
+            Ok(chunk) => chunk?,
+            Err(_) => {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("the download stalled, no data received for {} seconds", stall_timeout.as_secs()),
+                )))
+            }
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        if interrupt::requested() {
+            // The writer flushes and checkpoints what it received before stopping.
+            drop(chunks);
+            writer.await??;
+            return Err(Box::new(interrupt::Interrupted));
+        }
+        downloaded += chunk.len() as u64;
+        let chunk_len = chunk.len();
+        // The writer only stops early when a write failed, which awaiting it reports below.
+        if chunks.send(chunk).await.is_err() {
+            break;
+        }
+
+        if let Some(limit) = http::settings().bandwidth_limit {
+            let expected = Duration::from_secs_f64((downloaded - start) as f64 / limit.max(1) as f64);
+            if let Some(ahead) = expected.checked_sub(stats.elapsed()) {
+                tokio::time::sleep(ahead).await;
+            }
+        }
+        if let Some(monitor) = &mut link_monitor {
+            let delay = monitor.delay(chunk_len, downloaded - start);
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        stats.record(downloaded - start);
+
+        sender
+            .send(UpdateMessage::ProgressUpdate(ProgressUpdate {
+                downloaded,
+                total: total_size,
+                bytes_per_sec: stats.bytes_per_sec(),
+                eta: stats.eta(total_size.saturating_sub(downloaded)),
+                elapsed: stats.elapsed(),
+            }))
+            .unwrap();
+    }
+    drop(chunks);
+    let sha256 = writer.await??;
+
+    report_detail(
+        sender,
+        Verbosity::Verbose,
+        format!(
+            "Downloaded {} to {} in {} ({}/s)",
+            format_bytes(downloaded - start),
+            path.display(),
+            format_duration(stats.elapsed()),
+            format_bytes(((downloaded - start) as f64 / stats.elapsed().as_secs_f64().max(0.001)) as u64)
+        ),
+    );
+    Ok(sha256)
+}
+
+fn download_and_apply_update(
+    url: &str,
+    update_zip_path: &Path,
+    base_path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<ApplySummary, Box<dyn std::error::Error>> {
+    http::block_on(download_update(url, update_zip_path, sender))?;
+    let summary = apply_update(update_zip_path, base_path, sender)?;
+    cleanup(base_path);
+    Ok(summary)
+}
+
+/// Returns the SHA256 hash of the downloaded archive. It is downloaded to `update.zip.part` and
+/// only renamed to `update.zip` once complete, so a download interrupted by closing the updater
+/// continues on the next start instead of being mistaken for an archive to apply.
+async fn download_update(
+    url: &str,
+    update_zip_path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let base_path = update_zip_path.parent().unwrap_or(Path::new("."));
+    download_archive(url, update_zip_path, base_path, sender).await
+}
+
+/// `download_update` to a path outside the install folder, like the prefetch folder. The resume
+/// state is kept in `base_path`.
+async fn download_archive(
+    url: &str,
+    update_zip_path: &Path,
+    base_path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    sender
+        .send(UpdateMessage::Status("Downloading update...".to_string()))
+        .unwrap();
+    let partial_path = partial_download_path(update_zip_path);
+    let sha256 = match download_with_retries(url, &partial_path, base_path, sender).await {
+        Ok(sha256) => sha256,
+        // A server that answered over HTTPS answers the same over HTTP.
+        Err(e) if url.starts_with("https://") && !interrupt::requested() && !UpdaterError::is_permanent(e.as_ref()) => {
+            let http_url = url.replace("https", "http");
+            sender
+                .send(UpdateMessage::Log(
+                    "HTTPS download failed, trying HTTP...".to_string(),
+                ))
+                .unwrap();
+            download_resumable(&http_url, &partial_path, Some(base_path), sender).await?
+        }
+        Err(e) => return Err(e),
+    };
+    fs::rename(&partial_path, update_zip_path)?;
+    Ok(sha256)
+}
+
+fn partial_download_path(update_zip_path: &Path) -> PathBuf {
+    let mut partial_path = update_zip_path.as_os_str().to_owned();
+    partial_path.push(".part");
+    PathBuf::from(partial_path)
+}
+
+async fn get_latest_update_url(channel: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let url = format!("{}/version.json", channel_url(channel));
+    let client = http::async_client()?;
+    let timeout = Duration::from_secs(30);
+
+    let response = match client.get(&url).timeout(timeout).send().await {
+        Ok(res) => res,
+        Err(e) => {
+            diagnostics::record_request(&url, &e.to_string());
+            let http_url = url.replace("https", "http");
+            client.get(&http_url).timeout(timeout).send().await.inspect_err(|e| {
+                diagnostics::record_request(&http_url, &e.to_string());
+            })?
+        }
+    };
+    diagnostics::record_request(response.url().as_str(), response.status().as_str());
+
+    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).cloned();
+    let response_text = response.text().await?;
+
+    if let Some(content_type) = content_type {
+        if let Ok(content_type) = content_type.to_str() {
+            if content_type.contains("text/html") {
+                return Err(UpdaterError::HtmlResponse(response_text).into());
+            }
+        }
+    }
+
+    let json: Value = serde_json::from_str(&response_text)?;
+    if let Some(remote_channel) = json["channel"].as_str() {
+        if remote_channel != channel {
+            return Err(format!(
+                "Server returned a {} build for the {} channel",
+                remote_channel, channel
+            )
+            .into());
+        }
+    }
+    let update_url = json["latestUrl"]
+        .as_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid latestUrl in JSON"))?
+        .to_string();
+
+    Ok(update_url)
+}
+
+fn get_version_info(base_path: &Path) -> Result<VersionInfo, Box<dyn std::error::Error>> {
+    let version_file_path = base_path.join("version.json");
+    let version_content = fs::read_to_string(version_file_path)?;
+    let json: Value = serde_json::from_str(&version_content)?;
+
+    let version_code = json["versionCode"]
+        .as_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid versionCode in JSON"))?
+        .to_string();
+
+    let version_string = json["version"]
+        .as_str()
+        .unwrap_or(&version_code)
+        .to_string();
+
+    // Older updaters recorded the channel in version.json itself.
+    let channel = state::load_channel(base_path)
+        .or_else(|| json["channel"].as_str().map(|c| c.to_string()))
+        .unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+
+    let update_url = format!("{}/patches/{}.zip", channel_url(&channel), version_code);
+
+    Ok(VersionInfo {
+        version_code,
+        update_url,
+        version_string,
+        channel,
+    })
+}
+
+fn set_local_channel(base_path: &Path, channel: &str) -> Result<(), Box<dyn std::error::Error>> {
+    state::save_channel(base_path, channel)?;
+    Ok(())
+}
+
+/// Applies the archive at `update_zip_path` to the install in `base_path`, resuming where an
+/// interrupted attempt stopped.
+pub fn apply_update(update_zip_path: &Path, base_path: &Path, sender: &Sender<UpdateMessage>) -> io::Result<ApplySummary> {
+    sender
+        .send(UpdateMessage::Status("Applying update...".to_string()))
+        .unwrap();
+    // Entries are read from disk on demand so large archives never have to fit in memory.
+    let start_time = Instant::now();
+    report_detail(
+        sender,
+        Verbosity::Verbose,
+        format!("Applying {} to {}", update_zip_path.display(), base_path.display()),
+    );
+    let reader = io::BufReader::new(File::open(update_zip_path)?);
+    let mut archive = ZipArchive::new(reader)?;
+    let archive_len = archive.len();
+
+    let metadata = match archive.by_name(METADATA_FILE_NAME) {
+        Ok(mut file) => {
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            UpdateMetadata::parse(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        }
+        Err(_) => UpdateMetadata::default(),
+    };
+
+    let current_exe = env::current_exe()?;
+    let mut summary = ApplySummary::default();
+
+    let mut journal = ApplyJournal::open(base_path, update_zip_path)?;
+    if journal.completed > 0 {
+        sender
+            .send(UpdateMessage::Log(format!(
+                "Resuming the interrupted update at file {}/{}.",
+                journal.completed + 1,
+                archive_len
+            )))
+            .unwrap();
+    }
+
+    let manifest_path = base_path.join("install_manifest.txt");
+    let mut manifest = HashSet::new();
+    if manifest_path.exists() {
+        if let Ok(content) = fs::read_to_string(&manifest_path) {
+            for line in content.lines() {
+                if !line.is_empty() {
+                    manifest.insert(line.to_string());
+                }
+            }
+        }
+    }
+
+    let protected = ProtectedPaths::load(base_path);
+    let mut kept_protected = 0;
+    let mut pending = Vec::new();
+    // Entries that could not be applied, by index and name, with what went wrong.
+    let mut failed: Vec<(usize, String, ErrorCategory)> = Vec::new();
+    for i in 0..archive_len {
+        let mut file = match archive.by_index(i) {
+            Ok(file) => file,
+            Err(e) => {
+                sender
+                    .send(UpdateMessage::Error(
+                        format!("Error accessing file in archive: {}. Skipping.", e),
+                        ErrorCategory::of(&e),
+                        None,
+                    ))
+                    .unwrap();
+                failed.push((i, format!("entry {}", i + 1), ErrorCategory::of(&e)));
+                continue;
+            }
+        };
+        if file.name() == METADATA_FILE_NAME {
+            continue;
+        }
+        // Entries with absolute paths or `..` would write outside the installation.
+        let Some(out_path) = file.enclosed_name().map(|name| base_path.join(name)) else {
+            sender
+                .send(UpdateMessage::Error(
+                    format!("Unsafe path in archive: {}. Skipping.", file.name()),
+                    ErrorCategory::Patch,
+                    None,
+                ))
+                .unwrap();
+            failed.push((i, file.name().to_string(), ErrorCategory::Patch));
+            continue;
+        };
+
+        let is_updater = out_path.file_name() == current_exe.file_name() && out_path.parent() == current_exe.parent();
+        // Entries finished before an interruption are not written again, the updater among them.
+        if i < journal.completed {
+            if !is_updater {
+                manifest.insert(file.name().to_string());
+            }
+            continue;
+        }
+
+        if is_updater {
+            let staged_exe = current_exe.with_extension("exe.new");
+            let result = File::create(&staged_exe)
+                .and_then(|mut outfile| io::copy(&mut file, &mut outfile))
+                .and_then(|_| replace_updater(&current_exe, &staged_exe));
+            match result {
+                Ok(_) => {
+                    report_detail(sender, Verbosity::Verbose, format!("Replaced updater {}", current_exe.display()));
+                    summary.updater_replaced = true;
+                }
+                Err(e) => {
+                    fs::remove_file(&staged_exe).ok();
+                    sender
+                        .send(UpdateMessage::Error(
+                            format!("Error updating the updater: {}. Skipping.", e),
+                            ErrorCategory::of(&e),
+                            None,
+                        ))
+                        .unwrap();
+                    failed.push((i, file.name().to_string(), ErrorCategory::of(&e)));
+                }
+            }
+            continue;
+        }
+
+        // A partially extracted entry was not protected when it was started.
+        let resuming = journal.partial.as_ref().is_some_and(|partial| partial.index == i);
+        if let Some(touch) = protected.touched_by(base_path, file.name()).filter(|_| !resuming) {
+            // Left out of the install manifest too, so uninstalling keeps it.
+            report_detail(
+                sender,
+                Verbosity::Normal,
+                format!("Kept protected {}, it {}", file.name(), touch.describe()),
+            );
+            kept_protected += 1;
+            continue;
+        }
+        manifest.insert(file.name().to_string());
+        let concurrent = file.name().ends_with(".patch")
+            || (!file.name().ends_with('/')
+                && !file.name().ends_with(".delete")
+                && !platform::is_symlink_mode(file.unix_mode())
+                && file.size() < EXTRACT_CHECKPOINT_INTERVAL);
+        pending.push(PendingEntry {
+            index: i,
+            name: file.name().to_string(),
+            out_path,
+            concurrent,
+        });
+    }
+
+    let modified = mods::find_modified(base_path, &metadata, pending.iter().map(|entry| entry.name.as_str()));
+    let mod_choices = mods::choose(modified, sender);
+    let config = Config::load(base_path);
+    let context = EntryContext {
+        base_path,
+        metadata: &metadata,
+        mod_choices: &mod_choices,
+        sender,
+        archive_len,
+        config: &config,
+    };
+    let mut remaining = pending.as_slice();
+    while let Some(first) = remaining.first() {
+        let run = if first.concurrent {
+            remaining.iter().take_while(|entry| entry.concurrent).count()
+        } else {
+            1
+        };
+        let (batch, rest) = remaining.split_at(run);
+        remaining = rest;
+        journal.advance_to(first.index).ok();
+        interrupt::check()?;
+
+        if first.concurrent {
+            failed.extend(apply_concurrently(update_zip_path, batch, &context)?);
+        } else {
+            let mut file = archive.by_index(first.index)?;
+            match apply_entry(&context, &mut file, first.index, &first.out_path, Some(&mut journal)) {
+                Ok(()) => report_entry_applied(sender, first.index + 1, archive_len, file.name()),
+                Err(category) => failed.push((first.index, first.name.clone(), category)),
+            }
+        }
+    }
+    interrupt::check()?;
+    finish_deferred(base_path, sender);
+    if kept_protected > 0 {
+        sender
+            .send(UpdateMessage::Log(format!(
+                "{} protected file(s) were kept as they are.",
+                kept_protected
+            )))
+            .unwrap();
+    }
+
+    // A skipped file leaves the game half updated, so only files the patch marks as optional may
+    // fail, unless the player asked for --best-effort.
+    let mut critical: Vec<(usize, String, ErrorCategory)> = failed
+        .into_iter()
+        .filter(|(_, name, _)| !metadata.is_optional(name))
+        .collect();
+    if !critical.is_empty() && !BEST_EFFORT.load(Ordering::Relaxed) {
+        critical.sort_by_key(|(index, _, _)| *index);
+        let names: Vec<&str> = critical.iter().take(5).map(|(_, name, _)| name.as_str()).collect();
+        let mut message = format!(
+            "{} file(s) could not be updated: {}{}",
+            critical.len(),
+            names.join(", "),
+            if critical.len() > names.len() { ", ..." } else { "" }
+        );
+        if backup::exists(base_path) {
+            // The game goes back to the build the update started from instead of staying half
+            // updated. The update then starts over, so nothing of this attempt is kept.
+            journal.remove();
+            cleanup(base_path);
+            resume::finish(base_path);
+            match backup::restore(base_path) {
+                Ok(()) => message.push_str(". The update was rolled back"),
+                Err(e) => message.push_str(&format!(
+                    ". Rolling the update back failed: {}. Please reinstall the game",
+                    e
+                )),
+            }
+        } else {
+            // Retrying starts over from the first failed file.
+            journal.advance_to(critical[0].0).ok();
+        }
+        return Err(io::Error::other(UpdaterError::FilesFailed(critical[0].2, message)));
+    }
+
+    let mut manifest_content = String::new();
+    for entry in manifest {
+        manifest_content.push_str(&entry);
+        manifest_content.push('\n');
+    }
+    back_up(&manifest_path, backup::move_aside(base_path, &manifest_path));
+    fs::write(&manifest_path, manifest_content)?;
+    journal.remove();
+    let game_exe = base_path.join(GAME_EXECUTABLE);
+    if game_exe.exists() {
+        platform::ensure_executable(&game_exe)?;
+    }
+    if let Err(problem) = platform::verify_signature(base_path) {
+        sender
+            .send(UpdateMessage::Error(
+                format!("The game's code signature is invalid after updating: {}", problem),
+                ErrorCategory::Patch,
+                None,
+            ))
+            .unwrap();
+    }
+    report_detail(
+        sender,
+        Verbosity::Verbose,
+        format!(
+            "Applied {} archive entries in {}",
+            archive_len,
+            format_duration(start_time.elapsed())
+        ),
+    );
+
+    Ok(summary)
+}
+
+/// Windows allows renaming a running executable, so the old binary is moved aside and removed on the next start.
+fn replace_updater(current_exe: &Path, new_exe: &Path) -> io::Result<()> {
+    let old_exe = current_exe.with_extension("exe.old");
+    if old_exe.exists() {
+        fs::remove_file(&old_exe)?;
+    }
+    fs::rename(current_exe, &old_exe)?;
+    if let Err(e) = fs::rename(new_exe, current_exe) {
+        fs::rename(&old_exe, current_exe).ok();
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn remove_replaced_updater() {
+    if let Ok(current_exe) = env::current_exe() {
+        let old_exe = current_exe.with_extension("exe.old");
+        if old_exe.exists() {
+            fs::remove_file(old_exe).ok();
+        }
+    }
+}
+
+/// An archive entry that still has to be applied.
+struct PendingEntry {
+    index: usize,
+    name: String,
+    out_path: PathBuf,
+    /// Whether the entry only touches its own file, so it can be applied alongside its
+    /// neighbours. Entries large enough to be checkpointed are applied alone, since the journal
+    /// tracks a single partial entry.
+    concurrent: bool,
+}
+
+struct EntryContext<'a> {
+    base_path: &'a Path,
+    metadata: &'a UpdateMetadata,
+    /// What to do with files the player modified, by name.
+    mod_choices: &'a HashMap<String, ModChoice>,
+    sender: &'a Sender<UpdateMessage>,
+    archive_len: usize,
+    /// `updater.json`, read once for the whole archive.
+    config: &'a Config,
+}
+
+/// Applies a run of independent entries on worker threads, each reading its own handle to the
+/// archive. The journal only moves past the run once all of it is applied, so an interrupted run
+/// is redone as a whole. Returns the entries that could not be applied.
+fn apply_concurrently(
+    update_zip_path: &Path,
+    batch: &[PendingEntry],
+    context: &EntryContext,
+) -> io::Result<Vec<(usize, String, ErrorCategory)>> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    let first_index = batch[0].index;
+    let settings = &context.config.workers;
+    let priority = settings.priority(context.base_path);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..settings.count().min(batch.len()))
+            .map(|_| {
+                scope.spawn(|| -> io::Result<()> {
+                    settings.enter(priority);
+                    let mut archive = ZipArchive::new(io::BufReader::new(File::open(update_zip_path)?))?;
+                    while let Some(entry) = batch.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        // The journal still points at the start of the batch, so it is redone on resume.
+                        if interrupt::requested() {
+                            break;
+                        }
+                        let mut file = archive.by_index(entry.index)?;
+                        let applied = apply_entry(context, &mut file, entry.index, &entry.out_path, None);
+                        let position = first_index + done.fetch_add(1, Ordering::Relaxed) + 1;
+                        match applied {
+                            Ok(()) => report_entry_applied(context.sender, position, context.archive_len, file.name()),
+                            Err(category) => failed.lock().unwrap().push((entry.index, entry.name.clone(), category)),
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("an extraction thread panicked")))
+        })
+    })?;
+    Ok(failed.into_inner().unwrap())
+}
+
+fn report_entry_applied(sender: &Sender<UpdateMessage>, position: usize, archive_len: usize, name: &str) {
+    let progress_text = format!(
+        "Applying file {}/{}: {}",
+        position,
+        archive_len,
+        name
+    );
+    sender
+        .send(UpdateMessage::ApplyingProgress(progress_text))
+        .unwrap();
+    sender
+        .send(UpdateMessage::Progress(
+            position as f32 / archive_len as f32
+        ))
+        .unwrap();
+}
+
+/// Applies a single archive entry, reporting failures as errors. Without a journal the entry is
+/// applied on a worker thread: it is not checkpointed and its byte progress is not shown, since
+/// other entries are progressing at the same time. Returns the category of the failure it
+/// reported when the entry was not applied.
+fn apply_entry(
+    context: &EntryContext,
+    file: &mut zip::read::ZipFile,
+    index: usize,
+    out_path: &Path,
+    mut journal: Option<&mut ApplyJournal>,
+) -> Result<(), ErrorCategory> {
+    let EntryContext {
+        base_path,
+        mod_choices,
+        sender,
+        archive_len,
+        config,
+        ..
+    } = *context;
+    let detailed = journal.is_some();
+    if file.name().ends_with('/') {
+        if let Err(e) = fs::create_dir_all(out_path) {
+            return entry_failed(
+                sender,
+                ErrorCategory::of(&e),
+                format!(
+                    "Error creating directory {}: {}. Skipping.",
+                    out_path.display(),
+                    e
+                ),
+            );
+        }
+    } else if file.name().ends_with(".patch") {
+        let original_file = out_path.with_extension("");
+        let entry_name = file.name().trim_end_matches(".patch").to_string();
+        match mod_choices.get(&entry_name) {
+            Some(ModChoice::Keep) => {
+                report_detail(sender, Verbosity::Normal, format!("Kept modified {}", entry_name));
+                return Ok(());
+            }
+            Some(ModChoice::BackUpAndReplace) => {
+                if let Err(e) = mods::back_up(base_path, &entry_name) {
+                    return entry_failed(
+                        sender,
+                        ErrorCategory::of(&e),
+                        format!("Error backing up modified {}: {}. Skipping.", entry_name, e),
+                    );
+                }
+            }
+            Some(ModChoice::Replace) | None => {}
+        }
+        let mut patch_data = Vec::new();
+        if let Err(e) = file.read_to_end(&mut patch_data) {
+            return entry_failed(
+                sender,
+                ErrorCategory::of(&e).or(ErrorCategory::Patch),
+                format!(
+                    "Error reading patch data for {}: {}. Skipping.",
+                    original_file.display(),
+                    e
+                ),
+            );
+        }
+        back_up(&original_file, backup::keep_copy(base_path, &original_file));
+        let total = patch_output_size(&patch_data);
+        let mut report = |processed: u64| {
+            if detailed {
+                report_file_progress(sender, &entry_name, processed, total, index, archive_len)
+            }
+        };
+        if let Err(e) = apply_verified_patch(context, &original_file, &patch_data, &entry_name, &mut report) {
+            return entry_failed(
+                sender,
+                ErrorCategory::of(&e).or(ErrorCategory::Patch),
+                format!(
+                    "Error applying patch to {}: {}. Skipping.",
+                    original_file.display(),
+                    e
+                ),
+            );
+        }
+        report_detail(sender, Verbosity::Verbose, format!("Patched {}", original_file.display()));
+    } else if file.name().ends_with(".delete") {
+        let file_to_delete = out_path.with_extension("");
+        // Links are removed themselves, never what they point to.
+        if let Ok(metadata) = fs::symlink_metadata(&file_to_delete) {
+            report_detail(sender, Verbosity::Verbose, format!("Deleting {}", file_to_delete.display()));
+            // Moving it into the backup deletes it as well.
+            let moved = platform::retry_while_in_use(&file_to_delete, || backup::move_aside(base_path, &file_to_delete));
+            if back_up(&file_to_delete, moved) {
+                return Ok(());
+            }
+            if metadata.is_dir() {
+                if let Err(e) = platform::retry_while_in_use(&file_to_delete, || fs::remove_dir_all(&file_to_delete)) {
+                    return entry_failed(
+                        sender,
+                        ErrorCategory::of(&e),
+                        format!("Error deleting directory {}: {}", file_to_delete.display(), e),
+                    );
+                }
+            } else {
+                // Without a backup, the Recycle Bin is the only way back from a wrong delete entry.
+                if config.recycle_deleted_files && platform::move_to_trash(&file_to_delete).is_ok() {
+                    return Ok(());
+                }
+                let result = platform::retry_while_in_use(&file_to_delete, || fs::remove_file(&file_to_delete))
+                    .or_else(|e| defer_until_reboot(None, &file_to_delete, e));
+                if let Err(e) = result {
+                    return entry_failed(
+                        sender,
+                        ErrorCategory::of(&e),
+                        format!("Error deleting file {}: {}", file_to_delete.display(), e),
+                    );
+                }
+            }
+        }
+    } else {
+        if let Some(parent) = out_path.parent() {
+            if !parent.exists() {
+                if let Err(e) = fs::create_dir_all(parent) {
+                    return entry_failed(
+                        sender,
+                        ErrorCategory::of(&e),
+                        format!(
+                            "Error creating directory {}: {}. Skipping.",
+                            parent.display(),
+                            e
+                        ),
+                    );
+                }
+            }
+        }
+        back_up(
+            out_path,
+            platform::retry_while_in_use(out_path, || backup::move_aside(base_path, out_path)),
+        );
+        if platform::is_symlink_mode(file.unix_mode()) {
+            match platform::extract_symlink(file, base_path, out_path) {
+                Ok(()) => report_detail(sender, Verbosity::Verbose, format!("Linked {}", out_path.display())),
+                Err(e) => {
+                    return entry_failed(
+                        sender,
+                        ErrorCategory::of(&e),
+                        format!("Error creating link {}: {}. Skipping.", out_path.display(), e),
+                    );
+                }
+            }
+        } else {
+            let entry_name = file.name().to_string();
+            let total = file.size();
+            let mut report = |processed: u64| {
+                if detailed {
+                    report_file_progress(sender, &entry_name, processed, total, index, archive_len)
+                }
+            };
+            let resume = journal
+                .as_ref()
+                .and_then(|journal| journal.partial.as_ref())
+                .filter(|partial| partial.index == index)
+                .map(|partial| (partial.offset, partial.sha256.clone()));
+            let mut checkpoint = |offset: u64, sha256: String| match journal.as_deref_mut() {
+                Some(journal) => journal.checkpoint(index, offset, sha256),
+                None => Ok(()),
+            };
+            let result = match extract_resumable(file, out_path, resume, &mut checkpoint, &mut report) {
+                // Nothing was read from the entry yet, so it can still be staged for the restart.
+                Err(e) if platform::is_still_in_use(&e) => {
+                    let mut staged_path = out_path.as_os_str().to_owned();
+                    staged_path.push(".pending");
+                    let staged_path = PathBuf::from(staged_path);
+                    extract_resumable(file, &staged_path, None, &mut |_, _| Ok(()), &mut report)
+                        .and_then(|_| platform::apply_unix_mode(&staged_path, file.unix_mode()))
+                        .and_then(|_| defer_until_reboot(Some(&staged_path), out_path, e))
+                        .map(|_| 0)
+                }
+                result => result
+                    .and_then(|resumed_at| platform::apply_unix_mode(out_path, file.unix_mode()).map(|_| resumed_at)),
+            };
+            match result {
+                Ok(0) => {
+                    report_detail(sender, Verbosity::Verbose, format!("Extracted {}", out_path.display()))
+                }
+                Ok(resumed_at) => report_detail(
+                    sender,
+                    Verbosity::Verbose,
+                    format!(
+                        "Extracted {}, resumed after {}",
+                        out_path.display(),
+                        format_bytes(resumed_at)
+                    ),
+                ),
+                Err(e) => {
+                    return entry_failed(
+                        sender,
+                        ErrorCategory::of(&e),
+                        format!("Error writing to file {}: {}. Skipping.", out_path.display(), e),
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports an archive entry that could not be applied.
+fn entry_failed(sender: &Sender<UpdateMessage>, category: ErrorCategory, message: String) -> Result<(), ErrorCategory> {
+    sender.send(UpdateMessage::Error(message, category, None)).unwrap();
+    Err(category)
+}
+
+/// Takes the result of keeping the original of `path` for a rollback. The update goes ahead when
+/// that fails, only that file cannot be rolled back then.
+fn back_up<T: Default>(path: &Path, result: io::Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        log::warn!("Could not back up {}: {}", path.display(), e);
+        T::default()
+    })
+}
+
+/// Patches `original_file` after checking it against the hashes from the update metadata. When the
+/// original was modified or the result is wrong, the full file is downloaded instead.
+fn apply_verified_patch(
+    context: &EntryContext,
+    original_file: &Path,
+    patch_data: &[u8],
+    entry_name: &str,
+    on_progress: &mut dyn FnMut(u64),
+) -> io::Result<()> {
+    let EntryContext {
+        base_path,
+        metadata,
+        sender,
+        config,
+        ..
+    } = *context;
+    // An in-place patch that was interrupted is finished or undone before the file is looked at.
+    let recovered = inplace::in_progress(original_file) && inplace::recover(original_file, patch_data, on_progress)?;
+    let Some(hashes) = metadata.files.get(entry_name) else {
+        if recovered {
+            return Ok(());
+        }
+        let source = PatchSource::open(original_file)?;
+        return patch_file(base_path, config.in_place_patching, source, patch_data, original_file, on_progress).map(|_| ());
+    };
+
+    let source = PatchSource::open(original_file).ok();
+    let original_hash = source.as_ref().map(PatchSource::sha256);
+    report_detail(
+        sender,
+        Verbosity::Debug,
+        format!(
+            "{}: sha256 {}, expected {}",
+            entry_name,
+            original_hash.as_deref().unwrap_or("missing"),
+            hashes.old
+        ),
+    );
+    if original_hash.as_deref() == Some(hashes.new.as_str()) {
+        // Already patched by an attempt that was interrupted or failed later on.
+        return Ok(());
+    }
+    // Dropped unless it is patched, so the full file can replace it.
+    let source = source.filter(|_| original_hash.as_deref() == Some(hashes.old.as_str()));
+    if let Some(source) = source {
+        let patched_hash = patch_file(base_path, config.in_place_patching, source, patch_data, original_file, on_progress)?;
+        report_detail(
+            sender,
+            Verbosity::Debug,
+            format!("{}: patched sha256 {}, expected {}", entry_name, patched_hash, hashes.new),
+        );
+        if patched_hash == hashes.new {
+            return Ok(());
+        }
+        sender
+            .send(UpdateMessage::Log(format!(
+                "Patched {} does not match the expected result. Downloading the full file...",
+                entry_name
+            )))
+            .unwrap();
+    } else {
+        sender
+            .send(UpdateMessage::Log(format!(
+                "{} is missing or was modified. Downloading the full file...",
+                entry_name
+            )))
+            .unwrap();
+    }
+
+    download_verified_file(metadata, entry_name, &hashes.new, original_file, sender)
+}
+
+fn download_verified_file(
+    metadata: &UpdateMetadata,
+    entry_name: &str,
+    expected_hash: &str,
+    path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> io::Result<()> {
+    let url = metadata.file_url(entry_name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            UpdaterError::Patch("the file does not match the patch and no full file is available".to_string()),
+        )
+    })?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut download_path = path.as_os_str().to_owned();
+    download_path.push(".download");
+    let download_path = PathBuf::from(download_path);
+
+    let sha256 = download_file(&url, &download_path, sender)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    if sha256 != expected_hash {
+        fs::remove_file(&download_path).ok();
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            UpdaterError::Verification("the downloaded file does not match the expected hash".to_string()),
+        ));
+    }
+    platform::keep_permissions(path, &download_path)?;
+    platform::retry_while_in_use(path, || fs::rename(&download_path, path))
+        .or_else(|e| defer_until_reboot(Some(&download_path), path, e))
+}
+
+/// Each retry continues from the last checkpoint recorded in `base_path`.
+async fn download_with_retries(
+    url: &str,
+    path: &Path,
+    base_path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let retries = http::settings().retries;
+    let mut attempt = 0;
+    loop {
+        match download_resumable(url, path, Some(base_path), sender).await {
+            Ok(sha256) => return Ok(sha256),
+            Err(e) if attempt < retries && !interrupt::requested() && !UpdaterError::is_permanent(e.as_ref()) => {
+                attempt += 1;
+                sender
+                    .send(UpdateMessage::Log(format!(
+                        "Download failed: {}. Retrying ({}/{})...",
+                        e, attempt, retries
+                    )))
+                    .unwrap();
+                sender
+                    .send(UpdateMessage::Status(format!(
+                        "Connection problem. Retrying ({}/{})...",
+                        attempt, retries
+                    )))
+                    .unwrap();
+                tokio::time::sleep(Duration::from_secs(1 << attempt.min(5))).await;
+                sender
+                    .send(UpdateMessage::Status("Downloading update...".to_string()))
+                    .unwrap();
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn cleanup(base_path: &Path) {
+    let update_zip_path = base_path.join("update.zip");
+    if update_zip_path.exists() {
+        fs::remove_file(&update_zip_path).ok();
+    }
+    journal::remove_journal(base_path);
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+/// Formats a Unix timestamp as a UTC date and time.
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Converts days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        (time % 3600) / 60
+    )
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    const GIB: u64 = 1024 * MIB;
+
+    if bytes >= GIB {
+        format!("{:.2} GiB", bytes as f64 / GIB as f64)
+    } else if bytes >= MIB {
+        format!("{:.2} MiB", bytes as f64 / MIB as f64)
+    } else if bytes >= KIB {
+        format!("{:.2} KiB", bytes as f64 / KIB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+fn copy_updater_to_install_dir(install_path: &Path) -> std::io::Result<()> {
+    let current_exe = env::current_exe()?;
+    let target_exe = install_path.join(platform::UPDATER_EXECUTABLE);
+    if current_exe != target_exe {
+        fs::copy(current_exe, target_exe)?;
+    }
+    Ok(())
+}
+
+fn run_check(options: &Options) -> i32 {
+    let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let info = match get_version_info(&base_path) {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!("Failed to read version info: {}", e);
+            return 1;
+        }
+    };
+    let channel = options.channel.clone().unwrap_or(info.channel);
+    if let Some(pin) = pin::load(&base_path) {
+        println!("{}", pin::banner(&pin, &base_path));
+    }
+
+    match manifest::fetch_manifest(&channel, &base_path) {
+        Ok(Some(remote_manifest)) => {
+            match manifest::plan_update(&remote_manifest, &info.version_code, options.prefer_full) {
+                Ok(UpdatePlan::UpToDate) => println!("Up to date (version {}).", info.version_code),
+                Ok(plan) => {
+                    println!(
+                        "Update available: version {} -> {} ({} to download).",
+                        info.version_code,
+                        remote_manifest.latest,
+                        format_bytes(plan.download_size())
+                    );
+                    if manifest::is_below_minimum(&remote_manifest, &info.version_code) {
+                        println!("{}", minimum_version_notice(&remote_manifest, &info.version_code));
+                    }
+                    if let Some(notes) = notes::for_update(&remote_manifest, &info.version_code) {
+                        let color = platform::enable_ansi_colors();
+                        println!();
+                        println!("{}", notes::to_ansi(&format!("# {}", notes.title()), color));
+                        println!();
+                        println!("{}", notes::to_ansi(&notes.markdown, color));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to plan update: {}", e);
+                    return 1;
+                }
+            }
+            0
+        }
+        Ok(None) => {
+            println!("The update server does not publish a manifest for the {} channel.", channel);
+            0
+        }
+        Err(e) if UpdaterError::is_network(e.as_ref()) => {
+            match manifest::load_cached_manifest(&base_path, &channel) {
+                Some(cached) => {
+                    println!("{}", offline_status(&cached, &info.version_code));
+                    if manifest::is_below_minimum(&cached.manifest, &info.version_code) {
+                        println!("{}", minimum_version_notice(&cached.manifest, &info.version_code));
+                    }
+                    0
+                }
+                None => {
+                    eprintln!("Could not reach the update server: {}", e);
+                    1
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to get update manifest: {}", e);
+            1
+        }
+    }
+}
+
+/// Runs the updater with the command line it was started with.
+pub fn run() {
+    let args: Vec<String> = env::args().collect();
+    // Clicking a notification starts the updater with its link, in the system folder.
+    if let Some(click) = args.get(1).and_then(|arg| platform::NotificationClick::from_link(arg)) {
+        let install_path = schedule::install_path();
+        env::set_current_dir(&install_path).ok();
+        if click == platform::NotificationClick::Game {
+            std::process::exit(if launch_game(&install_path, &[]).is_ok() { 0 } else { 1 });
+        }
+    }
+    if args.len() > 1 && args[1] == "--uninstall" {
+        let exe_path = env::current_exe().unwrap_or_default();
+        let install_path = exe_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        uninstall::uninstall(&exe_path, &install_path, false);
+        std::process::exit(0);
+    }
+    if args.len() > 1 && args[1] == "uninstall" {
+        platform::attach_console();
+        std::process::exit(uninstall::run_uninstall(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "check" && args[2..].iter().any(|arg| arg == "--silent") {
+        std::process::exit(schedule::run_silent_check(&args[1..]));
+    }
+    if args.len() > 1 && args[1] == "check" {
+        platform::attach_console();
+        let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        std::process::exit(run_check(&load_options(&args[1..], &base_path)));
+    }
+    if args.len() > 1 && args[1] == "config" {
+        platform::attach_console();
+        std::process::exit(config::run_config(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "schedule" {
+        platform::attach_console();
+        std::process::exit(schedule::run_schedule(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "unschedule" {
+        platform::attach_console();
+        std::process::exit(schedule::run_unschedule(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "maintenance" {
+        std::process::exit(maintenance::run_maintenance(&args[1..]));
+    }
+    if args.len() > 1 && args[1] == "nettest" {
+        platform::attach_console();
+        std::process::exit(nettest::run_nettest(&args[1..]));
+    }
+    if args.len() > 1 && args[1] == "analyze" {
+        platform::attach_console();
+        std::process::exit(analyze::run_analyze(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "install" {
+        platform::attach_console();
+        logging::init();
+        interrupt::install();
+        std::process::exit(install::run_install(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "generate-patch" {
+        platform::attach_console();
+        std::process::exit(publish::run_generate_patch(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "simulate" {
+        platform::attach_console();
+        std::process::exit(publish::run_simulate(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "protected" {
+        platform::attach_console();
+        std::process::exit(protect::run_protected_report(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "encrypt-preload" {
+        platform::attach_console();
+        std::process::exit(preload::run_encrypt_preload(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "make-installer" {
+        platform::attach_console();
+        std::process::exit(bundle::run_make_installer(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "pin" {
+        platform::attach_console();
+        std::process::exit(pin::run_pin(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "unpin" {
+        platform::attach_console();
+        std::process::exit(pin::run_unpin(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "rollback" {
+        platform::attach_console();
+        logging::init();
+        std::process::exit(rollback::run_rollback(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "serve-cache" {
+        platform::attach_console();
+        std::process::exit(peercache::run_serve_cache(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "history" {
+        platform::attach_console();
+        std::process::exit(history::run_history(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "verify-release" {
+        platform::attach_console();
+        std::process::exit(publish::run_verify_release(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "soak" {
+        platform::attach_console();
+        std::process::exit(soak::run_soak(&args[2..]));
+    }
+    if args.len() > 1 && args[1] == "soak-apply" {
+        std::process::exit(soak::run_soak_apply(&args[2..]));
+    }
+    // Options after `--` belong to the game.
+    if args[1..].iter().take_while(|arg| *arg != "--").any(|arg| arg == "--dry-run") {
+        platform::attach_console();
+        let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        std::process::exit(dryrun::run_dry_run(&load_options(&args, &base_path), &base_path));
+    }
+    if args[1..].iter().take_while(|arg| *arg != "--").any(|arg| arg == "--download-only") {
+        platform::attach_console();
+        let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        std::process::exit(downloadonly::run_download_only(&load_options(&args, &base_path), &base_path));
+    }
+    let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    // Checked before the log is opened, which would rotate the running updater's log.
+    let handover = args.iter().any(|arg| arg == HANDOVER_ARG);
+    let Some(_instance) = claim_instance(&base_path, handover) else {
+        std::process::exit(report_already_running(&parse_options(&args)));
+    };
+    logging::init();
+    install_panic_hook();
+    let mut options = load_options(&args, &base_path);
+    // A shortcut passing `--windowless` has no console to write progress to.
+    if options.windowless && platform::launched_from_explorer() {
+        options.gui = true;
+    }
+    remove_replaced_updater();
+    let old_version = get_version_info(&base_path).ok().map(|info| info.version_code);
+
+    let version_exists = Path::new("version.json").exists();
+    // A first install needs the player to pick a location, so it always opens the window.
+    if options.windowless && !options.gui && version_exists {
+        interrupt::install();
+        let exit_code = windowless::run(options.clone());
+        exit_with_report(&options, &base_path, old_version.as_deref(), exit_code);
+    }
+    let initial_width = if version_exists { 272.0 } else { 450.0 };
+    // Room for the minimal window's errors and retry, but not for the log.
+    let (initial_height, min_height) = if options.gui && version_exists { (320.0, 240.0) } else { (500.0, 294.0) };
+
+    let native_options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([initial_width, initial_height])
+            .with_min_inner_size([272.0, min_height])
+            .with_icon(load_icon()),
+        run_and_return: true,
+        ..Default::default()
+    };
+
+    let shared_state = Arc::new(Mutex::new(SharedState {
+        update_complete: false,
+        exit_code: exit::CANCELLED,
+        deferred_downloads: Vec::new(),
+    }));
+    let shared_state_clone = Arc::clone(&shared_state);
+    let gui_options = options.clone();
+
+    eframe::run_native(
+        WINDOW_TITLE,
+        native_options,
+        Box::new(|_cc| Ok(Box::new(UpdateGUI::new(shared_state_clone, gui_options)))),
+    )
+    .unwrap();
+
+    let state = shared_state.lock().unwrap();
+    if !state.deferred_downloads.is_empty() {
+        prefetch_updates(&base_path, &state.deferred_downloads);
+    }
+    let exit_code = if state.update_complete { exit::UP_TO_DATE } else { state.exit_code };
+    exit_with_report(&options, &base_path, old_version.as_deref(), exit_code);
+}
+
+const WINDOW_TITLE: &str = "DREAMIO: AI-Powered Adventures - Updater";
+/// Passed to the updater started by one that restarts itself, which exits right after.
+const HANDOVER_ARG: &str = "--handover";
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Arguments for the updater taking over from this one.
+fn successor_args() -> Vec<String> {
+    let mut args: Vec<String> = env::args().skip(1).filter(|arg| arg != HANDOVER_ARG).collect();
+    args.push(HANDOVER_ARG.to_string());
+    args
+}
+
+/// Claims the install for this updater. A successor waits for its predecessor to exit.
+fn claim_instance(base_path: &Path, handover: bool) -> Option<platform::InstanceGuard> {
+    let started = Instant::now();
+    loop {
+        if let Some(guard) = platform::claim_instance(base_path) {
+            return Some(guard);
+        }
+        if !handover || started.elapsed() >= HANDOVER_TIMEOUT {
+            return None;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Another updater is working on this install. Its window is brought to the front when it has
+/// one, since a second double-click usually means the first window got lost behind others.
+fn report_already_running(options: &Options) -> i32 {
+    if !options.windowless && platform::focus_window(WINDOW_TITLE) {
+        return 0;
+    }
+    let message = "The updater is already running for this installation. Wait for it to finish, then start the game again.";
+    if options.windowless {
+        eprintln!("{}", message);
+    } else {
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Info)
+            .set_title("DREAMIO updater")
+            .set_description(message)
+            .set_buttons(rfd::MessageButtons::Ok)
+            .show();
+    }
+    1
+}
+
+fn exit_with_report(options: &Options, base_path: &Path, old_version: Option<&str>, exit_code: i32) -> ! {
+    let updated = get_version_info(base_path).ok().map(|info| info.version_code);
+    // Launchers and scripts can tell an update from a game that was already up to date.
+    let exit_code = if exit_code == exit::UP_TO_DATE && updated.as_deref() != old_version {
+        exit::UPDATED
+    } else {
+        exit_code
+    };
+    if let Some(target) = &options.report {
+        report::write(target, base_path, old_version, exit_code);
+    }
+    std::process::exit(exit_code)
+}
+
+/// Records panics in `updater.log`, since the updater has no console to print them to.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        log::error!("Updater crashed: {}", info);
+        log::logger().flush();
+        default_hook(info);
+    }));
+}
+
+fn load_icon() -> egui::IconData {
+    let (icon_rgba, icon_width, icon_height) = {
+        let icon_bytes = include_bytes!("../assets/icon.ico");
+        let image = image::load_from_memory(icon_bytes)
+            .expect("Failed to load icon from memory")
+            .into_rgba8();
+        let (width, height) = image.dimensions();
+        let rgba = image.into_raw();
+        (rgba, width, height)
+    };
+
+    egui::IconData {
+        rgba: icon_rgba,
+        width: icon_width,
+        height: icon_height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    /// An install folder inside a folder of its own, so anything written next to it shows up.
+    fn install() -> (tempfile::TempDir, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let base_path = root.path().join("game");
+        fs::create_dir(&base_path).unwrap();
+        (root, base_path)
+    }
+
+    fn write_archive(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// Applies an archive of `entries` to a fresh install, returning the result and the files that
+    /// ended up outside the install folder.
+    fn apply(entries: &[(&str, &[u8])]) -> (io::Result<ApplySummary>, Vec<PathBuf>) {
+        let (root, base_path) = install();
+        let zip_path = root.path().join("update.zip");
+        write_archive(&zip_path, entries);
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let result = apply_update(&zip_path, &base_path, &sender);
+        let outside = fs::read_dir(root.path())
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| *path != base_path && *path != zip_path)
+            .collect();
+        (result, outside)
+    }
+
+    #[test]
+    fn entries_leaving_the_install_are_refused() {
+        for name in ["../evil.txt", "a/../../evil.txt", "/tmp/evil.txt", "../game/../evil.txt"] {
+            let (result, outside) = apply(&[(name, b"evil"), ("ok.txt", b"ok")]);
+            assert!(result.is_err(), "{} was accepted", name);
+            assert!(outside.is_empty(), "{} wrote {:?}", name, outside);
+        }
+    }
+
+    #[test]
+    fn archives_that_are_not_zip_files_are_refused() {
+        let (root, base_path) = install();
+        let zip_path = root.path().join("update.zip");
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        for content in [&b""[..], b"PK\x03\x04", b"not an archive at all"] {
+            fs::write(&zip_path, content).unwrap();
+            assert!(apply_update(&zip_path, &base_path, &sender).is_err());
+        }
+    }
+
+    #[test]
+    fn archives_with_bad_metadata_are_refused() {
+        let (result, _) = apply(&[(METADATA_FILE_NAME, b"{not json"), ("ok.txt", b"ok")]);
+        assert!(result.is_err());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn arbitrary_entry_names_stay_inside_the_install(
+            names in prop::collection::vec("([a-z.]{1,3}|\\.\\.|/|\\\\|:|\\PC){1,8}", 1..4)
+        ) {
+            let entries: Vec<(&str, &[u8])> = names.iter().map(|name| (name.as_str(), &b"content"[..])).collect();
+            let (_, outside) = apply(&entries);
+            prop_assert!(outside.is_empty(), "{:?} wrote {:?}", names, outside);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sysinfo::{Pid, System, SystemExt};
+
+const LOCK_FILE_NAME: &str = "update.lock";
+const STALE_LOCK_AGE: Duration = Duration::from_secs(6 * 60 * 60);
+
+pub struct UpdateLock {
+    path: PathBuf,
+}
+
+pub struct LockOwner {
+    pub host: String,
+    pub pid: u32,
+    pub created: u64,
+}
+
+impl UpdateLock {
+    pub fn acquire(base_path: &Path) -> io::Result<Self> {
+        let path = base_path.join(LOCK_FILE_NAME);
+
+        if let Some(owner) = read_owner(&path) {
+            if is_stale(&owner) {
+                fs::remove_file(&path).ok();
+            } else if owner.host == host_name() {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "Another updater is already running on this computer.",
+                ));
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "The computer \"{}\" is currently updating this shared installation. Please wait for it to finish, then start the game again.",
+                        owner.host
+                    ),
+                ));
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| {
+                if e.kind() == io::ErrorKind::AlreadyExists {
+                    io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "Another update is already in progress for this installation.",
+                    )
+                } else {
+                    e
+                }
+            })?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        writeln!(file, "{}\n{}\n{}", host_name(), process::id(), now)?;
+        file.sync_all()?;
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for UpdateLock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+pub fn host_name() -> String {
+    env::var("COMPUTERNAME").unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn read_owner(path: &Path) -> Option<LockOwner> {
+    let content = fs::read_to_string(path).ok()?;
+    let mut lines = content.lines();
+    Some(LockOwner {
+        host: lines.next()?.to_string(),
+        pid: lines.next()?.parse().ok()?,
+        created: lines.next()?.parse().ok()?,
+    })
+}
+
+fn is_stale(owner: &LockOwner) -> bool {
+    let created = UNIX_EPOCH + Duration::from_secs(owner.created);
+    let age = SystemTime::now()
+        .duration_since(created)
+        .unwrap_or_default();
+    if age > STALE_LOCK_AGE {
+        return true;
+    }
+
+    // A lock left behind by a crashed updater on this machine can be reclaimed right away.
+    if owner.host == host_name() {
+        let mut system = System::new();
+        system.refresh_processes();
+        return system.process(Pid::from(owner.pid as usize)).is_none();
+    }
+
+    false
+}
@@ -0,0 +1,116 @@
+//! Detailed `updater.log` written next to the executable so failed updates can be diagnosed from
+//! the log instead of screenshots of the updater window.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const LOG_FILE_NAME: &str = "updater.log";
+/// The log is rotated once it grows past this size.
+const MAX_LOG_SIZE: u64 = 2 * 1024 * 1024;
+/// Number of rotated logs kept as `updater.log.1`, `updater.log.2`, ...
+const MAX_ROTATED_LOGS: usize = 3;
+
+/// Starts logging to `updater.log`. Failing to open the log is not fatal, the updater just runs
+/// without one.
+pub fn init() {
+    let Some(path) = log_path() else {
+        return;
+    };
+    let writer = match RotatingFile::open(path) {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    let result = fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{} {:<5} [{}] {}",
+                humantime::format_rfc3339_millis(SystemTime::now()),
+                record.level(),
+                record.target(),
+                message
+            ))
+        })
+        .level(log::LevelFilter::Debug)
+        .level_for("reqwest", log::LevelFilter::Info)
+        .level_for("hyper", log::LevelFilter::Info)
+        .chain(Box::new(writer) as Box<dyn Write + Send>)
+        .apply();
+    if result.is_ok() {
+        log::info!(
+            "DreamioUpdater {} started with arguments {:?}",
+            env!("CARGO_PKG_VERSION"),
+            env::args().skip(1).collect::<Vec<_>>()
+        );
+    }
+}
+
+fn log_path() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    Some(exe.parent()?.join(LOG_FILE_NAME))
+}
+
+/// The current log followed by the rotated ones that exist.
+pub fn log_files() -> Vec<PathBuf> {
+    let Some(path) = log_path() else {
+        return Vec::new();
+    };
+    let rotated = (1..=MAX_ROTATED_LOGS).map(|index| rotated_path(&path, index));
+    std::iter::once(path.clone())
+        .chain(rotated)
+        .filter(|p| p.exists())
+        .collect()
+}
+
+/// Appends to the log file, moving it aside whenever it grows past `MAX_LOG_SIZE`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..MAX_ROTATED_LOGS).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > MAX_LOG_SIZE {
+            // Keep logging to the current file if it cannot be moved, e.g. while a viewer has it open.
+            self.rotate().ok();
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
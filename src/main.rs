@@ -1,1628 +1,5 @@
 #![windows_subsystem = "windows"]
 
-use crossbeam_channel::{Receiver, Sender};
-use eframe::{egui, App, Frame};
-use egui::ColorImage;
-use qbsdiff::Bspatch;
-use raw_window_handle::{HasWindowHandle, RawWindowHandle};
-use reqwest;
-use serde_json::Value;
-use std::collections::HashSet;
-use std::env;
-use std::fs::{self, File};
-use std::io::{self, Cursor, Read, Write};
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::{Duration, Instant};
-use sysinfo::{ProcessExt, System, SystemExt};
-use rfd::FileDialog;
-use windows::{
-    core::{ComInterface, PCWSTR, HSTRING},
-    Win32::Foundation::HWND,
-    Win32::System::Com::{
-        CoCreateInstance, CoInitializeEx, CoTaskMemFree, IPersistFile, CLSCTX_INPROC_SERVER,
-        COINIT_APARTMENTTHREADED,
-    },
-    Win32::System::Registry::{
-        RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegSetValueExW, HKEY, HKEY_CURRENT_USER,
-        KEY_WRITE, REG_OPTION_NON_VOLATILE, REG_SZ,
-    },
-    Win32::UI::Shell::{
-        FOLDERID_Desktop, FOLDERID_LocalAppData, FOLDERID_Programs, SHGetKnownFolderPath,
-        IShellLinkW, ITaskbarList3, ShellLink, TaskbarList, KF_FLAG_DEFAULT, TBPFLAG,
-    },
-    Win32::UI::WindowsAndMessaging::{
-        FlashWindowEx, FLASHWINFO, FLASHW_ALL, FLASHW_TIMERNOFG,
-    },
-};
-use zip::ZipArchive;
-
-pub struct SharedState {
-    pub update_complete: bool,
-}
-
-#[derive(Clone, Copy)]
-pub enum ProgressState {
-    NoProgress,
-    Indeterminate,
-    Normal,
-    Error,
-}
-
-struct Taskbar {
-    taskbar_list: ITaskbarList3,
-}
-
-impl Taskbar {
-    fn new() -> Option<Self> {
-        unsafe {
-            if CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok() {
-                let taskbar_list: ITaskbarList3 =
-                    CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER).ok()?;
-                Some(Self { taskbar_list })
-            } else {
-                None
-            }
-        }
-    }
-
-    fn set_progress_state(&self, hwnd: HWND, state: ProgressState) {
-        let tbp_flags = match state {
-            ProgressState::NoProgress => TBPFLAG(0),
-            ProgressState::Indeterminate => TBPFLAG(0x1),
-            ProgressState::Normal => TBPFLAG(0x2),
-            ProgressState::Error => TBPFLAG(0x4),
-        };
-        unsafe {
-            self.taskbar_list.SetProgressState(hwnd, tbp_flags).ok();
-        }
-    }
-
-    fn set_progress_value(&self, hwnd: HWND, completed: u64, total: u64) {
-        unsafe {
-            self.taskbar_list
-                .SetProgressValue(hwnd, completed, total)
-                .ok();
-        }
-    }
-}
-
-pub struct ProgressUpdate {
-    pub downloaded: u64,
-    pub total: u64,
-    pub bytes_per_sec: f64,
-    pub eta: Duration,
-    pub elapsed: Duration,
-}
-
-pub enum UpdateMessage {
-    Log(String),
-    Error(String, Option<String>),
-    Status(String),
-    Progress(f32),
-    ProgressUpdate(ProgressUpdate),
-    ApplyingProgress(String),
-    UpdateComplete,
-    UpdateFailed,
-}
-
-pub struct LogEntry {
-    message: String,
-    is_error: bool,
-}
-
-pub struct VersionInfo {
-    pub version_code: String,
-    pub update_url: String,
-    pub version_string: String,
-}
-
-#[derive(PartialEq, Clone, Copy, Debug)]
-pub enum InstallerState {
-    Updater,
-    Terms,
-    Location,
-    Installing,
-    Finished,
-}
-
-fn get_default_install_path() -> String {
-    unsafe {
-        match SHGetKnownFolderPath(&FOLDERID_LocalAppData, KF_FLAG_DEFAULT, None) {
-            Ok(path) => {
-                let s = path.to_string().unwrap_or_default();
-                CoTaskMemFree(Some(path.as_ptr() as _));
-                PathBuf::from(s)
-                    .join("DREAMIO AI-Powered Adventures")
-                    .to_string_lossy()
-                    .to_string()
-            }
-            Err(_) => r"C:\DREAMIO".to_string(),
-        }
-    }
-}
-
-pub struct UpdateGUI {
-    logs: Vec<LogEntry>,
-    progress: f32,
-    status: String,
-    update_receiver: Receiver<UpdateMessage>,
-    update_sender: Sender<UpdateMessage>,
-    update_complete: bool,
-    bytes_downloaded: u64,
-    total_bytes: u64,
-    bytes_per_sec: f64,
-    eta: Duration,
-    elapsed: Duration,
-    applying_progress: String,
-    exit_code: i32,
-    update_failed: bool,
-    last_error_response: Option<String>,
-    shared_state: Arc<Mutex<SharedState>>,
-    image: Option<ColorImage>,
-    texture: Option<egui::TextureHandle>,
-    taskbar: Option<Taskbar>,
-    window_handle: Option<HWND>,
-    flashing: bool,
-    installer_state: InstallerState,
-    terms_accepted: bool,
-    install_path: String,
-    create_desktop_shortcut: bool,
-    create_startmenu_shortcut: bool,
-}
-
-impl UpdateGUI {
-    pub fn new(shared_state: Arc<Mutex<SharedState>>) -> Self {
-        let (sender, receiver) = crossbeam_channel::unbounded();
-
-        let image = image::load_from_memory(include_bytes!("../assets/logo.png")).unwrap();
-        let size = [image.width() as _, image.height() as _];
-        let image_buffer = image.to_rgba8();
-        let pixels = image_buffer.as_flat_samples();
-        let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
-
-        let version_exists = Path::new("version.json").exists();
-        let installer_state = if version_exists {
-            InstallerState::Updater
-        } else {
-            InstallerState::Terms
-        };
-
-        let install_path = get_default_install_path();
-
-        let app = Self {
-            logs: vec![],
-            progress: 0.0,
-            status: "Ready to update.".to_string(),
-            update_receiver: receiver,
-            update_sender: sender,
-            update_complete: false,
-            bytes_downloaded: 0,
-            total_bytes: 0,
-            bytes_per_sec: 0.0,
-            eta: Duration::from_secs(0),
-            elapsed: Duration::from_secs(0),
-            applying_progress: "".to_string(),
-            exit_code: 1,
-            update_failed: false,
-            last_error_response: None,
-            shared_state,
-            image: Some(color_image),
-            texture: None,
-            taskbar: Taskbar::new(),
-            window_handle: None,
-            flashing: false,
-            installer_state,
-            terms_accepted: false,
-            install_path,
-            create_desktop_shortcut: true,
-            create_startmenu_shortcut: true,
-        };
-        if installer_state == InstallerState::Updater {
-            app.start_update_thread();
-        }
-        app
-    }
-
-    #[cfg(not(test))]
-    fn start_update_thread(&self) {
-        let sender = self.update_sender.clone();
-        thread::spawn(move || {
-            let runtime = tokio::runtime::Runtime::new().unwrap();
-            runtime.block_on(async {
-                let target_path = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-                update_task(sender, target_path).await;
-            });
-        });
-    }
-
-    #[cfg(test)]
-    fn start_update_thread(&self) {
-        // Do not start the thread in tests
-    }
-
-    fn start_install_thread(&self, target_path: PathBuf) {
-        let sender = self.update_sender.clone();
-        thread::spawn(move || {
-            let runtime = tokio::runtime::Runtime::new().unwrap();
-            runtime.block_on(async move {
-                update_task(sender, target_path).await;
-            });
-        });
-    }
-
-    fn retry(&mut self) {
-        self.flash_window(false);
-        self.flashing = false;
-        if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-            taskbar.set_progress_state(hwnd, ProgressState::NoProgress);
-        }
-        let shared_state = Arc::clone(&self.shared_state);
-        *self = Self::new(shared_state);
-    }
-
-    fn flash_window(&self, start: bool) {
-        if let Some(hwnd) = self.window_handle {
-            let mut info = FLASHWINFO {
-                cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
-                hwnd,
-                dwFlags: if start {
-                    FLASHW_ALL | FLASHW_TIMERNOFG
-                } else {
-                    windows::Win32::UI::WindowsAndMessaging::FLASHWINFO_FLAGS(0)
-                },
-                uCount: if start { 0 } else { 0 },
-                dwTimeout: 0,
-            };
-            unsafe {
-                FlashWindowEx(&mut info);
-            }
-        }
-    }
-}
-
-impl App for UpdateGUI {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut Frame) {
-        if self.window_handle.is_none() {
-            if let Ok(window_handle) = frame.window_handle() {
-                let raw_handle: RawWindowHandle = window_handle.into();
-                if let RawWindowHandle::Win32(handle) = raw_handle {
-                    self.window_handle = Some(HWND(handle.hwnd.get() as isize));
-                }
-            }
-        }
-
-        while let Ok(msg) = self.update_receiver.try_recv() {
-            match msg {
-                UpdateMessage::Log(log) => self.logs.push(LogEntry {
-                    message: log,
-                    is_error: false,
-                }),
-                UpdateMessage::Error(log, response) => {
-                    self.logs.push(LogEntry {
-                        message: log,
-                        is_error: true,
-                    });
-                    self.last_error_response = response;
-                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-                        taskbar.set_progress_state(hwnd, ProgressState::Error);
-                    }
-                    self.flashing = true;
-                    self.flash_window(true);
-                }
-                UpdateMessage::Status(status) => self.status = status,
-                UpdateMessage::Progress(progress) => {
-                    self.progress = progress;
-                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-                        taskbar.set_progress_state(hwnd, ProgressState::Normal);
-                        taskbar.set_progress_value(hwnd, (progress * 100.0) as u64, 100);
-                    }
-                }
-                UpdateMessage::ProgressUpdate(update) => {
-                    self.bytes_downloaded = update.downloaded;
-                    self.total_bytes = update.total;
-                    self.bytes_per_sec = update.bytes_per_sec;
-                    self.eta = update.eta;
-                    self.elapsed = update.elapsed;
-                    self.progress = if update.total > 0 {
-                        update.downloaded as f32 / update.total as f32
-                    } else {
-                        0.0
-                    };
-                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-                        taskbar.set_progress_state(hwnd, ProgressState::Normal);
-                        taskbar.set_progress_value(hwnd, update.downloaded, update.total);
-                    }
-                }
-                UpdateMessage::ApplyingProgress(progress_text) => {
-                    self.applying_progress = progress_text;
-                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-                        taskbar.set_progress_state(hwnd, ProgressState::Indeterminate);
-                    }
-                }
-                UpdateMessage::UpdateComplete => {
-                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-                        taskbar.set_progress_state(hwnd, ProgressState::NoProgress);
-                    }
-                    self.update_complete = true;
-
-                    if self.installer_state == InstallerState::Installing {
-                        let path = PathBuf::from(&self.install_path);
-                        copy_updater_to_install_dir(&path).ok();
-                        create_shortcuts(
-                            &path,
-                            self.create_desktop_shortcut,
-                            self.create_startmenu_shortcut,
-                        );
-                        register_uninstaller(&path).ok();
-                        self.installer_state = InstallerState::Finished;
-                    } else {
-                        self.logs.push(LogEntry {
-                            message: "Launching game...".to_string(),
-                            is_error: false,
-                        });
-                        match Command::new("Dreamio.exe").spawn() {
-                            Ok(_) => {
-                                self.logs.push(LogEntry {
-                                    message: "Game launched successfully.".to_string(),
-                                    is_error: false,
-                                });
-                                let mut state = self.shared_state.lock().unwrap();
-                                state.update_complete = true;
-                                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                            }
-                            Err(e) => {
-                                self.logs.push(LogEntry {
-                                    message: format!("Failed to launch game: {}", e),
-                                    is_error: true,
-                                });
-                                let mut state = self.shared_state.lock().unwrap();
-                                state.update_complete = true;
-                                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                            }
-                        }
-                    }
-                }
-                UpdateMessage::UpdateFailed => {
-                    if let (Some(taskbar), Some(hwnd)) = (&self.taskbar, self.window_handle) {
-                        taskbar.set_progress_state(hwnd, ProgressState::Error);
-                    }
-                    self.update_complete = true;
-                    self.exit_code = 1;
-                    self.update_failed = true;
-                    self.flashing = true;
-                    self.flash_window(true);
-                }
-            }
-        }
-
-        egui::TopBottomPanel::top("header").show(ctx, |ui| {
-            let texture: &egui::TextureHandle = self.texture.get_or_insert_with(|| {
-                ui.ctx()
-                    .load_texture("icon", self.image.take().unwrap(), Default::default())
-            });
-
-            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-                ui.image(texture);
-            });
-
-            ui.separator();
-
-            match self.installer_state {
-                InstallerState::Terms => {
-                    ui.heading("Welcome to DREAMIO Setup");
-                    ui.label("Please review the terms below.");
-                }
-                InstallerState::Location => {
-                    ui.heading("Installation Options");
-                    ui.label("Choose where to install DREAMIO.");
-                }
-                InstallerState::Finished => {
-                    ui.heading("Installation Complete");
-                    ui.label("DREAMIO has been successfully installed.");
-                }
-                InstallerState::Updater | InstallerState::Installing => {
-                    ui.label(&self.status);
-
-                    let progress_text = if self.status == "Applying update..." {
-                        self.applying_progress.clone()
-                    } else {
-                        format!(
-                            "[{}] {}/{} ({}/s, ETA: {})",
-                            format_duration(self.elapsed),
-                            format_bytes(self.bytes_downloaded),
-                            format_bytes(self.total_bytes),
-                            format_bytes(self.bytes_per_sec as u64),
-                            format_duration(self.eta)
-                        )
-                    };
-                    ui.add(
-                        egui::ProgressBar::new(self.progress)
-                            .fill(egui::Color32::from_hex("#ddb99b").unwrap()),
-                    );
-                    ui.label(progress_text);
-
-                    if self.update_failed {
-                        ui.separator();
-
-                        if self.last_error_response.is_some() {
-                            ui.heading("A security appliance or firewall might be blocking the request. Please check your firewall software, for instance Xfinity Advanced Security.");
-                            ui.horizontal(|ui| {
-                                if ui.button("Retry").clicked() {
-                                    self.retry();
-                                }
-                                if ui.button("More details").clicked() {
-                                    if let Some(response) = &self.last_error_response {
-                                        let path = std::env::temp_dir().join("dreamio-updater-error.html");
-                                        if let Ok(mut file) = std::fs::File::create(&path) {
-                                            if file.write_all(response.as_bytes()).is_ok() {
-                                                opener::open(path).ok();
-                                            }
-                                        }
-                                    }
-                                }
-                            });
-                        } else {
-                            ui.heading("Please try again. If the issue persists, you can download the latest version of the game manually:");
-                            ui.hyperlink("https://storage.googleapis.com/dreamio/downloads/Builds/Windows/latest.zip");
-                            if ui.button("Retry").clicked() {
-                                self.retry();
-                            }
-                        }
-                    }
-                }
-            }
-        });
-
-        egui::CentralPanel::default().show(ctx, |ui| {
-            match self.installer_state {
-                InstallerState::Terms => {
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        ui.label("Before installing, please review Privacy Policy and Terms and Conditions.");
-                        ui.hyperlink("https://dreamio.xyz/privacy-policy/");
-                        ui.hyperlink("https://dreamio.xyz/terms-and-conditions/");
-                        ui.add_space(20.0);
-                        ui.checkbox(&mut self.terms_accepted, "I accept the Privacy Policy and Terms and Conditions");
-                        ui.add_space(20.0);
-                        if ui.add_enabled(self.terms_accepted, egui::Button::new("Next")).clicked() {
-                            self.installer_state = InstallerState::Location;
-                        }
-                    });
-                }
-                InstallerState::Location => {
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        ui.label("Install Location:");
-                        ui.horizontal(|ui| {
-                            ui.text_edit_singleline(&mut self.install_path);
-                            if ui.button("Browse...").clicked() {
-                                if let Some(path) = FileDialog::new().pick_folder() {
-                                    let mut new_path = path;
-                                    let path_str = new_path.to_string_lossy().to_string();
-                                    if !path_str.to_lowercase().contains("dreamio") {
-                                        new_path = new_path.join("DREAMIO AI-Powered Adventures");
-                                    }
-                                    self.install_path = new_path.display().to_string();
-                                }
-                            }
-                            if ui.button("Reset").clicked() {
-                                self.install_path = get_default_install_path();
-                            }
-                        });
-                        ui.add_space(10.0);
-                        ui.checkbox(&mut self.create_desktop_shortcut, "Create Desktop Shortcut");
-                        ui.checkbox(&mut self.create_startmenu_shortcut, "Create Start Menu Shortcut");
-                        ui.add_space(20.0);
-                        if ui.button("Install").clicked() {
-                            let mut path = PathBuf::from(&self.install_path);
-                            let path_str = path.to_string_lossy().to_string();
-                            if !path_str.to_lowercase().contains("dreamio") {
-                                path = path.join("DREAMIO AI-Powered Adventures");
-                                self.install_path = path.display().to_string();
-                            }
-                            match std::fs::create_dir_all(&path) {
-                                Ok(_) => {
-                                    self.installer_state = InstallerState::Installing;
-                                    self.start_install_thread(path);
-                                }
-                                Err(e) => {
-                                    let mut message = format!("Failed to create directory: {}.", path.display());
-                                    if e.kind() == std::io::ErrorKind::PermissionDenied {
-                                        message.push_str("\nAdministrator privileges might be required.");
-                                    } else {
-                                        message.push_str(&format!(" {}", e));
-                                    }
-                                    self.logs.push(LogEntry {
-                                        message,
-                                        is_error: true,
-                                    });
-                                }
-                            }
-                        }
-                        for log in &self.logs {
-                            if log.is_error {
-                                ui.colored_label(egui::Color32::RED, &log.message);
-                            }
-                        }
-                    });
-                }
-                InstallerState::Finished => {
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        ui.label("Installation has completed successfully.");
-                        ui.add_space(20.0);
-                        if ui.button("Launch DREAMIO").clicked() {
-                            let exe_path = PathBuf::from(&self.install_path).join("Dreamio.exe");
-                            match Command::new(&exe_path).spawn() {
-                                Ok(_) => {
-                                    let mut state = self.shared_state.lock().unwrap();
-                                    state.update_complete = true;
-                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                                }
-                                Err(e) => {
-                                    ui.colored_label(egui::Color32::RED, format!("Failed to launch: {}", e));
-                                }
-                            }
-                        }
-                        if ui.button("Exit").clicked() {
-                            let mut state = self.shared_state.lock().unwrap();
-                            state.update_complete = true;
-                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                        }
-                    });
-                }
-                InstallerState::Updater | InstallerState::Installing => {
-                    egui::ScrollArea::vertical().show(ui, |ui| {
-                        for log in &self.logs {
-                            let text = if log.is_error {
-                                egui::RichText::new(&log.message)
-                                    .color(egui::Color32::RED)
-                                    .monospace()
-                            } else {
-                                egui::RichText::new(&log.message).monospace()
-                            };
-                            ui.label(text);
-                        }
-                    });
-                }
-            }
-        });
-
-        if !self.update_complete || self.flashing {
-            ctx.request_repaint();
-        }
-    }
-}
-
-
-fn install_vcredist(base_path: &Path, sender: &Sender<UpdateMessage>) {
-
-    sender
-        .send(UpdateMessage::Log("Installing Microsoft Visual C++ Redistributable...".to_string()))
-        .unwrap();
-
-    let url = "https://aka.ms/vs/17/release/vc_redist.x64.exe";
-    let vcredist_path = base_path.join("vc_redist.x64.exe");
-
-    if let Err(e) = download_file(url, &vcredist_path, sender) {
-        sender
-            .send(UpdateMessage::Log(format!(
-                "Failed to download Microsoft Visual C++ Redistributable: {}",
-                e
-            )))
-            .unwrap();
-        return;
-    }
-
-    sender
-        .send(UpdateMessage::Log("Running Microsoft Visual C++ installer...".to_string()))
-        .unwrap();
-
-    match std::process::Command::new(&vcredist_path)
-        .args(["/install", "/quiet", "/norestart"])
-        .status()
-    {
-        Ok(status) => {
-            if status.success() {
-                sender
-                    .send(UpdateMessage::Log("Successfully installed Microsoft Visual C++ Redistributable.".to_string()))
-                    .unwrap();
-            } else {
-                sender
-                    .send(UpdateMessage::Log(format!("Microsoft Visual C++ installer returned non-zero status: {}", status)))
-                    .unwrap();
-            }
-        }
-        Err(e) => {
-            // Check if error is ERROR_ELEVATION_REQUIRED (740)
-            if e.raw_os_error() == Some(740) {
-                sender
-                    .send(UpdateMessage::Log("Elevation required. Prompting for administrator privileges...".to_string()))
-                    .unwrap();
-
-                // Fall back to powershell Start-Process -Verb RunAs
-                let escaped_path = vcredist_path.display().to_string().replace("'", "''");
-                let script = format!(
-                    "Start-Process -FilePath '{}' -ArgumentList '/install /quiet /norestart' -Verb RunAs -Wait",
-                    escaped_path
-                );
-
-                match std::process::Command::new("powershell")
-                    .args(["-NoProfile", "-ExecutionPolicy", "Bypass", "-Command", &script])
-                    .status()
-                {
-                    Ok(status) => {
-                        if status.success() {
-                            sender
-                                .send(UpdateMessage::Log("Successfully installed Microsoft Visual C++ Redistributable with elevated privileges.".to_string()))
-                                .unwrap();
-                        } else {
-                            sender
-                                .send(UpdateMessage::Log(format!("Elevated Microsoft Visual C++ installer returned non-zero status: {}", status)))
-                                .unwrap();
-                        }
-                    }
-                    Err(elevated_e) => {
-                        sender
-                            .send(UpdateMessage::Log(format!(
-                                "Failed to run elevated Microsoft Visual C++ installer: {}",
-                                elevated_e
-                            )))
-                            .unwrap();
-                    }
-                }
-            } else {
-                sender
-                    .send(UpdateMessage::Log(format!(
-                        "Failed to run Microsoft Visual C++ installer: {}",
-                        e
-                    )))
-                    .unwrap();
-            }
-        }
-    }
-
-    if vcredist_path.exists() {
-        let _ = std::fs::remove_file(&vcredist_path);
-    }
-}
-
-async fn update_task(sender: Sender<UpdateMessage>, target_path: PathBuf) {
-    sender
-        .send(UpdateMessage::Status("Checking for updates...".to_string()))
-        .unwrap();
-
-    let update_zip_path = target_path.join("update.zip");
-    let version_file_path = target_path.join("version.json");
-    let is_initial_install = !version_file_path.exists();
-    let mut system = System::new();
-    system.refresh_processes();
-
-    let pids_to_kill: Vec<_> = system
-        .processes_by_name("Dreamio.exe")
-        .map(|p| p.pid())
-        .collect();
-
-    if !pids_to_kill.is_empty() {
-        sender
-            .send(UpdateMessage::Log(
-                "Game process found. Shutting down...".to_string(),
-            ))
-            .unwrap();
-        for pid in &pids_to_kill {
-            if let Some(process) = system.process(*pid) {
-                if !process.kill() {
-                    sender
-                        .send(UpdateMessage::Error(
-                            format!("Failed to send kill signal to process {}", pid),
-                            None,
-                        ))
-                        .unwrap();
-                }
-            }
-        }
-
-        sender
-            .send(UpdateMessage::Log(
-                "Waiting for game process to fully terminate...".to_string(),
-            ))
-            .unwrap();
-        loop {
-            system.refresh_processes();
-            let any_process_alive = pids_to_kill
-                .iter()
-                .any(|pid| system.process(*pid).is_some());
-            if !any_process_alive {
-                break;
-            }
-            thread::sleep(Duration::from_millis(100));
-        }
-        sender
-            .send(UpdateMessage::Log(
-                "Game process terminated successfully.".to_string(),
-            ))
-            .unwrap();
-    } else {
-        sender
-            .send(UpdateMessage::Log(
-                "No running game process found.".to_string(),
-            ))
-            .unwrap();
-    }
-
-    if update_zip_path.exists() {
-        if let Err(e) = apply_update(&update_zip_path, &target_path, &sender) {
-            sender
-                .send(UpdateMessage::Error(
-                    format!("Failed to apply update: {}", e),
-                    None,
-                ))
-                .unwrap();
-            sender.send(UpdateMessage::UpdateFailed).unwrap();
-            cleanup(&target_path);
-            return;
-        }
-        cleanup(&target_path);
-    }
-
-    if !version_file_path.exists() {
-        sender
-            .send(UpdateMessage::Log("Downloading the game...".to_string()))
-            .unwrap();
-        match get_latest_update_url() {
-            Ok(latest_url) => {
-                if let Err(e) = download_and_apply_update(&latest_url, &update_zip_path, &target_path, &sender) {
-                    sender
-                        .send(UpdateMessage::Error(
-                            format!("Failed to download or apply update: {}", e),
-                            None,
-                        ))
-                        .unwrap();
-                    sender.send(UpdateMessage::UpdateFailed).unwrap();
-                    cleanup(&target_path);
-                    return;
-                }
-            }
-            Err(e) => {
-                let error_string = e.to_string();
-                if error_string.contains("Received an HTML response") {
-                    let response_body = error_string
-                        .splitn(2, "Response:")
-                        .nth(1)
-                        .map(|s| s.trim().to_string());
-                    sender
-                        .send(UpdateMessage::Error(
-                            "Failed to get latest update URL: Received an HTML response instead of JSON. A security appliance or firewall might be blocking the request. Please check your firewall software, for instance Xfinity Advanced Security.".to_string(),
-                            response_body,
-                        ))
-                        .unwrap();
-                } else {
-                    sender
-                        .send(UpdateMessage::Error(
-                            format!("Failed to get latest update URL: {}", e),
-                            None,
-                        ))
-                        .unwrap();
-                }
-                sender.send(UpdateMessage::UpdateFailed).unwrap();
-                cleanup(&target_path);
-                return;
-            }
-        }
-    }
-
-    loop {
-        match get_version_info(&target_path) {
-            Ok(info) => {
-                let version_code = info.version_code;
-                let update_url = info.update_url;
-
-                sender
-                    .send(UpdateMessage::Log(format!(
-                        "Downloading update for version {}...",
-                        version_code
-                    )))
-                    .unwrap();
-                match download_and_apply_update(&update_url, &update_zip_path, &target_path, &sender) {
-                    Ok(_) => {
-                        match get_version_info(&target_path) {
-                            Ok(new_info) => {
-                                update_registry_version(&target_path, &new_info.version_string).ok();
-                                if new_info.version_code == version_code {
-                                    sender
-                                        .send(UpdateMessage::Log(
-                                            "Update complete. No more updates available."
-                                                .to_string(),
-                                        ))
-                                        .unwrap();
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                sender
-                                    .send(UpdateMessage::Error(
-                                        format!("Failed to read updated version info: {}", e),
-                                        None,
-                                    ))
-                                    .unwrap();
-                                sender.send(UpdateMessage::UpdateFailed).unwrap();
-                                cleanup(&target_path);
-                                return;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        if e.to_string().contains("404") {
-                            sender
-                                .send(UpdateMessage::Log(
-                                    "No more updates available.".to_string(),
-                                ))
-                                .unwrap();
-                            break;
-                        } else {
-                            sender
-                                .send(UpdateMessage::Error(
-                                    format!("Error downloading update: {}", e),
-                                    None,
-                                ))
-                                .unwrap();
-                            sender.send(UpdateMessage::UpdateFailed).unwrap();
-                            cleanup(&target_path);
-                            return;
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                sender
-                    .send(UpdateMessage::Error(
-                        format!("Failed to read version info: {}", e),
-                        None,
-                    ))
-                    .unwrap();
-                sender.send(UpdateMessage::UpdateFailed).unwrap();
-                cleanup(&target_path);
-                return;
-            }
-        }
-    }
-
-    sender
-        .send(UpdateMessage::Status("Update complete.".to_string()))
-        .unwrap();
-    sender
-        .send(UpdateMessage::Log("Update process finished.".to_string()))
-        .unwrap();
-    if is_initial_install {
-        let sender_clone = sender.clone();
-        let target_path_clone = target_path.clone();
-        tokio::task::spawn_blocking(move || {
-            install_vcredist(&target_path_clone, &sender_clone);
-        })
-            .await
-            .unwrap();
-    }
-    sender.send(UpdateMessage::UpdateComplete).unwrap();
-}
-
-fn apply_patch(old_file: &Path, patch_data: &[u8], new_file: &Path) -> io::Result<()> {
-    let old_contents = fs::read(old_file)?;
-    let mut new_contents = Vec::new();
-
-    let patcher = Bspatch::new(patch_data)?;
-    patcher.apply(&old_contents, Cursor::new(&mut new_contents))?;
-
-    fs::write(new_file, &new_contents)?;
-
-    Ok(())
-}
-
-fn download_file(
-    url: &str,
-    path: &Path,
-    sender: &Sender<UpdateMessage>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("DreamioUpdater/1.0")
-        .timeout(Duration::from_secs(30))
-        .build()?;
-    let mut response = client.get(url).send()?;
-
-    if !response.status().is_success() {
-        return Err(Box::new(io::Error::new(
-            io::ErrorKind::Other,
-            format!("HTTP error: {}", response.status()),
-        )));
-    }
-
-    let total_size = response.content_length().unwrap_or(0);
-    let mut downloaded: u64 = 0;
-    let mut file = File::create(path)?;
-    let mut buffer = [0; 8192];
-    let start_time = Instant::now();
-
-    loop {
-        let n = match response.read(&mut buffer) {
-            Ok(n) => n,
-            Err(e) => return Err(Box::new(e)),
-        };
-
-        if n == 0 {
-            break;
-        }
-
-        file.write_all(&buffer[..n])?;
-        downloaded += n as u64;
-
-        let elapsed = start_time.elapsed();
-        let bytes_per_sec = if elapsed.as_secs() > 0 {
-            downloaded as f64 / elapsed.as_secs_f64()
-        } else {
-            0.0
-        };
-
-        let eta_secs = if bytes_per_sec > 0.0 {
-            (total_size - downloaded) as f64 / bytes_per_sec
-        } else {
-            0.0
-        };
-        let eta = Duration::from_secs(eta_secs as u64);
-
-        sender
-            .send(UpdateMessage::ProgressUpdate(ProgressUpdate {
-                downloaded,
-                total: total_size,
-                bytes_per_sec,
-                eta,
-                elapsed,
-            }))
-            .unwrap();
-    }
-
-    Ok(())
-}
-
-fn download_and_apply_update(
-    url: &str,
-    update_zip_path: &Path,
-    base_path: &Path,
-    sender: &Sender<UpdateMessage>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    sender
-        .send(UpdateMessage::Status("Downloading update...".to_string()))
-        .unwrap();
-    if let Err(e) = download_file(url, update_zip_path, sender) {
-        if url.starts_with("https://") {
-            let http_url = url.replace("https", "http");
-            sender
-                .send(UpdateMessage::Log(
-                    "HTTPS download failed, trying HTTP...".to_string(),
-                ))
-                .unwrap();
-            download_file(&http_url, update_zip_path, sender)?;
-        } else {
-            return Err(e);
-        }
-    }
-    apply_update(update_zip_path, base_path, sender)?;
-    cleanup(base_path);
-    Ok(())
-}
-
-fn get_latest_update_url() -> Result<String, Box<dyn std::error::Error>> {
-    let url = "https://storage.googleapis.com/dreamio/downloads/Builds/Windows/version.json";
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("DreamioUpdater/1.0")
-        .timeout(Duration::from_secs(30))
-        .build()?;
-
-    let response = match client.get(url).send() {
-        Ok(res) => res,
-        Err(_) => {
-            let http_url = url.replace("https", "http");
-            client.get(&http_url).send()?
-        }
-    };
-
-    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).cloned();
-    let response_text = response.text()?;
-
-    if let Some(content_type) = content_type {
-        if let Ok(content_type) = content_type.to_str() {
-            if content_type.contains("text/html") {
-                return Err(format!("Received an HTML response instead of JSON. A security appliance or firewall might be blocking the request. Please check your firewall software, for instance Xfinity Advanced Security. Response: {}", response_text).into());
-            }
-        }
-    }
-
-    let json: Value = serde_json::from_str(&response_text)?;
-    let update_url = json["latestUrl"]
-        .as_str()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid latestUrl in JSON"))?
-        .to_string();
-
-    Ok(update_url)
-}
-
-fn get_version_info(base_path: &Path) -> Result<VersionInfo, Box<dyn std::error::Error>> {
-    let version_file_path = base_path.join("version.json");
-    let version_content = fs::read_to_string(version_file_path)?;
-    let json: Value = serde_json::from_str(&version_content)?;
-
-    let version_code = json["versionCode"]
-        .as_str()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid versionCode in JSON"))?
-        .to_string();
-
-    let version_string = json["version"]
-        .as_str()
-        .unwrap_or(&version_code)
-        .to_string();
-
-    let update_url = format!(
-        "https://storage.googleapis.com/dreamio/downloads/Builds/Windows/patches/{}.zip",
-        version_code
-    );
-
-    Ok(VersionInfo {
-        version_code,
-        update_url,
-        version_string,
-    })
-}
-
-fn apply_update(update_zip_path: &Path, base_path: &Path, sender: &Sender<UpdateMessage>) -> io::Result<()> {
-    sender
-        .send(UpdateMessage::Status("Applying update...".to_string()))
-        .unwrap();
-    let update_zip_data = fs::read(update_zip_path)?;
-    let reader = Cursor::new(update_zip_data);
-    let mut archive = ZipArchive::new(reader)?;
-    let archive_len = archive.len();
-
-    let current_exe = env::current_exe()?;
-    let current_exe_name = current_exe.file_name().unwrap().to_str().unwrap();
-
-    let manifest_path = base_path.join("install_manifest.txt");
-    let mut manifest = HashSet::new();
-    if manifest_path.exists() {
-        if let Ok(content) = fs::read_to_string(&manifest_path) {
-            for line in content.lines() {
-                if !line.is_empty() {
-                    manifest.insert(line.to_string());
-                }
-            }
-        }
-    }
-
-    for i in 0..archive_len {
-        let mut file = match archive.by_index(i) {
-            Ok(file) => file,
-            Err(e) => {
-                sender
-                    .send(UpdateMessage::Error(
-                        format!("Error accessing file in archive: {}. Skipping.", e),
-                        None,
-                    ))
-                    .unwrap();
-                continue;
-            }
-        };
-        let out_path = base_path.join(file.name());
-
-        if out_path
-            .file_name()
-            .map(|f| f == current_exe_name)
-            .unwrap_or(false)
-        {
-            continue;
-        }
-
-        manifest.insert(file.name().to_string());
-
-        if file.name().ends_with('/') {
-            if let Err(e) = fs::create_dir_all(&out_path) {
-                sender
-                    .send(UpdateMessage::Error(
-                        format!(
-                            "Error creating directory {}: {}. Skipping.",
-                            out_path.display(),
-                            e
-                        ),
-                        None,
-                    ))
-                    .unwrap();
-                continue;
-            }
-        } else if file.name().ends_with(".patch") {
-            let original_file = out_path.with_extension("");
-            let mut patch_data = Vec::new();
-            if let Err(e) = file.read_to_end(&mut patch_data) {
-                sender
-                    .send(UpdateMessage::Error(
-                        format!(
-                            "Error reading patch data for {}: {}. Skipping.",
-                            original_file.display(),
-                            e
-                        ),
-                        None,
-                    ))
-                    .unwrap();
-                continue;
-            }
-            if let Err(e) = apply_patch(&original_file, &patch_data, &original_file) {
-                sender
-                    .send(UpdateMessage::Error(
-                        format!(
-                            "Error applying patch to {}: {}. Skipping.",
-                            original_file.display(),
-                            e
-                        ),
-                        None,
-                    ))
-                    .unwrap();
-                continue;
-            }
-        } else if file.name().ends_with(".delete") {
-            let file_to_delete = out_path.with_extension("");
-            if file_to_delete.exists() {
-                if file_to_delete.is_dir() {
-                    if let Err(e) = fs::remove_dir_all(&file_to_delete) {
-                        sender
-                            .send(UpdateMessage::Error(
-                                format!(
-                                    "Error deleting directory {}: {}. Skipping.",
-                                    file_to_delete.display(),
-                                    e
-                                ),
-                                None,
-                            ))
-                            .unwrap();
-                    }
-                } else {
-                    if let Err(e) = fs::remove_file(&file_to_delete) {
-                        sender
-                            .send(UpdateMessage::Error(
-                                format!(
-                                    "Error deleting file {}: {}. Skipping.",
-                                    file_to_delete.display(),
-                                    e
-                                ),
-                                None,
-                            ))
-                            .unwrap();
-                    }
-                }
-            }
-        } else {
-            if let Some(parent) = out_path.parent() {
-                if !parent.exists() {
-                    if let Err(e) = fs::create_dir_all(parent) {
-                        sender
-                            .send(UpdateMessage::Error(
-                                format!(
-                                    "Error creating directory {}: {}. Skipping.",
-                                    parent.display(),
-                                    e
-                                ),
-                                None,
-                            ))
-                            .unwrap();
-                        continue;
-                    }
-                }
-            }
-            let mut outfile = match File::create(&out_path) {
-                Ok(file) => file,
-                Err(e) => {
-                    sender
-                        .send(UpdateMessage::Error(
-                            format!("Error creating file {}: {}. Skipping.", out_path.display(), e),
-                            None,
-                        ))
-                        .unwrap();
-                    continue;
-                }
-            };
-            if let Err(e) = io::copy(&mut file, &mut outfile) {
-                sender
-                    .send(UpdateMessage::Error(
-                        format!("Error writing to file {}: {}. Skipping.", out_path.display(), e),
-                        None,
-                    ))
-                    .unwrap();
-                continue;
-            }
-        }
-        let progress_text = format!(
-            "Applying file {}/{}: {}",
-            i + 1,
-            archive_len,
-            file.name()
-        );
-        sender
-            .send(UpdateMessage::ApplyingProgress(progress_text))
-            .unwrap();
-        sender
-            .send(UpdateMessage::Progress(
-                (i + 1) as f32 / archive_len as f32
-            ))
-            .unwrap();
-    }
-
-    let mut manifest_content = String::new();
-    for entry in manifest {
-        manifest_content.push_str(&entry);
-        manifest_content.push('\n');
-    }
-    fs::write(&manifest_path, manifest_content)?;
-
-    Ok(())
-}
-
-fn cleanup(base_path: &Path) {
-    let update_zip_path = base_path.join("update.zip");
-    if update_zip_path.exists() {
-        fs::remove_file(&update_zip_path).ok();
-    }
-}
-
-fn format_duration(duration: Duration) -> String {
-    let secs = duration.as_secs();
-    format!(
-        "{:02}:{:02}:{:02}",
-        secs / 3600,
-        (secs % 3600) / 60,
-        secs % 60
-    )
-}
-
-fn format_bytes(bytes: u64) -> String {
-    const KIB: u64 = 1024;
-    const MIB: u64 = 1024 * KIB;
-    const GIB: u64 = 1024 * MIB;
-
-    if bytes >= GIB {
-        format!("{:.2} GiB", bytes as f64 / GIB as f64)
-    } else if bytes >= MIB {
-        format!("{:.2} MiB", bytes as f64 / MIB as f64)
-    } else if bytes >= KIB {
-        format!("{:.2} KiB", bytes as f64 / KIB as f64)
-    } else {
-        format!("{} B", bytes)
-    }
-}
-
-fn copy_updater_to_install_dir(install_path: &Path) -> std::io::Result<()> {
-    let current_exe = env::current_exe()?;
-    let target_exe = install_path.join("DreamioUpdater.exe");
-    if current_exe != target_exe {
-        fs::copy(current_exe, target_exe)?;
-    }
-    Ok(())
-}
-
-fn create_shortcut(
-    target: &Path,
-    shortcut_path: &Path,
-    description: &str,
-) -> windows::core::Result<()> {
-    unsafe {
-        let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
-
-        let target_h = HSTRING::from(target.as_os_str().to_str().unwrap_or_default());
-        link.SetPath(PCWSTR::from_raw(target_h.as_ptr()))?;
-
-        let desc_h = HSTRING::from(description);
-        link.SetDescription(PCWSTR::from_raw(desc_h.as_ptr()))?;
-
-        if let Some(parent) = target.parent() {
-            let parent_h = HSTRING::from(parent.as_os_str().to_str().unwrap_or_default());
-            link.SetWorkingDirectory(PCWSTR::from_raw(parent_h.as_ptr()))?;
-        }
-
-        let persist: IPersistFile = link.cast()?;
-        let shortcut_h = HSTRING::from(shortcut_path.as_os_str().to_str().unwrap_or_default());
-        persist.Save(PCWSTR::from_raw(shortcut_h.as_ptr()), true)?;
-
-        Ok(())
-    }
-}
-
-fn create_shortcuts(install_path: &Path, create_desktop: bool, create_startmenu: bool) {
-    let exe_path = install_path.join("Dreamio.exe");
-    let description = "DREAMIO: AI-Powered Adventures";
-
-    if create_desktop {
-        unsafe {
-            if let Ok(path) = SHGetKnownFolderPath(&FOLDERID_Desktop, KF_FLAG_DEFAULT, None) {
-                let s = path.to_string().unwrap_or_default();
-                CoTaskMemFree(Some(path.as_ptr() as _));
-                let desktop_path = PathBuf::from(s).join("DREAMIO AI-Powered Adventures.lnk");
-                create_shortcut(&exe_path, &desktop_path, description).ok();
-            }
-        }
-    }
-
-    if create_startmenu {
-        unsafe {
-            if let Ok(path) = SHGetKnownFolderPath(&FOLDERID_Programs, KF_FLAG_DEFAULT, None) {
-                let s = path.to_string().unwrap_or_default();
-                CoTaskMemFree(Some(path.as_ptr() as _));
-                let programs_path = PathBuf::from(s).join("DREAMIO AI-Powered Adventures");
-                fs::create_dir_all(&programs_path).ok();
-                let link_path = programs_path.join("DREAMIO AI-Powered Adventures.lnk");
-                create_shortcut(&exe_path, &link_path, description).ok();
-            }
-        }
-    }
-}
-
-fn update_registry_version(_install_path: &Path, version_string: &str) -> windows::core::Result<()> {
-    let subkey =
-        "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\Oleg Skutte DREAMIO: AI-Powered Adventures";
-    let subkey_h = HSTRING::from(subkey);
-
-    let mut hkey = HKEY::default();
-    unsafe {
-        RegCreateKeyExW(
-            HKEY_CURRENT_USER,
-            PCWSTR::from_raw(subkey_h.as_ptr()),
-            0,
-            None,
-            REG_OPTION_NON_VOLATILE,
-            KEY_WRITE,
-            None,
-            &mut hkey,
-            None,
-        )?;
-    }
-
-    let write_str = |name: &str, value: &str| -> windows::core::Result<()> {
-        let name_h = HSTRING::from(name);
-        let mut val_vec: Vec<u16> = value.encode_utf16().collect();
-        val_vec.push(0);
-
-        let slice_u8 =
-            unsafe { std::slice::from_raw_parts(val_vec.as_ptr() as *const u8, val_vec.len() * 2) };
-
-        unsafe {
-            RegSetValueExW(
-                hkey,
-                PCWSTR::from_raw(name_h.as_ptr()),
-                0,
-                REG_SZ,
-                Some(slice_u8),
-            )?;
-        }
-        Ok(())
-    };
-
-    write_str("DisplayVersion", version_string)?;
-
-    unsafe {
-        let _ = RegCloseKey(hkey);
-    };
-
-    Ok(())
-}
-
-fn register_uninstaller(install_path: &Path) -> windows::core::Result<()> {
-    let subkey =
-        "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\Oleg Skutte DREAMIO: AI-Powered Adventures";
-    let subkey_h = HSTRING::from(subkey);
-
-    let mut hkey = HKEY::default();
-    unsafe {
-        RegCreateKeyExW(
-            HKEY_CURRENT_USER,
-            PCWSTR::from_raw(subkey_h.as_ptr()),
-            0,
-            None,
-            REG_OPTION_NON_VOLATILE,
-            KEY_WRITE,
-            None,
-            &mut hkey,
-            None,
-        )?;
-    }
-
-    let write_str = |name: &str, value: &str| -> windows::core::Result<()> {
-        let name_h = HSTRING::from(name);
-        let mut val_vec: Vec<u16> = value.encode_utf16().collect();
-        val_vec.push(0);
-
-        let slice_u8 =
-            unsafe { std::slice::from_raw_parts(val_vec.as_ptr() as *const u8, val_vec.len() * 2) };
-
-        unsafe {
-            RegSetValueExW(
-                hkey,
-                PCWSTR::from_raw(name_h.as_ptr()),
-                0,
-                REG_SZ,
-                Some(slice_u8),
-            )?;
-        }
-        Ok(())
-    };
-
-    let exe_path = install_path.join("DreamioUpdater.exe");
-    let display_icon = install_path.join("Dreamio.exe");
-
-    let version_info = get_version_info(install_path)
-        .map(|v| v.version_string)
-        .unwrap_or_default();
-
-    write_str("DisplayName", "DREAMIO: AI-Powered Adventures")?;
-    write_str(
-        "UninstallString",
-        &format!("\"{}\" --uninstall", exe_path.to_string_lossy()),
-    )?;
-    write_str("InstallLocation", &install_path.to_string_lossy())?;
-    write_str("DisplayIcon", &display_icon.to_string_lossy())?;
-    write_str("Publisher", "Oleg Skutte")?;
-    write_str("DisplayVersion", &version_info)?;
-
-    unsafe {
-        let _ = RegCloseKey(hkey);
-    };
-
-    Ok(())
-}
-
-fn perform_uninstall() {
-    let exe_path = env::current_exe().unwrap_or_default();
-    let install_path = exe_path.parent().unwrap_or(Path::new("."));
-
-    let manifest_path = install_path.join("install_manifest.txt");
-    let mut use_legacy_uninstall = false;
-
-    if manifest_path.exists() {
-        if let Ok(content) = fs::read_to_string(&manifest_path) {
-            let mut entries: Vec<&str> = content.lines().filter(|l| !l.is_empty()).collect();
-            entries.sort_by_key(|a| std::cmp::Reverse(a.len()));
-            for entry in entries {
-                let target_path = install_path.join(entry);
-                if target_path.exists() {
-                    if target_path.is_dir() {
-                        let _ = fs::remove_dir(&target_path);
-                    } else {
-                        let _ = fs::remove_file(&target_path);
-                    }
-                }
-            }
-        }
-        let _ = fs::remove_file(&manifest_path);
-    } else {
-        let path_str = install_path.to_string_lossy().to_string();
-        if path_str.to_lowercase().contains("dreamio") {
-            use_legacy_uninstall = true;
-        }
-    }
-
-    // Delete Shortcuts
-    unsafe {
-        if let Ok(path) = SHGetKnownFolderPath(&FOLDERID_Desktop, KF_FLAG_DEFAULT, None) {
-            let s = path.to_string().unwrap_or_default();
-            CoTaskMemFree(Some(path.as_ptr() as _));
-            let link = PathBuf::from(s).join("DREAMIO AI-Powered Adventures.lnk");
-            let _ = fs::remove_file(link);
-        }
-    }
-
-    unsafe {
-        if let Ok(path) = SHGetKnownFolderPath(&FOLDERID_Programs, KF_FLAG_DEFAULT, None) {
-            let s = path.to_string().unwrap_or_default();
-            CoTaskMemFree(Some(path.as_ptr() as _));
-            let dir = PathBuf::from(s).join("DREAMIO AI-Powered Adventures");
-            let _ = fs::remove_dir_all(dir);
-        }
-    }
-
-    // Delete Registry
-    let subkey =
-        "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\Oleg Skutte DREAMIO: AI-Powered Adventures";
-    let subkey_h = HSTRING::from(subkey);
-    unsafe {
-        let _ = RegDeleteKeyW(HKEY_CURRENT_USER, PCWSTR::from_raw(subkey_h.as_ptr()));
-    }
-
-    // Self-delete
-    if use_legacy_uninstall {
-        let _ = Command::new("cmd")
-            .args([
-                "/C",
-                "ping",
-                "127.0.0.1",
-                "-n",
-                "3",
-                ">",
-                "nul",
-                "&",
-                "rmdir",
-                "/s",
-                "/q",
-                &install_path.to_string_lossy(),
-            ])
-            .spawn();
-    } else {
-        let _ = Command::new("cmd")
-            .args([
-                "/C",
-                "ping",
-                "127.0.0.1",
-                "-n",
-                "3",
-                ">",
-                "nul",
-                "&",
-                "del",
-                "/q",
-                &exe_path.to_string_lossy(),
-                "&",
-                "rmdir",
-                &install_path.to_string_lossy(),
-            ])
-            .spawn();
-    }
-
-    std::process::exit(0);
-}
-
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "--uninstall" {
-        perform_uninstall();
-        return;
-    }
-
-    let version_exists = Path::new("version.json").exists();
-    let initial_width = if version_exists { 272.0 } else { 450.0 };
-
-    let native_options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([initial_width, 500.0])
-            .with_min_inner_size([272.0, 294.0])
-            .with_icon(load_icon()),
-        run_and_return: true,
-        ..Default::default()
-    };
-
-    let shared_state = Arc::new(Mutex::new(SharedState {
-        update_complete: false,
-    }));
-    let shared_state_clone = Arc::clone(&shared_state);
-
-    eframe::run_native(
-        "DREAMIO: AI-Powered Adventures - Updater",
-        native_options,
-        Box::new(|_cc| Ok(Box::new(UpdateGUI::new(shared_state_clone)))),
-    )
-    .unwrap();
-
-    let state = shared_state.lock().unwrap();
-    if state.update_complete {
-        std::process::exit(0);
-    } else {
-        std::process::exit(1);
-    }
-}
-
-fn load_icon() -> egui::IconData {
-    let (icon_rgba, icon_width, icon_height) = {
-        let icon_bytes = include_bytes!("../assets/icon.ico");
-        let image = image::load_from_memory(icon_bytes)
-            .expect("Failed to load icon from memory")
-            .into_rgba8();
-        let (width, height) = image.dimensions();
-        let rgba = image.into_raw();
-        (rgba, width, height)
-    };
-
-    egui::IconData {
-        rgba: icon_rgba,
-        width: icon_width,
-        height: icon_height,
-    }
+    dreamio_updater::run();
 }
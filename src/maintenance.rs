@@ -0,0 +1,190 @@
+//! Maintenance windows for shared machines that must stay current, such as lab or café PCs.
+//! `maintenanceWindows` in `updater.json` lists when the updater may update on its own:
+//!
+//! ```json
+//! "maintenanceWindows": [{ "days": ["mon", "tue", "wed", "thu", "fri"], "start": "02:00", "end": "05:00" }]
+//! ```
+//!
+//! Times are local; a window ending before it starts runs past midnight, and one without `days`
+//! opens every day. `schedule --maintenance` registers `maintenance` to run every few minutes as
+//! SYSTEM, whether or not anyone is logged in. Inside a window it updates the install whoever is
+//! logged in: players still in the game are warned in every session and the game is closed once
+//! `maintenanceCountdownMinutes`, 5 by default, have passed. Pinned installs are left alone.
+
+use serde_json::Value;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{System, SystemExt};
+
+use crate::config::Config;
+use crate::manifest::{self, UpdatePlan};
+use crate::mods::ModChoice;
+use crate::{get_version_info, load_options, logging, pin, platform, schedule, spawn_update_task, UpdateMessage};
+
+/// How often the scheduled task looks for an open window.
+pub const CHECK_INTERVAL_MINUTES: u32 = 15;
+pub const DEFAULT_COUNTDOWN: Duration = Duration::from_secs(5 * 60);
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const GAME_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct MaintenanceWindow {
+    /// Indexed from Monday.
+    days: [bool; 7],
+    /// Minutes after local midnight.
+    start: u32,
+    end: u32,
+}
+
+impl MaintenanceWindow {
+    pub fn parse(json: &Value) -> Option<Self> {
+        let days = match json["days"].as_array() {
+            Some(names) => {
+                let mut days = [false; 7];
+                for name in names {
+                    let name = name.as_str()?.to_lowercase();
+                    let day = DAY_NAMES.iter().position(|day| name.starts_with(day))?;
+                    days[day] = true;
+                }
+                days
+            }
+            None => [true; 7],
+        };
+        Some(Self {
+            days,
+            start: parse_time(json["start"].as_str()?)?,
+            end: parse_time(json["end"].as_str()?)?,
+        })
+    }
+
+    fn contains(&self, weekday: usize, minute: u32) -> bool {
+        if self.start <= self.end {
+            return self.days[weekday] && (self.start..self.end).contains(&minute);
+        }
+        // Past midnight, the window belongs to the day it started on.
+        (self.days[weekday] && minute >= self.start) || (self.days[(weekday + 6) % 7] && minute < self.end)
+    }
+}
+
+fn parse_time(time: &str) -> Option<u32> {
+    let (hour, minute) = time.split_once(':')?;
+    let (hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+    (hour < 24 && minute < 60).then_some(hour * 60 + minute)
+}
+
+/// Whether one of `windows` is open right now.
+pub fn is_open(windows: &[MaintenanceWindow]) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let local = now + platform::local_utc_offset();
+    let days = local.div_euclid(86400);
+    // 1970-01-01 was a Thursday.
+    let weekday = (days + 3).rem_euclid(7) as usize;
+    let minute = (local.rem_euclid(86400) / 60) as u32;
+    windows.iter().any(|window| window.contains(weekday, minute))
+}
+
+/// `maintenance`, run by the task `schedule --maintenance` registers: updates the install the
+/// updater is in when a maintenance window is open and an update is available.
+pub fn run_maintenance(args: &[String]) -> i32 {
+    let install_path = schedule::install_path();
+    let config = Config::load(&install_path);
+    if !is_open(&config.maintenance_windows) || pin::load(&install_path).is_some() {
+        return 0;
+    }
+    // The updater already running installs the update itself.
+    let Some(_instance) = platform::claim_instance(&install_path) else {
+        return 0;
+    };
+    let mut options = load_options(args, &install_path);
+    options.assume_yes = true;
+    options.no_launch = true;
+    options.maintenance = true;
+
+    // Checked first so the game is only closed, and the log only rotated, for an actual update.
+    let Ok(info) = get_version_info(&install_path) else {
+        return 1;
+    };
+    let channel = options.channel.clone().unwrap_or(info.channel);
+    match manifest::fetch_manifest(&channel, &install_path) {
+        Ok(Some(remote_manifest)) => {
+            if let Ok(UpdatePlan::UpToDate) =
+                manifest::plan_update(&remote_manifest, &info.version_code, options.prefer_full)
+            {
+                return 0;
+            }
+        }
+        // Without a manifest, the legacy update finds out itself whether there is one.
+        Ok(None) => {}
+        Err(_) => return 1,
+    }
+
+    logging::init();
+    log::info!("Maintenance window open, updating {}", install_path.display());
+    close_game_after_countdown(&install_path, config.maintenance_countdown.unwrap_or(DEFAULT_COUNTDOWN));
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    spawn_update_task(sender, install_path.clone(), options);
+    for message in receiver {
+        match message {
+            UpdateMessage::Status(message) | UpdateMessage::Log(message) => log::info!("{}", message),
+            UpdateMessage::Error(message, _, _) => log::error!("{}", message),
+            UpdateMessage::OfferLaunchAnyway(_, _, reply) => {
+                reply.send(false).ok();
+            }
+            // SYSTEM already has the rights, and nobody could answer a prompt.
+            UpdateMessage::OfferElevation(_, reply) => {
+                reply.send(false).ok();
+            }
+            UpdateMessage::ReleaseCountdown(_, _, reply) => {
+                reply.send(true).ok();
+            }
+            UpdateMessage::ModifiedFiles(files, reply) => {
+                reply.send(vec![ModChoice::BackUpAndReplace; files.len()]).ok();
+            }
+            // The next run of the task continues with the new updater.
+            UpdateMessage::RestartUpdater | UpdateMessage::RelaunchedElevated => return 0,
+            UpdateMessage::UpdateComplete => {
+                log::info!("Maintenance update finished");
+                return 0;
+            }
+            UpdateMessage::UpdateFailed(_) | UpdateMessage::Interrupted => return 1,
+            _ => {}
+        }
+    }
+    1
+}
+
+/// Warns everyone logged in while the game runs, and waits `countdown` or until the game was
+/// closed. The update then closes what is still running.
+fn close_game_after_countdown(install_path: &Path, countdown: Duration) {
+    let processes = manifest::game_executables(install_path).processes;
+    let mut system = System::new();
+    let mut game_running = || {
+        system.refresh_processes();
+        processes.iter().any(|name| system.processes_by_name(name).next().is_some())
+    };
+    if !game_running() {
+        return;
+    }
+    let minutes = countdown.as_secs().div_ceil(60);
+    platform::broadcast_message(&format!(
+        "DREAMIO is being updated for scheduled maintenance. Save your progress: the game closes in {} minute(s).",
+        minutes
+    ));
+    let started = Instant::now();
+    let mut warned_last_minute = minutes <= 1;
+    while started.elapsed() < countdown {
+        if !game_running() {
+            return;
+        }
+        if !warned_last_minute && countdown.saturating_sub(started.elapsed()) <= Duration::from_secs(60) {
+            platform::broadcast_message("DREAMIO closes in 1 minute for scheduled maintenance.");
+            warned_last_minute = true;
+        }
+        thread::sleep(GAME_POLL_INTERVAL);
+    }
+    log::info!("Maintenance countdown of {:?} ended, closing the game", countdown);
+}
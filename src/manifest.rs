@@ -0,0 +1,695 @@
+//! Remote `manifest.json` published next to `version.json` for each channel:
+//!
+//! ```json
+//! {
+//!   "schemaVersion": "1.0",
+//!   "latest": "124",
+//!   "minimumVersion": "120",
+//!   "full": { "url": "latest.zip", "size": 1900000000 },
+//!   "patches": [{ "from": "123", "to": "124", "url": "patches/123.zip", "size": 5200000, "sha256": "...", "mandatory": false,
+//!                 "reverse": { "url": "reverse/124.zip", "size": 4900000, "sha256": "..." } }],
+//!   "selfCheck": ["Dreamio.exe", "--verify-content"],
+//!   "processes": ["Dreamio.exe", "CrashReporter.exe"],
+//!   "launch": ["Dreamio.exe", "-skipintro"],
+//!   "changelogUrl": "https://dreamio.xyz/changelog/{version}",
+//!   "helpLinks": { "network": { "en": "https://...", "de": "https://..." } },
+//!   "mirrors": ["https://mirror.example.com/dreamio"],
+//!   "protected": ["Saves/", "*.cfg"],
+//!   "hardlinkSafe": ["Dreamio_Data/StreamingAssets/"],
+//!   "releaseNotes": { "124": "## Fixes\n- Saves no longer vanish after a crash" },
+//!   "preload": { "latest": "125", "releaseAt": "2025-06-01T17:00:00Z", "patches": [{ "from": "124", "to": "125", "url": "preload/124.zip", "size": 8100000000 }] }
+//! }
+//! ```
+//!
+//! Relative URLs are resolved against the channel's download folder; others pick their `source` by
+//! scheme, e.g. `s3://`, `gs://`, `file://` or a UNC path. Installs older than the optional
+//! `minimumVersion` can no longer be played and must update before launching. The optional
+//! `selfCheck` command is run in the install folder after updating, and the update is rolled back
+//! when it fails. `processes` lists every program of the build closed before updating and `launch`
+//! is the command that starts the game; both default to the platform's executable. `changelogUrl`
+//! is opened after updating when players choose to see the changelog. `helpLinks` are
+//! shown when an update fails, by error category and language. `mirrors` are other servers with the
+//! same files, measured by `nettest`. `protected` paths are never changed by updates, see
+//! `protect`, and duplicates matching `hardlinkSafe` may be hard linked by `analyze`. `preload`
+//! describes the next version in the same format while it is unreleased; its archives are encrypted
+//! and downloaded ahead of time, and the entries of the released version give the `key` to decrypt
+//! them. With `releaseAt`, the keys can be published with the preload and it unlocks at that moment.
+//! `releaseNotes` are Markdown by version, shown for the versions an update installs; see `notes`.
+//! A patch's optional `reverse` archive takes an install back from `to` to `from`; the updater keeps
+//! those of the latest update for `rollback`.
+//!
+//! `schemaVersion` is `"major.minor"`, `1.0` when missing. Servers raise the minor version for
+//! additions older updaters can do without and the major version for changes they cannot:
+//!
+//! | Manifest                     | This updater (1.0)                                          |
+//! |------------------------------|-------------------------------------------------------------|
+//! | missing, `1`, `"1.0"`        | read; a malformed entry fails the check                     |
+//! | `1.1`, `"1.1"` and later     | read; unknown fields and patch entries it cannot read are skipped |
+//! | `2`, `"2.0"` and later       | refused with an error asking the player to update the updater |
+
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::UpdaterError;
+use crate::{channel_url, diagnostics, http, platform, source};
+
+/// Chains longer than this are slow and risky to apply, so the full archive wins unless it is
+/// much larger than the patches.
+const LONG_PATCH_CHAIN: usize = 10;
+const LONG_CHAIN_FULL_SIZE_FACTOR: u64 = 2;
+
+const CACHE_FILE_NAME: &str = "manifest_cache.json";
+
+/// The newest manifest format this updater knows.
+const SCHEMA_MAJOR: u64 = 1;
+const SCHEMA_MINOR: u64 = 0;
+const LATEST_UPDATER_URL: &str = "https://github.com/SkutteOleg/Dreamio-updater/releases/latest";
+
+#[derive(Clone)]
+pub struct Artifact {
+    pub url: String,
+    pub size: u64,
+    pub sha256: Option<String>,
+    /// Hex key of an archive published encrypted as a preload, once its version is released.
+    pub key: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PatchEdge {
+    pub from: String,
+    pub to: String,
+    pub artifact: Artifact,
+    /// Versions reached through a mandatory patch cannot be skipped.
+    pub mandatory: bool,
+    /// The archive undoing this patch, kept after updating so `rollback` can go back.
+    pub reverse: Option<Artifact>,
+}
+
+pub struct RemoteManifest {
+    pub latest: String,
+    pub minimum_version: Option<String>,
+    pub full: Option<Artifact>,
+    pub patches: Vec<PatchEdge>,
+    /// Program and arguments checking an updated install. Empty when there is no check.
+    pub self_check: Vec<String>,
+    /// The next version, published as encrypted archives before its release.
+    pub preload: Option<Box<RemoteManifest>>,
+    /// When a preloaded version unlocks. Only set on `preload`.
+    pub release_at: Option<SystemTime>,
+    /// Other servers publishing the same files.
+    pub mirrors: Vec<String>,
+    /// Markdown by version.
+    pub release_notes: HashMap<String, String>,
+}
+
+pub struct GameExecutables {
+    /// Names of the processes closed before updating.
+    pub processes: Vec<String>,
+    /// Program relative to the install folder and its arguments. Empty for the platform's default.
+    pub launch: Vec<String>,
+}
+
+/// The last manifest fetched successfully, used to report the last known state when offline.
+pub struct CachedManifest {
+    pub manifest: RemoteManifest,
+    pub fetched_at: u64,
+}
+
+pub enum UpdatePlan {
+    UpToDate,
+    Patches(Vec<PatchEdge>),
+    Full(Artifact),
+}
+
+impl UpdatePlan {
+    pub fn download_size(&self) -> u64 {
+        match self {
+            UpdatePlan::UpToDate => 0,
+            UpdatePlan::Patches(edges) => edges.iter().map(|e| e.artifact.size).sum(),
+            UpdatePlan::Full(artifact) => artifact.size,
+        }
+    }
+}
+
+/// What the server sent along with a manifest to tell later whether it changed.
+#[derive(Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+enum Fetched {
+    Missing,
+    /// The manifest is still the one the validators were sent for.
+    NotModified,
+    Manifest(Value, Validators),
+}
+
+/// Fetches `manifest.json` for the channel and caches it in `base_path`. Returns `Ok(None)` when
+/// the server does not publish one.
+///
+/// The request is conditional when the cache holds the manifest from the same URL, so an
+/// unchanged manifest costs the server a `304 Not Modified` instead of the whole file.
+pub fn fetch_manifest(channel: &str, base_path: &Path) -> Result<Option<RemoteManifest>, Box<dyn std::error::Error>> {
+    let url = format!("{}/manifest.json", channel_url(channel));
+    // Validators are only sent for a cached manifest that can still be used in its place.
+    let cached = read_cache(base_path)
+        .filter(|cache| cache["channel"].as_str() == Some(channel) && cache["url"].as_str() == Some(url.as_str()))
+        .and_then(|cache| {
+            let manifest = parse_manifest(&cache["manifest"], &channel_url(channel)).ok()?;
+            Some((manifest, cache))
+        });
+    let validators = cached.as_ref().map(|(_, cache)| Validators {
+        etag: cache["etag"].as_str().map(|s| s.to_string()),
+        last_modified: cache["lastModified"].as_str().map(|s| s.to_string()),
+    });
+    match (fetch_manifest_json(&url, validators.as_ref())?, cached) {
+        (Fetched::NotModified, Some((manifest, cache))) => {
+            save_cache(base_path, channel, &url, cache["manifest"].clone(), validators.unwrap_or_default());
+            Ok(Some(manifest))
+        }
+        (Fetched::Missing | Fetched::NotModified, _) => Ok(None),
+        (Fetched::Manifest(json, validators), _) => {
+            let manifest = parse_manifest(&json, &channel_url(channel))?;
+            save_cache(base_path, channel, &url, json, validators);
+            Ok(Some(manifest))
+        }
+    }
+}
+
+/// Fetches a manifest from an explicit URL, resolving relative artifact URLs against its folder.
+pub fn fetch_manifest_from(url: &str) -> Result<Option<RemoteManifest>, Box<dyn std::error::Error>> {
+    Ok(fetch_manifest_document(url)?.map(|(manifest, _)| manifest))
+}
+
+/// Like `fetch_manifest_from`, also returning the manifest as published, to save a copy of it.
+pub fn fetch_manifest_document(url: &str) -> Result<Option<(RemoteManifest, Value)>, Box<dyn std::error::Error>> {
+    let base_url = url.rsplit_once('/').map(|(base, _)| base).unwrap_or(url);
+    match fetch_manifest_json(url, None)? {
+        Fetched::Manifest(json, _) => Ok(Some((parse_manifest(&json, base_url)?, json))),
+        Fetched::Missing | Fetched::NotModified => Ok(None),
+    }
+}
+
+/// Reads a manifest saved next to downloaded archives, resolving relative artifact URLs against
+/// its folder so archives laid out like on the server are found where they are.
+pub fn read_manifest_file(path: &Path) -> Result<RemoteManifest, Box<dyn std::error::Error>> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let folder = path.parent().unwrap_or(Path::new("."));
+    parse_manifest(&json, &folder.to_string_lossy())
+}
+
+fn fetch_manifest_json(url: &str, validators: Option<&Validators>) -> Result<Fetched, Box<dyn std::error::Error>> {
+    let client = http::client(Duration::from_secs(30))?;
+
+    let mut request = client.get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().inspect_err(|e| {
+        diagnostics::record_request(url, &e.to_string());
+    })?;
+    diagnostics::record_request(url, response.status().as_str());
+    log::info!("GET {} -> {}", url, response.status());
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(Fetched::Missing);
+    }
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED && validators.is_some() {
+        return Ok(Fetched::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(UpdaterError::HttpStatus(response.status()).into());
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let validators = Validators {
+        etag: header(reqwest::header::ETAG),
+        last_modified: header(reqwest::header::LAST_MODIFIED),
+    };
+    let json: Value = serde_json::from_str(&response.text()?).map_err(|e| {
+        UpdaterError::Manifest(format!("The update server sent a manifest that is not valid JSON: {}", e))
+    })?;
+    Ok(Fetched::Manifest(json, validators))
+}
+
+fn read_cache(base_path: &Path) -> Option<Value> {
+    let content = fs::read_to_string(base_path.join(CACHE_FILE_NAME)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+pub fn load_cached_manifest(base_path: &Path, channel: &str) -> Option<CachedManifest> {
+    let cache = read_cache(base_path)?;
+    if cache["channel"].as_str() != Some(channel) {
+        return None;
+    }
+    Some(CachedManifest {
+        manifest: parse_manifest(&cache["manifest"], &channel_url(channel)).ok()?,
+        fetched_at: cache["fetchedAt"].as_u64()?,
+    })
+}
+
+fn save_cache(base_path: &Path, channel: &str, url: &str, manifest: Value, validators: Validators) {
+    let fetched_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let cache = json!({
+        "channel": channel,
+        "url": url,
+        "fetchedAt": fetched_at,
+        "etag": validators.etag,
+        "lastModified": validators.last_modified,
+        "manifest": manifest,
+    });
+    if let Ok(content) = serde_json::to_string_pretty(&cache) {
+        fs::write(base_path.join(CACHE_FILE_NAME), content).ok();
+    }
+}
+
+/// `schemaVersion` as major and minor version. Servers writing it as a number, like `1.1`, mean
+/// the same as the string.
+fn schema_version(json: &Value) -> Option<(u64, u64)> {
+    let version = match &json["schemaVersion"] {
+        Value::Null => return Some((1, 0)),
+        Value::Number(version) => version.to_string(),
+        Value::String(version) => version.trim().to_string(),
+        _ => return None,
+    };
+    let (major, minor) = version.split_once('.').unwrap_or((&version, "0"));
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Reads a manifest, with its relative URLs below `base_url`.
+pub fn parse_manifest(json: &Value, base_url: &str) -> Result<RemoteManifest, Box<dyn std::error::Error>> {
+    let (major, minor) = schema_version(json)
+        .ok_or_else(|| UpdaterError::Manifest("Invalid schemaVersion in manifest".to_string()))?;
+    if major > SCHEMA_MAJOR {
+        return Err(UpdaterError::Manifest(format!(
+            "The update server uses manifest format {}.{}, which this updater (format {}.{}) cannot read. Please download the latest updater from {} and run it again.",
+            major, minor, SCHEMA_MAJOR, SCHEMA_MINOR, LATEST_UPDATER_URL
+        ))
+        .into());
+    }
+    // A newer minor version may describe patches in ways this updater does not know yet.
+    let tolerant = major == SCHEMA_MAJOR && minor > SCHEMA_MINOR;
+    parse_manifest_body(json, base_url, tolerant)
+}
+
+fn parse_manifest_body(json: &Value, base_url: &str, tolerant: bool) -> Result<RemoteManifest, Box<dyn std::error::Error>> {
+    let latest = json["latest"]
+        .as_str()
+        .ok_or_else(|| UpdaterError::Manifest("Invalid latest in manifest".to_string()))?
+        .to_string();
+
+    let minimum_version = json["minimumVersion"].as_str().map(|s| s.to_string());
+    let full = parse_artifact(&json["full"], base_url);
+
+    let mut patches = Vec::new();
+    if let Some(entries) = json["patches"].as_array() {
+        for entry in entries {
+            let (Some(from), Some(to), Some(artifact)) = (
+                entry["from"].as_str(),
+                entry["to"].as_str(),
+                parse_artifact(entry, base_url),
+            ) else {
+                if tolerant {
+                    log::warn!("Skipping a patch entry this updater cannot read: {}", entry);
+                    continue;
+                }
+                return Err(UpdaterError::Manifest("Invalid patch entry in manifest".to_string()).into());
+            };
+            patches.push(PatchEdge {
+                from: from.to_string(),
+                to: to.to_string(),
+                artifact,
+                mandatory: entry["mandatory"].as_bool().unwrap_or(false),
+                reverse: parse_artifact(&entry["reverse"], base_url),
+            });
+        }
+    }
+
+    let self_check = string_array(&json["selfCheck"]);
+    let preload = if json["preload"].is_object() {
+        Some(Box::new(parse_manifest_body(&json["preload"], base_url, tolerant)?))
+    } else {
+        None
+    };
+
+    Ok(RemoteManifest {
+        latest,
+        minimum_version,
+        full,
+        patches,
+        self_check,
+        preload,
+        release_at: json["releaseAt"]
+            .as_str()
+            .and_then(|s| humantime::parse_rfc3339_weak(s).ok()),
+        mirrors: string_array(&json["mirrors"])
+            .into_iter()
+            .map(|mirror| mirror.trim_end_matches('/').to_string())
+            .collect(),
+        release_notes: json["releaseNotes"]
+            .as_object()
+            .map(|notes| {
+                notes
+                    .iter()
+                    .filter_map(|(version, text)| Some((version.clone(), text.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+fn string_array(json: &Value) -> Vec<String> {
+    json.as_array()
+        .map(|values| values.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default()
+}
+
+/// The last manifest fetched for any channel as is, for settings that have to work offline too.
+pub fn cached_manifest_json(base_path: &Path) -> Value {
+    read_cache(base_path)
+        .map(|cache| cache["manifest"].clone())
+        .unwrap_or(Value::Null)
+}
+
+/// Read from the last manifest fetched for any channel. Before updating, that is the manifest of
+/// the build whose processes are running; after updating, that of the build just installed.
+pub fn game_executables(base_path: &Path) -> GameExecutables {
+    let manifest = cached_manifest_json(base_path);
+    let mut processes = string_array(&manifest["processes"]);
+    if processes.is_empty() {
+        processes.push(platform::GAME_PROCESS_NAME.to_string());
+    }
+    GameExecutables {
+        processes,
+        launch: string_array(&manifest["launch"]),
+    }
+}
+
+fn parse_artifact(json: &Value, base_url: &str) -> Option<Artifact> {
+    let url = json["url"].as_str()?;
+    let url = if source::is_absolute(url) {
+        url.to_string()
+    } else {
+        format!("{}/{}", base_url, url.trim_start_matches('/'))
+    };
+    Some(Artifact {
+        url,
+        size: json["size"].as_u64().unwrap_or(0),
+        sha256: json["sha256"].as_str().map(|s| s.to_lowercase()),
+        key: json["key"].as_str().map(|s| s.to_string()),
+    })
+}
+
+/// Picks the smallest chain of patches from `current` to the latest version, or the full
+/// archive when it is cheaper, no chain exists, or `prefer_full` is set and more than one
+/// patch would be needed.
+pub fn plan_update(
+    manifest: &RemoteManifest,
+    current: &str,
+    prefer_full: bool,
+) -> Result<UpdatePlan, Box<dyn std::error::Error>> {
+    if current == manifest.latest {
+        return Ok(UpdatePlan::UpToDate);
+    }
+
+    let chain = shortest_patch_chain(&manifest.patches, current, &manifest.latest);
+    match (chain, &manifest.full) {
+        (Some(chain), Some(full)) => {
+            let patch_size: u64 = chain.iter().map(|e| e.artifact.size).sum();
+            let full_is_cheaper = if chain.len() > LONG_PATCH_CHAIN {
+                full.size <= patch_size.saturating_mul(LONG_CHAIN_FULL_SIZE_FACTOR)
+            } else {
+                full.size > 0 && full.size < patch_size
+            };
+            if full_is_cheaper || (prefer_full && chain.len() > 1) {
+                Ok(UpdatePlan::Full(full.clone()))
+            } else {
+                Ok(UpdatePlan::Patches(chain))
+            }
+        }
+        (Some(chain), None) => Ok(UpdatePlan::Patches(chain)),
+        (None, Some(full)) => Ok(UpdatePlan::Full(full.clone())),
+        (None, None) => Err(format!(
+            "No update path from version {} to version {}",
+            current, manifest.latest
+        )
+        .into()),
+    }
+}
+
+/// The versions an update from `current` passes through, oldest first and ending with the latest.
+/// Without a chain of patches, only the latest is known.
+pub fn versions_between(manifest: &RemoteManifest, current: &str) -> Vec<String> {
+    if current == manifest.latest {
+        return Vec::new();
+    }
+    match shortest_patch_chain(&manifest.patches, current, &manifest.latest) {
+        Some(chain) => chain.into_iter().map(|edge| edge.to).collect(),
+        None => vec![manifest.latest.clone()],
+    }
+}
+
+/// Archives to preload for the next version, for installs on the latest version. Empty when no
+/// version is preloading or there is no way to reach it.
+pub fn preload_downloads(manifest: &RemoteManifest) -> Vec<(String, Artifact)> {
+    let Some(preload) = &manifest.preload else {
+        return Vec::new();
+    };
+    match plan_update(preload, &manifest.latest, false) {
+        Ok(UpdatePlan::Patches(edges)) => edges.into_iter().map(|edge| (edge.to, edge.artifact)).collect(),
+        Ok(UpdatePlan::Full(artifact)) => vec![(preload.latest.clone(), artifact)],
+        Ok(UpdatePlan::UpToDate) | Err(_) => Vec::new(),
+    }
+}
+
+/// Treats the preload as released once its `releaseAt` has passed, when its archives already come
+/// with their keys, so players can unlock it on time without waiting for the manifest to change.
+/// Before that it is never applied, even with the keys at hand.
+pub fn with_unlocked_preload(mut manifest: RemoteManifest) -> RemoteManifest {
+    let unlocked = manifest.preload.as_ref().is_some_and(|preload| {
+        preload.release_at.is_some_and(|release_at| release_at <= SystemTime::now())
+            && preload
+                .full
+                .iter()
+                .chain(preload.patches.iter().map(|edge| &edge.artifact))
+                .all(|artifact| artifact.key.is_some())
+    });
+    if !unlocked {
+        return manifest;
+    }
+    let Some(preload) = manifest.preload.take() else {
+        return manifest;
+    };
+    manifest.latest = preload.latest;
+    manifest.patches.extend(preload.patches);
+    if preload.full.is_some() {
+        manifest.full = preload.full;
+    }
+    if !preload.self_check.is_empty() {
+        manifest.self_check = preload.self_check;
+    }
+    manifest
+}
+
+/// Points the artifacts published under `from` at the same files under `to`, e.g. a mirror.
+pub fn rebase(manifest: &mut RemoteManifest, from: &str, to: &str) {
+    let artifacts = manifest
+        .full
+        .iter_mut()
+        .chain(
+            manifest
+                .patches
+                .iter_mut()
+                .flat_map(|edge| std::iter::once(&mut edge.artifact).chain(edge.reverse.as_mut())),
+        );
+    for artifact in artifacts {
+        if let Some(path) = artifact.url.strip_prefix(from) {
+            artifact.url = format!("{}{}", to, path);
+        }
+    }
+    if let Some(preload) = &mut manifest.preload {
+        rebase(preload, from, to);
+    }
+}
+
+/// Whether the update from `current` to the latest version must be installed before playing.
+/// Installs too old to be patched or below the minimum version always have to update.
+pub fn is_mandatory(manifest: &RemoteManifest, current: &str) -> bool {
+    if is_below_minimum(manifest, current) {
+        return true;
+    }
+    match shortest_patch_chain(&manifest.patches, current, &manifest.latest) {
+        Some(chain) => chain.iter().any(|edge| edge.mandatory),
+        None => true,
+    }
+}
+
+/// Whether `current` is older than the minimum playable version declared by the server. Version
+/// codes are compared numerically; codes that are not numbers never count as too old.
+pub fn is_below_minimum(manifest: &RemoteManifest, current: &str) -> bool {
+    let Some(minimum) = &manifest.minimum_version else {
+        return false;
+    };
+    match (current.parse::<u64>(), minimum.parse::<u64>()) {
+        (Ok(current), Ok(minimum)) => current < minimum,
+        _ => false,
+    }
+}
+
+fn shortest_patch_chain(patches: &[PatchEdge], from: &str, to: &str) -> Option<Vec<PatchEdge>> {
+    let mut best: HashMap<&str, u64> = HashMap::new();
+    let mut previous: HashMap<&str, &PatchEdge> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+
+    best.insert(from, 0);
+    queue.push(Reverse((0u64, from)));
+
+    while let Some(Reverse((cost, version))) = queue.pop() {
+        if version == to {
+            break;
+        }
+        if best.get(version).map(|&b| cost > b).unwrap_or(false) {
+            continue;
+        }
+        for edge in patches.iter().filter(|e| e.from == version) {
+            let next_cost = cost + edge.artifact.size;
+            if best.get(edge.to.as_str()).map(|&b| next_cost < b).unwrap_or(true) {
+                best.insert(&edge.to, next_cost);
+                previous.insert(&edge.to, edge);
+                queue.push(Reverse((next_cost, edge.to.as_str())));
+            }
+        }
+    }
+
+    let mut chain = Vec::new();
+    let mut version = to;
+    while version != from {
+        let edge = previous.get(version)?;
+        chain.push((*edge).clone());
+        version = &edge.from;
+    }
+    chain.reverse();
+    Some(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    /// Any JSON value, nested a few levels deep.
+    fn any_json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".*".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+                prop::collection::hash_map(".*", inner, 0..8)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    /// A manifest-shaped object whose fields hold any JSON, so the parser gets past the first
+    /// checks more often than with arbitrary values.
+    fn any_manifest() -> impl Strategy<Value = Value> {
+        let field = prop_oneof![
+            "schemaVersion", "latest", "minimumVersion", "mandatory", "full", "patches", "from", "to", "url",
+            "size", "sha256", "key", "reverse", "mirrors", "preload", "releaseAt", "releaseNotes", "launch",
+            "processes", "selfCheck",
+        ];
+        prop::collection::vec((field, any_json()), 0..10)
+            .prop_map(|fields| Value::Object(fields.into_iter().collect()))
+    }
+
+    fn version_of(version: Value) -> Option<(u64, u64)> {
+        schema_version(&json!({ "schemaVersion": version }))
+    }
+
+    #[test]
+    fn schema_version_defaults_to_1_0() {
+        assert_eq!(schema_version(&json!({})), Some((1, 0)));
+    }
+
+    #[test]
+    fn schema_version_reads_numbers() {
+        assert_eq!(version_of(json!(1)), Some((1, 0)));
+        assert_eq!(version_of(json!(1.1)), Some((1, 1)));
+        assert_eq!(version_of(json!(2)), Some((2, 0)));
+    }
+
+    #[test]
+    fn schema_version_reads_strings() {
+        assert_eq!(version_of(json!("1.0")), Some((1, 0)));
+        assert_eq!(version_of(json!("1.7")), Some((1, 7)));
+        assert_eq!(version_of(json!("2.0")), Some((2, 0)));
+    }
+
+    #[test]
+    fn schema_version_rejects_garbage() {
+        assert_eq!(version_of(json!("one")), None);
+        assert_eq!(version_of(json!("1.x")), None);
+        assert_eq!(version_of(json!(-1)), None);
+        assert_eq!(version_of(json!(true)), None);
+        assert_eq!(version_of(json!([1, 0])), None);
+    }
+
+    #[test]
+    fn newer_minor_versions_are_read_and_newer_major_versions_refused() {
+        let manifest = |version: Value| json!({ "schemaVersion": version, "latest": "2" });
+        assert!(parse_manifest(&manifest(json!(1.1)), "https://example.com").is_ok());
+        assert!(parse_manifest(&manifest(json!("1.7")), "https://example.com").is_ok());
+        assert!(parse_manifest(&manifest(json!(2)), "https://example.com").is_err());
+        assert!(parse_manifest(&manifest(json!("2.0")), "https://example.com").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_json_does_not_panic(json in any_json()) {
+            let _ = parse_manifest(&json, "https://example.com");
+        }
+
+        #[test]
+        fn arbitrary_manifests_do_not_panic(json in any_manifest()) {
+            let _ = parse_manifest(&json, "https://example.com");
+        }
+
+        #[test]
+        fn manifests_without_a_latest_version_are_refused(mut json in any_manifest(), latest in any_json()) {
+            prop_assume!(!latest.is_string());
+            json["latest"] = latest;
+            prop_assert!(parse_manifest(&json, "https://example.com").is_err());
+        }
+
+        #[test]
+        fn arbitrary_text_does_not_panic(text in ".*") {
+            if let Ok(json) = serde_json::from_str::<Value>(&text) {
+                let _ = parse_manifest(&json, "https://example.com");
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! `update_metadata.json` is written by `generate-patch` into the root of every patch archive. It
+//! holds the expected hashes of each patched file so clients can detect local modifications
+//! before patching and corruption after it, and the files whose failure does not fail the update.
+
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+pub const METADATA_FILE_NAME: &str = "update_metadata.json";
+
+pub struct FileHashes {
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Default)]
+pub struct UpdateMetadata {
+    /// Folder with the unpacked files of the target build, used when a file cannot be patched.
+    pub file_base_url: Option<String>,
+    pub files: HashMap<String, FileHashes>,
+    /// Files the game works without, e.g. readmes, which are skipped when they cannot be updated.
+    pub optional: HashSet<String>,
+}
+
+impl UpdateMetadata {
+    pub fn parse(content: &str) -> Result<Self, serde_json::Error> {
+        let json: Value = serde_json::from_str(content)?;
+        let mut metadata = UpdateMetadata {
+            file_base_url: json["fileBaseUrl"].as_str().map(|s| s.trim_end_matches('/').to_string()),
+            files: HashMap::new(),
+            optional: json["optional"]
+                .as_array()
+                .map(|names| names.iter().filter_map(|name| name.as_str().map(|s| s.to_string())).collect())
+                .unwrap_or_default(),
+        };
+        if let Some(files) = json["files"].as_object() {
+            for (name, hashes) in files {
+                if let (Some(old), Some(new)) = (hashes["old"].as_str(), hashes["new"].as_str()) {
+                    metadata.files.insert(
+                        name.clone(),
+                        FileHashes {
+                            old: old.to_string(),
+                            new: new.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(metadata)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut files = Map::new();
+        for (name, hashes) in &self.files {
+            files.insert(name.clone(), json!({ "old": hashes.old, "new": hashes.new }));
+        }
+        let mut optional: Vec<&String> = self.optional.iter().collect();
+        optional.sort();
+        let mut root = json!({ "files": files, "optional": optional });
+        if let Some(file_base_url) = &self.file_base_url {
+            root["fileBaseUrl"] = Value::String(file_base_url.clone());
+        }
+        serde_json::to_string_pretty(&root).unwrap_or_default()
+    }
+
+    /// Whether a failure to apply archive entry `entry_name` may be skipped.
+    pub fn is_optional(&self, entry_name: &str) -> bool {
+        let name = entry_name
+            .strip_suffix(".patch")
+            .or_else(|| entry_name.strip_suffix(".delete"))
+            .unwrap_or(entry_name);
+        self.optional.contains(name)
+    }
+
+    pub fn file_url(&self, name: &str) -> Option<String> {
+        self.file_base_url
+            .as_ref()
+            .map(|base| format!("{}/{}", base, name))
+    }
+}
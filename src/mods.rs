@@ -0,0 +1,133 @@
+//! Files the player changed, e.g. to mod the game. Patching a changed file would corrupt it, so
+//! before an archive is applied, every file it patches is compared with the hash the patch expects.
+//! For each changed file, the player chooses to keep it, to replace it with the original, or to
+//! back it up to `mod_backups/<version>/` first and then replace it.
+
+use crossbeam_channel::Sender;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::hashing;
+use crate::inplace;
+use crate::metadata::UpdateMetadata;
+use crate::plugins::{self, Conflict};
+use crate::{get_version_info, UpdateMessage};
+
+pub const MOD_BACKUP_DIR: &str = "mod_backups";
+
+/// Set when someone can answer, in the updater window, `--windowless` and `install`. Tools like
+/// `simulate` replace changed files as before.
+static ASK: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ModChoice {
+    /// Skips the file, leaving the changed version in place.
+    Keep,
+    /// Downloads the original file of the new version over it.
+    Replace,
+    BackUpAndReplace,
+}
+
+impl ModChoice {
+    pub const ALL: [ModChoice; 3] = [ModChoice::Keep, ModChoice::Replace, ModChoice::BackUpAndReplace];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ModChoice::Keep => "Keep mod",
+            ModChoice::Replace => "Replace",
+            ModChoice::BackUpAndReplace => "Back up and replace",
+        }
+    }
+}
+
+pub fn ask_player() {
+    ASK.store(true, Ordering::Relaxed);
+}
+
+/// Files patched by archive entries `names` whose local copy matches neither the version the patch
+/// expects nor its result. Missing files are not mods and are downloaded as usual.
+pub fn find_modified<'a>(
+    base_path: &Path,
+    metadata: &UpdateMetadata,
+    names: impl Iterator<Item = &'a str>,
+) -> Vec<String> {
+    let mut modified = Vec::new();
+    for name in names {
+        let Some(target) = name.strip_suffix(".patch") else {
+            continue;
+        };
+        let Some(hashes) = metadata.files.get(target) else {
+            continue;
+        };
+        let path = base_path.join(target);
+        // Half patched in place, matching neither; applying the patch finishes it.
+        if inplace::in_progress(&path) {
+            continue;
+        }
+        let Ok(hash) = hashing::sha256_file_cached(&path) else {
+            continue;
+        };
+        if hash != hashes.old && hash != hashes.new {
+            modified.push(target.to_string());
+        }
+    }
+    modified
+}
+
+/// Decides what happens to each modified file: plugins decide first, the player the rest.
+pub fn choose(modified: Vec<String>, sender: &Sender<UpdateMessage>) -> HashMap<String, ModChoice> {
+    let mut choices = HashMap::new();
+    if modified.is_empty() {
+        return choices;
+    }
+    log::info!("Modified files: {}", modified.join(", "));
+
+    let mut undecided = Vec::new();
+    for file in modified {
+        match plugins::on_conflict(&file, "modified") {
+            Conflict::Keep => {
+                choices.insert(file, ModChoice::Keep);
+            }
+            Conflict::Replace => {
+                choices.insert(file, ModChoice::Replace);
+            }
+            Conflict::Default => undecided.push(file),
+        }
+    }
+    if undecided.is_empty() {
+        return choices;
+    }
+
+    let answers = if ASK.load(Ordering::Relaxed) {
+        let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+        sender
+            .send(UpdateMessage::ModifiedFiles(undecided.clone(), reply_sender))
+            .unwrap();
+        reply_receiver.recv().unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    for (index, file) in undecided.into_iter().enumerate() {
+        let choice = answers.get(index).copied().unwrap_or(ModChoice::Replace);
+        choices.insert(file, choice);
+    }
+    choices
+}
+
+/// Copies modified `name` to `mod_backups/<installed version>/`, replacing an earlier backup of the
+/// same file from that version.
+pub fn back_up(base_path: &Path, name: &str) -> io::Result<()> {
+    let version = get_version_info(base_path)
+        .map(|info| info.version_code)
+        .unwrap_or_else(|_| "unknown".to_string());
+    let target = base_path.join(MOD_BACKUP_DIR).join(version).join(name);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(base_path.join(name), &target)?;
+    log::info!("Backed up modified {} to {}", name, target.display());
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! `nettest` measures the connection to each download server: whether it can be reached, how long
+//! requests take and how fast a download runs. The report is printed and saved to
+//! `nettest_report.txt`, so players can paste it into a support request as is.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{System, SystemExt};
+
+use crate::manifest::cached_manifest_json;
+use crate::{downloads_url, format_bytes, format_timestamp, http, load_options, DEFAULT_DOWNLOADS_URL};
+
+const REPORT_FILE_NAME: &str = "nettest_report.txt";
+const LATENCY_REQUESTS: usize = 3;
+/// The download test stops after this much data or this long, whichever comes first.
+const THROUGHPUT_BYTES: u64 = 32 * 1024 * 1024;
+const THROUGHPUT_DURATION: Duration = Duration::from_secs(10);
+
+struct ServerResult {
+    url: String,
+    latencies: Vec<Duration>,
+    throughput: Option<(u64, Duration)>,
+    errors: Vec<String>,
+}
+
+pub fn run_nettest(args: &[String]) -> i32 {
+    let base_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    load_options(args, &base_path);
+
+    let mut servers = vec![downloads_url().to_string()];
+    if downloads_url() != DEFAULT_DOWNLOADS_URL {
+        servers.push(DEFAULT_DOWNLOADS_URL.to_string());
+    }
+    // Other servers publishing the same files, listed in `mirrors` of the last manifest fetched.
+    if let Some(mirrors) = cached_manifest_json(&base_path)["mirrors"].as_array() {
+        for mirror in mirrors.iter().filter_map(|m| m.as_str()) {
+            let mirror = mirror.trim_end_matches('/').to_string();
+            if !servers.contains(&mirror) {
+                servers.push(mirror);
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    for server in servers {
+        println!("Testing {}...", server);
+        results.push(test_server(&server));
+    }
+
+    let report = format_report(&results);
+    println!();
+    print!("{}", report);
+    match fs::write(base_path.join(REPORT_FILE_NAME), &report) {
+        Ok(()) => println!("\nSaved to {}", base_path.join(REPORT_FILE_NAME).display()),
+        Err(e) => eprintln!("\nFailed to save {}: {}", REPORT_FILE_NAME, e),
+    }
+
+    if results.iter().any(|result| !result.latencies.is_empty()) {
+        0
+    } else {
+        1
+    }
+}
+
+fn test_server(server: &str) -> ServerResult {
+    let mut result = ServerResult {
+        url: server.to_string(),
+        latencies: Vec::new(),
+        throughput: None,
+        errors: Vec::new(),
+    };
+    let client = match http::client(Duration::from_secs(30)) {
+        Ok(client) => client,
+        Err(e) => {
+            result.errors.push(e.to_string());
+            return result;
+        }
+    };
+
+    let version_url = format!("{}/version.json", server);
+    for _ in 0..LATENCY_REQUESTS {
+        let started = Instant::now();
+        match client.get(&version_url).send() {
+            Ok(response) if response.status().is_success() => result.latencies.push(started.elapsed()),
+            Ok(response) => result.errors.push(format!("GET version.json -> {}", response.status())),
+            Err(e) => result.errors.push(format!("GET version.json: {}", e)),
+        }
+    }
+
+    let archive_url = format!("{}/latest.zip", server);
+    let response = client
+        .get(&archive_url)
+        .header(reqwest::header::RANGE, format!("bytes=0-{}", THROUGHPUT_BYTES - 1))
+        .send();
+    match response {
+        Ok(mut response) if response.status().is_success() => {
+            let started = Instant::now();
+            let mut buffer = vec![0u8; 256 * 1024];
+            let mut received = 0;
+            while received < THROUGHPUT_BYTES && started.elapsed() < THROUGHPUT_DURATION {
+                match response.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(n) => received += n as u64,
+                    Err(e) => {
+                        result.errors.push(format!("downloading latest.zip: {}", e));
+                        break;
+                    }
+                }
+            }
+            result.throughput = Some((received, started.elapsed()));
+        }
+        Ok(response) => result.errors.push(format!("GET latest.zip -> {}", response.status())),
+        Err(e) => result.errors.push(format!("GET latest.zip: {}", e)),
+    }
+    result
+}
+
+fn format_report(results: &[ServerResult]) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut report = String::new();
+    writeln!(report, "DREAMIO connection test, {}", format_timestamp(now)).ok();
+    writeln!(report, "Updater version: {}", env!("CARGO_PKG_VERSION")).ok();
+    writeln!(
+        report,
+        "OS: {}",
+        System::new().long_os_version().unwrap_or_else(|| "unknown".to_string())
+    )
+    .ok();
+    if let Some(proxy) = &http::settings().proxy {
+        writeln!(report, "Proxy: {}", proxy).ok();
+    }
+    for result in results {
+        writeln!(report).ok();
+        writeln!(report, "Server: {}", result.url).ok();
+        if result.latencies.is_empty() {
+            writeln!(report, "  Reachable: no").ok();
+        } else {
+            let mut latencies = result.latencies.clone();
+            latencies.sort();
+            writeln!(
+                report,
+                "  Reachable: yes ({}/{} requests)",
+                latencies.len(),
+                LATENCY_REQUESTS
+            )
+            .ok();
+            writeln!(
+                report,
+                "  Request time: {} ms best, {} ms median",
+                latencies[0].as_millis(),
+                latencies[latencies.len() / 2].as_millis()
+            )
+            .ok();
+        }
+        if let Some((bytes, elapsed)) = result.throughput {
+            let per_second = (bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+            writeln!(
+                report,
+                "  Download speed: {}/s ({} in {:.1} s)",
+                format_bytes(per_second),
+                format_bytes(bytes),
+                elapsed.as_secs_f64()
+            )
+            .ok();
+        }
+        for error in &result.errors {
+            writeln!(report, "  Error: {}", error).ok();
+        }
+    }
+    report
+}
@@ -0,0 +1,201 @@
+//! Release notes from `releaseNotes` in the manifest, Markdown by version. An update shows them as
+//! "What's new in <version>" for every version it installs, newest first: `check` prints them to
+//! the console, the updater window shows them in a scrollable pane while it updates, and
+//! `--windowless` passes them on to the game as a `releaseNotes` event.
+//!
+//! Headings, lists, `**bold**`, `*italic*`, `` `code` `` and `[links](https://...)` are rendered;
+//! anything else is shown as written.
+
+use std::env;
+
+use crate::manifest::{self, RemoteManifest};
+
+const BOLD: &str = "\x1b[1m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+pub struct ReleaseNotes {
+    /// The version the update ends at.
+    pub version: String,
+    pub markdown: String,
+}
+
+impl ReleaseNotes {
+    pub fn title(&self) -> String {
+        format!("What's new in {}", self.version)
+    }
+}
+
+/// The notes of the versions an update from `current` installs. `None` when none of them has any.
+pub fn for_update(manifest: &RemoteManifest, current: &str) -> Option<ReleaseNotes> {
+    let notes: Vec<(String, &str)> = manifest::versions_between(manifest, current)
+        .into_iter()
+        .rev()
+        .filter_map(|version| {
+            let text = manifest.release_notes.get(&version)?.trim();
+            (!text.is_empty()).then_some((version, text))
+        })
+        .collect();
+    let markdown = match notes.as_slice() {
+        [] => return None,
+        // Versions in between are told apart by a heading of their own.
+        [(version, text)] if *version == manifest.latest => text.to_string(),
+        _ => notes
+            .iter()
+            .map(|(version, text)| format!("# {}\n\n{}", version, text))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    };
+    Some(ReleaseNotes {
+        version: manifest.latest.clone(),
+        markdown,
+    })
+}
+
+enum Block<'a> {
+    Heading(&'a str),
+    Bullet(&'a str),
+    Text(&'a str),
+    Blank,
+}
+
+fn block(line: &str) -> Block<'_> {
+    let line = line.trim_end();
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() {
+        return Block::Blank;
+    }
+    if trimmed.starts_with('#') {
+        return Block::Heading(trimmed.trim_start_matches('#').trim());
+    }
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(marker) {
+            return Block::Bullet(item);
+        }
+    }
+    Block::Text(line)
+}
+
+enum Span<'a> {
+    Text(String),
+    Bold(&'a str),
+    Italic(&'a str),
+    Code(&'a str),
+    Link(&'a str, &'a str),
+}
+
+/// Splits a line into its inline formatting. Markers without a closing one are kept as text.
+fn spans(line: &str) -> Vec<Span<'_>> {
+    let mut spans = Vec::new();
+    let mut text = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        let parsed = match c {
+            '*' if after.starts_with('*') => after[1..]
+                .split_once("**")
+                .filter(|(inner, _)| !inner.is_empty())
+                .map(|(inner, after)| (Span::Bold(inner), after)),
+            '*' => after
+                .split_once('*')
+                .filter(|(inner, _)| !inner.is_empty() && !inner.starts_with(' '))
+                .map(|(inner, after)| (Span::Italic(inner), after)),
+            '`' => after.split_once('`').map(|(inner, after)| (Span::Code(inner), after)),
+            '[' => after.split_once("](").and_then(|(label, after)| {
+                after
+                    .split_once(')')
+                    .map(|(url, after)| (Span::Link(label, url), after))
+            }),
+            _ => None,
+        };
+        match parsed {
+            Some((span, after)) => {
+                if !text.is_empty() {
+                    spans.push(Span::Text(std::mem::take(&mut text)));
+                }
+                spans.push(span);
+                rest = after;
+            }
+            None => {
+                text.push(c);
+                rest = after;
+            }
+        }
+    }
+    if !text.is_empty() {
+        spans.push(Span::Text(text));
+    }
+    spans
+}
+
+/// Renders `markdown` for a terminal, with ANSI escape sequences when `color` is set.
+pub fn to_ansi(markdown: &str, color: bool) -> String {
+    // NO_COLOR, see https://no-color.org.
+    let color = color && env::var_os("NO_COLOR").is_none();
+    let style = |code: &str, text: &str| {
+        if color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let inline = |line: &str| {
+        spans(line)
+            .into_iter()
+            .map(|span| match span {
+                Span::Text(text) => text,
+                Span::Bold(text) => style(BOLD, text),
+                Span::Italic(text) => style(ITALIC, text),
+                Span::Code(text) => style(CYAN, text),
+                Span::Link(label, url) => format!("{} ({})", style(UNDERLINE, label), url),
+            })
+            .collect::<String>()
+    };
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        lines.push(match block(line) {
+            Block::Heading(text) if color => format!("{}{}{}{}", BOLD, UNDERLINE, inline(text), RESET),
+            Block::Heading(text) => inline(text).to_uppercase(),
+            Block::Bullet(item) => format!("  {} {}", if color { "•" } else { "-" }, inline(item)),
+            Block::Text(text) => inline(text),
+            Block::Blank => String::new(),
+        });
+    }
+    lines.join("\n")
+}
+
+/// Shows `markdown` in the updater window.
+pub fn show(ui: &mut egui::Ui, markdown: &str) {
+    for line in markdown.lines() {
+        let (prefix, text, heading) = match block(line) {
+            Block::Heading(text) => ("", text, true),
+            Block::Bullet(item) => ("  • ", item, false),
+            Block::Text(text) => ("", text, false),
+            Block::Blank => {
+                ui.add_space(4.0);
+                continue;
+            }
+        };
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            if !prefix.is_empty() {
+                ui.label(prefix);
+            }
+            for span in spans(text) {
+                let rich = match span {
+                    Span::Text(text) => egui::RichText::new(text),
+                    Span::Bold(text) => egui::RichText::new(text).strong(),
+                    Span::Italic(text) => egui::RichText::new(text).italics(),
+                    Span::Code(text) => egui::RichText::new(text).code(),
+                    Span::Link(label, url) => {
+                        ui.hyperlink_to(label, url);
+                        continue;
+                    }
+                };
+                ui.label(if heading { rich.strong().size(16.0) } else { rich });
+            }
+        });
+    }
+}
@@ -0,0 +1,198 @@
+//! `--from-file <path>` updates from archives downloaded on another computer, for players whose
+//! connection cannot download them. `<path>` is `latest.zip`, a patch archive, or a folder holding
+//! several of them, e.g. a whole chain of patches.
+//!
+//! The archives are identified by the channel's `manifest.json`: saved in the same folder, the way
+//! the server publishes it, or else the manifest the updater cached on its last check. An archive
+//! is taken for the manifest entry with the same file name, or with the same hash for renamed
+//! files, and it then goes through the same checks as a download: its hash against the manifest,
+//! the files it patches against the update's metadata, and `version.json` against the version it
+//! should install. Archives the manifest does not list are not applied.
+
+use crossbeam_channel::Sender;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+
+use crate::error::UpdaterError;
+use crate::hashing;
+use crate::manifest::{self, Artifact, RemoteManifest};
+use crate::publish::collect_files;
+use crate::source;
+use crate::{apply_update, cleanup, download_update, get_version_info, http, ApplySummary, UpdateMessage};
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// The manifest of the update in `from`, reduced to the archives found there, with their local
+/// paths as URLs. `latest` becomes the newest version they reach from `current_version`; without
+/// one, as for a first install, only the full archive is of use.
+pub fn local_manifest(
+    base_path: &Path,
+    channel: &str,
+    from: &Path,
+    current_version: Option<&str>,
+) -> Result<RemoteManifest, Box<dyn Error>> {
+    let from = path::absolute(from)?;
+    let folder = if from.is_dir() {
+        from.clone()
+    } else {
+        from.parent().map(Path::to_path_buf).unwrap_or_default()
+    };
+    let manifest_path = folder.join(MANIFEST_FILE_NAME);
+    let mut manifest = if manifest_path.exists() {
+        manifest::read_manifest_file(&manifest_path)?
+    } else {
+        manifest::load_cached_manifest(base_path, channel)
+            .map(|cached| cached.manifest)
+            .ok_or_else(|| {
+                format!(
+                    "There is no {} next to the update, and the updater has not checked the {} channel before. Download {} along with the update.",
+                    MANIFEST_FILE_NAME, channel, MANIFEST_FILE_NAME
+                )
+            })?
+    };
+
+    // Folders are searched with their subfolders, so a copy of the server's `patches/` works too.
+    let archives = if from.is_dir() {
+        collect_files(&from)?
+            .into_iter()
+            .filter(|name| name.ends_with(".zip"))
+            .map(|name| from.join(name))
+            .collect()
+    } else {
+        vec![from.clone()]
+    };
+    let mut finder = ArchiveFinder {
+        archives: &archives,
+        hashes: HashMap::new(),
+    };
+    manifest.full = manifest.full.take().and_then(|full| finder.locate(full));
+    manifest.patches = std::mem::take(&mut manifest.patches)
+        .into_iter()
+        .filter_map(|mut edge| {
+            edge.artifact = finder.locate(edge.artifact)?;
+            Some(edge)
+        })
+        .collect();
+    // Nothing else is downloaded while offline.
+    manifest.preload = None;
+    manifest.mirrors.clear();
+
+    if manifest.full.is_none() && manifest.patches.is_empty() {
+        return Err(format!(
+            "{} holds no archive of the {} channel's manifest, which lists version {}",
+            from.display(),
+            channel,
+            manifest.latest
+        )
+        .into());
+    }
+    if let Some(current) = current_version {
+        if manifest.full.is_none() {
+            manifest.latest = newest_reachable(&manifest, current).ok_or_else(|| {
+                format!("None of the patches in {} applies to the installed version {}", from.display(), current)
+            })?;
+        }
+    }
+    Ok(manifest)
+}
+
+/// Installs the full archive in `from` into `target_path`, for a first install or a channel switch.
+pub fn install(
+    from: &Path,
+    channel: &str,
+    update_zip_path: &Path,
+    target_path: &Path,
+    sender: &Sender<UpdateMessage>,
+) -> Result<ApplySummary, Box<dyn Error>> {
+    let manifest = local_manifest(target_path, channel, from, None)?;
+    let full = manifest.full.ok_or_else(|| {
+        format!(
+            "Installing the game needs the full archive of version {}, which {} does not hold",
+            manifest.latest,
+            from.display()
+        )
+    })?;
+    sender
+        .send(UpdateMessage::Log(format!("Installing version {} from {}...", manifest.latest, full.url)))
+        .unwrap();
+    let sha256 = http::block_on(download_update(&full.url, update_zip_path, sender))?;
+    if full.sha256.as_ref().is_some_and(|expected| *expected != sha256) {
+        fs::remove_file(update_zip_path).ok();
+        return Err(
+            UpdaterError::Verification("the archive does not match the hash published in the manifest".to_string()).into(),
+        );
+    }
+    let summary = apply_update(update_zip_path, target_path, sender)?;
+    cleanup(target_path);
+    let installed = get_version_info(target_path)?.version_code;
+    if installed != manifest.latest {
+        return Err(format!(
+            "Expected version {} after installing, found version {}",
+            manifest.latest, installed
+        )
+        .into());
+    }
+    Ok(summary)
+}
+
+struct ArchiveFinder<'a> {
+    archives: &'a [PathBuf],
+    /// Hashed only when no file name matches, since that reads the whole archive.
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl ArchiveFinder<'_> {
+    /// `artifact` pointing at its archive in the folder, or `None` when it is not there.
+    fn locate(&mut self, mut artifact: Artifact) -> Option<Artifact> {
+        let archives = self.archives;
+        let file_name = artifact.url.rsplit(['/', '\\']).next().unwrap_or("").to_string();
+        let size_matches = |path: &Path| {
+            artifact.size == 0 || fs::metadata(path).map(|metadata| metadata.len() == artifact.size).unwrap_or(false)
+        };
+        let laid_out = source::local_path(&artifact.url).filter(|path| archives.contains(path));
+        let found = laid_out
+            .or_else(|| {
+                archives
+                    .iter()
+                    .find(|path| path.file_name().is_some_and(|name| name == file_name.as_str()) && size_matches(path))
+                    .cloned()
+            })
+            .or_else(|| {
+                let expected = artifact.sha256.as_ref()?;
+                archives.iter().find(|path| self.sha256(path).as_ref() == Some(expected)).cloned()
+            })?;
+        log::info!("Using {} for {}", found.display(), artifact.url);
+        artifact.url = found.to_string_lossy().to_string();
+        Some(artifact)
+    }
+
+    fn sha256(&mut self, path: &Path) -> Option<String> {
+        if !self.hashes.contains_key(path) {
+            let sha256 = hashing::sha256_file(path).ok()?;
+            self.hashes.insert(path.to_path_buf(), sha256);
+        }
+        self.hashes.get(path).cloned()
+    }
+}
+
+/// The latest version when the patches lead there from `current`, and otherwise the version they
+/// lead to in the most steps. `None` when none applies.
+fn newest_reachable(manifest: &RemoteManifest, current: &str) -> Option<String> {
+    let mut seen = HashSet::from([current.to_string()]);
+    let mut queue = VecDeque::from([current.to_string()]);
+    let mut newest = None;
+    while let Some(version) = queue.pop_front() {
+        for edge in manifest.patches.iter().filter(|edge| edge.from == version) {
+            if seen.insert(edge.to.clone()) {
+                newest = Some(edge.to.clone());
+                queue.push_back(edge.to.clone());
+            }
+        }
+    }
+    if seen.contains(&manifest.latest) {
+        return Some(manifest.latest.clone());
+    }
+    newest
+}
@@ -0,0 +1,343 @@
+//! A peer cache lets computers on the same network share update archives, so a household or an
+//! internet café downloads a multi-gigabyte update once instead of on every computer.
+//!
+//! With `peerCache` in `updater.json` or `--peer-cache`, an HTTP URL or a folder such as
+//! `\\server\dreamio`, each archive is looked for there first, at its path relative to the
+//! download server, e.g. `<peer>/beta/patches/123.zip`. It is used only when it matches the hash
+//! the manifest publishes; anything else falls back to the server. Archives without a published
+//! hash are always downloaded from the server.
+//!
+//! With `shareDownloads`, the archives an update downloaded are kept in `peer_cache/`, laid out the
+//! same way, until the next update replaces them. `serve-cache` serves that folder, or any other
+//! such as one filled by `--download-only`, over HTTP to the other computers.
+
+use crossbeam_channel::Sender;
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+
+use crate::manifest::Artifact;
+use crate::publish::collect_files;
+use crate::{download_file, downloads_url, UpdateMessage};
+
+pub const CACHE_DIR_NAME: &str = "peer_cache";
+const DEFAULT_PORT: u16 = 8750;
+
+static PEER: OnceLock<String> = OnceLock::new();
+
+/// Sets the peer cache for the rest of the run, from `--peer-cache` or `updater.json`.
+pub fn configure(peer: Option<String>) {
+    if let Some(peer) = peer {
+        PEER.set(peer.trim_end_matches(['/', '\\']).to_string()).ok();
+    }
+}
+
+/// Where `artifact` is on the download server, relative to its root. `None` for archives on other
+/// servers, like mirrors, which the cache does not hold.
+fn relative_path(artifact: &Artifact) -> Option<&str> {
+    artifact
+        .url
+        .strip_prefix(downloads_url())
+        .and_then(|path| path.strip_prefix('/'))
+        .filter(|path| !path.is_empty())
+}
+
+/// Downloads `artifact` from the peer cache to `path`. Returns its hash when it was there and
+/// matched the manifest, and `None` when the caller should download it from the server.
+pub fn fetch(artifact: &Artifact, path: &Path, sender: &Sender<UpdateMessage>) -> Option<String> {
+    let peer = PEER.get()?;
+    let expected = artifact.sha256.as_ref()?;
+    let url = format!("{}/{}", peer, relative_path(artifact)?);
+    sender
+        .send(UpdateMessage::Log(format!("Looking for the update in the peer cache at {}...", peer)))
+        .unwrap();
+    // Downloaded next to `path`, so an interrupted download is never taken for the archive.
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".peer");
+    let partial = PathBuf::from(partial);
+    match download_file(&url, &partial, sender) {
+        Ok(sha256) if sha256 == *expected => match fs::rename(&partial, path) {
+            Ok(()) => {
+                log::info!("Downloaded {} from the peer cache", url);
+                return Some(sha256);
+            }
+            Err(e) => log::warn!("Failed to move {} into place: {}", partial.display(), e),
+        },
+        Ok(_) => log::warn!("{} does not match the hash published in the manifest", url),
+        Err(e) => log::info!("The peer cache has no {}: {}", url, e),
+    }
+    fs::remove_file(&partial).ok();
+    sender
+        .send(UpdateMessage::Log("Not in the peer cache, downloading from the server.".to_string()))
+        .unwrap();
+    None
+}
+
+/// Keeps the verified archive at `path` for other computers, when `shareDownloads` is on. A hard
+/// link costs no space, since the download is removed once it is applied.
+pub fn share(base_path: &Path, artifact: &Artifact, path: &Path) {
+    let Some(relative) = relative_path(artifact) else {
+        return;
+    };
+    let shared = base_path.join(CACHE_DIR_NAME).join(relative);
+    let result = shared
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| match fs::remove_file(&shared) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        })
+        .and_then(|_| fs::hard_link(path, &shared).or_else(|_| fs::copy(path, &shared).map(|_| ())));
+    if let Err(e) = result {
+        log::warn!("Failed to share {} in {}: {}", relative, CACHE_DIR_NAME, e);
+    }
+}
+
+/// Removes the shared archives of earlier updates, keeping those of `artifacts`.
+pub fn prune(base_path: &Path, artifacts: &[(String, Artifact)]) {
+    let dir = base_path.join(CACHE_DIR_NAME);
+    let Ok(files) = collect_files(&dir) else {
+        return;
+    };
+    let keep: HashSet<&str> = artifacts.iter().filter_map(|(_, artifact)| relative_path(artifact)).collect();
+    for name in files.iter().filter(|name| !keep.contains(name.as_str())) {
+        fs::remove_file(dir.join(name)).ok();
+    }
+}
+
+/// `serve-cache [--port <port>] [<dir>]` serves `<dir>`, by default the install's shared
+/// downloads, to the other computers until it is stopped.
+pub fn run_serve_cache(args: &[String]) -> i32 {
+    let mut port = DEFAULT_PORT;
+    let mut dir = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => match args.next().and_then(|port| port.parse().ok()) {
+                Some(value) => port = value,
+                None => {
+                    eprintln!("Usage: DreamioUpdater serve-cache [--port <port>] [<dir>]");
+                    return 2;
+                }
+            },
+            _ if dir.is_none() && !arg.starts_with('-') => dir = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("Usage: DreamioUpdater serve-cache [--port <port>] [<dir>]");
+                return 2;
+            }
+        }
+    }
+    let root = dir.unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join(CACHE_DIR_NAME));
+    if !root.is_dir() {
+        eprintln!(
+            "{} does not exist. Turn on shareDownloads in updater.json and update once, or pass the folder to serve.",
+            root.display()
+        );
+        return 1;
+    }
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on port {}: {}", port, e);
+            return 1;
+        }
+    };
+    println!(
+        "Serving {} on port {}. Set \"peerCache\": \"http://<this computer>:{}\" in updater.json on the other computers. Press Ctrl+C to stop.",
+        root.display(),
+        port,
+        port
+    );
+    for stream in listener.incoming().flatten() {
+        let root = root.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+            if let Err(e) = serve(stream, &root) {
+                log::info!("Connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+    0
+}
+
+/// Answers the `GET` and `HEAD` requests of one connection, with ranges so downloads resume.
+fn serve(stream: TcpStream, root: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line)? == 0 {
+            return Ok(());
+        }
+        let mut range_start = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("range") {
+                    range_start = parse_range(value.trim()).unwrap_or(0);
+                }
+            }
+        }
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        if method != "GET" && method != "HEAD" {
+            respond_empty(&mut stream, "405 Method Not Allowed")?;
+            continue;
+        }
+        let Some(path) = resolve(root, target).filter(|path| path.is_file()) else {
+            println!("{} {}: not found", method, target);
+            respond_empty(&mut stream, "404 Not Found")?;
+            continue;
+        };
+        let mut file = File::open(&path)?;
+        let len = file.metadata()?.len();
+        if range_start > len {
+            // Tells the client how long the file is, so it can start over.
+            let response = format!(
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\n\r\n",
+                len
+            );
+            stream.write_all(response.as_bytes())?;
+            stream.flush()?;
+            continue;
+        }
+        let status = if range_start > 0 { "206 Partial Content" } else { "200 OK" };
+        let mut headers = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/zip\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n",
+            status,
+            len - range_start
+        );
+        if range_start > 0 {
+            headers.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n", range_start, len.saturating_sub(1), len));
+        }
+        headers.push_str("\r\n");
+        stream.write_all(headers.as_bytes())?;
+        if method == "GET" {
+            println!("GET {} from byte {}", target, range_start);
+            file.seek(SeekFrom::Start(range_start))?;
+            io::copy(&mut Read::by_ref(&mut file).take(len - range_start), &mut stream)?;
+        }
+        stream.flush()?;
+    }
+}
+
+fn respond_empty(stream: &mut TcpStream, status: &str) -> io::Result<()> {
+    stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).as_bytes())?;
+    stream.flush()
+}
+
+/// The start of a `bytes=<start>-` range, the only kind downloads send.
+fn parse_range(value: &str) -> Option<u64> {
+    value.strip_prefix("bytes=")?.split('-').next()?.trim().parse().ok()
+}
+
+/// The file `target` names below `root`. Paths leaving `root` are refused.
+fn resolve(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = percent_decode(target.split(['?', '#']).next()?)?;
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    Some(root.join(relative))
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            // Two hex digits exactly; `from_str_radix` alone would take a sign as well.
+            let hex = [input.next()?, input.next()?];
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `path` is `root` followed only by plain names.
+    fn inside(root: &Path, path: &Path) -> bool {
+        path.strip_prefix(root)
+            .is_ok_and(|relative| relative.components().all(|component| matches!(component, Component::Normal(_))))
+    }
+
+    #[test]
+    fn percent_decode_decodes_escapes() {
+        assert_eq!(percent_decode("a%20b").as_deref(), Some("a b"));
+        assert_eq!(percent_decode("%2e%2E").as_deref(), Some(".."));
+        assert_eq!(percent_decode("%2F").as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn percent_decode_rejects_broken_escapes() {
+        assert_eq!(percent_decode("%"), None);
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn resolve_finds_files_below_the_root() {
+        let root = Path::new("cache");
+        assert_eq!(resolve(root, "/abc.zip"), Some(root.join("abc.zip")));
+        assert_eq!(resolve(root, "/abc.zip?x=1#top"), Some(root.join("abc.zip")));
+        assert_eq!(resolve(root, "/sub%2Fabc.zip"), Some(root.join("sub").join("abc.zip")));
+    }
+
+    #[test]
+    fn resolve_refuses_parent_directories() {
+        let root = Path::new("cache");
+        for target in ["/..", "/../secret", "/a/../../secret", "/%2e%2e/secret", "/%2E%2e%2Fsecret", "/./abc.zip"] {
+            assert_eq!(resolve(root, target), None, "{}", target);
+        }
+    }
+
+    #[test]
+    fn resolve_keeps_absolute_paths_below_the_root() {
+        let root = Path::new("cache");
+        assert_eq!(resolve(root, "//etc/passwd"), Some(root.join("etc").join("passwd")));
+        assert_eq!(resolve(root, "/%2Fetc%2Fpasswd"), Some(root.join("etc").join("passwd")));
+    }
+
+    #[test]
+    fn resolve_never_leaves_the_root_with_windows_paths() {
+        let root = Path::new("cache");
+        let targets = [
+            "/C:/Windows/win.ini",
+            "/C:%5CWindows%5Cwin.ini",
+            "/%5C%5Cserver%5Cshare%5Cfile",
+            "/%5C%5C%3F%5CC:%5Cfile",
+            "/..%5C..%5Csecret",
+            "/a%5C..%5C..%5Csecret",
+        ];
+        for target in targets {
+            assert!(resolve(root, target).is_none_or(|path| inside(root, &path)), "{}", target);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn resolve_refuses_windows_prefixes_and_backslashes() {
+        let root = Path::new("cache");
+        for target in ["/C:/Windows/win.ini", "/%5C%5Cserver%5Cshare%5Cfile", "/..%5C..%5Csecret"] {
+            assert_eq!(resolve(root, target), None, "{}", target);
+        }
+    }
+}
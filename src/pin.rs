@@ -0,0 +1,153 @@
+//! Version pins for tournament and exhibition machines. `pin --version <v>` writes
+//! `update_pin.json` next to the game, and until `unpin` removes it the updater launches the
+//! installed build without checking for, downloading or installing updates. Every start shows a
+//! banner saying so, so a pinned machine is never mistaken for an up-to-date one.
+//!
+//! Only the installed version can be pinned, which lets organizers confirm they froze the build
+//! they meant to.
+
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{format_timestamp, get_version_info, platform};
+
+const PIN_FILE_NAME: &str = "update_pin.json";
+
+pub struct Pin {
+    pub version: String,
+    pub pinned_at: u64,
+}
+
+/// The pin of the install in `base_path`, if it has one.
+pub fn load(base_path: &Path) -> Option<Pin> {
+    let content = fs::read_to_string(base_path.join(PIN_FILE_NAME)).ok()?;
+    let json: Value = match serde_json::from_str(&content) {
+        Ok(json) => json,
+        Err(e) => {
+            // A damaged pin still holds updates back; organizers rely on it.
+            log::warn!("Failed to parse {}: {}", PIN_FILE_NAME, e);
+            Value::Null
+        }
+    };
+    Some(Pin {
+        version: json["version"].as_str().unwrap_or("unknown").to_string(),
+        pinned_at: json["pinnedAt"].as_u64().unwrap_or(0),
+    })
+}
+
+/// The line shown on every start of a pinned install.
+pub fn banner(pin: &Pin, base_path: &Path) -> String {
+    let mut banner = format!("Updates are paused: this install is pinned to version {}", pin.version);
+    if pin.pinned_at > 0 {
+        banner.push_str(&format!(" since {}", format_timestamp(pin.pinned_at)));
+    }
+    banner.push_str(&format!(
+        ". Run `{} unpin` to allow updates again.",
+        platform::UPDATER_EXECUTABLE
+    ));
+    if let Ok(info) = get_version_info(base_path) {
+        if info.version_code != pin.version {
+            banner.push_str(&format!(" The installed version is {}.", info.version_code));
+        }
+    }
+    banner
+}
+
+/// `pin --version <version> [<install dir>]` freezes the install at `version`, which must be the
+/// installed one.
+pub fn run_pin(args: &[String]) -> i32 {
+    let mut version = None;
+    let mut base_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--version" => version = args.next().cloned(),
+            _ if base_path.is_none() && !arg.starts_with('-') => base_path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                version = None;
+                break;
+            }
+        }
+    }
+    let Some(version) = version else {
+        eprintln!("Usage: DreamioUpdater pin --version <version> [<install dir>]");
+        return 2;
+    };
+    let base_path = base_path.unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+
+    let info = match get_version_info(&base_path) {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!("Failed to read version info in {}: {}", base_path.display(), e);
+            return 1;
+        }
+    };
+    if version != info.version_code && version != info.version_string {
+        eprintln!(
+            "The installed version is {} ({}), not {}. Only the installed version can be pinned.",
+            info.version_string, info.version_code, version
+        );
+        return 1;
+    }
+    // Pinning halfway would leave a mix of two versions frozen in place.
+    if base_path.join("update_state.json").exists() || base_path.join("update.zip").exists() {
+        eprintln!("An update is waiting to be installed. Run the updater once to finish it before pinning.");
+        return 1;
+    }
+
+    let pinned_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let pin = json!({ "version": info.version_code, "pinnedAt": pinned_at });
+    match write(&base_path, &pin) {
+        Ok(()) => {
+            println!(
+                "Pinned {} to version {} ({}). Updates are paused until `DreamioUpdater unpin`.",
+                base_path.display(),
+                info.version_string,
+                info.version_code
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", PIN_FILE_NAME, e);
+            1
+        }
+    }
+}
+
+/// `unpin [<install dir>]` removes the pin; the next start updates as usual.
+pub fn run_unpin(args: &[String]) -> i32 {
+    let base_path = match args {
+        [] => env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        [dir] if !dir.starts_with('-') => PathBuf::from(dir),
+        _ => {
+            eprintln!("Usage: DreamioUpdater unpin [<install dir>]");
+            return 2;
+        }
+    };
+    match fs::remove_file(base_path.join(PIN_FILE_NAME)) {
+        Ok(()) => {
+            println!("Unpinned {}. The next start updates the game as usual.", base_path.display());
+            0
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("{} is not pinned.", base_path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to remove {}: {}", PIN_FILE_NAME, e);
+            1
+        }
+    }
+}
+
+fn write(base_path: &Path, pin: &Value) -> io::Result<()> {
+    fs::write(base_path.join(PIN_FILE_NAME), serde_json::to_string_pretty(pin)?)
+}
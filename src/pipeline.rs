@@ -0,0 +1,1535 @@
+//! The update is run as a pipeline of named steps sharing an `UpdateContext`. Steps can be inserted
+//! before or after the standard ones, e.g. to run extra work around closing the game or applying
+//! the update, without touching the rest of the flow.
+//!
+//! Programs embedding the updater build the same pipeline with `UpdatePipeline::for_install` and
+//! add their own steps, e.g. re-activating DRM before `launch` or syncing saves after `commit`:
+//!
+//! ```no_run
+//! use dreamio_updater::pipeline::{UpdateContext, UpdatePipeline, UpdateStep};
+//! use std::ops::ControlFlow;
+//!
+//! struct ActivateDrm;
+//!
+//! impl UpdateStep for ActivateDrm {
+//!     fn name(&self) -> &'static str {
+//!         "activate-drm"
+//!     }
+//!
+//!     fn run(&mut self, _context: &mut UpdateContext) -> ControlFlow<()> {
+//!         ControlFlow::Continue(())
+//!     }
+//! }
+//!
+//! let (sender, _messages) = crossbeam_channel::unbounded();
+//! let mut context = UpdateContext::new(sender, "Dreamio".into(), Default::default());
+//! let _ = UpdatePipeline::for_install(&context)
+//!     .before("launch", ActivateDrm)
+//!     .build()
+//!     .run(&mut context);
+//! ```
+
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use std::env;
+use std::fs;
+use std::ops::ControlFlow;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{PidExt, ProcessExt, System, SystemExt};
+
+use crate::backup;
+use crate::bundle;
+use crate::config::Config;
+use crate::error::{ErrorCategory, UpdaterError};
+use crate::hashing;
+use crate::history;
+use crate::interrupt;
+use crate::lock::{self, UpdateLock};
+use crate::manifest::{self, Artifact, RemoteManifest, UpdatePlan};
+use crate::notes;
+use crate::offline;
+use crate::peercache;
+use crate::platform::{self, is_network_path};
+use crate::plugins;
+use crate::preload;
+use crate::resume;
+use crate::rollback;
+use crate::session;
+use crate::{
+    apply_patches_sequentially, apply_update, channel_url, cleanup, download_and_apply_update, download_archive,
+    downloads_url, format_bytes, format_timestamp, get_latest_update_url, get_version_info, http, install_vcredist,
+    minimum_version_notice, offline_status, prefetch_path, quick_integrity_check, remove_prefetched, report_failure,
+    request_updater_restart, set_local_channel, telemetry, BackgroundDownload, Options, UpdateMessage, CHANNELS,
+    DEFAULT_CHANNEL, PREFETCH_DIR,
+};
+
+pub trait UpdateStep: Send {
+    /// Identifies the step so other steps can be inserted around it.
+    fn name(&self) -> &'static str;
+
+    /// Breaking stops the pipeline. The step reports why before breaking.
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()>;
+}
+
+/// What the check step found out about available updates.
+pub enum CheckResult {
+    Pending,
+    Manifest(Box<RemoteManifest>),
+    /// The server has no manifest and only publishes single-step patches.
+    Legacy,
+    /// The server could not be reached and the installed version is launched as is.
+    Offline,
+    /// A plugin postponed the update and the installed version is launched as is.
+    Declined,
+}
+
+pub struct UpdateContext {
+    pub sender: Sender<UpdateMessage>,
+    pub target_path: PathBuf,
+    pub options: Options,
+    pub update_zip_path: PathBuf,
+    pub is_initial_install: bool,
+    pub is_network_install: bool,
+    /// Set by the channel step.
+    pub channel: String,
+    /// Set by the check step.
+    pub current_version: String,
+    pub check: CheckResult,
+    /// Set by the plan step: the update chosen and the archives it installs, in order. `None` when
+    /// there is nothing to install now.
+    pub plan: Option<UpdatePlan>,
+    pub archives: Vec<(String, Artifact)>,
+    /// Archives of the same chain an interrupted update already installed.
+    pub archives_installed: usize,
+    /// Where each archive is and its hash. Set by the download step for the first archive, the
+    /// stage step adds the rest as they are downloaded.
+    pub downloads: Vec<(PathBuf, String)>,
+    /// Set by the close-game step, restarted after the update.
+    pub closed_programs: Option<platform::ClosedPrograms>,
+    /// Held until the update finishes.
+    _update_lock: Option<UpdateLock>,
+}
+
+impl UpdateContext {
+    pub fn new(sender: Sender<UpdateMessage>, target_path: PathBuf, options: Options) -> Self {
+        Self {
+            update_zip_path: target_path.join("update.zip"),
+            is_initial_install: !target_path.join("version.json").exists(),
+            is_network_install: is_network_path(&target_path),
+            channel: DEFAULT_CHANNEL.to_string(),
+            current_version: String::new(),
+            check: CheckResult::Pending,
+            plan: None,
+            archives: Vec::new(),
+            archives_installed: 0,
+            downloads: Vec::new(),
+            closed_programs: None,
+            _update_lock: None,
+            sender,
+            target_path,
+            options,
+        }
+    }
+}
+
+pub struct UpdatePipeline {
+    steps: Vec<Box<dyn UpdateStep>>,
+}
+
+impl UpdatePipeline {
+    /// The steps every update runs: taking the lock, waiting for a story session to end, closing
+    /// the game, choosing the channel, checking for updates, planning, downloading, verifying,
+    /// staging and committing them, queueing the preload of the next version or waiting for it to
+    /// unlock, checking the result, restarting the programs closed for the update and handing the
+    /// game over to be launched.
+    pub fn standard() -> UpdatePipelineBuilder {
+        UpdatePipelineBuilder { steps: Vec::new() }
+            .step(LockStep)
+            .step(SessionStep)
+            .step(CloseGameStep)
+            .step(ChannelStep)
+            .step(CheckStep)
+            .step(PlanStep)
+            .step(DownloadStep)
+            .step(VerifyStep)
+            .step(StageStep)
+            .step(CommitStep)
+            .step(PreloadStep)
+            .step(ReleaseStep)
+            .step(SelfCheckStep)
+            .step(RestartProgramsStep)
+            .step(LaunchStep)
+    }
+
+    /// The standard steps and those the install in `context` needs: finishing an interrupted
+    /// update, installing the Visual C++ runtime with the game, plugins and save sync.
+    pub fn for_install(context: &UpdateContext) -> UpdatePipelineBuilder {
+        let mut pipeline = Self::standard();
+        if context.update_zip_path.exists() {
+            pipeline = pipeline.before("channel", ResumeStep);
+        }
+        if context.is_initial_install && cfg!(windows) {
+            pipeline = pipeline.after("commit", RuntimeStep);
+        }
+        if plugins::load(&context.target_path) {
+            pipeline = pipeline.after("check", PluginStep);
+        }
+        if let Some(command) = Config::load(&context.target_path).save_sync_command {
+            pipeline = pipeline
+                .before("close-game", SaveSyncStep::before_update(command.clone()))
+                .after("commit", SaveSyncStep::after_update(command));
+        }
+        pipeline
+    }
+
+    /// The steps installing what the check step found, for steps that find a newer version later.
+    fn install() -> UpdatePipelineBuilder {
+        UpdatePipelineBuilder { steps: Vec::new() }
+            .step(PlanStep)
+            .step(DownloadStep)
+            .step(VerifyStep)
+            .step(StageStep)
+            .step(CommitStep)
+    }
+
+    pub fn run(mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        for step in &mut self.steps {
+            log::debug!("Running update step {}", step.name());
+            if step.run(context).is_break() {
+                log::info!("Update stopped in step {}", step.name());
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+pub struct UpdatePipelineBuilder {
+    steps: Vec<Box<dyn UpdateStep>>,
+}
+
+impl UpdatePipelineBuilder {
+    pub fn step(mut self, step: impl UpdateStep + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Inserts `step` before the step called `name`, or at the end when there is none.
+    pub fn before(mut self, name: &str, step: impl UpdateStep + 'static) -> Self {
+        let index = self.position(name).unwrap_or(self.steps.len());
+        self.steps.insert(index, Box::new(step));
+        self
+    }
+
+    /// Inserts `step` after the step called `name`, or at the end when there is none.
+    pub fn after(mut self, name: &str, step: impl UpdateStep + 'static) -> Self {
+        let index = self.position(name).map(|i| i + 1).unwrap_or(self.steps.len());
+        self.steps.insert(index, Box::new(step));
+        self
+    }
+
+    pub fn build(self) -> UpdatePipeline {
+        UpdatePipeline { steps: self.steps }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.steps.iter().position(|step| step.name() == name)
+    }
+}
+
+pub struct LockStep;
+
+impl UpdateStep for LockStep {
+    fn name(&self) -> &'static str {
+        "lock"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let sender = &context.sender;
+        if context.is_network_install {
+            sender
+                .send(UpdateMessage::Log(format!(
+                    "The game is installed on a network share. Updates should only be run from one computer at a time; this update is running on \"{}\".",
+                    lock::host_name()
+                )))
+                .unwrap();
+        }
+
+        match UpdateLock::acquire(&context.target_path) {
+            Ok(update_lock) => {
+                context._update_lock = Some(update_lock);
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                let category = ErrorCategory::of(&e);
+                sender
+                    .send(UpdateMessage::Error(
+                        format!("Failed to start update: {}", e),
+                        category,
+                        None,
+                    ))
+                    .unwrap();
+                sender.send(UpdateMessage::UpdateFailed(category)).unwrap();
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+const SESSION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Unattended updates wait for a story session in progress to end instead of closing the game
+/// mid-story. When the player confirmed closing the game, the update goes ahead right away.
+pub struct SessionStep;
+
+impl UpdateStep for SessionStep {
+    fn name(&self) -> &'static str {
+        "session"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        // The maintenance countdown already gave players time to finish.
+        if !context.options.assume_yes || context.options.maintenance || !session::is_session_active(&context.target_path) {
+            return ControlFlow::Continue(());
+        }
+        let sender = &context.sender;
+        sender
+            .send(UpdateMessage::Status("Waiting for the current story to end...".to_string()))
+            .unwrap();
+        sender
+            .send(UpdateMessage::Log(
+                "A story session is in progress. The update will start once it ends.".to_string(),
+            ))
+            .unwrap();
+        let start = Instant::now();
+        while session::is_session_active(&context.target_path) {
+            // Cancel stops the wait too, nothing was changed yet.
+            if let Err(e) = interrupt::check() {
+                report_failure(sender, &context.target_path, ErrorCategory::of(&e), e.to_string());
+                return ControlFlow::Break(());
+            }
+            thread::sleep(SESSION_POLL_INTERVAL);
+        }
+        log::info!("Story session ended after {:?}", start.elapsed());
+        sender
+            .send(UpdateMessage::Status("Checking for updates...".to_string()))
+            .unwrap();
+        ControlFlow::Continue(())
+    }
+}
+
+/// Closes every program holding game files through the Windows Restart Manager, which also
+/// catches overlays and tools, then kills the build's processes that are still running by name.
+pub struct CloseGameStep;
+
+impl UpdateStep for CloseGameStep {
+    fn name(&self) -> &'static str {
+        "close-game"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let sender = &context.sender;
+        // On shared computers, another user's game is never closed; the update waits for them
+        // instead. Maintenance updates warned every session beforehand.
+        if !context.options.maintenance && game_runs_in_other_session(&context.target_path) {
+            sender
+                .send(UpdateMessage::Status(
+                    "Another user on this computer is playing. The update waits until they quit.".to_string(),
+                ))
+                .unwrap();
+            sender
+                .send(UpdateMessage::Log(
+                    "The game is running in another user's session, so it is not closed. Starting the installed version; the update is installed once nobody else is playing."
+                        .to_string(),
+                ))
+                .unwrap();
+            sender.send(UpdateMessage::UpdateComplete).unwrap();
+            return ControlFlow::Break(());
+        }
+        match platform::close_programs_using(&installed_files(&context.target_path)) {
+            Ok(Some(closed)) => {
+                sender
+                    .send(UpdateMessage::Log(format!(
+                        "Closed programs using game files: {}.",
+                        closed.names().join(", ")
+                    )))
+                    .unwrap();
+                context.closed_programs = Some(closed);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Could not close programs using game files: {}", e),
+        }
+
+        let mut system = System::new();
+        system.refresh_processes();
+
+        let mut pids_to_kill: Vec<_> = manifest::game_executables(&context.target_path)
+            .processes
+            .iter()
+            .flat_map(|name| system.processes_by_name(name).map(|p| p.pid()))
+            .collect();
+        pids_to_kill.sort();
+        pids_to_kill.dedup();
+
+        if pids_to_kill.is_empty() {
+            sender
+                .send(UpdateMessage::Log(
+                    "No running game process found.".to_string(),
+                ))
+                .unwrap();
+            return ControlFlow::Continue(());
+        }
+
+        sender
+            .send(UpdateMessage::Log(
+                "Game process found. Shutting down...".to_string(),
+            ))
+            .unwrap();
+        for pid in &pids_to_kill {
+            if let Some(process) = system.process(*pid) {
+                if !process.kill() {
+                    sender
+                        .send(UpdateMessage::Error(
+                            format!("Failed to send kill signal to process {}", pid),
+                            ErrorCategory::Other,
+                            None,
+                        ))
+                        .unwrap();
+                }
+            }
+        }
+
+        sender
+            .send(UpdateMessage::Log(
+                "Waiting for game process to fully terminate...".to_string(),
+            ))
+            .unwrap();
+        loop {
+            system.refresh_processes();
+            let any_process_alive = pids_to_kill
+                .iter()
+                .any(|pid| system.process(*pid).is_some());
+            if !any_process_alive {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        sender
+            .send(UpdateMessage::Log(
+                "Game process terminated successfully.".to_string(),
+            ))
+            .unwrap();
+        ControlFlow::Continue(())
+    }
+}
+
+/// Whether the game runs in a login session of another user, e.g. through fast user switching or
+/// Remote Desktop.
+fn game_runs_in_other_session(target_path: &Path) -> bool {
+    let mut system = System::new();
+    system.refresh_processes();
+    let other_session = manifest::game_executables(target_path)
+        .processes
+        .iter()
+        .flat_map(|name| system.processes_by_name(name).map(|p| p.pid().as_u32()))
+        .any(platform::in_other_session);
+    other_session
+}
+
+/// Files of the install as recorded by the last update, and the programs that start the game.
+fn installed_files(target_path: &Path) -> Vec<PathBuf> {
+    let manifest = fs::read_to_string(target_path.join("install_manifest.txt")).unwrap_or_default();
+    let mut files: Vec<PathBuf> = manifest
+        .lines()
+        .filter(|name| !name.is_empty() && !name.ends_with('/') && !name.ends_with(".delete"))
+        .map(|name| target_path.join(name.trim_end_matches(".patch")))
+        .filter(|path| path.is_file())
+        .collect();
+    let launch = manifest::game_executables(target_path).launch;
+    let executables = [Some(platform::GAME_EXECUTABLE), launch.first().map(|p| p.as_str())];
+    for executable in executables.into_iter().flatten() {
+        let path = target_path.join(executable);
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Restarts the programs the close-game step closed, once the update is done.
+pub struct RestartProgramsStep;
+
+impl UpdateStep for RestartProgramsStep {
+    fn name(&self) -> &'static str {
+        "restart-programs"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        if let Some(closed) = context.closed_programs.take() {
+            if let Err(e) = closed.restart() {
+                log::warn!("Could not restart the programs closed for the update: {}", e);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Finishes applying an `update.zip` left behind by an interrupted update.
+pub struct ResumeStep;
+
+impl UpdateStep for ResumeStep {
+    fn name(&self) -> &'static str {
+        "resume"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let sender = &context.sender;
+        match apply_update(&context.update_zip_path, &context.target_path, sender) {
+            Ok(summary) => {
+                cleanup(&context.target_path);
+                if summary.updater_replaced {
+                    request_updater_restart(sender);
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                report_failure(
+                    sender,
+                    &context.target_path,
+                    ErrorCategory::of(&e),
+                    format!("Failed to apply update: {}", e),
+                );
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+/// Picks the update channel and downloads the full game on first install or when switching.
+pub struct ChannelStep;
+
+impl UpdateStep for ChannelStep {
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let sender = &context.sender;
+        let target_path = &context.target_path;
+        let local_channel = get_version_info(target_path).ok().map(|info| info.channel);
+        let channel = context
+            .options
+            .channel
+            .clone()
+            .or_else(|| local_channel.clone())
+            .or_else(|| bundle::embedded().map(|bundle| bundle.channel.clone()))
+            .unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+        if !CHANNELS.contains(&channel.as_str()) {
+            sender
+                .send(UpdateMessage::Error(
+                    format!(
+                        "Unknown update channel \"{}\". Available channels: {}.",
+                        channel,
+                        CHANNELS.join(", ")
+                    ),
+                    ErrorCategory::Other,
+                    None,
+                ))
+                .unwrap();
+            sender.send(UpdateMessage::UpdateFailed(ErrorCategory::Other)).unwrap();
+            return ControlFlow::Break(());
+        }
+        context.channel = channel.clone();
+
+        // Patch chains of different channels diverge, so switching channels requires a full download.
+        let switching_channel = local_channel
+            .as_ref()
+            .map(|local| *local != channel)
+            .unwrap_or(false);
+
+        if target_path.join("version.json").exists() && !switching_channel {
+            return ControlFlow::Continue(());
+        }
+        if switching_channel {
+            sender
+                .send(UpdateMessage::Log(format!(
+                    "Switching from the {} channel to the {} channel. Downloading the full game...",
+                    local_channel.unwrap_or_default(),
+                    channel
+                )))
+                .unwrap();
+        } else {
+            sender
+                .send(UpdateMessage::Log("Downloading the game...".to_string()))
+                .unwrap();
+        }
+        let installed_version = get_version_info(target_path).ok().map(|info| info.version_code);
+        // Installers made by `make-installer` start from the version they pin.
+        let pinned = bundle::embedded().filter(|bundle| {
+            installed_version.is_none() && bundle.channel == channel && context.options.from_file.is_none()
+        });
+        let pinned_result = pinned.and_then(|bundle| {
+            telemetry::record_transition(&channel, None, &bundle.version);
+            bundle.install(&context.update_zip_path, target_path, sender).transpose()
+        });
+        let result = match (pinned_result, &context.options.from_file) {
+            (Some(result), _) => result,
+            (None, Some(from)) => offline::install(from, &channel, &context.update_zip_path, target_path, sender),
+            (None, None) => {
+                telemetry::record_transition(&channel, installed_version.as_deref(), "latest");
+                match http::block_on(get_latest_update_url(&channel)) {
+                    Ok(latest_url) => download_and_apply_update(&latest_url, &context.update_zip_path, target_path, sender),
+                    Err(e) => {
+                        // A firewall's block page is kept for the player to open, it often names the product.
+                        let response_body = match UpdaterError::find(e.as_ref()) {
+                            Some(UpdaterError::HtmlResponse(body)) => Some(body.clone()),
+                            _ => None,
+                        };
+                        let category = ErrorCategory::of(e.as_ref());
+                        sender
+                            .send(UpdateMessage::Error(
+                                format!("Failed to get latest update URL: {}", e),
+                                category,
+                                response_body,
+                            ))
+                            .unwrap();
+                        sender.send(UpdateMessage::UpdateFailed(category)).unwrap();
+                        cleanup(target_path);
+                        return ControlFlow::Break(());
+                    }
+                }
+            }
+        };
+        match result {
+            Ok(summary) => {
+                if let Err(e) = set_local_channel(target_path, &channel) {
+                    sender
+                        .send(UpdateMessage::Error(
+                            format!("Failed to record update channel: {}", e),
+                            ErrorCategory::of(e.as_ref()),
+                            None,
+                        ))
+                        .unwrap();
+                }
+                if summary.updater_replaced {
+                    request_updater_restart(sender);
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                report_failure(
+                    sender,
+                    target_path,
+                    ErrorCategory::of(e.as_ref()),
+                    format!("Failed to download or apply update: {}", e),
+                );
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+/// Reads the installed version and fetches the channel's manifest, or reads the archives given
+/// with `--from-file`.
+pub struct CheckStep;
+
+impl UpdateStep for CheckStep {
+    fn name(&self) -> &'static str {
+        "check"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let sender = &context.sender;
+        let target_path = &context.target_path;
+        let current_version = match get_version_info(target_path) {
+            Ok(info) => info.version_code,
+            Err(e) => {
+                report_failure(
+                    sender,
+                    target_path,
+                    ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version),
+                    format!("Failed to read version info: {}", e),
+                );
+                return ControlFlow::Break(());
+            }
+        };
+
+        if let Some(from) = &context.options.from_file {
+            context.check = match offline::local_manifest(target_path, &context.channel, from, Some(&current_version)) {
+                Ok(local_manifest) => CheckResult::Manifest(Box::new(local_manifest)),
+                Err(e) => {
+                    report_failure(
+                        sender,
+                        target_path,
+                        ErrorCategory::of(e.as_ref()),
+                        format!("Failed to read the update in {}: {}", from.display(), e),
+                    );
+                    return ControlFlow::Break(());
+                }
+            };
+            context.current_version = current_version;
+            return ControlFlow::Continue(());
+        }
+
+        context.check = match manifest::fetch_manifest(&context.channel, target_path) {
+            Ok(Some(remote_manifest)) => {
+                let remote_manifest = manifest::with_unlocked_preload(remote_manifest);
+                telemetry::record_transition(&context.channel, Some(&current_version), &remote_manifest.latest);
+                CheckResult::Manifest(Box::new(remote_manifest))
+            }
+            Ok(None) => CheckResult::Legacy,
+            // Without a network the installed version is launched as long as it looks playable.
+            Err(e) if UpdaterError::is_network(e.as_ref()) => {
+                if let Err(problem) = quick_integrity_check(target_path) {
+                    report_failure(
+                        sender,
+                        target_path,
+                        ErrorCategory::Network,
+                        format!("Could not reach the update server ({}) and the installed game is incomplete: {}", e, problem),
+                    );
+                    return ControlFlow::Break(());
+                }
+                let cached = manifest::load_cached_manifest(target_path, &context.channel);
+                if let Some(cached) = &cached {
+                    if manifest::is_below_minimum(&cached.manifest, &current_version) {
+                        report_failure(
+                            sender,
+                            target_path,
+                            ErrorCategory::Network,
+                            format!(
+                                "Could not reach the update server ({}). {} Please connect to the internet and try again.",
+                                e,
+                                minimum_version_notice(&cached.manifest, &current_version)
+                            ),
+                        );
+                        return ControlFlow::Break(());
+                    }
+                }
+                let note = match cached {
+                    Some(cached) => offline_status(&cached, &current_version),
+                    None => "Couldn't check for updates. Launching the installed version.".to_string(),
+                };
+                sender.send(UpdateMessage::Log(note)).unwrap();
+                CheckResult::Offline
+            }
+            Err(e) => {
+                report_failure(
+                    sender,
+                    target_path,
+                    ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version),
+                    format!("Failed to get update manifest: {}", e),
+                );
+                return ControlFlow::Break(());
+            }
+        };
+        context.current_version = current_version;
+        ControlFlow::Continue(())
+    }
+}
+
+/// Lets policy plugins postpone the update or pick the mirror it is downloaded from. Only part of
+/// the pipeline when plugins are installed.
+pub struct PluginStep;
+
+impl UpdateStep for PluginStep {
+    fn name(&self) -> &'static str {
+        "plugins"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let CheckResult::Manifest(remote_manifest) = &mut context.check else {
+            return ControlFlow::Continue(());
+        };
+        let plan = match manifest::plan_update(remote_manifest, &context.current_version, context.options.prefer_full) {
+            Ok(UpdatePlan::UpToDate) | Err(_) => return ControlFlow::Continue(()),
+            Ok(plan) => plan,
+        };
+        if !manifest::is_mandatory(remote_manifest, &context.current_version)
+            && !plugins::should_update(
+                &context.current_version,
+                &remote_manifest.latest,
+                &context.channel,
+                plan.download_size(),
+            )
+        {
+            context
+                .sender
+                .send(UpdateMessage::Log(format!(
+                    "A plugin postponed the update to version {}.",
+                    remote_manifest.latest
+                )))
+                .unwrap();
+            context.check = CheckResult::Declined;
+            return ControlFlow::Continue(());
+        }
+
+        if !remote_manifest.mirrors.is_empty() {
+            // Mirrors have the same layout, with the channel folders below their root.
+            let channel_url = channel_url(&context.channel);
+            let folder = channel_url.strip_prefix(downloads_url()).unwrap_or("");
+            let mut servers = vec![downloads_url().to_string()];
+            servers.extend(remote_manifest.mirrors.iter().cloned());
+            if let Some(index) = plugins::pick_mirror(&servers).filter(|&index| index > 0) {
+                log::info!("A plugin picked the mirror {}", servers[index]);
+                manifest::rebase(remote_manifest, &channel_url, &format!("{}{}", servers[index], folder));
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Chooses the archives that bring the game up to date from what the check step found. Nothing
+/// is downloaded yet, so the player can still postpone a big update.
+pub struct PlanStep;
+
+impl UpdateStep for PlanStep {
+    fn name(&self) -> &'static str {
+        "plan"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        context.plan = None;
+        context.archives.clear();
+        context.archives_installed = 0;
+        context.downloads.clear();
+        let CheckResult::Manifest(remote_manifest) = &context.check else {
+            return ControlFlow::Continue(());
+        };
+        let sender = &context.sender;
+        let target_path = &context.target_path;
+        let current_version = context.current_version.as_str();
+        let plan = match manifest::plan_update(remote_manifest, current_version, context.options.prefer_full) {
+            Ok(plan) => plan,
+            Err(e) => {
+                report_failure(
+                    sender,
+                    target_path,
+                    ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version),
+                    format!("Failed to plan update: {}", e),
+                );
+                return ControlFlow::Break(());
+            }
+        };
+
+        if manifest::is_below_minimum(remote_manifest, current_version) {
+            sender
+                .send(UpdateMessage::Log(minimum_version_notice(remote_manifest, current_version)))
+                .unwrap();
+        }
+
+        let archives: Vec<(String, Artifact)> = match plan {
+            UpdatePlan::UpToDate => {
+                // An update interrupted right after installing its last archive.
+                resume::finish(target_path);
+                sender
+                    .send(UpdateMessage::Log("The game is up to date.".to_string()))
+                    .unwrap();
+                return ControlFlow::Continue(());
+            }
+            UpdatePlan::Patches(ref edges) => {
+                sender
+                    .send(UpdateMessage::Log(format!(
+                        "Updating from version {} to version {}: {} patch(es), {} to download.",
+                        current_version,
+                        remote_manifest.latest,
+                        edges.len(),
+                        format_bytes(plan.download_size())
+                    )))
+                    .unwrap();
+                edges
+                    .iter()
+                    .map(|edge| (edge.to.clone(), edge.artifact.clone()))
+                    .collect()
+            }
+            UpdatePlan::Full(ref artifact) => {
+                sender
+                    .send(UpdateMessage::Log(format!(
+                        "Updating from version {} to version {}: downloading the full game ({}) instead of patches.",
+                        current_version,
+                        remote_manifest.latest,
+                        format_bytes(plan.download_size())
+                    )))
+                    .unwrap();
+                vec![(remote_manifest.latest.clone(), artifact.clone())]
+            }
+        };
+        if let Some(notes) = notes::for_update(remote_manifest, current_version) {
+            sender.send(UpdateMessage::ReleaseNotes(notes)).unwrap();
+        }
+
+        let download_size = plan.download_size();
+        let can_launch_anyway = Config::load(target_path)
+            .launch_anyway_above
+            .map(|ceiling| download_size > ceiling)
+            .unwrap_or(false);
+        if can_launch_anyway && !manifest::is_mandatory(remote_manifest, current_version) {
+            let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+            sender
+                .send(UpdateMessage::OfferLaunchAnyway(
+                    remote_manifest.latest.clone(),
+                    download_size,
+                    reply_sender,
+                ))
+                .unwrap();
+            if reply_receiver.recv().unwrap_or(false) {
+                sender
+                    .send(UpdateMessage::Log(
+                        "Update postponed. It will be downloaded in the background and installed next time."
+                            .to_string(),
+                    ))
+                    .unwrap();
+                sender.send(UpdateMessage::DeferUpdate(archives)).unwrap();
+                return ControlFlow::Continue(());
+            }
+        }
+
+        let installed = resume::start_chain(target_path, current_version, &archives);
+        if installed > 0 {
+            sender
+                .send(UpdateMessage::Log(format!(
+                    "Resuming the interrupted update: {} of {} archive(s) already installed.",
+                    installed,
+                    installed + archives.len()
+                )))
+                .unwrap();
+        }
+        context.plan = Some(plan);
+        context.archives = archives;
+        context.archives_installed = installed;
+        ControlFlow::Continue(())
+    }
+}
+
+/// Every archive is one download step followed by one apply step, and those an interrupted update
+/// installed count as done.
+fn report_archive_step(context: &UpdateContext, step: usize, description: String) {
+    let step_count = (context.archives_installed + context.archives.len()) * 2;
+    context
+        .sender
+        .send(UpdateMessage::Step(context.archives_installed * 2 + step, step_count, description))
+        .unwrap();
+}
+
+/// Downloads archive `index` of the plan into the prefetch folder, or keeps it when a postponed
+/// update or the background download already put it there. Returns where it is and its hash.
+fn fetch_archive(context: &UpdateContext, index: usize) -> Result<(PathBuf, String), Box<dyn std::error::Error>> {
+    let sender = &context.sender;
+    let (target_version, artifact) = &context.archives[index];
+    report_archive_step(context, index * 2 + 1, format!("downloading update to version {}", target_version));
+    sender
+        .send(UpdateMessage::Log(format!(
+            "Downloading update to version {}...",
+            target_version
+        )))
+        .unwrap();
+    let path = prefetch_path(&context.target_path, target_version, artifact);
+    // Kept unless it is damaged or was published anew since.
+    let prefetched = path
+        .exists()
+        .then(|| hashing::sha256_file(&path).ok())
+        .flatten()
+        .filter(|sha256| artifact.sha256.as_ref().is_none_or(|expected| expected == sha256));
+    let sha256 = match prefetched {
+        Some(sha256) => {
+            sender
+                .send(UpdateMessage::Log("Using the update already downloaded.".to_string()))
+                .unwrap();
+            sha256
+        }
+        None => {
+            fs::remove_file(&path).ok();
+            match peercache::fetch(artifact, &path, sender) {
+                Some(sha256) => sha256,
+                None => http::block_on(download_archive(&artifact.url, &path, &context.target_path, sender))?,
+            }
+        }
+    };
+    Ok((path, sha256))
+}
+
+/// Checks download `index` against the hash the manifest publishes. One that does not match is
+/// removed so the next attempt downloads it again; one that does is shared with other computers
+/// when `shareDownloads` is on.
+fn verify_download(context: &UpdateContext, index: usize) -> ControlFlow<()> {
+    let (target_version, artifact) = &context.archives[index];
+    let (path, sha256) = &context.downloads[index];
+    if artifact.sha256.as_ref().is_some_and(|expected| expected != sha256) {
+        fs::remove_file(path).ok();
+        let error = UpdaterError::Verification(
+            "the download does not match the hash published in the manifest".to_string(),
+        );
+        report_failure(
+            &context.sender,
+            &context.target_path,
+            ErrorCategory::of(&error),
+            format!("Error downloading update to version {}: {}", target_version, error),
+        );
+        return ControlFlow::Break(());
+    }
+    if Config::load(&context.target_path).share_downloads {
+        peercache::share(&context.target_path, artifact, path);
+    }
+    ControlFlow::Continue(())
+}
+
+/// Downloads the first planned archive into the prefetch folder. The rest of a patch chain are
+/// downloaded by the stage step, each in the background while the archive before it is installed.
+pub struct DownloadStep;
+
+impl UpdateStep for DownloadStep {
+    fn name(&self) -> &'static str {
+        "download"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        context.downloads.clear();
+        if context.archives.is_empty() {
+            return ControlFlow::Continue(());
+        }
+        let download = fs::create_dir_all(context.target_path.join(PREFETCH_DIR))
+            .map_err(|e| e.into())
+            .and_then(|_| fetch_archive(context, 0));
+        match download {
+            Ok(download) => context.downloads.push(download),
+            Err(e) => {
+                report_failure(
+                    &context.sender,
+                    &context.target_path,
+                    ErrorCategory::of(e.as_ref()),
+                    format!("Error downloading update: {}", e),
+                );
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Checks the downloads against the hashes the manifest publishes before they are installed.
+pub struct VerifyStep;
+
+impl UpdateStep for VerifyStep {
+    fn name(&self) -> &'static str {
+        "verify"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        (0..context.downloads.len()).try_for_each(|index| verify_download(context, index))
+    }
+}
+
+/// Installs the planned archives one after another and checks the version each one leads to.
+/// While one is installed, the next one downloads in the background. When one replaces the
+/// updater, the new updater is started to install the rest. Servers without a manifest only name
+/// the next patch, so theirs are downloaded and installed here one at a time.
+pub struct StageStep;
+
+impl UpdateStep for StageStep {
+    fn name(&self) -> &'static str {
+        "stage"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        if let CheckResult::Legacy = context.check {
+            return apply_patches_sequentially(&context.sender, &context.target_path, &context.update_zip_path);
+        }
+        let full = matches!(context.plan, Some(UpdatePlan::Full(_)));
+        let mut next_download: Option<BackgroundDownload> = None;
+        for index in 0..context.archives.len() {
+            if index == context.downloads.len() {
+                if let Some(download) = next_download.take() {
+                    if let Err(e) = download.finish(&context.sender) {
+                        context
+                            .sender
+                            .send(UpdateMessage::Log(format!("Background download failed: {}. Downloading again...", e)))
+                            .unwrap();
+                    }
+                }
+                match fetch_archive(context, index) {
+                    Ok(download) => context.downloads.push(download),
+                    Err(e) => {
+                        report_failure(
+                            &context.sender,
+                            &context.target_path,
+                            ErrorCategory::of(e.as_ref()),
+                            format!("Error downloading update: {}", e),
+                        );
+                        return ControlFlow::Break(());
+                    }
+                }
+                verify_download(context, index)?;
+            }
+
+            let sender = &context.sender;
+            let target_path = &context.target_path;
+            let update_zip_path = &context.update_zip_path;
+            let (target_version, artifact) = &context.archives[index];
+            resume::enter_step(target_path, context.archives_installed + index);
+            report_archive_step(context, index * 2 + 2, format!("applying update to version {}", target_version));
+            if let Some((next_version, next_artifact)) = context.archives.get(index + 1) {
+                next_download = BackgroundDownload::start(target_path, next_version, next_artifact);
+            }
+            if let Err(e) = fs::rename(&context.downloads[index].0, update_zip_path) {
+                report_failure(sender, target_path, ErrorCategory::of(&e), format!("Failed to apply update: {}", e));
+                return ControlFlow::Break(());
+            }
+            // Preloaded archives stay encrypted on disk until the released manifest gives their key.
+            if let Some(key) = &artifact.key {
+                sender
+                    .send(UpdateMessage::Status("Unlocking update...".to_string()))
+                    .unwrap();
+                if let Err(e) = preload::decrypt_in_place(update_zip_path, key) {
+                    report_failure(
+                        sender,
+                        target_path,
+                        ErrorCategory::of(&e),
+                        format!("Failed to unlock update: {}", e),
+                    );
+                    return ControlFlow::Break(());
+                }
+            }
+            let changes = history::archive_changes(update_zip_path, target_path, full);
+            let summary = match apply_update(update_zip_path, target_path, sender) {
+                Ok(summary) => summary,
+                Err(e) => {
+                    report_failure(
+                        sender,
+                        target_path,
+                        ErrorCategory::of(&e),
+                        format!("Failed to apply update: {}", e),
+                    );
+                    return ControlFlow::Break(());
+                }
+            };
+            cleanup(target_path);
+
+            match get_version_info(target_path) {
+                Ok(new_info) => {
+                    if new_info.version_code != *target_version {
+                        report_failure(
+                            sender,
+                            target_path,
+                            ErrorCategory::Version,
+                            format!(
+                                "Expected version {} after applying the update, found version {}",
+                                target_version, new_info.version_code
+                            ),
+                        );
+                        return ControlFlow::Break(());
+                    }
+                    match changes {
+                        Ok(changes) => history::record(target_path, target_version, &new_info.channel, changes),
+                        Err(e) => log::warn!("Failed to read the changes of version {}: {}", target_version, e),
+                    }
+                }
+                Err(e) => {
+                    report_failure(
+                        sender,
+                        target_path,
+                        ErrorCategory::of(e.as_ref()).or(ErrorCategory::Version),
+                        format!("Failed to read updated version info: {}", e),
+                    );
+                    return ControlFlow::Break(());
+                }
+            }
+
+            if summary.updater_replaced {
+                request_updater_restart(sender);
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Completes an update once all of its archives are installed: keeps the reverse patches for
+/// `rollback`, forgets the chain and removes the downloads no longer needed.
+pub struct CommitStep;
+
+impl UpdateStep for CommitStep {
+    fn name(&self) -> &'static str {
+        "commit"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let (Some(plan), CheckResult::Manifest(remote_manifest)) = (&context.plan, &context.check) else {
+            return ControlFlow::Continue(());
+        };
+        let target_path = &context.target_path;
+        match plan {
+            UpdatePlan::Patches(edges) => rollback::store(target_path, edges, &context.sender),
+            _ => rollback::clear(target_path),
+        }
+        resume::finish(target_path);
+        if Config::load(target_path).share_downloads {
+            peercache::prune(target_path, &context.archives);
+        }
+        remove_prefetched(target_path, &manifest::preload_downloads(remote_manifest));
+        context
+            .sender
+            .send(UpdateMessage::Log("Update complete. No more updates available.".to_string()))
+            .unwrap();
+        ControlFlow::Continue(())
+    }
+}
+
+/// Queues the encrypted archives of an unreleased version for download once the game is up to
+/// date, so they are already on disk when the version is released.
+pub struct PreloadStep;
+
+impl UpdateStep for PreloadStep {
+    fn name(&self) -> &'static str {
+        "preload"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let CheckResult::Manifest(remote_manifest) = &context.check else {
+            return ControlFlow::Continue(());
+        };
+        let Some(preload) = &remote_manifest.preload else {
+            return ControlFlow::Continue(());
+        };
+        // A postponed update is downloaded in the background instead.
+        if !is_on_latest(&context.target_path, remote_manifest) {
+            return ControlFlow::Continue(());
+        }
+
+        let downloads: Vec<_> = manifest::preload_downloads(remote_manifest)
+            .into_iter()
+            .filter(|(version, artifact)| !prefetch_path(&context.target_path, version, artifact).exists())
+            .collect();
+        let unlocks = match preload.release_at.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+            Some(release_at) => format!("It unlocks on {}.", format_timestamp(release_at.as_secs())),
+            None => "It unlocks when it is released.".to_string(),
+        };
+        let message = if downloads.is_empty() {
+            format!("Version {} is preloaded. {}", preload.latest, unlocks)
+        } else {
+            let size: u64 = downloads.iter().map(|(_, artifact)| artifact.size).sum();
+            format!(
+                "Preloading version {} ({}) in the background. {}",
+                preload.latest,
+                format_bytes(size),
+                unlocks
+            )
+        };
+        context.sender.send(UpdateMessage::Log(message)).unwrap();
+        if !downloads.is_empty() {
+            context.sender.send(UpdateMessage::DeferUpdate(downloads)).unwrap();
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Players starting the updater this close to the release of a preloaded version are offered to
+/// wait for it. Earlier, the installed version is launched as usual.
+const RELEASE_COUNTDOWN_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// How often and for how long the manifest is fetched again after the unlock time until the
+/// release is published.
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_secs(15);
+const RELEASE_POLL_LIMIT: Duration = Duration::from_secs(10 * 60);
+
+/// Counts down to the release of a fully preloaded version, then unlocks and applies it, so
+/// players who leave the updater running start playing the moment it is out. The player can stop
+/// waiting and play the installed version instead.
+pub struct ReleaseStep;
+
+impl UpdateStep for ReleaseStep {
+    fn name(&self) -> &'static str {
+        "release"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let CheckResult::Manifest(remote_manifest) = &context.check else {
+            return ControlFlow::Continue(());
+        };
+        let Some(preload) = &remote_manifest.preload else {
+            return ControlFlow::Continue(());
+        };
+        let Some(release_at) = preload.release_at else {
+            return ControlFlow::Continue(());
+        };
+        let now = SystemTime::now();
+        let remaining = release_at.duration_since(now).unwrap_or_default();
+        // Past the unlock time, the release is only waited for while it may still be on its way.
+        let overdue = now.duration_since(release_at).unwrap_or_default() > RELEASE_POLL_LIMIT;
+        let downloads = manifest::preload_downloads(remote_manifest);
+        let preloaded = !downloads.is_empty()
+            && downloads
+                .iter()
+                .all(|(version, artifact)| prefetch_path(&context.target_path, version, artifact).exists());
+        if remaining > RELEASE_COUNTDOWN_WINDOW
+            || overdue
+            || !preloaded
+            || !is_on_latest(&context.target_path, remote_manifest)
+        {
+            return ControlFlow::Continue(());
+        }
+        let version = preload.latest.clone();
+        let installed = remote_manifest.latest.clone();
+        let sender = &context.sender;
+
+        let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+        sender
+            .send(UpdateMessage::ReleaseCountdown(version.clone(), release_at, reply_sender))
+            .unwrap();
+        match reply_receiver.recv_timeout(remaining) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(_) | Err(RecvTimeoutError::Disconnected) => return ControlFlow::Continue(()),
+        }
+
+        sender
+            .send(UpdateMessage::Status(format!("Unlocking version {}...", version)))
+            .unwrap();
+        let deadline = Instant::now() + RELEASE_POLL_LIMIT;
+        let released = loop {
+            match manifest::fetch_manifest(&context.channel, &context.target_path) {
+                Ok(Some(fetched)) => {
+                    let fetched = manifest::with_unlocked_preload(fetched);
+                    if fetched.latest == version {
+                        break Some(fetched);
+                    }
+                }
+                Ok(None) => break None,
+                Err(e) => log::warn!("Failed to fetch the manifest for the release of version {}: {}", version, e),
+            }
+            if Instant::now() >= deadline {
+                break None;
+            }
+            thread::sleep(RELEASE_POLL_INTERVAL);
+        };
+        let Some(released) = released else {
+            sender
+                .send(UpdateMessage::Log(format!(
+                    "Version {} is not released yet. Launching the installed version.",
+                    version
+                )))
+                .unwrap();
+            return ControlFlow::Continue(());
+        };
+
+        context.current_version = installed;
+        context.check = CheckResult::Manifest(Box::new(released));
+        UpdatePipeline::install().build().run(context)
+    }
+}
+
+fn is_on_latest(target_path: &Path, remote_manifest: &RemoteManifest) -> bool {
+    let installed = get_version_info(target_path).map(|info| info.version_code);
+    installed.ok().as_deref() == Some(remote_manifest.latest.as_str())
+}
+
+/// Self-checks taking longer than this count as failed.
+const SELF_CHECK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Runs the manifest's `selfCheck` command on the updated install without a window and rolls the
+/// update back when it fails, before the player ever launches the broken build. Otherwise the
+/// backup of the previous build is discarded. A first install has nothing to roll back to.
+pub struct SelfCheckStep;
+
+impl UpdateStep for SelfCheckStep {
+    fn name(&self) -> &'static str {
+        "self-check"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let self_check = match &context.check {
+            CheckResult::Manifest(remote_manifest) => remote_manifest.self_check.clone(),
+            CheckResult::Legacy => Vec::new(),
+            // Nothing was applied, so a backup left by an interrupted update is kept for it.
+            CheckResult::Pending | CheckResult::Offline | CheckResult::Declined => return ControlFlow::Continue(()),
+        };
+        let sender = &context.sender;
+        let target_path = &context.target_path;
+        let Some((program, args)) = self_check.split_first() else {
+            backup::discard(target_path);
+            return ControlFlow::Continue(());
+        };
+        if context.is_initial_install || !backup::exists(target_path) {
+            backup::discard(target_path);
+            return ControlFlow::Continue(());
+        }
+
+        sender
+            .send(UpdateMessage::Status("Checking the updated game...".to_string()))
+            .unwrap();
+        let mut command = Command::new(target_path.join(program));
+        command.args(args).current_dir(target_path);
+        platform::hide_window(&mut command);
+        let result = command
+            .spawn()
+            .and_then(|mut child| wait_with_timeout(&mut child, SELF_CHECK_TIMEOUT));
+        let problem = match result {
+            Ok(Some(status)) if status.success() => None,
+            Ok(Some(status)) => Some(format!("it returned {}", status)),
+            Ok(None) => Some("it timed out".to_string()),
+            Err(e) => Some(format!("it could not be started: {}", e)),
+        };
+        let Some(problem) = problem else {
+            sender
+                .send(UpdateMessage::Log("The updated game passed its self-check.".to_string()))
+                .unwrap();
+            backup::discard(target_path);
+            return ControlFlow::Continue(());
+        };
+
+        sender
+            .send(UpdateMessage::Status("Rolling back the update...".to_string()))
+            .unwrap();
+        let message = match backup::restore(target_path) {
+            Ok(()) => format!(
+                "The updated game failed its self-check: {}. The update was rolled back to version {}.",
+                problem, context.current_version
+            ),
+            Err(e) => format!(
+                "The updated game failed its self-check: {}. Rolling the update back failed: {}. Please reinstall the game.",
+                problem, e
+            ),
+        };
+        report_failure(sender, target_path, ErrorCategory::Other, message);
+        ControlFlow::Break(())
+    }
+}
+
+/// Waits for `child` to exit, killing it after `timeout`. Returns `None` when it timed out.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() > timeout {
+            child.kill().ok();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Installs the Visual C++ runtime the game needs after a first install.
+pub struct RuntimeStep;
+
+impl UpdateStep for RuntimeStep {
+    fn name(&self) -> &'static str {
+        "runtime"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        // Installers cannot be run reliably from a network share, so download to the local temp directory.
+        let vcredist_dir = if context.is_network_install {
+            env::temp_dir()
+        } else {
+            context.target_path.clone()
+        };
+        install_vcredist(&vcredist_dir, &context.sender);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Save syncs taking longer than this are stopped so a stuck sync cannot block updates forever.
+const SAVE_SYNC_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Runs the game's cloud save sync command. The phase is passed in the `DREAMIO_UPDATE_PHASE`
+/// environment variable as `before` or `after`. A failed sync is reported but does not stop the
+/// update.
+pub struct SaveSyncStep {
+    after_update: bool,
+    command: Vec<String>,
+}
+
+impl SaveSyncStep {
+    pub fn before_update(command: Vec<String>) -> Self {
+        Self {
+            after_update: false,
+            command,
+        }
+    }
+
+    pub fn after_update(command: Vec<String>) -> Self {
+        Self {
+            after_update: true,
+            command,
+        }
+    }
+}
+
+impl UpdateStep for SaveSyncStep {
+    fn name(&self) -> &'static str {
+        if self.after_update {
+            "save-sync-after"
+        } else {
+            "save-sync-before"
+        }
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        let sender = &context.sender;
+        let Some((program, args)) = self.command.split_first() else {
+            return ControlFlow::Continue(());
+        };
+        sender
+            .send(UpdateMessage::Log("Syncing cloud saves...".to_string()))
+            .unwrap();
+
+        let result = Command::new(context.target_path.join(program))
+            .args(args)
+            .current_dir(&context.target_path)
+            .env("DREAMIO_UPDATE_PHASE", if self.after_update { "after" } else { "before" })
+            .spawn()
+            .and_then(|mut child| wait_with_timeout(&mut child, SAVE_SYNC_TIMEOUT));
+        let problem = match result {
+            Ok(Some(status)) if status.success() => None,
+            Ok(Some(status)) => Some(format!("the sync command returned {}", status)),
+            Ok(None) => Some("the sync command timed out".to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        match problem {
+            None => sender
+                .send(UpdateMessage::Log("Cloud saves synced.".to_string()))
+                .unwrap(),
+            Some(problem) => sender
+                .send(UpdateMessage::Error(
+                    format!("Cloud save sync failed: {}. Continuing with the update.", problem),
+                    ErrorCategory::Other,
+                    None,
+                ))
+                .unwrap(),
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Hands the up-to-date game over to be launched, or to whatever `afterUpdate` asks for, which the
+/// window or the console carries out. Steps that have to run before the game starts, like
+/// re-activating DRM, go before this one.
+pub struct LaunchStep;
+
+impl UpdateStep for LaunchStep {
+    fn name(&self) -> &'static str {
+        "launch"
+    }
+
+    fn run(&mut self, context: &mut UpdateContext) -> ControlFlow<()> {
+        // Installs registered with Windows show the new version and size, and their shortcuts
+        // follow the game executable.
+        if let Ok(info) = get_version_info(&context.target_path) {
+            platform::update_registry_version(&context.target_path, &info.version_string).ok();
+        }
+        platform::refresh_shortcuts(&context.target_path);
+
+        let sender = &context.sender;
+        sender
+            .send(UpdateMessage::Status("Update complete.".to_string()))
+            .unwrap();
+        sender
+            .send(UpdateMessage::Log("Update process finished.".to_string()))
+            .unwrap();
+        sender.send(UpdateMessage::UpdateComplete).unwrap();
+        ControlFlow::Continue(())
+    }
+}
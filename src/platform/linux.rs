@@ -0,0 +1,160 @@
+use std::env;
+use std::fs::{self, Permissions};
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+use super::unix::home_dir;
+use super::{NotificationClick, ThreadPriority, DESCRIPTION, DISPLAY_NAME};
+
+pub const GAME_EXECUTABLE: &str = "Dreamio.x86_64";
+pub const GAME_PROCESS_NAME: &str = GAME_EXECUTABLE;
+pub const UPDATER_EXECUTABLE: &str = "DreamioUpdater";
+
+/// Filesystem types from `/proc/mounts` that live on another machine.
+const NETWORK_FILESYSTEMS: [&str; 6] = ["nfs", "nfs4", "cifs", "smb3", "smbfs", "fuse.sshfs"];
+
+/// `$XDG_DATA_HOME`, defaulting to `~/.local/share`.
+fn data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir().join(".local/share"))
+}
+
+fn desktop_dir() -> PathBuf {
+    env::var_os("XDG_DESKTOP_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join("Desktop"))
+}
+
+pub fn default_install_path() -> String {
+    data_dir().join(DISPLAY_NAME).to_string_lossy().to_string()
+}
+
+/// Looks up the filesystem of the mount point that contains `path` in `/proc/mounts`.
+pub fn is_network_path(path: &Path) -> bool {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+        return false;
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let filesystem = fields.next()?;
+            Some((mount_point, filesystem))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, filesystem)| NETWORK_FILESYSTEMS.contains(&filesystem))
+        .unwrap_or(false)
+}
+
+/// Starts `launch`, a program relative to the install folder and its arguments, or the game
+/// executable when it is empty, with `extra_args` added.
+pub fn launch_game(base_path: &Path, launch: &[String], extra_args: &[String]) -> io::Result<Child> {
+    let (program, args) = match launch.split_first() {
+        Some((program, args)) => (program.as_str(), args),
+        None => (GAME_EXECUTABLE, &[][..]),
+    };
+    Command::new(base_path.join(program))
+        .args(args)
+        .args(extra_args)
+        .current_dir(base_path)
+        .spawn()
+}
+
+/// Files are overwritten in place.
+pub fn prepare_replace(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Linux builds are not signed.
+pub fn verify_signature(_base_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+fn desktop_entry(install_path: &Path) -> String {
+    let exe_path = install_path.join(GAME_EXECUTABLE);
+    format!(
+        "[Desktop Entry]\nType=Application\nName={}\nComment={}\nExec=\"{}\"\nPath={}\nTerminal=false\nCategories=Game;\n",
+        DISPLAY_NAME,
+        DESCRIPTION,
+        exe_path.display(),
+        install_path.display()
+    )
+}
+
+fn desktop_file_name() -> String {
+    format!("{}.desktop", DISPLAY_NAME)
+}
+
+fn write_desktop_entry(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    fs::set_permissions(path, Permissions::from_mode(0o755))
+}
+
+pub fn create_shortcuts(install_path: &Path, create_desktop: bool, create_startmenu: bool) {
+    let entry = desktop_entry(install_path);
+    if create_desktop {
+        write_desktop_entry(&desktop_dir().join(desktop_file_name()), &entry).ok();
+    }
+    if create_startmenu {
+        write_desktop_entry(&data_dir().join("applications").join(desktop_file_name()), &entry).ok();
+    }
+}
+
+pub fn remove_shortcuts() {
+    let _ = fs::remove_file(desktop_dir().join(desktop_file_name()));
+    let _ = fs::remove_file(data_dir().join("applications").join(desktop_file_name()));
+}
+
+/// Shows a desktop notification with `notify-send`, when the desktop provides it. Clicking it does
+/// nothing, since notification servers differ in how they report clicks.
+pub fn show_notification(_base_path: &Path, title: &str, message: &str, _click: NotificationClick) {
+    if let Err(e) = Command::new("notify-send").args(["--app-name", DISPLAY_NAME, title, message]).status() {
+        log::warn!("Failed to show a notification: {}", e);
+    }
+}
+
+/// Moves `path` to the desktop's trash with `gio`, which desktops following the freedesktop.org
+/// trash specification provide.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let status = Command::new("gio").arg("trash").arg(path).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("gio trash returned {}", status)));
+    }
+    Ok(())
+}
+
+/// Linux keeps a nice value per thread, so only the current thread is lowered.
+pub fn set_thread_priority(priority: ThreadPriority) -> io::Result<()> {
+    let nice = match priority {
+        ThreadPriority::Normal => 0,
+        ThreadPriority::Low => 10,
+        ThreadPriority::Idle => 19,
+    };
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, nice) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Keeps the current thread on `cores`, numbered from 0.
+pub fn set_thread_affinity(cores: &[usize]) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let max_cores = std::mem::size_of::<libc::cpu_set_t>() * 8;
+    for &core in cores.iter().filter(|&&core| core < max_cores) {
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! The game ships as `Dreamio.app` inside the install directory. Update archives contain the
+//! bundle tree as is, including the executable bits and the symlinks of embedded frameworks.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::process::{Child, Command};
+
+use super::unix::home_dir;
+use super::{NotificationClick, ThreadPriority, DISPLAY_NAME};
+
+const GAME_BUNDLE: &str = "Dreamio.app";
+pub const GAME_EXECUTABLE: &str = "Dreamio.app/Contents/MacOS/Dreamio";
+pub const GAME_PROCESS_NAME: &str = "Dreamio";
+pub const UPDATER_EXECUTABLE: &str = "DreamioUpdater";
+
+/// Filesystem types reported by `mount` that live on another machine.
+const NETWORK_FILESYSTEMS: [&str; 5] = ["nfs", "smbfs", "afpfs", "webdav", "macfuse"];
+
+/// Installed per user, since `/Applications` needs administrator rights.
+pub fn default_install_path() -> String {
+    home_dir()
+        .join("Applications")
+        .join(DISPLAY_NAME)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Finds the mount that contains `path` in the output of `mount`, whose lines look like
+/// `//user@server/share on /Volumes/share (smbfs, nodev, nosuid, mounted by user)`.
+pub fn is_network_path(path: &Path) -> bool {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Ok(output) = Command::new("mount").output() else {
+        return false;
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(" on ")?;
+            let (mount_point, options) = rest.rsplit_once(" (")?;
+            let filesystem = options.split(',').next()?.trim_end_matches(')');
+            Some((mount_point.to_string(), filesystem.to_string()))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, filesystem)| NETWORK_FILESYSTEMS.contains(&filesystem.as_str()))
+        .unwrap_or(false)
+}
+
+/// Bundles are started through Launch Services so the Dock, menu bar and permissions behave as
+/// when the player opens the game from Finder.
+/// Opens `launch`, an app bundle relative to the install folder and its arguments, or the game
+/// bundle when it is empty, with `extra_args` added.
+pub fn launch_game(base_path: &Path, launch: &[String], extra_args: &[String]) -> io::Result<Child> {
+    let (bundle, args) = match launch.split_first() {
+        Some((bundle, args)) => (bundle.as_str(), args),
+        None => (GAME_BUNDLE, &[][..]),
+    };
+    let mut command = Command::new("open");
+    command.arg(base_path.join(bundle));
+    if !args.is_empty() || !extra_args.is_empty() {
+        command.arg("--args").args(args).args(extra_args);
+    }
+    command.spawn()
+}
+
+/// The kernel caches code signatures per file, so a signed binary overwritten in place is killed
+/// on its next launch. Removing the old file first makes the new contents land in a new file.
+pub fn prepare_replace(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the bundle's code signature after an update, so a partially applied update is noticed
+/// before Gatekeeper refuses to open the game. Unsigned development builds are accepted.
+pub fn verify_signature(base_path: &Path) -> Result<(), String> {
+    let bundle = base_path.join(GAME_BUNDLE);
+    if !bundle.exists() {
+        return Ok(());
+    }
+    let output = Command::new("codesign")
+        .args(["--verify", "--deep", "--strict"])
+        .arg(&bundle)
+        .output()
+        .map_err(|e| format!("could not run codesign: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() || stderr.contains("not signed at all") {
+        Ok(())
+    } else {
+        Err(stderr.trim().to_string())
+    }
+}
+
+/// Links the bundle from the Desktop and, unless it is already installed there, from
+/// `~/Applications`, which is where Launchpad and Spotlight find it.
+pub fn create_shortcuts(install_path: &Path, create_desktop: bool, create_startmenu: bool) {
+    let bundle = install_path.join(GAME_BUNDLE);
+    let applications = home_dir().join("Applications");
+    if create_desktop {
+        link_bundle(&bundle, &home_dir().join("Desktop").join(shortcut_name()));
+    }
+    if create_startmenu && !install_path.starts_with(&applications) {
+        link_bundle(&bundle, &applications.join(shortcut_name()));
+    }
+}
+
+fn shortcut_name() -> String {
+    format!("{}.app", DISPLAY_NAME)
+}
+
+fn link_bundle(bundle: &Path, link: &Path) {
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    fs::remove_file(link).ok();
+    symlink(bundle, link).ok();
+}
+
+pub fn remove_shortcuts() {
+    for path in [
+        home_dir().join("Desktop").join(shortcut_name()),
+        home_dir().join("Applications").join(shortcut_name()),
+    ] {
+        // Only links are removed, never a bundle someone copied there.
+        if fs::symlink_metadata(&path).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// Shows a notification in Notification Center through AppleScript, without a click action.
+pub fn show_notification(_base_path: &Path, title: &str, message: &str, _click: NotificationClick) {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let script = format!("display notification {} with title {}", quote(message), quote(title));
+    if let Err(e) = Command::new("osascript").args(["-e", &script]).status() {
+        log::warn!("Failed to show a notification: {}", e);
+    }
+}
+
+/// Moves `path` to the Trash. A name already there gets a number added, like Finder does.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let trash = home_dir().join(".Trash");
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut target = trash.join(name);
+    let mut number = 2;
+    while fs::symlink_metadata(&target).is_ok() {
+        let stem = path.file_stem().unwrap_or(name).to_string_lossy();
+        target = match path.extension() {
+            Some(extension) => trash.join(format!("{} {}.{}", stem, number, extension.to_string_lossy())),
+            None => trash.join(format!("{} {}", stem, number)),
+        };
+        number += 1;
+    }
+    fs::rename(path, target)
+}
+
+/// macOS has a single background band for threads, which both lower priorities use. It also
+/// throttles the thread's disk access.
+pub fn set_thread_priority(priority: ThreadPriority) -> io::Result<()> {
+    let value = if priority == ThreadPriority::Normal { 0 } else { libc::PRIO_DARWIN_BG };
+    if unsafe { libc::setpriority(libc::PRIO_DARWIN_THREAD, 0, value) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// macOS does not let programs keep threads on particular cores.
+pub fn set_thread_affinity(_cores: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "core affinity is not supported on macOS"))
+}
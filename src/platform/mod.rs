@@ -0,0 +1,229 @@
+//! Everything that differs between the platforms the game is published for: executable names,
+//! the build folder on the update server, and the desktop integration used by the installer.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::*;
+#[cfg(target_os = "macos")]
+pub use self::macos::*;
+#[cfg(unix)]
+pub use self::unix::*;
+#[cfg(windows)]
+pub use self::windows::*;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// File type bits of a symbolic link in a zip entry's unix mode.
+const SYMLINK_MODE: u32 = 0o120000;
+const FILE_TYPE_MASK: u32 = 0o170000;
+
+/// Retries of an operation on a file another program holds, waiting twice as long each time,
+/// for about eight seconds in total.
+const IN_USE_RETRIES: u32 = 5;
+const IN_USE_FIRST_DELAY: Duration = Duration::from_millis(250);
+
+/// Tells hard links to the same data apart from copies of it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    pub volume: u64,
+    pub index: u64,
+    /// How many names the data has.
+    pub links: u64,
+}
+
+/// Scheduling priority of a worker thread, relative to everything else running.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ThreadPriority {
+    Normal,
+    Low,
+    /// Only runs when nothing else wants the CPU.
+    Idle,
+}
+
+#[derive(Clone, Copy)]
+pub enum ProgressState {
+    NoProgress,
+    Indeterminate,
+    Normal,
+    Error,
+}
+
+/// Names the install in `base_path` for the single-instance guard, however its path is spelled.
+fn instance_key(base_path: &Path) -> String {
+    let path = fs::canonicalize(base_path).unwrap_or_else(|_| base_path.to_path_buf());
+    let path = path.to_string_lossy();
+    let path = if cfg!(windows) { path.to_lowercase() } else { path.into_owned() };
+    crate::hashing::sha256_bytes(path.as_bytes())[..16].to_string()
+}
+
+/// Names the task scheduled for `purpose` for the install in `base_path`.
+fn scheduled_task_name(base_path: &Path, purpose: &str) -> String {
+    format!("DreamioUpdater-{}-{}", purpose, instance_key(base_path))
+}
+
+/// What clicking a notification opens.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NotificationClick {
+    /// The updater with its window, to install an update.
+    Updater,
+    Game,
+}
+
+impl NotificationClick {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Updater => "update",
+            Self::Game => "play",
+        }
+    }
+
+    /// The link a notification of the install in `base_path` opens, such as `dreamio-<key>:play`.
+    #[cfg(windows)]
+    fn link(self, base_path: &Path) -> String {
+        format!("{}:{}", notification_scheme(base_path), self.name())
+    }
+
+    /// What a link given on the command line asks for, when the updater was started from a
+    /// notification.
+    pub fn from_link(arg: &str) -> Option<Self> {
+        let (scheme, action) = arg.split_once(':')?;
+        if !scheme.starts_with("dreamio-") {
+            return None;
+        }
+        [Self::Updater, Self::Game]
+            .into_iter()
+            .find(|click| action.trim_end_matches('/') == click.name())
+    }
+}
+
+/// The link protocol notifications of the install in `base_path` open.
+#[cfg(windows)]
+fn notification_scheme(base_path: &Path) -> String {
+    format!("dreamio-{}", instance_key(base_path))
+}
+
+/// Name of the default install folder and of the shortcuts.
+pub const DISPLAY_NAME: &str = "DREAMIO AI-Powered Adventures";
+#[cfg(not(target_os = "macos"))]
+const DESCRIPTION: &str = "DREAMIO: AI-Powered Adventures";
+
+/// Runs `operation` on `path`, retrying while another program holds the file. Most holders, like
+/// virus scanners, let go within seconds. If the file is still in use afterwards, the error names
+/// the programs holding it where the system can tell.
+pub fn retry_while_in_use<T>(path: &Path, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = IN_USE_FIRST_DELAY;
+    for _ in 0..IN_USE_RETRIES {
+        match operation() {
+            Err(e) if is_in_use_error(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation().map_err(|e| {
+        if !is_in_use_error(&e) {
+            return e;
+        }
+        let programs = processes_using(path);
+        let advice = if programs.is_empty() {
+            "Close any program that might be using it".to_string()
+        } else {
+            format!("Close {}", programs.join(", "))
+        };
+        io::Error::new(
+            e.kind(),
+            StillInUse(format!("{} is in use by another program. {} and try again", path.display(), advice)),
+        )
+    })
+}
+
+/// The error `retry_while_in_use` gives up with, so callers can still tell it apart.
+#[derive(Debug)]
+struct StillInUse(String);
+
+impl fmt::Display for StillInUse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for StillInUse {}
+
+/// Whether `error` is a file staying in use through every retry of `retry_while_in_use`.
+pub fn is_still_in_use(error: &io::Error) -> bool {
+    error.get_ref().map(|inner| inner.is::<StillInUse>()).unwrap_or(false)
+}
+
+/// Whether a zip entry's unix mode marks it as a symlink. Archives made on Linux and macOS store
+/// links this way, with the link target as the entry's contents.
+pub fn is_symlink_mode(mode: Option<u32>) -> bool {
+    mode.map(|mode| mode & FILE_TYPE_MASK == SYMLINK_MODE).unwrap_or(false)
+}
+
+/// Reads the target of a symlink entry extracted to `path`. Targets that would point outside
+/// `base_path` are rejected.
+fn read_link_target(entry: &mut impl Read, base_path: &Path, path: &Path) -> io::Result<PathBuf> {
+    let mut target = String::new();
+    entry.read_to_string(&mut target)?;
+    // Rebuilt from its components so the target uses the platform's separators.
+    let target: PathBuf = Path::new(&target).components().collect();
+    let depth = path
+        .strip_prefix(base_path)
+        .ok()
+        .and_then(|relative| relative.parent())
+        .map(|parent| parent.components().count())
+        .unwrap_or(0);
+    if !stays_inside(depth, &target) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("symlink to {} leaves the installation", target.display()),
+        ));
+    }
+    Ok(target)
+}
+
+/// Whether `target`, followed from a directory `depth` levels below the installation root, stays
+/// inside the installation. Bundles use relative links like `Versions/Current/Resources`.
+fn stays_inside(depth: usize, target: &Path) -> bool {
+    let mut depth = depth as isize;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => depth -= 1,
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    true
+}
+
+/// Removes whatever is at `path`, so a link can take its place.
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        // Links to directories are directories themselves on Windows.
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+        }
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
@@ -0,0 +1,396 @@
+//! Parts shared by Linux and macOS.
+
+use std::env;
+use std::fs::{self, File, Permissions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::thread;
+use sysinfo::{DiskExt, Pid, ProcessExt, System, SystemExt};
+use tokio::signal::unix::{signal, SignalKind};
+
+use super::{read_link_target, remove_existing, FileIdentity, ProgressState};
+
+/// There is no taskbar progress or window flashing the updater can rely on, so there is never a
+/// window handle or taskbar to drive.
+pub type WindowHandle = ();
+
+pub enum Taskbar {}
+
+impl Taskbar {
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    pub fn set_progress_state(&self, _window: WindowHandle, _state: ProgressState) {
+        match *self {}
+    }
+
+    pub fn set_progress_value(&self, _window: WindowHandle, _completed: u64, _total: u64) {
+        match *self {}
+    }
+}
+
+pub fn window_handle(_frame: &eframe::Frame) -> Option<WindowHandle> {
+    None
+}
+
+pub fn flash_window(_window: WindowHandle, _start: bool) {}
+
+/// Console commands inherit the terminal they were started from.
+pub fn attach_console() {}
+
+/// Terminals show ANSI escape sequences as they are.
+pub fn enable_ansi_colors() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Held while this updater runs. The pid file naming it is removed when it exits.
+pub struct InstanceGuard {
+    path: PathBuf,
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Claims the install in `base_path` for this updater with a pid file in the temporary folder.
+/// Returns `None` when another updater holds it. A pid file left by a crash names a process that
+/// no longer runs and is taken over.
+pub fn claim_instance(base_path: &Path) -> Option<InstanceGuard> {
+    let path = env::temp_dir().join(format!("dreamio-updater-{}.pid", super::instance_key(base_path)));
+    let owner = fs::read_to_string(&path).ok().and_then(|content| content.trim().parse::<usize>().ok());
+    if let Some(pid) = owner.filter(|&pid| pid != process::id() as usize) {
+        if System::new().refresh_process(Pid::from(pid)) {
+            return None;
+        }
+    }
+    // Without the pid file, a second updater is still better than none.
+    fs::write(&path, process::id().to_string()).ok();
+    Some(InstanceGuard { path })
+}
+
+/// File managers start programs the same way scripts do, so this cannot be told here.
+pub fn launched_from_explorer() -> bool {
+    false
+}
+
+/// There is no portable way to raise another program's window.
+pub fn focus_window(_title: &str) -> bool {
+    false
+}
+
+/// Calls `handler` on SIGINT, SIGTERM and SIGHUP. The process keeps running after each, so
+/// `handler` is always told it does not end yet.
+pub fn on_console_interrupt(handler: fn(bool)) {
+    thread::spawn(move || {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else {
+            return;
+        };
+        for kind in [SignalKind::interrupt(), SignalKind::terminate(), SignalKind::hangup()] {
+            runtime.spawn(async move {
+                if let Ok(mut signals) = signal(kind) {
+                    while signals.recv().await.is_some() {
+                        handler(false);
+                    }
+                }
+            });
+        }
+        runtime.block_on(std::future::pending::<()>());
+    });
+}
+
+pub(super) fn home_dir() -> PathBuf {
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn free_disk_space(path: &Path) -> Option<u64> {
+    let path = fs::canonicalize(path).ok()?;
+    let mut system = System::new();
+    system.refresh_disks_list();
+    system
+        .disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// `ETXTBSY`, raised when writing to an executable that is running.
+const TEXT_FILE_BUSY: i32 = 26;
+
+/// Files can be replaced while open, except running executables on some systems.
+pub fn is_in_use_error(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(TEXT_FILE_BUSY)
+}
+
+/// There is no portable way to ask which process holds a file.
+pub fn processes_using(_path: &Path) -> Vec<String> {
+    Vec::new()
+}
+
+/// Whether process `pid` belongs to another user than the updater, such as a game left running
+/// in another login session.
+pub fn in_other_session(pid: u32) -> bool {
+    let own = Pid::from(process::id() as usize);
+    let other = Pid::from(pid as usize);
+    let mut system = System::new();
+    if !system.refresh_process(own) || !system.refresh_process(other) {
+        return false;
+    }
+    match (system.process(own).and_then(|p| p.user_id()), system.process(other).and_then(|p| p.user_id())) {
+        (Some(own), Some(other)) => own != other,
+        _ => false,
+    }
+}
+
+/// Open files never block replacing them here, so nothing has to wait for a restart.
+pub fn schedule_on_reboot(_source: &Path, _target: Option<&Path>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "replacing files at restart is not supported on this system",
+    ))
+}
+
+/// Open files do not keep the update from replacing them here, so the game is only closed by
+/// its process name and no other program has to be.
+pub enum ClosedPrograms {}
+
+impl ClosedPrograms {
+    pub fn names(&self) -> &[String] {
+        match *self {}
+    }
+
+    pub fn restart(self) -> io::Result<()> {
+        match self {}
+    }
+}
+
+pub fn close_programs_using(_files: &[PathBuf]) -> io::Result<Option<ClosedPrograms>> {
+    Ok(None)
+}
+
+/// The player's locale, such as `de-AT`, from the usual environment variables.
+pub fn user_locale() -> Option<String> {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())?;
+    // `de_AT.UTF-8@euro` becomes `de-AT`.
+    let locale = value.split(['.', '@']).next()?.replace('_', "-");
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return None;
+    }
+    Some(locale)
+}
+
+/// Players run the updater with sudo themselves where an install needs it.
+pub fn can_relaunch_elevated() -> bool {
+    false
+}
+
+pub fn relaunch_elevated(_args: &[String]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "relaunching as administrator is not supported on this system",
+    ))
+}
+
+/// Programs started from the updater never open a terminal here.
+pub fn hide_window(_command: &mut Command) {}
+
+/// Takes ownership of a descriptor the parent process passed on the command line.
+pub fn file_from_descriptor(descriptor: u64) -> File {
+    unsafe { File::from_raw_fd(descriptor as RawFd) }
+}
+
+/// Connects to the Unix domain socket a launcher listens on, in the temporary folder unless
+/// `name` is a path.
+pub fn connect_pipe(name: &str) -> io::Result<Box<dyn Write + Send>> {
+    let path = if name.contains('/') { PathBuf::from(name) } else { env::temp_dir().join(name) };
+    Ok(Box::new(UnixStream::connect(path)?))
+}
+
+/// Regions skipped with a seek are left unallocated on every common Unix filesystem, so files
+/// need no marking.
+pub fn set_sparse(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Restores the permission bits stored in the archive, e.g. to keep the game executable.
+pub fn apply_unix_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    match mode {
+        Some(mode) => fs::set_permissions(path, Permissions::from_mode(mode & 0o7777)),
+        None => Ok(()),
+    }
+}
+
+/// Patched or redownloaded files are written as new files, so the game loses its executable bit
+/// unless it is set again.
+pub fn ensure_executable(path: &Path) -> io::Result<()> {
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o111 == 0o111 {
+        return Ok(());
+    }
+    fs::set_permissions(path, Permissions::from_mode(mode | 0o755))
+}
+
+/// Recreates a symlink stored in the archive.
+pub fn extract_symlink(entry: &mut impl Read, base_path: &Path, path: &Path) -> io::Result<()> {
+    let target = read_link_target(entry, base_path, path)?;
+    remove_existing(path)?;
+    symlink(&target, path)
+}
+
+pub fn file_identity(path: &Path) -> Option<FileIdentity> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(FileIdentity {
+        volume: metadata.dev(),
+        index: metadata.ino(),
+        links: metadata.nlink(),
+    })
+}
+
+/// Patched and redownloaded files are written as new files, which would otherwise get the default
+/// permissions instead of those of the file they replace.
+pub fn keep_permissions(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::metadata(from) {
+        Ok(metadata) => fs::set_permissions(to, metadata.permissions()),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Installs are not registered anywhere; `version.json` is the only record.
+pub fn update_registry_version(_install_path: &Path, _version_string: &str) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn register_uninstaller(_install_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn unregister_uninstaller() {}
+
+/// Desktop entries and app links point at the install folder, which updates do not move.
+pub fn refresh_shortcuts(_install_path: &Path) {}
+
+/// A running executable can be deleted, so the files are removed right away.
+pub fn remove_install(exe_path: &Path, install_path: &Path, remove_directory: bool) {
+    if remove_directory {
+        let _ = fs::remove_dir_all(install_path);
+    } else {
+        let _ = fs::remove_file(exe_path);
+        let _ = fs::remove_dir(install_path);
+    }
+}
+
+/// Adds a line to the user's crontab running `program` with `args` every day at `hour:minute`,
+/// replacing the line added for the same install.
+pub fn schedule_daily(base_path: &Path, program: &Path, args: &[&str], hour: u32, minute: u32) -> io::Result<()> {
+    add_cron_line(
+        &super::scheduled_task_name(base_path, "check"),
+        &format!("{} {} * * *", minute, hour),
+        program,
+        args,
+    )
+}
+
+/// Adds a line to the user's crontab running `program` with `args` every `minutes`. Cron runs it
+/// whether or not the user is logged in; use root's crontab for shared machines.
+pub fn schedule_periodic(base_path: &Path, program: &Path, args: &[&str], minutes: u32) -> io::Result<()> {
+    add_cron_line(
+        &super::scheduled_task_name(base_path, "maintenance"),
+        &format!("*/{} * * * *", minutes),
+        program,
+        args,
+    )
+}
+
+/// Removes the lines added for the install. Returns whether there were any.
+pub fn unschedule(base_path: &Path) -> io::Result<bool> {
+    let mut lines = crontab_lines()?;
+    let count = lines.len();
+    for purpose in ["check", "maintenance"] {
+        let marker = format!("# {}", super::scheduled_task_name(base_path, purpose));
+        lines.retain(|line| !line.ends_with(&marker));
+    }
+    if lines.len() == count {
+        return Ok(false);
+    }
+    write_crontab(&lines)?;
+    Ok(true)
+}
+
+fn add_cron_line(name: &str, timing: &str, program: &Path, args: &[&str]) -> io::Result<()> {
+    let marker = format!("# {}", name);
+    let mut lines = crontab_lines()?;
+    lines.retain(|line| !line.ends_with(&marker));
+    let mut line = format!("{} {}", timing, shell_quote(&program.to_string_lossy()));
+    for arg in args {
+        line.push(' ');
+        line.push_str(&shell_quote(arg));
+    }
+    lines.push(format!("{} {}", line, marker));
+    write_crontab(&lines)
+}
+
+fn crontab_lines() -> io::Result<Vec<String>> {
+    let output = Command::new("crontab").arg("-l").output()?;
+    // `crontab -l` fails when the user has no crontab yet.
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+fn write_crontab(lines: &[String]) -> io::Result<()> {
+    let mut child = Command::new("crontab").arg("-").stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let mut content = lines.join("\n");
+        content.push('\n');
+        stdin.write_all(content.as_bytes())?;
+    }
+    if !child.wait()?.success() {
+        return Err(io::Error::other("crontab refused the new schedule"));
+    }
+    Ok(())
+}
+
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Shows `message` on every terminal with `wall`. Best effort.
+pub fn broadcast_message(message: &str) {
+    if let Err(e) = Command::new("wall").arg(message).status() {
+        log::warn!("Failed to show a message to logged-on users: {}", e);
+    }
+}
+
+/// How far local time is ahead of UTC right now, in seconds, as `date` reports it.
+pub fn local_utc_offset() -> i64 {
+    let Ok(output) = Command::new("date").arg("+%z").output() else {
+        return 0;
+    };
+    // `+0130` or `-0800`.
+    let offset = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let (sign, digits) = match offset.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, offset.trim_start_matches('+')),
+    };
+    let (Some(hours), Some(minutes)) = (
+        digits.get(..2).and_then(|h| h.parse::<i64>().ok()),
+        digits.get(2..4).and_then(|m| m.parse::<i64>().ok()),
+    ) else {
+        return 0;
+    };
+    sign * (hours * 3600 + minutes * 60)
+}
+
+/// Notifications open nothing when clicked here, so there is no link protocol to remove.
+pub fn unregister_notifications(_base_path: &Path) {}
@@ -0,0 +1,1057 @@
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::env;
+use std::ffi::c_void;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::{symlink_dir, symlink_file};
+use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::OnceLock;
+use sysinfo::{Pid, ProcessExt, System, SystemExt};
+use windows::{
+    core::{ComInterface, GUID, HSTRING, PCWSTR, PWSTR},
+    Data::Xml::Dom::XmlDocument,
+    UI::Notifications::{ToastNotification, ToastNotificationManager},
+    Win32::Foundation::{
+        BOOL, CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, ERROR_MORE_DATA, ERROR_SUCCESS, FALSE, HANDLE, HWND, TRUE,
+        WIN32_ERROR,
+    },
+    Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY},
+    Win32::Globalization::GetUserDefaultLocaleName,
+    Win32::Storage::FileSystem::{
+        GetDiskFreeSpaceExW, GetDriveTypeW, GetFileInformationByHandle, MoveFileExW,
+        BY_HANDLE_FILE_INFORMATION, MOVEFILE_DELAY_UNTIL_REBOOT, MOVEFILE_REPLACE_EXISTING,
+    },
+    Win32::System::Console::{
+        AttachConsole, GetConsoleMode, GetStdHandle, SetConsoleCtrlHandler, SetConsoleMode, ATTACH_PARENT_PROCESS,
+        CONSOLE_MODE, CTRL_BREAK_EVENT, CTRL_C_EVENT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE,
+    },
+    Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoTaskMemFree, IPersistFile, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    },
+    Win32::System::Ioctl::FSCTL_SET_SPARSE,
+    Win32::System::RemoteDesktop::ProcessIdToSessionId,
+    Win32::System::Threading::{
+        CreateMutexW, GetCurrentProcess, GetCurrentProcessId, GetCurrentThread, OpenProcessToken, SetThreadAffinityMask,
+        SetThreadPriority, THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_IDLE, THREAD_PRIORITY_NORMAL,
+    },
+    Win32::System::Time::{GetTimeZoneInformation, TIME_ZONE_INFORMATION},
+    Win32::System::RestartManager::{
+        RmEndSession, RmForceShutdown, RmGetList, RmRegisterResources, RmRestart, RmShutdown,
+        RmStartSession, CCH_RM_SESSION_KEY, RM_PROCESS_INFO,
+    },
+    Win32::System::Registry::{
+        RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegDeleteTreeW, RegOpenKeyExW, RegSetValueExW, HKEY,
+        HKEY_CURRENT_USER, KEY_WRITE, REG_DWORD, REG_OPTION_NON_VOLATILE, REG_SZ, REG_VALUE_TYPE,
+    },
+    Win32::System::IO::DeviceIoControl,
+    Win32::UI::Shell::{
+        FOLDERID_Desktop, FOLDERID_LocalAppData, FOLDERID_Programs, SHGetKnownFolderPath,
+        IShellLinkW, ITaskbarList3, SHFileOperationW, ShellExecuteW, ShellLink, TaskbarList, FOF_ALLOWUNDO,
+        FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT, FO_DELETE, KF_FLAG_DEFAULT, SHFILEOPSTRUCTW, TBPFLAG,
+    },
+    Win32::UI::WindowsAndMessaging::{
+        FindWindowW, FlashWindowEx, SetForegroundWindow, ShowWindow, FLASHWINFO, FLASHWINFO_FLAGS, FLASHW_ALL,
+        FLASHW_TIMERNOFG, SW_RESTORE, SW_SHOWNORMAL,
+    },
+};
+
+use super::{
+    read_link_target, remove_existing, FileIdentity, NotificationClick, ProgressState, ThreadPriority, DESCRIPTION,
+    DISPLAY_NAME,
+};
+use crate::get_version_info;
+
+pub const GAME_EXECUTABLE: &str = "Dreamio.exe";
+pub const GAME_PROCESS_NAME: &str = GAME_EXECUTABLE;
+pub const UPDATER_EXECUTABLE: &str = "DreamioUpdater.exe";
+
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+const ERROR_SHARING_VIOLATION: i32 = 32;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// Toasts are shown under this app ID, registered for unpackaged programs.
+const APP_USER_MODEL_ID: &str = "Dreamio.Updater";
+const UNINSTALL_KEY: &str =
+    "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\Oleg Skutte DREAMIO: AI-Powered Adventures";
+
+pub type WindowHandle = HWND;
+
+pub struct Taskbar {
+    taskbar_list: ITaskbarList3,
+}
+
+impl Taskbar {
+    pub fn new() -> Option<Self> {
+        unsafe {
+            if CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok() {
+                let taskbar_list: ITaskbarList3 =
+                    CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER).ok()?;
+                Some(Self { taskbar_list })
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn set_progress_state(&self, hwnd: HWND, state: ProgressState) {
+        let tbp_flags = match state {
+            ProgressState::NoProgress => TBPFLAG(0),
+            ProgressState::Indeterminate => TBPFLAG(0x1),
+            ProgressState::Normal => TBPFLAG(0x2),
+            ProgressState::Error => TBPFLAG(0x4),
+        };
+        unsafe {
+            self.taskbar_list.SetProgressState(hwnd, tbp_flags).ok();
+        }
+    }
+
+    pub fn set_progress_value(&self, hwnd: HWND, completed: u64, total: u64) {
+        unsafe {
+            self.taskbar_list
+                .SetProgressValue(hwnd, completed, total)
+                .ok();
+        }
+    }
+}
+
+pub fn window_handle(frame: &eframe::Frame) -> Option<WindowHandle> {
+    let window_handle = frame.window_handle().ok()?;
+    match window_handle.into() {
+        RawWindowHandle::Win32(handle) => Some(HWND(handle.hwnd.get() as isize)),
+        _ => None,
+    }
+}
+
+pub fn flash_window(hwnd: HWND, start: bool) {
+    let mut info = FLASHWINFO {
+        cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
+        hwnd,
+        dwFlags: if start {
+            FLASHW_ALL | FLASHW_TIMERNOFG
+        } else {
+            FLASHWINFO_FLAGS(0)
+        },
+        uCount: 0,
+        dwTimeout: 0,
+    };
+    unsafe {
+        FlashWindowEx(&mut info);
+    }
+}
+
+/// The updater is a GUI application, so console commands have to attach to the parent console to print anything.
+pub fn attach_console() {
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Turns on ANSI escape sequences in the console. Returns false when the output is not a console
+/// or the console is too old for them.
+pub fn enable_ansi_colors() -> bool {
+    unsafe {
+        let Ok(handle) = GetStdHandle(STD_OUTPUT_HANDLE) else {
+            return false;
+        };
+        let mut mode = CONSOLE_MODE::default();
+        GetConsoleMode(handle, &mut mode).is_ok()
+            && SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING).is_ok()
+    }
+}
+
+/// Held while this updater runs. Windows releases the mutex when the process ends, even after a
+/// crash.
+pub struct InstanceGuard(HANDLE);
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+/// Claims the install in `base_path` for this updater with a named mutex. Returns `None` when
+/// another updater holds it.
+pub fn claim_instance(base_path: &Path) -> Option<InstanceGuard> {
+    let name = HSTRING::from(format!("Local\\DreamioUpdater-{}", super::instance_key(base_path)));
+    unsafe {
+        // Without the mutex, a second updater is still better than none.
+        let Ok(handle) = CreateMutexW(None, false, &name) else {
+            return Some(InstanceGuard(HANDLE::default()));
+        };
+        if GetLastError() == ERROR_ALREADY_EXISTS {
+            let _ = CloseHandle(handle);
+            return None;
+        }
+        Some(InstanceGuard(handle))
+    }
+}
+
+/// Whether Explorer started the updater, from a shortcut or a double-click, so there is no console
+/// showing what it writes.
+pub fn launched_from_explorer() -> bool {
+    let own = Pid::from(std::process::id() as usize);
+    let mut system = System::new();
+    if !system.refresh_process(own) {
+        return false;
+    }
+    let Some(parent) = system.process(own).and_then(|process| process.parent()) else {
+        return false;
+    };
+    system.refresh_process(parent)
+        && system
+            .process(parent)
+            .is_some_and(|process| process.name().eq_ignore_ascii_case("explorer.exe"))
+}
+
+/// Brings the window titled `title` to the front, restoring it when minimized. Returns whether
+/// there is one.
+pub fn focus_window(title: &str) -> bool {
+    unsafe {
+        let hwnd = FindWindowW(PCWSTR::null(), &HSTRING::from(title));
+        if hwnd.0 == 0 {
+            return false;
+        }
+        let _ = ShowWindow(hwnd, SW_RESTORE);
+        let _ = SetForegroundWindow(hwnd);
+        true
+    }
+}
+
+static CONSOLE_INTERRUPT: OnceLock<fn(bool)> = OnceLock::new();
+
+unsafe extern "system" fn console_control(control_type: u32) -> BOOL {
+    let Some(handler) = CONSOLE_INTERRUPT.get() else {
+        return FALSE;
+    };
+    // Closing the console, logging off and shutting down end the process once this returns.
+    handler(!matches!(control_type, CTRL_C_EVENT | CTRL_BREAK_EVENT));
+    TRUE
+}
+
+/// Calls `handler` on Ctrl+C and Ctrl+Break, and when the console window is closed or Windows logs
+/// off or shuts down. It is told whether the process ends as soon as it returns.
+pub fn on_console_interrupt(handler: fn(bool)) {
+    if CONSOLE_INTERRUPT.set(handler).is_ok() {
+        unsafe {
+            let _ = SetConsoleCtrlHandler(Some(console_control), true);
+        }
+    }
+}
+
+fn known_folder(id: &GUID) -> Option<PathBuf> {
+    unsafe {
+        let path = SHGetKnownFolderPath(id, KF_FLAG_DEFAULT, None).ok()?;
+        let s = path.to_string().unwrap_or_default();
+        CoTaskMemFree(Some(path.as_ptr() as _));
+        Some(PathBuf::from(s))
+    }
+}
+
+pub fn default_install_path() -> String {
+    match known_folder(&FOLDERID_LocalAppData) {
+        Some(path) => path.join(DISPLAY_NAME).to_string_lossy().to_string(),
+        None => r"C:\DREAMIO".to_string(),
+    }
+}
+
+pub fn is_network_path(path: &Path) -> bool {
+    const DRIVE_REMOTE: u32 = 4;
+
+    let path_str = path.to_string_lossy();
+    if path_str.starts_with(r"\\") {
+        return true;
+    }
+
+    match path.components().next() {
+        Some(std::path::Component::Prefix(prefix)) => {
+            let root = format!("{}\\", prefix.as_os_str().to_string_lossy());
+            let root_h = HSTRING::from(root);
+            unsafe { GetDriveTypeW(PCWSTR::from_raw(root_h.as_ptr())) == DRIVE_REMOTE }
+        }
+        _ => false,
+    }
+}
+
+pub fn free_disk_space(path: &Path) -> Option<u64> {
+    let mut free_bytes = 0u64;
+    let path = HSTRING::from(path.as_os_str());
+    unsafe { GetDiskFreeSpaceExW(&path, Some(&mut free_bytes), None, None) }.ok()?;
+    Some(free_bytes)
+}
+
+/// Antivirus scanners, Explorer previews and backup tools open files without sharing them.
+pub fn is_in_use_error(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION))
+}
+
+/// Asks the Restart Manager which programs have `path` open.
+pub fn processes_using(path: &Path) -> Vec<String> {
+    match RestartSession::start(&[path.to_path_buf()]) {
+        Ok(session) => session.process_names(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Whether process `pid` runs in another Windows session than the updater, such as another
+/// user's through fast user switching or Remote Desktop.
+pub fn in_other_session(pid: u32) -> bool {
+    let mut own = 0;
+    let mut other = 0;
+    unsafe {
+        ProcessIdToSessionId(GetCurrentProcessId(), &mut own).is_ok()
+            && ProcessIdToSessionId(pid, &mut other).is_ok()
+            && own != other
+    }
+}
+
+/// Programs the Restart Manager closed because they had game files open. `restart` starts them
+/// again once the update is done.
+pub struct ClosedPrograms {
+    session: RestartSession,
+    names: Vec<String>,
+}
+
+impl ClosedPrograms {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Restarts the programs that registered with Windows to be restarted.
+    pub fn restart(self) -> io::Result<()> {
+        rm_result(unsafe { RmRestart(self.session.handle, 0, None) })
+    }
+}
+
+/// Closes every program that has one of `files` open, including overlays and tools besides the
+/// game. Programs are asked to close first, so they can save, and only terminated when they
+/// refuse. Returns `None` when no program had them open.
+pub fn close_programs_using(files: &[PathBuf]) -> io::Result<Option<ClosedPrograms>> {
+    let session = RestartSession::start(files)?;
+    let names = session.process_names();
+    if names.is_empty() {
+        return Ok(None);
+    }
+    if unsafe { RmShutdown(session.handle, 0, None) } != ERROR_SUCCESS {
+        rm_result(unsafe { RmShutdown(session.handle, RmForceShutdown.0 as u32, None) })?;
+    }
+    Ok(Some(ClosedPrograms { session, names }))
+}
+
+/// A Restart Manager session with files registered, ended when dropped.
+struct RestartSession {
+    handle: u32,
+}
+
+impl RestartSession {
+    fn start(files: &[PathBuf]) -> io::Result<Self> {
+        let mut handle = 0;
+        let mut session_key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+        rm_result(unsafe { RmStartSession(&mut handle, 0, PWSTR(session_key.as_mut_ptr())) })?;
+        let session = Self { handle };
+        let paths: Vec<HSTRING> = files.iter().map(|file| HSTRING::from(file.as_os_str())).collect();
+        let files: Vec<PCWSTR> = paths.iter().map(|path| PCWSTR::from_raw(path.as_ptr())).collect();
+        rm_result(unsafe { RmRegisterResources(session.handle, Some(&files), None, None) })?;
+        Ok(session)
+    }
+
+    fn process_names(&self) -> Vec<String> {
+        let mut needed = 0;
+        let mut count = 0;
+        let mut reasons = 0;
+        unsafe {
+            // The first call only reports how many processes there are.
+            if RmGetList(self.handle, &mut needed, &mut count, None, &mut reasons) != ERROR_MORE_DATA {
+                return Vec::new();
+            }
+            let mut processes = vec![RM_PROCESS_INFO::default(); needed as usize];
+            count = needed;
+            if RmGetList(self.handle, &mut needed, &mut count, Some(processes.as_mut_ptr()), &mut reasons)
+                != ERROR_SUCCESS
+            {
+                return Vec::new();
+            }
+            processes[..count as usize]
+                .iter()
+                .map(|process| {
+                    let name = &process.strAppName;
+                    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                    String::from_utf16_lossy(&name[..len])
+                })
+                .collect()
+        }
+    }
+}
+
+impl Drop for RestartSession {
+    fn drop(&mut self) {
+        unsafe { RmEndSession(self.handle) };
+    }
+}
+
+fn rm_result(result: WIN32_ERROR) -> io::Result<()> {
+    if result == ERROR_SUCCESS {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(result.0 as i32))
+    }
+}
+
+/// The player's locale, such as `de-AT`.
+pub fn user_locale() -> Option<String> {
+    // LOCALE_NAME_MAX_LENGTH
+    let mut name = [0u16; 85];
+    let len = unsafe { GetUserDefaultLocaleName(&mut name) };
+    if len <= 1 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&name[..len as usize - 1]))
+}
+
+/// Whether relaunching through UAC would give the updater rights it does not have yet.
+pub fn can_relaunch_elevated() -> bool {
+    unsafe {
+        let mut token = HANDLE::default();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).is_err() {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut c_void),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        );
+        CloseHandle(token).ok();
+        result.is_ok() && elevation.TokenIsElevated == 0
+    }
+}
+
+/// Starts the updater again as administrator with `args`, in the current working directory. The
+/// UAC prompt is the player's to answer, so this fails when it is declined.
+pub fn relaunch_elevated(args: &[String]) -> io::Result<()> {
+    let exe = HSTRING::from(env::current_exe()?.as_os_str());
+    let parameters: Vec<String> = args.iter().map(|arg| quote_argument(arg)).collect();
+    let parameters = HSTRING::from(parameters.join(" "));
+    let directory = HSTRING::from(env::current_dir()?.as_os_str());
+    let result = unsafe {
+        ShellExecuteW(
+            HWND(0),
+            &HSTRING::from("runas"),
+            &exe,
+            &parameters,
+            &directory,
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values up to 32 are error codes.
+    if result.0 <= 32 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("the administrator prompt was declined or failed (code {})", result.0),
+        ));
+    }
+    Ok(())
+}
+
+/// Quotes `arg` so `CommandLineToArgvW` splits it back out unchanged.
+fn quote_argument(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // Backslashes only escape when a quote follows them.
+        if c == '"' {
+            quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+        } else {
+            quoted.push_str(&"\\".repeat(backslashes));
+        }
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Keeps console programs from opening a console window.
+pub fn hide_window(command: &mut Command) {
+    command.creation_flags(CREATE_NO_WINDOW);
+}
+
+/// Has Windows move `source` over `target`, or delete `source` without a target, at the next
+/// restart before any program runs. Windows only accepts this from an administrator.
+pub fn schedule_on_reboot(source: &Path, target: Option<&Path>) -> io::Result<()> {
+    let source = HSTRING::from(source.as_os_str());
+    let target = target.map(|target| HSTRING::from(target.as_os_str()));
+    let target_ptr = match &target {
+        Some(target) => PCWSTR::from_raw(target.as_ptr()),
+        None => PCWSTR::null(),
+    };
+    unsafe {
+        MoveFileExW(
+            &source,
+            target_ptr,
+            MOVEFILE_DELAY_UNTIL_REBOOT | MOVEFILE_REPLACE_EXISTING,
+        )
+    }
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Moves `path` to the Recycle Bin without asking. On drives without one, Windows deletes it.
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    // The list of paths ends with an empty one.
+    let from: Vec<u16> = path.as_os_str().encode_wide().chain([0, 0]).collect();
+    let mut operation = SHFILEOPSTRUCTW {
+        wFunc: FO_DELETE,
+        pFrom: PCWSTR::from_raw(from.as_ptr()),
+        fFlags: (FOF_ALLOWUNDO | FOF_NOCONFIRMATION | FOF_NOERRORUI | FOF_SILENT) as u16,
+        ..Default::default()
+    };
+    match unsafe { SHFileOperationW(&mut operation) } {
+        0 if operation.fAnyOperationsAborted.as_bool() => {
+            Err(io::Error::new(io::ErrorKind::Interrupted, "moving to the Recycle Bin was cancelled"))
+        }
+        0 => Ok(()),
+        code => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("moving to the Recycle Bin failed with code {:#x}", code),
+        )),
+    }
+}
+
+/// Takes ownership of an inheritable handle the parent process passed on the command line.
+pub fn file_from_descriptor(descriptor: u64) -> File {
+    unsafe { File::from_raw_handle(descriptor as usize as RawHandle) }
+}
+
+/// Connects to the named pipe `\\.\pipe\<name>` a launcher listens on.
+pub fn connect_pipe(name: &str) -> io::Result<Box<dyn Write + Send>> {
+    let pipe = OpenOptions::new().write(true).open(format!(r"\\.\pipe\{}", name))?;
+    Ok(Box::new(pipe))
+}
+
+pub fn file_identity(path: &Path) -> Option<FileIdentity> {
+    let file = File::open(path).ok()?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle() as isize), &mut info) }.ok()?;
+    Some(FileIdentity {
+        volume: info.dwVolumeSerialNumber as u64,
+        index: (info.nFileIndexHigh as u64) << 32 | info.nFileIndexLow as u64,
+        links: info.nNumberOfLinks as u64,
+    })
+}
+
+pub fn set_sparse(file: &File) -> io::Result<()> {
+    let handle = HANDLE(file.as_raw_handle() as isize);
+    let mut bytes_returned = 0u32;
+    unsafe {
+        DeviceIoControl(
+            handle,
+            FSCTL_SET_SPARSE,
+            None,
+            0,
+            None,
+            0,
+            Some(&mut bytes_returned),
+            None,
+        )
+    }
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Windows has no executable bit. The read-only attribute is not derived from the mode either,
+/// since it would make the file fail to update later.
+pub fn apply_unix_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn ensure_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Replacing a file also replaces its attributes, so there is nothing to carry over.
+pub fn keep_permissions(_from: &Path, _to: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Recreates a symlink stored in the archive. Creating symlinks needs Developer Mode or
+/// administrator rights, so without them a link to a file already extracted is replaced by a copy
+/// of it.
+pub fn extract_symlink(entry: &mut impl Read, base_path: &Path, path: &Path) -> io::Result<()> {
+    let target = read_link_target(entry, base_path, path)?;
+    remove_existing(path)?;
+    let resolved = path.parent().unwrap_or(base_path).join(&target);
+    let result = if resolved.is_dir() {
+        symlink_dir(&target, path)
+    } else {
+        symlink_file(&target, path)
+    };
+    match result {
+        Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) && resolved.is_file() => {
+            fs::copy(&resolved, path).map(|_| ())
+        }
+        Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) => Err(io::Error::new(
+            e.kind(),
+            "creating links needs Developer Mode or administrator rights",
+        )),
+        result => result,
+    }
+}
+
+/// Starts `launch`, a program relative to the install folder and its arguments, or the game
+/// executable when it is empty, with `extra_args` added.
+pub fn launch_game(base_path: &Path, launch: &[String], extra_args: &[String]) -> io::Result<Child> {
+    let (program, args) = match launch.split_first() {
+        Some((program, args)) => (program.as_str(), args),
+        None => (GAME_EXECUTABLE, &[][..]),
+    };
+    Command::new(base_path.join(program))
+        .args(args)
+        .args(extra_args)
+        .current_dir(base_path)
+        .spawn()
+}
+
+/// Files are overwritten in place.
+pub fn prepare_replace(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Windows builds are checked by the hashes in the update metadata only.
+pub fn verify_signature(_base_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+fn create_shortcut(
+    target: &Path,
+    shortcut_path: &Path,
+    description: &str,
+) -> windows::core::Result<()> {
+    unsafe {
+        let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+
+        let target_h = HSTRING::from(target.as_os_str().to_str().unwrap_or_default());
+        link.SetPath(PCWSTR::from_raw(target_h.as_ptr()))?;
+
+        let desc_h = HSTRING::from(description);
+        link.SetDescription(PCWSTR::from_raw(desc_h.as_ptr()))?;
+
+        if let Some(parent) = target.parent() {
+            let parent_h = HSTRING::from(parent.as_os_str().to_str().unwrap_or_default());
+            link.SetWorkingDirectory(PCWSTR::from_raw(parent_h.as_ptr()))?;
+        }
+
+        let persist: IPersistFile = link.cast()?;
+        let shortcut_h = HSTRING::from(shortcut_path.as_os_str().to_str().unwrap_or_default());
+        persist.Save(PCWSTR::from_raw(shortcut_h.as_ptr()), true)?;
+
+        Ok(())
+    }
+}
+
+pub fn create_shortcuts(install_path: &Path, create_desktop: bool, create_startmenu: bool) {
+    let exe_path = install_path.join(GAME_EXECUTABLE);
+    let link_name = format!("{}.lnk", DISPLAY_NAME);
+
+    if create_desktop {
+        if let Some(desktop) = known_folder(&FOLDERID_Desktop) {
+            create_shortcut(&exe_path, &desktop.join(&link_name), DESCRIPTION).ok();
+        }
+    }
+
+    if create_startmenu {
+        if let Some(programs) = known_folder(&FOLDERID_Programs) {
+            let programs_path = programs.join(DISPLAY_NAME);
+            fs::create_dir_all(&programs_path).ok();
+            create_shortcut(&exe_path, &programs_path.join(&link_name), DESCRIPTION).ok();
+        }
+    }
+}
+
+/// Recreates the shortcuts the player kept, so they point at the game executable and pick up its
+/// icon after an update. Removed shortcuts stay removed.
+pub fn refresh_shortcuts(install_path: &Path) {
+    let link_name = format!("{}.lnk", DISPLAY_NAME);
+    let desktop = known_folder(&FOLDERID_Desktop).map(|desktop| desktop.join(&link_name));
+    let startmenu = known_folder(&FOLDERID_Programs).map(|programs| programs.join(DISPLAY_NAME).join(&link_name));
+    create_shortcuts(
+        install_path,
+        desktop.is_some_and(|path| path.exists()),
+        startmenu.is_some_and(|path| path.exists()),
+    );
+}
+
+pub fn remove_shortcuts() {
+    if let Some(desktop) = known_folder(&FOLDERID_Desktop) {
+        let _ = fs::remove_file(desktop.join(format!("{}.lnk", DISPLAY_NAME)));
+    }
+    if let Some(programs) = known_folder(&FOLDERID_Programs) {
+        let _ = fs::remove_dir_all(programs.join(DISPLAY_NAME));
+    }
+}
+
+/// Writes values below `subkey` of the current user, creating it when `create` is set. Otherwise a
+/// missing key is left alone.
+fn write_registry_values(
+    subkey: &str,
+    strings: &[(&str, &str)],
+    numbers: &[(&str, u32)],
+    create: bool,
+) -> windows::core::Result<()> {
+    let subkey_h = HSTRING::from(subkey);
+
+    let mut hkey = HKEY::default();
+    unsafe {
+        if create {
+            RegCreateKeyExW(
+                HKEY_CURRENT_USER,
+                PCWSTR::from_raw(subkey_h.as_ptr()),
+                0,
+                None,
+                REG_OPTION_NON_VOLATILE,
+                KEY_WRITE,
+                None,
+                &mut hkey,
+                None,
+            )?;
+        } else if RegOpenKeyExW(HKEY_CURRENT_USER, PCWSTR::from_raw(subkey_h.as_ptr()), 0, KEY_WRITE, &mut hkey).is_err() {
+            return Ok(());
+        }
+    }
+
+    let write_value = |name: &str, kind: REG_VALUE_TYPE, data: &[u8]| -> windows::core::Result<()> {
+        let name_h = HSTRING::from(name);
+        unsafe { RegSetValueExW(hkey, PCWSTR::from_raw(name_h.as_ptr()), 0, kind, Some(data)) }
+    };
+    let write_str = |name: &str, value: &str| -> windows::core::Result<()> {
+        let mut val_vec: Vec<u16> = value.encode_utf16().collect();
+        val_vec.push(0);
+
+        let slice_u8 =
+            unsafe { std::slice::from_raw_parts(val_vec.as_ptr() as *const u8, val_vec.len() * 2) };
+        write_value(name, REG_SZ, slice_u8)
+    };
+
+    let result = strings
+        .iter()
+        .try_for_each(|(name, value)| write_str(name, value))
+        .and_then(|_| {
+            numbers
+                .iter()
+                .try_for_each(|(name, value)| write_value(name, REG_DWORD, &value.to_le_bytes()))
+        });
+
+    unsafe {
+        let _ = RegCloseKey(hkey);
+    };
+
+    result
+}
+
+/// Add/Remove Programs shows sizes in KiB.
+fn estimated_size_kib(install_path: &Path) -> u32 {
+    (folder_size(install_path) / 1024).min(u32::MAX as u64) as u32
+}
+
+fn folder_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => folder_size(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Refreshes the version and size shown in Add/Remove Programs after an update, for installs that
+/// registered an uninstall entry.
+pub fn update_registry_version(install_path: &Path, version_string: &str) -> windows::core::Result<()> {
+    write_registry_values(
+        UNINSTALL_KEY,
+        &[("DisplayVersion", version_string)],
+        &[("EstimatedSize", estimated_size_kib(install_path))],
+        false,
+    )
+}
+
+pub fn register_uninstaller(install_path: &Path) -> windows::core::Result<()> {
+    let exe_path = install_path.join(UPDATER_EXECUTABLE);
+    let display_icon = install_path.join(GAME_EXECUTABLE);
+
+    let version_info = get_version_info(install_path)
+        .map(|v| v.version_string)
+        .unwrap_or_default();
+
+    write_registry_values(
+        UNINSTALL_KEY,
+        &[
+            ("DisplayName", "DREAMIO: AI-Powered Adventures"),
+            (
+                "UninstallString",
+                &format!("\"{}\" --uninstall", exe_path.to_string_lossy()),
+            ),
+            ("InstallLocation", &install_path.to_string_lossy()),
+            ("DisplayIcon", &display_icon.to_string_lossy()),
+            ("Publisher", "Oleg Skutte"),
+            ("DisplayVersion", &version_info),
+        ],
+        &[
+            ("EstimatedSize", estimated_size_kib(install_path)),
+            ("NoModify", 1),
+            ("NoRepair", 1),
+        ],
+        true,
+    )
+}
+
+pub fn unregister_uninstaller() {
+    let subkey_h = HSTRING::from(UNINSTALL_KEY);
+    unsafe {
+        let _ = RegDeleteKeyW(HKEY_CURRENT_USER, PCWSTR::from_raw(subkey_h.as_ptr()));
+    }
+}
+
+/// A running executable cannot be deleted on Windows, so a detached `cmd` waits for the updater to
+/// exit and then removes it together with the install directory, or the whole directory when
+/// purging or for legacy installs without a manifest.
+pub fn remove_install(exe_path: &Path, install_path: &Path, remove_directory: bool) {
+    let spawned = if remove_directory {
+        Command::new("cmd")
+            .args([
+                "/C",
+                "ping",
+                "127.0.0.1",
+                "-n",
+                "3",
+                ">",
+                "nul",
+                "&",
+                "rmdir",
+                "/s",
+                "/q",
+                &install_path.to_string_lossy(),
+            ])
+            .spawn()
+    } else {
+        Command::new("cmd")
+            .args([
+                "/C",
+                "ping",
+                "127.0.0.1",
+                "-n",
+                "3",
+                ">",
+                "nul",
+                "&",
+                "del",
+                "/q",
+                &exe_path.to_string_lossy(),
+                "&",
+                "rmdir",
+                &install_path.to_string_lossy(),
+            ])
+            .spawn()
+    };
+    // Without `cmd`, Windows deletes the updater and the then empty folder at the next restart.
+    if spawned.is_err() {
+        schedule_on_reboot(exe_path, None).ok();
+        schedule_on_reboot(install_path, None).ok();
+    }
+}
+
+/// Registers a Task Scheduler task running `program` with `args` every day at `hour:minute`, as
+/// the current user and only while they are logged on, so notifications reach their desktop.
+/// Replaces the task registered for the same install.
+pub fn schedule_daily(base_path: &Path, program: &Path, args: &[&str], hour: u32, minute: u32) -> io::Result<()> {
+    let time = format!("{:02}:{:02}", hour, minute);
+    create_task(
+        &super::scheduled_task_name(base_path, "check"),
+        &["/SC", "DAILY", "/ST", &time],
+        program,
+        args,
+    )
+}
+
+/// Registers a Task Scheduler task running `program` with `args` every `minutes`, as SYSTEM so it
+/// runs whether or not anyone is logged on. Needs administrator rights.
+pub fn schedule_periodic(base_path: &Path, program: &Path, args: &[&str], minutes: u32) -> io::Result<()> {
+    create_task(
+        &super::scheduled_task_name(base_path, "maintenance"),
+        &["/SC", "MINUTE", "/MO", &minutes.to_string(), "/RU", "SYSTEM"],
+        program,
+        args,
+    )
+}
+
+/// Removes the tasks registered for the install. Returns whether there were any.
+pub fn unschedule(base_path: &Path) -> io::Result<bool> {
+    let mut removed = false;
+    for purpose in ["check", "maintenance"] {
+        removed |= delete_task(&super::scheduled_task_name(base_path, purpose))?;
+    }
+    Ok(removed)
+}
+
+fn create_task(name: &str, schedule: &[&str], program: &Path, args: &[&str]) -> io::Result<()> {
+    let mut task = quote_argument(&program.to_string_lossy());
+    for arg in args {
+        task.push(' ');
+        task.push_str(&quote_argument(arg));
+    }
+    let mut command = Command::new("schtasks");
+    command.args(["/Create", "/F", "/TN", name, "/TR", &task]).args(schedule);
+    run_schtasks(command)
+}
+
+fn delete_task(name: &str) -> io::Result<bool> {
+    let mut query = Command::new("schtasks");
+    query.args(["/Query", "/TN", name]);
+    hide_window(&mut query);
+    if !query.output()?.status.success() {
+        return Ok(false);
+    }
+    let mut command = Command::new("schtasks");
+    command.args(["/Delete", "/F", "/TN", name]);
+    run_schtasks(command)?;
+    Ok(true)
+}
+
+fn run_schtasks(mut command: Command) -> io::Result<()> {
+    hide_window(&mut command);
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Shows a toast from the updater. Clicking it opens what `click` names, through a link protocol
+/// registered for the install, since the updater is not running anymore by then. Best effort:
+/// nothing is shown on systems without toasts.
+pub fn show_notification(base_path: &Path, title: &str, message: &str, click: NotificationClick) {
+    if let Err(e) = register_notifications(base_path).and_then(|()| show_toast(base_path, title, message, click)) {
+        log::warn!("Failed to show a notification: {}", e);
+    }
+}
+
+fn show_toast(base_path: &Path, title: &str, message: &str, click: NotificationClick) -> windows::core::Result<()> {
+    let content = format!(
+        "<toast activationType=\"protocol\" launch=\"{}\"><visual><binding template=\"ToastGeneric\"><text>{}</text><text>{}</text></binding></visual></toast>",
+        xml_escape(&click.link(base_path)),
+        xml_escape(title),
+        xml_escape(message)
+    );
+    unsafe {
+        // WinRT needs COM on this thread; it may already be initialized.
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+    }
+    let xml = XmlDocument::new()?;
+    xml.LoadXml(&HSTRING::from(content))?;
+    let toast = ToastNotification::CreateToastNotification(&xml)?;
+    ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_USER_MODEL_ID))?.Show(&toast)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Registers the app ID toasts are shown under, which unpackaged programs need for Windows to
+/// accept them, and the link protocol clicking a toast opens.
+fn register_notifications(base_path: &Path) -> windows::core::Result<()> {
+    let exe_path = base_path.join(UPDATER_EXECUTABLE);
+    write_registry_values(
+        &format!("Software\\Classes\\AppUserModelId\\{}", APP_USER_MODEL_ID),
+        &[("DisplayName", "DREAMIO updater")],
+        &[],
+        true,
+    )?;
+    let protocol_key = format!("Software\\Classes\\{}", super::notification_scheme(base_path));
+    write_registry_values(&protocol_key, &[("", "URL:DREAMIO updater"), ("URL Protocol", "")], &[], true)?;
+    write_registry_values(
+        &format!("{}\\shell\\open\\command", protocol_key),
+        &[("", &format!("\"{}\" \"%1\"", exe_path.to_string_lossy()))],
+        &[],
+        true,
+    )
+}
+
+/// Removes the link protocol of the install. The app ID stays for other installs.
+pub fn unregister_notifications(base_path: &Path) {
+    let protocol_key = HSTRING::from(format!("Software\\Classes\\{}", super::notification_scheme(base_path)));
+    unsafe {
+        let _ = RegDeleteTreeW(HKEY_CURRENT_USER, PCWSTR::from_raw(protocol_key.as_ptr()));
+    }
+}
+
+/// Shows `message` in every session on this computer, including other users' and from the
+/// SYSTEM account. Best effort.
+pub fn broadcast_message(message: &str) {
+    let mut command = Command::new("msg");
+    command.args(["*", "/TIME:60", message]);
+    hide_window(&mut command);
+    if let Err(e) = command.status() {
+        log::warn!("Failed to show a message to logged-on users: {}", e);
+    }
+}
+
+/// How far local time is ahead of UTC right now, in seconds.
+pub fn local_utc_offset() -> i64 {
+    // TIME_ZONE_ID_DAYLIGHT
+    const DAYLIGHT: u32 = 2;
+    let mut zone = TIME_ZONE_INFORMATION::default();
+    let id = unsafe { GetTimeZoneInformation(&mut zone) };
+    let bias = if id == DAYLIGHT {
+        zone.Bias + zone.DaylightBias
+    } else {
+        zone.Bias + zone.StandardBias
+    };
+    // Windows gives the minutes to add to local time to get UTC.
+    -(bias as i64) * 60
+}
+
+pub fn set_thread_priority(priority: ThreadPriority) -> io::Result<()> {
+    let level = match priority {
+        ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+        ThreadPriority::Low => THREAD_PRIORITY_BELOW_NORMAL,
+        ThreadPriority::Idle => THREAD_PRIORITY_IDLE,
+    };
+    unsafe { SetThreadPriority(GetCurrentThread(), level) }.map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Keeps the current thread on `cores`, numbered from 0. Only the first 64 cores can be chosen.
+pub fn set_thread_affinity(cores: &[usize]) -> io::Result<()> {
+    let mask = cores.iter().filter(|&&core| core < 64).fold(0usize, |mask, core| mask | 1 << core);
+    if mask == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no usable core"));
+    }
+    if unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
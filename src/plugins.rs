@@ -0,0 +1,195 @@
+//! Policy plugins: WebAssembly modules in the `plugins` folder next to the game that can veto or
+//! change some of the updater's decisions, for community servers with rules of their own. They run
+//! sandboxed in an interpreter with a bounded amount of fuel and memory per call, and can do
+//! nothing but log; they see no files, network or environment.
+//!
+//! A plugin exports `memory` and `alloc(len: i32) -> i32`, returning where the updater may write
+//! `len` bytes, and any of these hooks. Each receives a UTF-8 JSON object at `(ptr, len)`:
+//!
+//! - `should_update(ptr, len) -> i32` with `current`, `latest`, `channel` and `downloadSize`.
+//!   Returning 0 postpones the update. Mandatory updates cannot be postponed.
+//! - `pick_mirror(ptr, len) -> i32` with `servers`, the download server first and then the
+//!   `mirrors` of the manifest. Returns the index of the server to download from, or -1.
+//! - `on_conflict(ptr, len) -> i32` with `file` and `reason` (`modified`) when a file to patch was
+//!   changed locally. Returns 1 to keep the local file, 2 to replace it, or 0 to leave the choice
+//!   to the player.
+//!
+//! The only import is `env.log(ptr: i32, len: i32)`, writing a UTF-8 message to `updater.log`.
+//! A plugin that fails to load, traps or runs out of fuel is logged and has no say.
+
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use wasmi::{Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+pub const PLUGINS_DIR: &str = "plugins";
+/// Instructions a single hook call may execute, plenty for a policy and little enough that a
+/// runaway loop is stopped within a fraction of a second.
+const FUEL_PER_CALL: u64 = 50_000_000;
+const MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+struct HostState {
+    name: String,
+    limits: StoreLimits,
+}
+
+struct Plugin {
+    name: String,
+    store: Store<HostState>,
+    instance: Instance,
+    memory: Memory,
+}
+
+static PLUGINS: OnceLock<Mutex<Vec<Plugin>>> = OnceLock::new();
+
+/// What a plugin wants done with a file that was changed locally.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Conflict {
+    Default,
+    Keep,
+    Replace,
+}
+
+/// Loads the plugins of the install in `base_path`, once per run. Returns whether any is loaded.
+pub fn load(base_path: &Path) -> bool {
+    let plugins = PLUGINS.get_or_init(|| {
+        let mut plugins = Vec::new();
+        let Ok(entries) = fs::read_dir(base_path.join(PLUGINS_DIR)) else {
+            return Mutex::new(plugins);
+        };
+        let mut paths: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "wasm"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            match Plugin::load(&path) {
+                Ok(plugin) => {
+                    log::info!("Loaded plugin {}", plugin.name);
+                    plugins.push(plugin);
+                }
+                Err(e) => log::warn!("Failed to load plugin {}: {}", path.display(), e),
+            }
+        }
+        Mutex::new(plugins)
+    });
+    plugins.lock().map(|plugins| !plugins.is_empty()).unwrap_or(false)
+}
+
+/// Whether every plugin allows updating. Without plugins, updates always go ahead.
+pub fn should_update(current: &str, latest: &str, channel: &str, download_size: u64) -> bool {
+    let request = json!({
+        "current": current,
+        "latest": latest,
+        "channel": channel,
+        "downloadSize": download_size,
+    });
+    call_each("should_update", &request).into_iter().all(|(name, answer)| {
+        if answer == 0 {
+            log::info!("Plugin {} postponed the update to version {}", name, latest);
+        }
+        answer != 0
+    })
+}
+
+/// The server to download from, chosen by the first plugin with a preference.
+pub fn pick_mirror(servers: &[String]) -> Option<usize> {
+    let request = json!({ "servers": servers });
+    call_each("pick_mirror", &request)
+        .into_iter()
+        .find_map(|(_, answer)| usize::try_from(answer).ok().filter(|&index| index < servers.len()))
+}
+
+/// What to do with `file`, changed locally, according to the first plugin with an opinion.
+pub fn on_conflict(file: &str, reason: &str) -> Conflict {
+    let request = json!({ "file": file, "reason": reason });
+    call_each("on_conflict", &request)
+        .into_iter()
+        .find_map(|(_, answer)| match answer {
+            1 => Some(Conflict::Keep),
+            2 => Some(Conflict::Replace),
+            _ => None,
+        })
+        .unwrap_or(Conflict::Default)
+}
+
+/// Calls `hook` on every plugin exporting it and returns their answers in load order.
+fn call_each(hook: &str, request: &Value) -> Vec<(String, i32)> {
+    let Some(plugins) = PLUGINS.get() else {
+        return Vec::new();
+    };
+    let Ok(mut plugins) = plugins.lock() else {
+        return Vec::new();
+    };
+    let input = request.to_string();
+    let mut answers = Vec::new();
+    for plugin in plugins.iter_mut() {
+        match plugin.call(hook, input.as_bytes()) {
+            Ok(Some(answer)) => answers.push((plugin.name.clone(), answer)),
+            Ok(None) => {}
+            Err(e) => log::warn!("Plugin {} failed in {}: {}", plugin.name, hook, e),
+        }
+    }
+    answers
+}
+
+impl Plugin {
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &fs::read(path)?[..])?;
+        let mut store = Store::new(
+            &engine,
+            HostState {
+                name: name.clone(),
+                limits: StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).instances(1).build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+
+        let mut linker = Linker::<HostState>::new(&engine);
+        linker.func_wrap("env", "log", |caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                return;
+            };
+            let mut message = vec![0u8; len.clamp(0, 4096) as usize];
+            if memory.read(&caller, ptr as usize, &mut message).is_ok() {
+                log::info!("[{}] {}", caller.data().name, String::from_utf8_lossy(&message));
+            }
+        })?;
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("the module does not export its memory")?;
+        Ok(Self {
+            name,
+            store,
+            instance,
+            memory,
+        })
+    }
+
+    /// Calls `hook` with `input`, or returns `None` when the plugin does not export it.
+    fn call(&mut self, hook: &str, input: &[u8]) -> Result<Option<i32>, Box<dyn Error>> {
+        let Ok(function) = self.instance.get_typed_func::<(i32, i32), i32>(&self.store, hook) else {
+            return Ok(None);
+        };
+        let alloc = self.instance.get_typed_func::<i32, i32>(&self.store, "alloc")?;
+        self.store.add_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as usize, input)
+            .map_err(|e| e.to_string())?;
+        Ok(Some(function.call(&mut self.store, (ptr, len))?))
+    }
+}
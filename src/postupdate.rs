@@ -0,0 +1,122 @@
+//! What the updater does once the game is up to date: start it, open the changelog, show what was
+//! installed and wait, run a command, or nothing. `afterUpdate` in `updater.json` or
+//! `--after-update` choose. Without either, the updater window starts the game, as it always did,
+//! while `--windowless`, maintenance and installs from the console do nothing, since nobody might
+//! be there to play.
+//!
+//! The changelog is `changelogUrl` from `updater.json`, or else from the manifest of the installed
+//! build, with `{version}` replaced by the installed version.
+
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::{get_version_info, launch_game, manifest, Options};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostUpdateAction {
+    Launch,
+    OpenChangelog,
+    /// Shows the version installed and its release notes until the player closes the window.
+    Summary,
+    Nothing,
+    /// Program relative to the install folder and its arguments.
+    Command(Vec<String>),
+}
+
+impl PostUpdateAction {
+    /// `launch`, `changelog`, `summary` or `none`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "launch" => Some(Self::Launch),
+            "changelog" => Some(Self::OpenChangelog),
+            "summary" => Some(Self::Summary),
+            "none" => Some(Self::Nothing),
+            _ => None,
+        }
+    }
+
+    /// One of the names, or a command as an array of the program and its arguments.
+    pub fn from_json(json: &Value) -> Option<Self> {
+        match json {
+            Value::String(name) => Self::parse(name),
+            Value::Array(args) => {
+                let command: Vec<String> = args.iter().filter_map(|arg| arg.as_str().map(|s| s.to_string())).collect();
+                (!command.is_empty()).then_some(Self::Command(command))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Launch => "start the game".to_string(),
+            Self::OpenChangelog => "open the changelog".to_string(),
+            Self::Summary => "show a summary".to_string(),
+            Self::Nothing => "nothing".to_string(),
+            Self::Command(command) => format!("run {}", command.join(" ")),
+        }
+    }
+}
+
+/// The action in effect for a run with `options`. `--no-launch` only rules out starting the game.
+pub fn action(options: &Options) -> PostUpdateAction {
+    match &options.after_update {
+        Some(PostUpdateAction::Launch) | None if options.no_launch => PostUpdateAction::Nothing,
+        Some(action) => action.clone(),
+        None if options.windowless || options.maintenance => PostUpdateAction::Nothing,
+        None => PostUpdateAction::Launch,
+    }
+}
+
+/// Carries out `action` for the install in `base_path`. The summary is left to the caller, which
+/// knows how to show it.
+pub fn run(base_path: &Path, action: &PostUpdateAction, launch_args: &[String]) -> Result<(), String> {
+    match action {
+        PostUpdateAction::Launch => launch_game(base_path, launch_args)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to launch game: {}", e)),
+        PostUpdateAction::OpenChangelog => {
+            let url = changelog_url(base_path).ok_or("No changelog is published for this game.")?;
+            log::info!("Opening the changelog at {}", url);
+            opener::open(&url).map_err(|e| format!("Failed to open the changelog at {}: {}", url, e))
+        }
+        PostUpdateAction::Command(command) => run_command(base_path, command)
+            .map_err(|e| format!("Failed to run the after-update command {}: {}", command.join(" "), e)),
+        PostUpdateAction::Summary | PostUpdateAction::Nothing => Ok(()),
+    }
+}
+
+/// The changelog of the installed version, when one is configured or published.
+pub fn changelog_url(base_path: &Path) -> Option<String> {
+    let url = Config::load(base_path)
+        .changelog_url
+        .or_else(|| manifest::cached_manifest_json(base_path)["changelogUrl"].as_str().map(|s| s.to_string()))?;
+    let version = get_version_info(base_path).map(|info| info.version_code).unwrap_or_default();
+    Some(url.replace("{version}", &version))
+}
+
+/// Started without waiting for it, in the install folder, with the installed version in
+/// `DREAMIO_VERSION`.
+fn run_command(base_path: &Path, command: &[String]) -> io::Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the command is empty"))?;
+    let version = get_version_info(base_path).map(|info| info.version_code).unwrap_or_default();
+    Command::new(base_path.join(program))
+        .args(args)
+        .current_dir(base_path)
+        .env("DREAMIO_VERSION", version)
+        .spawn()
+        .map(|_| ())
+}
+
+/// One line telling the player what was installed.
+pub fn summary(base_path: &Path) -> String {
+    match get_version_info(base_path) {
+        Ok(info) => format!("DREAMIO is up to date at version {}.", info.version_string),
+        Err(_) => "DREAMIO is up to date.".to_string(),
+    }
+}
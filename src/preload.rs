@@ -0,0 +1,122 @@
+//! Preloads of a version published before its release. Its archives are encrypted by the publisher
+//! and downloaded into the prefetch folder as they are, so the content of a big drop is on the
+//! player's disk ahead of time but unreadable until the manifest releases the version along with
+//! the key. The archives are only decrypted while applying the update.
+//!
+//! The cipher is SHA-256 in counter mode: block `i` of the keystream is `sha256(key || i)`, with
+//! `i` as 8 little-endian bytes. It only keeps the content secret; the manifest's hash of the
+//! encrypted archive and the zip's own checksums catch corruption.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::hashing::sha256_file;
+
+const BLOCK_SIZE: usize = 32;
+
+/// Parses a hex encoded key as published in the manifest.
+pub fn parse_key(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// XORs everything read from `reader` with the keystream of `key` into `writer`. Encrypting and
+/// decrypting are the same operation.
+fn apply_keystream<R: Read, W: Write>(key: &[u8], reader: &mut R, writer: &mut W) -> io::Result<()> {
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut block = [0u8; BLOCK_SIZE];
+    let mut counter: u64 = 0;
+    let mut used = BLOCK_SIZE;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return writer.flush();
+        }
+        for byte in &mut buffer[..n] {
+            if used == BLOCK_SIZE {
+                let mut hasher = Sha256::new();
+                hasher.update(key);
+                hasher.update(counter.to_le_bytes());
+                block.copy_from_slice(&hasher.finalize());
+                counter += 1;
+                used = 0;
+            }
+            *byte ^= block[used];
+            used += 1;
+        }
+        writer.write_all(&buffer[..n])?;
+    }
+}
+
+fn transform_file(source: &Path, target: &Path, key: &[u8]) -> io::Result<()> {
+    let mut reader = File::open(source)?;
+    let mut writer = BufWriter::new(File::create(target)?);
+    apply_keystream(key, &mut reader, &mut writer)
+}
+
+/// Decrypts the archive at `path` in place with the hex encoded `key` from the manifest.
+pub fn decrypt_in_place(path: &Path, key: &str) -> io::Result<()> {
+    let key = parse_key(key).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Invalid preload key in manifest")
+    })?;
+    let mut decrypted = path.as_os_str().to_owned();
+    decrypted.push(".decrypted");
+    let decrypted = PathBuf::from(decrypted);
+    if let Err(e) = transform_file(path, &decrypted, &key) {
+        fs::remove_file(&decrypted).ok();
+        return Err(e);
+    }
+    fs::rename(&decrypted, path)
+}
+
+/// Encrypts an archive for publishing as a preload, printing what goes into the manifest.
+pub fn run_encrypt_preload(args: &[String]) -> i32 {
+    let mut input = None;
+    let mut output = None;
+    let mut key = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-i" | "--input" => input = args.next().map(PathBuf::from),
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            "--key" => key = args.next().cloned(),
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                return 2;
+            }
+        }
+    }
+
+    let (Some(input), Some(output), Some(key)) = (input, output, key) else {
+        eprintln!("Usage: DreamioUpdater encrypt-preload -i <archive> -o <encrypted archive> --key <hex key>");
+        return 2;
+    };
+    let Some(key_bytes) = parse_key(&key) else {
+        eprintln!("The key must be hex encoded, e.g. from `openssl rand -hex 32`");
+        return 2;
+    };
+
+    let result = transform_file(&input, &output, &key_bytes)
+        .and_then(|_| Ok((sha256_file(&output)?, fs::metadata(&output)?.len())));
+    match result {
+        Ok((sha256, size)) => {
+            println!("Encrypted {} to {}", input.display(), output.display());
+            println!("size:   {}", size);
+            println!("sha256: {}", sha256);
+            println!("Publish the key as \"key\" of the archive's manifest entry once the version is released.");
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to encrypt {}: {}", input.display(), e);
+            1
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Paths an update never overwrites, patches or deletes: save games, mods and settings living in
+//! the install folder. They come from `protected` in `manifest.json` and from `preserve.txt` next to
+//! the game, one pattern per line:
+//!
+//! ```text
+//! # Saves and my own mods
+//! Saves/
+//! Mods/**
+//! *.cfg
+//! ```
+//!
+//! A pattern ending in `/` covers the folder and everything in it. `*` matches within a path
+//! segment and `**` across segments. Patterns without a `/` match the file name in any folder.
+//! Archives may still add a protected file that does not exist yet, such as default settings.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+use crate::manifest::cached_manifest_json;
+use crate::metadata::METADATA_FILE_NAME;
+
+pub const PRESERVE_FILE_NAME: &str = "preserve.txt";
+
+/// How an archive entry would have touched a protected path.
+pub enum Touch {
+    Overwrite,
+    Patch,
+    Delete,
+}
+
+impl Touch {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Touch::Overwrite => "would be overwritten",
+            Touch::Patch => "would be patched",
+            Touch::Delete => "would be deleted",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ProtectedPaths {
+    patterns: Vec<String>,
+}
+
+impl ProtectedPaths {
+    /// The patterns for the install in `base_path`, from the last manifest fetched and
+    /// `preserve.txt`.
+    pub fn load(base_path: &Path) -> Self {
+        let mut patterns: Vec<String> = cached_manifest_json(base_path)["protected"]
+            .as_array()
+            .map(|values| values.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default();
+        if let Ok(content) = fs::read_to_string(base_path.join(PRESERVE_FILE_NAME)) {
+            patterns.extend(
+                content
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.to_string()),
+            );
+        }
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| normalize(&pattern))
+            .filter(|pattern| !pattern.is_empty() && pattern != "/")
+            .collect();
+        Self { patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether `name`, relative to the install folder, is protected.
+    pub fn covers(&self, name: &str) -> bool {
+        let name = normalize(name);
+        let name = name.trim_end_matches('/');
+        self.patterns.iter().any(|pattern| matches(pattern, name))
+    }
+
+    /// How applying archive entry `name` to `base_path` would touch a protected path, if at all.
+    /// Deleting a folder touches every protected file still in it.
+    pub fn touched_by(&self, base_path: &Path, name: &str) -> Option<Touch> {
+        if self.is_empty() || name.ends_with('/') {
+            return None;
+        }
+        if let Some(target) = name.strip_suffix(".delete") {
+            let path = base_path.join(target);
+            let protected = if path.is_dir() {
+                self.covers(target) || self.any_within(base_path, &path)
+            } else {
+                self.covers(target) && fs::symlink_metadata(&path).is_ok()
+            };
+            return protected.then_some(Touch::Delete);
+        }
+        if let Some(target) = name.strip_suffix(".patch") {
+            return self.covers(target).then_some(Touch::Patch);
+        }
+        (self.covers(name) && fs::symlink_metadata(base_path.join(name)).is_ok()).then_some(Touch::Overwrite)
+    }
+
+    fn any_within(&self, base_path: &Path, dir: &Path) -> bool {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return false;
+        };
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            let relative = path.strip_prefix(base_path).unwrap_or(&path).to_string_lossy().to_string();
+            self.covers(&relative) || (path.is_dir() && !path.is_symlink() && self.any_within(base_path, &path))
+        })
+    }
+}
+
+/// Whether `name`, relative to the install folder, matches any of `patterns`, written like the
+/// lines of `preserve.txt`.
+pub fn matches_any(patterns: &[String], name: &str) -> bool {
+    let name = normalize(name);
+    patterns
+        .iter()
+        .any(|pattern| matches(&normalize(pattern), name.trim_end_matches('/')))
+}
+
+/// Forward slashes, no leading `./` or `/`, and lowercase on Windows, whose paths ignore case.
+fn normalize(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    if cfg!(windows) {
+        path.to_lowercase()
+    } else {
+        path.to_string()
+    }
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    if let Some(folder) = pattern.strip_suffix('/') {
+        let segments = folder.split('/').count();
+        let parts: Vec<&str> = name.split('/').collect();
+        return parts.len() >= segments && glob(folder, &parts[..segments].join("/"));
+    }
+    if !pattern.contains('/') {
+        return name.rsplit('/').next().is_some_and(|file_name| glob(pattern, file_name));
+    }
+    glob(pattern, name)
+}
+
+/// Matches `*` within a segment and `**` across segments.
+fn glob(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    fn go(p: &[u8], n: &[u8]) -> bool {
+        match p.first() {
+            None => n.is_empty(),
+            Some(b'*') if p.get(1) == Some(&b'*') => {
+                let rest = p[2..].strip_prefix(b"/").unwrap_or(&p[2..]);
+                (0..=n.len()).any(|i| (i == 0 || n[i - 1] == b'/' || rest.is_empty()) && go(rest, &n[i..]))
+            }
+            Some(b'*') => (0..=n.len())
+                .take_while(|&i| i == 0 || n[i - 1] != b'/')
+                .any(|i| go(&p[1..], &n[i..])),
+            Some(&c) => n.first() == Some(&c) && go(&p[1..], &n[1..]),
+        }
+    }
+    go(pattern, name)
+}
+
+/// `protected <archive> [<install dir>]` lists what applying the archive would have done to
+/// protected files, without changing anything.
+pub fn run_protected_report(args: &[String]) -> i32 {
+    let Some(archive_path) = args.first().map(PathBuf::from) else {
+        eprintln!("Usage: DreamioUpdater protected <archive.zip> [<install dir>]");
+        return 2;
+    };
+    let base_path = args
+        .get(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+
+    let protected = ProtectedPaths::load(&base_path);
+    if protected.is_empty() {
+        println!(
+            "No protected paths: neither manifest.json nor {} lists any.",
+            base_path.join(PRESERVE_FILE_NAME).display()
+        );
+        return 0;
+    }
+    println!("Protected paths:");
+    for pattern in &protected.patterns {
+        println!("  {}", pattern);
+    }
+
+    let archive = fs::File::open(&archive_path).map_err(zip::result::ZipError::from).and_then(ZipArchive::new);
+    let mut archive = match archive {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", archive_path.display(), e);
+            return 1;
+        }
+    };
+    let mut kept = 0;
+    for i in 0..archive.len() {
+        let Ok(file) = archive.by_index(i) else {
+            continue;
+        };
+        if file.name() == METADATA_FILE_NAME {
+            continue;
+        }
+        if let Some(touch) = protected.touched_by(&base_path, file.name()) {
+            let name = file.name().trim_end_matches(".delete").trim_end_matches(".patch");
+            println!("  {} {}, it will be kept", name, touch.describe());
+            kept += 1;
+        }
+    }
+    if kept == 0 {
+        println!("{} does not touch any protected file.", archive_path.display());
+    } else {
+        println!("{} protected file(s) would have been changed by {}.", kept, archive_path.display());
+    }
+    0
+}
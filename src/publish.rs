@@ -0,0 +1,505 @@
+//! Publisher-side tooling for building update archives. These commands are run from a console by
+//! the release pipeline, never by players.
+
+use qbsdiff::Bsdiff;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::error::UpdaterError;
+use crate::hashing::{sha256_bytes, sha256_file, sha256_reader};
+use crate::manifest::{self, Artifact};
+use crate::metadata::{FileHashes, UpdateMetadata, METADATA_FILE_NAME};
+use crate::{apply_update, format_bytes, http, UpdateMessage};
+
+/// Formats that are already compressed and gain nothing from deflate.
+const STORED_EXTENSIONS: [&str; 14] = [
+    "ogg", "mp3", "wav", "webp", "png", "jpg", "jpeg", "mp4", "webm", "bundle", "zip", "7z", "gz",
+    "ress",
+];
+
+/// Text formats that are worth the extra CPU of maximum compression.
+const TEXT_EXTENSIONS: [&str; 10] = [
+    "json", "txt", "xml", "csv", "yaml", "yml", "ini", "cfg", "md", "html",
+];
+
+const REPORT_TOP_ENTRIES: usize = 20;
+
+pub struct EntryStats {
+    name: String,
+    kind: &'static str,
+    file_size: u64,
+    archive_size: u64,
+}
+
+pub fn run_generate_patch(args: &[String]) -> i32 {
+    let mut old_dir = None;
+    let mut new_dir = None;
+    let mut output = None;
+    let mut report_path = None;
+    let mut file_base_url = None;
+    let mut reverse_output = None;
+    let mut optional = HashSet::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--old" => old_dir = args.next().map(PathBuf::from),
+            "--new" => new_dir = args.next().map(PathBuf::from),
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            "--report" => report_path = args.next().map(PathBuf::from),
+            "--file-base-url" => file_base_url = args.next().cloned(),
+            "--reverse" => reverse_output = args.next().map(PathBuf::from),
+            "--optional" => optional.extend(args.next().cloned()),
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                return 2;
+            }
+        }
+    }
+
+    let (Some(old_dir), Some(new_dir), Some(output)) = (old_dir, new_dir, output) else {
+        eprintln!("Usage: DreamioUpdater generate-patch --old <dir> --new <dir> -o <patch.zip> [--report <report.txt>] [--file-base-url <url>] [--reverse <reverse.zip>] [--optional <file>]...");
+        return 2;
+    };
+
+    // The reverse patch takes the new build back to the old one, for players rolling back.
+    if let Some(reverse_output) = &reverse_output {
+        if let Err(e) = generate_patch(&new_dir, &old_dir, reverse_output, None, optional.clone()) {
+            eprintln!("Failed to generate reverse patch: {}", e);
+            return 1;
+        }
+        println!("Reverse patch written to {}", reverse_output.display());
+    }
+
+    match generate_patch(&old_dir, &new_dir, &output, file_base_url, optional) {
+        Ok(stats) => {
+            let report = format_report(&stats);
+            print!("{}", report);
+            if let Some(report_path) = report_path {
+                if let Err(e) = fs::write(&report_path, &report) {
+                    eprintln!("Failed to write report to {}: {}", report_path.display(), e);
+                    return 1;
+                }
+            }
+            println!("Patch written to {}", output.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to generate patch: {}", e);
+            1
+        }
+    }
+}
+
+pub fn run_simulate(args: &[String]) -> i32 {
+    let mut base_dir = None;
+    let mut patch_path = None;
+    let mut expected_dir = None;
+    let mut keep_staging = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--base" => base_dir = args.next().map(PathBuf::from),
+            "--patch" => patch_path = args.next().map(PathBuf::from),
+            "--expected" => expected_dir = args.next().map(PathBuf::from),
+            "--keep" => keep_staging = true,
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                return 2;
+            }
+        }
+    }
+
+    let (Some(base_dir), Some(patch_path), Some(expected_dir)) = (base_dir, patch_path, expected_dir) else {
+        eprintln!("Usage: DreamioUpdater simulate --base <dir> --patch <patch.zip> --expected <new build dir> [--keep]");
+        return 2;
+    };
+
+    let staging_dir = std::env::temp_dir().join(format!("dreamio-simulate-{}", std::process::id()));
+    let result = simulate(&base_dir, &patch_path, &expected_dir, &staging_dir);
+    if keep_staging {
+        println!("Staging directory kept at {}", staging_dir.display());
+    } else {
+        fs::remove_dir_all(&staging_dir).ok();
+    }
+
+    match result {
+        Ok(problems) if problems.is_empty() => {
+            println!("Simulation passed: the patched install matches {}", expected_dir.display());
+            0
+        }
+        Ok(problems) => {
+            for problem in &problems {
+                println!("  {}", problem);
+            }
+            println!("Simulation failed: {} problem(s) found", problems.len());
+            1
+        }
+        Err(e) => {
+            eprintln!("Simulation failed: {}", e);
+            1
+        }
+    }
+}
+
+/// Applies `patch_path` to a copy of `base_dir` and lists every difference to `expected_dir`.
+fn simulate(base_dir: &Path, patch_path: &Path, expected_dir: &Path, staging_dir: &Path) -> io::Result<Vec<String>> {
+    if staging_dir.exists() {
+        fs::remove_dir_all(staging_dir)?;
+    }
+    for name in collect_files(base_dir)? {
+        let target = staging_dir.join(&name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(base_dir.join(&name), target)?;
+    }
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    apply_update(patch_path, staging_dir, &sender)?;
+
+    let mut problems = Vec::new();
+    for message in receiver.try_iter() {
+        if let UpdateMessage::Error(error, _, _) = message {
+            problems.push(error);
+        }
+    }
+
+    let staged_files: HashSet<String> = collect_files(staging_dir)?
+        .into_iter()
+        .filter(|name| name != "install_manifest.txt")
+        .collect();
+    let expected_files = collect_files(expected_dir)?;
+    for name in &expected_files {
+        if !staged_files.contains(name) {
+            problems.push(format!("Missing after patching: {}", name));
+        } else if fs::read(staging_dir.join(name))? != fs::read(expected_dir.join(name))? {
+            problems.push(format!("Content differs: {}", name));
+        }
+    }
+    let expected_set: HashSet<&String> = expected_files.iter().collect();
+    let mut extra: Vec<&String> = staged_files.iter().filter(|name| !expected_set.contains(name)).collect();
+    extra.sort();
+    for name in extra {
+        problems.push(format!("Not part of the new build: {}", name));
+    }
+
+    Ok(problems)
+}
+
+pub fn run_verify_release(args: &[String]) -> i32 {
+    let mut manifest_url = None;
+    let mut local_dir = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--manifest" => manifest_url = args.next().cloned(),
+            "--local" => local_dir = args.next().map(PathBuf::from),
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                return 2;
+            }
+        }
+    }
+
+    let (Some(manifest_url), Some(local_dir)) = (manifest_url, local_dir) else {
+        eprintln!("Usage: DreamioUpdater verify-release --manifest <url> --local <build output dir>");
+        return 2;
+    };
+
+    let remote_manifest = match manifest::fetch_manifest_from(&manifest_url) {
+        Ok(Some(remote_manifest)) => remote_manifest,
+        Ok(None) => {
+            eprintln!("No manifest found at {}", manifest_url);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch manifest: {}", e);
+            return 1;
+        }
+    };
+
+    let base_url = manifest_url.rsplit_once('/').map(|(base, _)| base).unwrap_or(&manifest_url);
+    let artifacts = remote_manifest
+        .full
+        .iter()
+        .chain(remote_manifest.patches.iter().map(|edge| &edge.artifact));
+
+    let mut divergent = 0;
+    let mut checked = 0;
+    for artifact in artifacts {
+        checked += 1;
+        let relative = artifact
+            .url
+            .strip_prefix(&format!("{}/", base_url))
+            .unwrap_or_else(|| artifact.url.rsplit('/').next().unwrap_or(&artifact.url));
+        match verify_artifact(artifact, &local_dir.join(relative)) {
+            Ok(problems) if problems.is_empty() => println!("OK        {}", relative),
+            Ok(problems) => {
+                divergent += 1;
+                println!("DIVERGES  {}", relative);
+                for problem in problems {
+                    println!("          {}", problem);
+                }
+            }
+            Err(e) => {
+                divergent += 1;
+                println!("ERROR     {}: {}", relative, e);
+            }
+        }
+    }
+
+    println!("{} artifact(s) checked, {} diverging", checked, divergent);
+    if divergent == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+/// Downloads a published artifact and compares it with the local build output and the manifest.
+fn verify_artifact(artifact: &Artifact, local_path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let client = http::client(Duration::from_secs(30))?;
+    let mut response = client.get(&artifact.url).send()?;
+    if !response.status().is_success() {
+        return Err(UpdaterError::HttpStatus(response.status()).into());
+    }
+    let published_hash = sha256_reader(&mut response)?;
+
+    let mut problems = Vec::new();
+    match sha256_file(local_path) {
+        Ok(local_hash) if local_hash != published_hash => problems.push(format!(
+            "published {} differs from local build {}",
+            published_hash, local_hash
+        )),
+        Ok(_) => {}
+        Err(e) => problems.push(format!("local build output {} unavailable: {}", local_path.display(), e)),
+    }
+    if let Some(expected) = &artifact.sha256 {
+        if *expected != published_hash {
+            problems.push(format!(
+                "published {} differs from manifest hash {}",
+                published_hash, expected
+            ));
+        }
+    }
+    if artifact.size > 0 {
+        if let Ok(local) = fs::metadata(local_path) {
+            if local.len() != artifact.size {
+                problems.push(format!(
+                    "manifest size {} differs from local size {}",
+                    artifact.size,
+                    local.len()
+                ));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+pub fn generate_patch(
+    old_dir: &Path,
+    new_dir: &Path,
+    output: &Path,
+    file_base_url: Option<String>,
+    optional: HashSet<String>,
+) -> io::Result<Vec<EntryStats>> {
+    let old_files = collect_files(old_dir)?;
+    let new_files = collect_files(new_dir)?;
+    let old_set: HashSet<&String> = old_files.iter().collect();
+    let new_set: HashSet<&String> = new_files.iter().collect();
+
+    let mut zip = ZipWriter::new(File::create(output)?);
+    let mut entries: Vec<(String, &'static str, u64)> = Vec::new();
+    let mut metadata = UpdateMetadata {
+        file_base_url,
+        optional,
+        ..Default::default()
+    };
+
+    for name in &new_files {
+        let new_contents = fs::read(new_dir.join(name))?;
+        if old_set.contains(name) {
+            let old_contents = fs::read(old_dir.join(name))?;
+            if old_contents == new_contents {
+                continue;
+            }
+            let mut patch = Vec::new();
+            Bsdiff::new(&old_contents, &new_contents).compare(Cursor::new(&mut patch))?;
+            if patch.len() < new_contents.len() {
+                // bsdiff output is already bzip2-compressed.
+                let entry = format!("{}.patch", name);
+                zip.start_file(entry.as_str(), stored_options())?;
+                zip.write_all(&patch)?;
+                entries.push((entry, "patch", new_contents.len() as u64));
+                metadata.files.insert(
+                    name.clone(),
+                    FileHashes {
+                        old: sha256_bytes(&old_contents),
+                        new: sha256_bytes(&new_contents),
+                    },
+                );
+                continue;
+            }
+        }
+        zip.start_file(name.as_str(), compression_options(name))?;
+        zip.write_all(&new_contents)?;
+        entries.push((name.clone(), "full", new_contents.len() as u64));
+    }
+
+    let mut deleted_dirs: Vec<String> = Vec::new();
+    for name in &old_files {
+        if new_set.contains(name) {
+            continue;
+        }
+        if deleted_dirs.iter().any(|d| name.starts_with(&format!("{}/", d))) {
+            continue;
+        }
+        // A whole folder that disappeared is deleted with a single entry.
+        let entry = match removed_parent_dir(name, new_dir) {
+            Some(dir) => {
+                deleted_dirs.push(dir.clone());
+                dir
+            }
+            None => name.clone(),
+        };
+        let entry = format!("{}.delete", entry);
+        zip.start_file(entry.as_str(), stored_options())?;
+        entries.push((entry, "delete", 0));
+    }
+
+    if !metadata.files.is_empty() || !metadata.optional.is_empty() {
+        zip.start_file(METADATA_FILE_NAME, compression_options(METADATA_FILE_NAME))?;
+        zip.write_all(metadata.to_json().as_bytes())?;
+    }
+
+    zip.finish()?;
+
+    let mut archive = ZipArchive::new(File::open(output)?)?;
+    let mut archive_sizes = HashMap::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        archive_sizes.insert(file.name().to_string(), file.compressed_size());
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|(name, kind, file_size)| EntryStats {
+            archive_size: archive_sizes.get(&name).copied().unwrap_or(0),
+            name,
+            kind,
+            file_size,
+        })
+        .collect())
+}
+
+fn format_report(stats: &[EntryStats]) -> String {
+    let mut report = String::new();
+    let total: u64 = stats.iter().map(|s| s.archive_size).sum();
+    let _ = writeln!(report, "Patch report: {} entries, {} total", stats.len(), format_bytes(total));
+
+    let mut largest: Vec<&EntryStats> = stats.iter().collect();
+    largest.sort_by_key(|s| std::cmp::Reverse(s.archive_size));
+    let _ = writeln!(report, "\nLargest entries:");
+    for s in largest.iter().take(REPORT_TOP_ENTRIES) {
+        let _ = writeln!(report, "  {:>12}  {} ({})", format_bytes(s.archive_size), s.name, s.kind);
+    }
+
+    // A patch close to the size of the file itself means the file diffs poorly between builds.
+    let mut worst: Vec<&EntryStats> = stats
+        .iter()
+        .filter(|s| s.kind == "patch" && s.file_size > 0)
+        .collect();
+    worst.sort_by(|a, b| diff_ratio(b).total_cmp(&diff_ratio(a)));
+    let _ = writeln!(report, "\nWorst-diffing files (patch size / file size):");
+    for s in worst.iter().take(REPORT_TOP_ENTRIES) {
+        let _ = writeln!(
+            report,
+            "  {:>6.1}%  {} ({} of {})",
+            diff_ratio(s) * 100.0,
+            s.name,
+            format_bytes(s.archive_size),
+            format_bytes(s.file_size)
+        );
+    }
+
+    let mut by_directory: BTreeMap<&str, u64> = BTreeMap::new();
+    for s in stats {
+        let directory = s.name.rsplit_once('/').map(|(dir, _)| dir).unwrap_or(".");
+        *by_directory.entry(directory).or_default() += s.archive_size;
+    }
+    let mut by_directory: Vec<(&str, u64)> = by_directory.into_iter().collect();
+    by_directory.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    let _ = writeln!(report, "\nSize by directory:");
+    for (directory, size) in by_directory {
+        let _ = writeln!(report, "  {:>12}  {}", format_bytes(size), directory);
+    }
+
+    report
+}
+
+fn diff_ratio(stats: &EntryStats) -> f64 {
+    stats.archive_size as f64 / stats.file_size as f64
+}
+
+/// Returns the topmost parent folder of `name` that no longer exists in the new build.
+fn removed_parent_dir(name: &str, new_dir: &Path) -> Option<String> {
+    let mut prefix = String::new();
+    for component in name.split('/').take(name.matches('/').count()) {
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+        prefix.push_str(component);
+        if !new_dir.join(&prefix).exists() {
+            return Some(prefix);
+        }
+    }
+    None
+}
+
+fn compression_options(name: &str) -> FileOptions {
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if STORED_EXTENSIONS.contains(&extension.as_str()) {
+        stored_options()
+    } else if TEXT_EXTENSIONS.contains(&extension.as_str()) {
+        FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(9))
+    } else {
+        FileOptions::default().compression_method(CompressionMethod::Deflated)
+    }
+}
+
+fn stored_options() -> FileOptions {
+    FileOptions::default().compression_method(CompressionMethod::Stored)
+}
+
+/// Lists all files below `root` as `/`-separated relative paths, sorted for reproducible archives.
+pub fn collect_files(root: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let name: Vec<String> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect();
+                files.push(name.join("/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,92 @@
+//! Result record for the process that started the updater, so launchers can tell what happened
+//! without parsing output. With `--report-file <path>` or `--report-fd <descriptor>`, a single
+//! JSON object is written when the updater exits:
+//!
+//! ```json
+//! {"status":"updated","oldVersion":"123","newVersion":"125","exitCode":2,"errorCode":null,"error":null}
+//! ```
+//!
+//! `status` is `updated`, `up-to-date`, `cancelled` or `failed`, `exitCode` is the code the updater
+//! exits with, see `exit`, and `errorCode` is the same coarse category failure reports use.
+//!
+//! When the updater restarts itself to finish an update, only the restarted updater writes the
+//! record.
+
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::error::ErrorCategory;
+use crate::{exit, get_version_info, platform};
+
+#[derive(Clone)]
+pub enum ReportTarget {
+    File(PathBuf),
+    /// A pipe or file descriptor (a handle on Windows) inherited from the parent process.
+    Descriptor(u64),
+}
+
+static LAST_ERROR: Mutex<Option<(String, ErrorCategory)>> = Mutex::new(None);
+static RESTARTING: AtomicBool = AtomicBool::new(false);
+
+/// Remembers the most recent error for the record.
+pub fn record_error(message: &str, category: ErrorCategory) {
+    if let Ok(mut last_error) = LAST_ERROR.lock() {
+        *last_error = Some((message.to_string(), category));
+    }
+}
+
+/// Leaves the record to the updater that was started to continue the update.
+pub fn record_restart() {
+    RESTARTING.store(true, Ordering::Relaxed);
+}
+
+/// Writes the record for a run that started at `old_version` and exits with `exit_code`.
+pub fn write(target: &ReportTarget, base_path: &Path, old_version: Option<&str>, exit_code: i32) {
+    if RESTARTING.load(Ordering::Relaxed) {
+        return;
+    }
+    let new_version = get_version_info(base_path).ok().map(|info| info.version_code);
+    let failed = exit::is_failure(exit_code);
+    let error = LAST_ERROR.lock().ok().and_then(|mut e| e.take()).filter(|_| failed);
+    let status = if exit_code == exit::CANCELLED {
+        "cancelled"
+    } else if failed {
+        "failed"
+    } else if new_version.as_deref() == old_version {
+        "up-to-date"
+    } else {
+        "updated"
+    };
+    let record = json!({
+        "status": status,
+        "oldVersion": old_version,
+        "newVersion": new_version,
+        "exitCode": exit_code,
+        "errorCode": error.as_ref().map(|(_, category)| category.name()),
+        "error": error.map(|(message, _)| message),
+    });
+
+    let result = match target {
+        ReportTarget::File(path) => write_file(path, &record.to_string()),
+        ReportTarget::Descriptor(descriptor) => {
+            let mut file = platform::file_from_descriptor(*descriptor);
+            writeln!(file, "{}", record)
+        }
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to write the result record: {}", e);
+    }
+}
+
+/// Written to a temporary file first, so a launcher polling for the file never reads half of it.
+fn write_file(path: &Path, content: &str) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    File::create(&temp_path)?.write_all(content.as_bytes())?;
+    fs::rename(&temp_path, path)
+}
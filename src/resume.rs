@@ -0,0 +1,117 @@
+//! `update_state.json` records where an update stands: the patch chain being installed, the step it
+//! reached and how much of the archive being downloaded is safely on disk. Closing the updater, a
+//! crash or a reboot only pauses the update. The next start continues the chain at the same step
+//! and the download from its last checkpoint, and `update_journal.json` skips the files already
+//! applied and verified. The file is removed once the whole chain is installed.
+
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::manifest::Artifact;
+
+const STATE_FILE_NAME: &str = "update_state.json";
+/// Downloads are flushed to disk and checkpointed each time this much more arrived.
+pub const CHECKPOINT_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Checkpoints come from the download's writer thread while the update thread moves on.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+fn load(base_path: &Path) -> Value {
+    fs::read_to_string(base_path.join(STATE_FILE_NAME))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or(Value::Null)
+}
+
+// Written to a temporary file first so a crash never leaves a half-written state file.
+fn save(base_path: &Path, state: &Value) -> io::Result<()> {
+    let path = base_path.join(STATE_FILE_NAME);
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&temp_path, &path)
+}
+
+fn step_json(version: &str, artifact: &Artifact) -> Value {
+    json!({ "version": version, "url": artifact.url })
+}
+
+/// Starts recording the chain `steps` from `current_version`. When an interrupted update left the
+/// rest of the same chain, it is continued instead and the number of steps it already installed is
+/// returned, so progress keeps counting from there.
+pub fn start_chain(base_path: &Path, current_version: &str, steps: &[(String, Artifact)]) -> usize {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let remaining: Vec<Value> = steps.iter().map(|(version, artifact)| step_json(version, artifact)).collect();
+    let saved = load(base_path);
+    if let (Some(saved_steps), Some(step)) = (saved["steps"].as_array(), saved["step"].as_u64()) {
+        // The updater may have stopped after applying a step but before recording it.
+        let continued = (step as usize..saved_steps.len()).find(|&step| saved_steps[step..] == remaining[..]);
+        if let Some(step) = continued {
+            if saved["step"].as_u64() != Some(step as u64) {
+                let mut state = saved.clone();
+                state["step"] = json!(step);
+                if let Some(state) = state.as_object_mut() {
+                    state.remove("download");
+                }
+                save(base_path, &state).ok();
+            }
+            return step;
+        }
+    }
+    let state = json!({
+        "from": current_version,
+        "steps": remaining,
+        "step": 0,
+    });
+    if let Err(e) = save(base_path, &state) {
+        log::warn!("Failed to save the update state: {}", e);
+    }
+    0
+}
+
+/// Records that the chain reached `step`, counted from its start. The checkpoint of the previous
+/// step's download is dropped.
+pub fn enter_step(base_path: &Path, step: usize) {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = load(base_path);
+    if state.is_null() || state["step"].as_u64() == Some(step as u64) {
+        return;
+    }
+    state["step"] = json!(step);
+    if let Some(state) = state.as_object_mut() {
+        state.remove("download");
+    }
+    save(base_path, &state).ok();
+}
+
+/// Where to continue downloading `url` for the current step: the bytes known to be on disk. Only
+/// downloads of a recorded chain are resumed, since `latest.zip` keeps its URL across versions.
+pub fn saved_download(base_path: &Path, url: &str) -> u64 {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let state = load(base_path);
+    let download = &state["download"];
+    if download["url"].as_str() != Some(url) || download["step"] != state["step"] {
+        return 0;
+    }
+    download["bytes"].as_u64().unwrap_or(0)
+}
+
+/// Records that the first `bytes` of `url` are on disk, after they were flushed.
+pub fn record_download(base_path: &Path, url: &str, bytes: u64) {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = load(base_path);
+    if state.is_null() {
+        return;
+    }
+    let step = state["step"].clone();
+    state["download"] = json!({ "url": url, "step": step, "bytes": bytes });
+    save(base_path, &state).ok();
+}
+
+/// The chain is installed; nothing is left to resume.
+pub fn finish(base_path: &Path) {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    fs::remove_file(base_path.join(STATE_FILE_NAME)).ok();
+}
@@ -0,0 +1,237 @@
+//! `rollback [<install dir>]` undoes the latest update with reverse patches: archives that take a
+//! build back to the version it was patched from, published next to the forward patches. After
+//! updating through patches that have them, the updater keeps them in `rollback/`, newest first, so
+//! going back costs a few small deltas rather than a backup of every file the update replaced.
+//!
+//! `rollback/rollback.json` records the version the reverse patches apply to and, for each, the
+//! version it leads to and its hash. Only the latest update is kept; an update installed from the
+//! full archive, or through a patch without a reverse patch, leaves nothing to roll back.
+
+use crossbeam_channel::Sender;
+use serde_json::{json, Value};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::error::UpdaterError;
+use crate::hashing;
+use crate::lock::UpdateLock;
+use crate::manifest::PatchEdge;
+use crate::pin;
+use crate::{apply_update, download_file, format_bytes, get_version_info, is_game_running, UpdateMessage};
+
+const ROLLBACK_DIR_NAME: &str = "rollback";
+const RECORD_FILE_NAME: &str = "rollback.json";
+
+struct Step {
+    to: String,
+    path: PathBuf,
+    sha256: Option<String>,
+}
+
+/// Keeps the reverse patches of the update just installed through `edges`, replacing those of the
+/// previous update. Failing to get them only costs the rollback, so it is logged and the update
+/// goes on.
+pub fn store(base_path: &Path, edges: &[PatchEdge], sender: &Sender<UpdateMessage>) {
+    clear(base_path);
+    let Some(installed) = edges.last().map(|edge| edge.to.clone()) else {
+        return;
+    };
+    if edges.iter().any(|edge| edge.reverse.is_none()) {
+        log::info!("Not every patch of the update has a reverse patch, so it cannot be rolled back");
+        return;
+    }
+    let size: u64 = edges.iter().filter_map(|edge| edge.reverse.as_ref()).map(|reverse| reverse.size).sum();
+    sender
+        .send(UpdateMessage::Log(format!(
+            "Keeping reverse patches ({}) to roll back to version {}...",
+            format_bytes(size),
+            edges[0].from
+        )))
+        .unwrap();
+    if let Err(e) = download_reverse_patches(base_path, &installed, edges, sender) {
+        clear(base_path);
+        sender
+            .send(UpdateMessage::Log(format!(
+                "Failed to download the reverse patches: {}. This update cannot be rolled back.",
+                e
+            )))
+            .unwrap();
+    }
+}
+
+fn download_reverse_patches(
+    base_path: &Path,
+    installed: &str,
+    edges: &[PatchEdge],
+    sender: &Sender<UpdateMessage>,
+) -> Result<(), Box<dyn Error>> {
+    let dir = base_path.join(ROLLBACK_DIR_NAME);
+    fs::create_dir_all(&dir)?;
+    let mut steps = Vec::new();
+    // The last patch applied is the first undone.
+    for (index, edge) in edges.iter().rev().enumerate() {
+        let Some(reverse) = &edge.reverse else {
+            continue;
+        };
+        let file_name = format!("{}.zip", index);
+        let sha256 = download_file(&reverse.url, &dir.join(&file_name), sender)?;
+        if reverse.sha256.as_ref().is_some_and(|expected| *expected != sha256) {
+            return Err(UpdaterError::Verification(format!(
+                "the reverse patch to version {} does not match the hash published in the manifest",
+                edge.from
+            ))
+            .into());
+        }
+        steps.push(json!({ "to": edge.from, "file": file_name, "sha256": sha256 }));
+    }
+    let record = json!({ "version": installed, "steps": steps });
+    fs::write(dir.join(RECORD_FILE_NAME), serde_json::to_string_pretty(&record)?)?;
+    Ok(())
+}
+
+/// Records that `steps` remain after rolling back to `version`, so a rollback stopped halfway can
+/// go on from there.
+fn save_remaining(base_path: &Path, version: &str, steps: &[Step]) -> std::io::Result<()> {
+    let steps: Vec<Value> = steps
+        .iter()
+        .map(|step| {
+            let file_name = step.path.file_name().unwrap_or_default().to_string_lossy();
+            json!({ "to": step.to, "file": file_name, "sha256": step.sha256 })
+        })
+        .collect();
+    let record = json!({ "version": version, "steps": steps });
+    fs::write(
+        base_path.join(ROLLBACK_DIR_NAME).join(RECORD_FILE_NAME),
+        serde_json::to_string_pretty(&record)?,
+    )
+}
+
+/// Forgets the reverse patches, once the version they undo is no longer installed.
+pub fn clear(base_path: &Path) {
+    let dir = base_path.join(ROLLBACK_DIR_NAME);
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            log::warn!("Failed to remove {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// The version the kept reverse patches apply to, and the steps back.
+fn load(base_path: &Path) -> Option<(String, Vec<Step>)> {
+    let dir = base_path.join(ROLLBACK_DIR_NAME);
+    let content = fs::read_to_string(dir.join(RECORD_FILE_NAME)).ok()?;
+    let record: Value = serde_json::from_str(&content).ok()?;
+    let steps = record["steps"]
+        .as_array()?
+        .iter()
+        .map(|step| {
+            Some(Step {
+                to: step["to"].as_str()?.to_string(),
+                path: dir.join(step["file"].as_str()?),
+                sha256: step["sha256"].as_str().map(|s| s.to_string()),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((record["version"].as_str()?.to_string(), steps))
+}
+
+pub fn run_rollback(args: &[String]) -> i32 {
+    let base_path = match args {
+        [] => env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        [dir] if !dir.starts_with('-') => PathBuf::from(dir),
+        _ => {
+            eprintln!("Usage: DreamioUpdater rollback [<install dir>]");
+            return 2;
+        }
+    };
+    let Some((version, steps)) = load(&base_path).filter(|(_, steps)| !steps.is_empty()) else {
+        eprintln!("There is no update to roll back in {}. Only updates installed through patches with reverse patches can be rolled back.", base_path.display());
+        return 1;
+    };
+    let installed = match get_version_info(&base_path) {
+        Ok(info) => info.version_code,
+        Err(e) => {
+            eprintln!("Failed to read version info in {}: {}", base_path.display(), e);
+            return 1;
+        }
+    };
+    if installed != version {
+        eprintln!(
+            "The reverse patches undo version {}, but version {} is installed.",
+            version, installed
+        );
+        return 1;
+    }
+    if pin::load(&base_path).is_some() {
+        eprintln!("The install is pinned to its version. Run `DreamioUpdater unpin` before rolling back.");
+        return 1;
+    }
+    if base_path.join("update_state.json").exists() || base_path.join("update.zip").exists() {
+        eprintln!("An update is waiting to be installed. Run the updater once to finish it before rolling back.");
+        return 1;
+    }
+    if is_game_running(&base_path) {
+        eprintln!("Close the game before rolling back.");
+        return 1;
+    }
+    let _lock = match UpdateLock::acquire(&base_path) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let target = steps.last().map(|step| step.to.clone()).unwrap_or_default();
+    println!("Rolling back from version {} to version {}...", version, target);
+    for (index, step) in steps.iter().enumerate() {
+        if let Err(e) = undo_step(&base_path, step) {
+            eprintln!("Failed to roll back to version {}: {}", step.to, e);
+            // The backup holds the files as they were before this step.
+            if let Err(e) = backup::restore(&base_path) {
+                eprintln!("Failed to restore the files changed by the failed step: {}", e);
+            }
+            return 1;
+        }
+        backup::discard(&base_path);
+        fs::remove_file(&step.path).ok();
+        if let Err(e) = save_remaining(&base_path, &step.to, &steps[index + 1..]) {
+            log::warn!("Failed to record the rollback progress: {}", e);
+        }
+        println!("Rolled back to version {}.", step.to);
+    }
+    clear(&base_path);
+    println!(
+        "The game is at version {}. The next start updates it again, unless you pin it with `DreamioUpdater pin --version {}`.",
+        target, target
+    );
+    0
+}
+
+fn undo_step(base_path: &Path, step: &Step) -> Result<(), Box<dyn Error>> {
+    let sha256 = hashing::sha256_file(&step.path)?;
+    if step.sha256.as_ref().is_some_and(|expected| *expected != sha256) {
+        return Err(UpdaterError::Verification("the reverse patch was changed since it was downloaded".to_string()).into());
+    }
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let result = apply_update(&step.path, base_path, &sender);
+    let errors: Vec<String> = receiver
+        .try_iter()
+        .filter_map(|message| match message {
+            UpdateMessage::Error(error, _, _) => Some(error),
+            _ => None,
+        })
+        .collect();
+    result?;
+    if let Some(error) = errors.last() {
+        return Err(error.clone().into());
+    }
+    let now = get_version_info(base_path)?.version_code;
+    if now != step.to {
+        return Err(format!("expected version {} afterwards, found version {}", step.to, now).into());
+    }
+    Ok(())
+}
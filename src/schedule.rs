@@ -0,0 +1,193 @@
+//! Daily update checks in the background. `schedule` registers a Task Scheduler task, or a crontab
+//! line outside Windows, running `check --silent` once a day. When an update is available, the
+//! silent check downloads it into `prefetch/` like a postponed update and shows a notification,
+//! so the next start only has to install it. Clicking the notification opens the updater.
+//! `unschedule` removes the task.
+//!
+//! The task runs the updater in the game folder and checks the install it is in, since Task
+//! Scheduler starts it in the system folder. `schedule --maintenance` registers the task that
+//! updates in maintenance windows instead; see `maintenance`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::manifest::{self, Artifact, UpdatePlan};
+use crate::platform::NotificationClick;
+use crate::{format_bytes, get_version_info, load_options, maintenance, pin, platform, prefetch_path, prefetch_updates};
+
+const DEFAULT_TIME: (u32, u32) = (12, 0);
+
+/// The folder of the install this updater belongs to.
+pub fn install_path() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|parent| parent.to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn parse_time(time: &str) -> Option<(u32, u32)> {
+    let (hour, minute) = time.split_once(':')?;
+    let (hour, minute) = (hour.parse().ok()?, minute.parse().ok()?);
+    (hour < 24 && minute < 60).then_some((hour, minute))
+}
+
+/// `schedule [--time <HH:MM>]` checks for updates every day at that time, noon by default.
+/// `schedule --maintenance` instead updates in the maintenance windows of `updater.json`.
+pub fn run_schedule(args: &[String]) -> i32 {
+    let mut time = Some(DEFAULT_TIME);
+    let mut maintenance = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--time" => time = args.next().and_then(|time| parse_time(time)),
+            "--maintenance" => maintenance = true,
+            _ => time = None,
+        }
+        if time.is_none() {
+            break;
+        }
+    }
+    let Some((hour, minute)) = time else {
+        eprintln!("Usage: DreamioUpdater schedule [--time <HH:MM>] [--maintenance]");
+        return 2;
+    };
+
+    let install_path = install_path();
+    if !install_path.join("version.json").exists() {
+        eprintln!(
+            "{} has no game installed. Run the updater in the game folder to schedule update checks.",
+            install_path.display()
+        );
+        return 1;
+    }
+    let exe = install_path.join(platform::UPDATER_EXECUTABLE);
+    if maintenance {
+        return schedule_maintenance(&install_path, &exe);
+    }
+    match platform::schedule_daily(&install_path, &exe, &["check", "--silent"], hour, minute) {
+        Ok(()) => {
+            println!(
+                "Checking for updates to {} every day at {:02}:{:02}. Updates are downloaded in the background and installed on the next start.",
+                install_path.display(),
+                hour,
+                minute
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to schedule update checks: {}", e);
+            1
+        }
+    }
+}
+
+fn schedule_maintenance(install_path: &Path, exe: &Path) -> i32 {
+    if Config::load(install_path).maintenance_windows.is_empty() {
+        eprintln!("updater.json in {} has no maintenanceWindows.", install_path.display());
+        return 1;
+    }
+    let interval = maintenance::CHECK_INTERVAL_MINUTES;
+    match platform::schedule_periodic(install_path, exe, &["maintenance"], interval) {
+        Ok(()) => {
+            println!(
+                "Updating {} in its maintenance windows, checked every {} minutes.",
+                install_path.display(),
+                interval
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to schedule maintenance updates: {}", e);
+            if cfg!(windows) {
+                eprintln!("Maintenance updates run as SYSTEM, which needs the updater to run as administrator.");
+            }
+            1
+        }
+    }
+}
+
+/// `unschedule` removes the daily checks and maintenance updates of the install this updater is in.
+pub fn run_unschedule(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        eprintln!("Usage: DreamioUpdater unschedule");
+        return 2;
+    }
+    let install_path = install_path();
+    match platform::unschedule(&install_path) {
+        Ok(true) => {
+            println!("Removed the scheduled update tasks of {}.", install_path.display());
+            0
+        }
+        Ok(false) => {
+            println!("No update tasks are scheduled for {}.", install_path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to remove the scheduled update checks: {}", e);
+            1
+        }
+    }
+}
+
+/// `check --silent`, run by the scheduled task: downloads an available update without installing
+/// it and tells the player it is ready. Prints nothing.
+pub fn run_silent_check(args: &[String]) -> i32 {
+    let install_path = install_path();
+    // The updater already running installs the update itself.
+    let Some(_instance) = platform::claim_instance(&install_path) else {
+        return 0;
+    };
+    if pin::load(&install_path).is_some() {
+        return 0;
+    }
+    let options = load_options(args, &install_path);
+    let Ok(info) = get_version_info(&install_path) else {
+        return 1;
+    };
+    let channel = options.channel.clone().unwrap_or(info.channel);
+    let remote_manifest = match manifest::fetch_manifest(&channel, &install_path) {
+        Ok(Some(remote_manifest)) => remote_manifest,
+        // Servers without a manifest only publish patches one version at a time.
+        Ok(None) => return 0,
+        Err(e) => {
+            log::warn!("Scheduled update check failed: {}", e);
+            return 1;
+        }
+    };
+    let plan = match manifest::plan_update(&remote_manifest, &info.version_code, options.prefer_full) {
+        Ok(UpdatePlan::UpToDate) => return 0,
+        Ok(plan) => plan,
+        Err(e) => {
+            log::warn!("Scheduled update check failed: {}", e);
+            return 1;
+        }
+    };
+    let downloads: Vec<(String, Artifact)> = match &plan {
+        UpdatePlan::Patches(edges) => edges.iter().map(|edge| (edge.to.clone(), edge.artifact.clone())).collect(),
+        UpdatePlan::Full(artifact) => vec![(remote_manifest.latest.clone(), artifact.clone())],
+        UpdatePlan::UpToDate => Vec::new(),
+    };
+    prefetch_updates(&install_path, &downloads);
+    let downloaded = downloads
+        .iter()
+        .all(|(target_version, artifact)| prefetch_path(&install_path, target_version, artifact).exists());
+    let message = if downloaded {
+        format!(
+            "Downloaded in the background ({}). Click to install it now, or it installs the next time you start the game.",
+            format_bytes(plan.download_size())
+        )
+    } else {
+        format!("Click to download ({}) and install it.", format_bytes(plan.download_size()))
+    };
+    platform::show_notification(
+        &install_path,
+        &format!("DREAMIO update {} available", remote_manifest.latest),
+        &message,
+        NotificationClick::Updater,
+    );
+    if !downloaded {
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,50 @@
+//! Lets the game tell the updater that an AI story session is in progress, so unattended updates
+//! wait for it to end instead of closing the game mid-story.
+//!
+//! The protocol:
+//!
+//! - When a story session starts, the game creates `session.lock` in the install directory with
+//!   its process id on the first line.
+//! - While the session lasts, the game rewrites or touches the file at least every
+//!   `HEARTBEAT_INTERVAL`, so its modification time stays recent.
+//! - When the session ends, the game deletes the file.
+//!
+//! A heartbeat older than `HEARTBEAT_TIMEOUT`, or one whose process is no longer running, is left
+//! over from a crash and ignored. The updater never writes or deletes the file.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use sysinfo::{Pid, System, SystemExt};
+
+const SESSION_FILE_NAME: &str = "session.lock";
+/// How often the game refreshes the heartbeat during a session.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(HEARTBEAT_INTERVAL.as_secs() * 3);
+
+/// Whether the game in `base_path` reports a story session in progress.
+pub fn is_session_active(base_path: &Path) -> bool {
+    let path = base_path.join(SESSION_FILE_NAME);
+    let Ok(metadata) = fs::metadata(&path) else {
+        return false;
+    };
+    let age = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default();
+    if age > HEARTBEAT_TIMEOUT {
+        return false;
+    }
+
+    let pid = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| content.lines().next()?.trim().parse::<usize>().ok());
+    match pid {
+        Some(pid) => {
+            let mut system = System::new();
+            system.refresh_process(Pid::from(pid))
+        }
+        None => true,
+    }
+}
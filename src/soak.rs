@@ -0,0 +1,317 @@
+//! Hidden `soak` command that checks, before an updater release, that interrupted updates never
+//! corrupt an install. It generates random builds in a scratch directory, patches an install
+//! forward to the new build and back to the old one, and kills the process applying each patch at
+//! random moments. Every interrupted update must resume from its journal and end in a tree that
+//! matches the expected build exactly.
+//!
+//! ```text
+//! DreamioUpdater soak [--dir <scratch dir>] [--duration 4h] [--seed <n>] [--kill-rate 0.5]
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{apply_update, backup};
+use crate::publish::{collect_files, generate_patch};
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_KILL_RATE: f64 = 0.5;
+/// Gives up on a patch that keeps getting killed, which means applying it never gets anywhere.
+const MAX_ATTEMPTS: u32 = 50;
+const MAX_FILE_SIZE: usize = 512 * 1024;
+
+/// xorshift64*, which is plenty for picking files and delays and keeps runs reproducible from the
+/// printed seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let value = self.next().to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+    }
+}
+
+#[derive(Default)]
+struct SoakStats {
+    iterations: u64,
+    applies: u64,
+    kills: u64,
+    /// How long the last uninterrupted apply took, which kills are timed within.
+    typical_apply: Option<Duration>,
+}
+
+pub fn run_soak(args: &[String]) -> i32 {
+    let mut scratch_dir = None;
+    let mut duration = DEFAULT_DURATION;
+    let mut seed = None;
+    let mut kill_rate = DEFAULT_KILL_RATE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => scratch_dir = args.next().map(PathBuf::from),
+            "--duration" => match args.next().map(|d| humantime::parse_duration(d)) {
+                Some(Ok(d)) => duration = d,
+                _ => {
+                    eprintln!("--duration expects a duration such as 30m or 4h");
+                    return 2;
+                }
+            },
+            "--seed" => seed = args.next().and_then(|s| s.parse().ok()),
+            "--kill-rate" => match args.next().and_then(|r| r.parse().ok()) {
+                Some(rate) => kill_rate = rate,
+                None => {
+                    eprintln!("--kill-rate expects a probability between 0 and 1");
+                    return 2;
+                }
+            },
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                eprintln!("Usage: DreamioUpdater soak [--dir <scratch dir>] [--duration 4h] [--seed <n>] [--kill-rate 0.5]");
+                return 2;
+            }
+        }
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1)
+    });
+    let scratch_dir = scratch_dir
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("dreamio-soak-{}", std::process::id())));
+    println!("Soaking in {} with seed {} for {}", scratch_dir.display(), seed, humantime::format_duration(duration));
+
+    let mut rng = Rng(seed | 1);
+    let mut stats = SoakStats::default();
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        match soak_iteration(&scratch_dir, &mut rng, kill_rate, &mut stats) {
+            Ok(problems) if problems.is_empty() => {
+                stats.iterations += 1;
+                if stats.iterations % 10 == 0 {
+                    println!(
+                        "{} iterations, {} updates applied, {} interrupted",
+                        stats.iterations, stats.applies, stats.kills
+                    );
+                }
+            }
+            Ok(problems) => {
+                for problem in &problems {
+                    println!("  {}", problem);
+                }
+                println!(
+                    "Soak failed after {} iterations: {} problem(s) found. The trees are kept in {}",
+                    stats.iterations,
+                    problems.len(),
+                    scratch_dir.display()
+                );
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("Soak failed after {} iterations: {}. The trees are kept in {}", stats.iterations, e, scratch_dir.display());
+                return 1;
+            }
+        }
+    }
+
+    fs::remove_dir_all(&scratch_dir).ok();
+    println!(
+        "Soak passed: {} iterations, {} updates applied, {} interrupted",
+        stats.iterations, stats.applies, stats.kills
+    );
+    0
+}
+
+/// Runs in the child process that the soak kills: applies `patch` to `dir`, resuming from the
+/// journal when an earlier attempt was interrupted.
+pub fn run_soak_apply(args: &[String]) -> i32 {
+    let [patch, dir] = args else {
+        eprintln!("Usage: DreamioUpdater soak-apply <patch.zip> <install dir>");
+        return 2;
+    };
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let result = apply_update(Path::new(patch), Path::new(dir), &sender);
+    if result.is_ok() {
+        backup::discard(Path::new(dir));
+    }
+    let mut failed = false;
+    for message in receiver.try_iter() {
+        if let crate::UpdateMessage::Error(error, _, _) = message {
+            eprintln!("{}", error);
+            failed = true;
+        }
+    }
+    match result {
+        Ok(_) if !failed => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Generates two builds, patches an install from the old one to the new one and back, and
+/// returns every difference from the expected trees.
+fn soak_iteration(scratch_dir: &Path, rng: &mut Rng, kill_rate: f64, stats: &mut SoakStats) -> io::Result<Vec<String>> {
+    if scratch_dir.exists() {
+        fs::remove_dir_all(scratch_dir)?;
+    }
+    let old_dir = scratch_dir.join("old");
+    let new_dir = scratch_dir.join("new");
+    let install_dir = scratch_dir.join("install");
+    let forward = scratch_dir.join("forward.zip");
+    let backward = scratch_dir.join("backward.zip");
+
+    generate_build(&old_dir, rng)?;
+    mutate_build(&old_dir, &new_dir, rng)?;
+    generate_patch(&old_dir, &new_dir, &forward, None, HashSet::new())?;
+    generate_patch(&new_dir, &old_dir, &backward, None, HashSet::new())?;
+    copy_tree(&old_dir, &install_dir)?;
+
+    apply_with_faults(&forward, &install_dir, rng, kill_rate, stats)?;
+    let mut problems = compare_trees(&install_dir, &new_dir)?;
+    if problems.is_empty() {
+        apply_with_faults(&backward, &install_dir, rng, kill_rate, stats)?;
+        problems = compare_trees(&install_dir, &old_dir)?;
+    }
+    Ok(problems)
+}
+
+/// Applies `patch` in a child process until one attempt runs to completion. Attempts are killed
+/// at a random point within the time an uninterrupted apply took last, so kills land anywhere
+/// between opening the archive and removing the journal.
+fn apply_with_faults(patch: &Path, dir: &Path, rng: &mut Rng, kill_rate: f64, stats: &mut SoakStats) -> io::Result<()> {
+    let exe = std::env::current_exe()?;
+    let typical = stats.typical_apply.unwrap_or(Duration::from_millis(200));
+    for _ in 0..MAX_ATTEMPTS {
+        let started = Instant::now();
+        let mut child = Command::new(&exe).arg("soak-apply").arg(patch).arg(dir).spawn()?;
+        if rng.chance(kill_rate) {
+            let delay = Duration::from_micros(rng.below(typical.as_micros() as u64 * 6 / 5));
+            thread::sleep(delay);
+            if child.try_wait()?.is_none() {
+                child.kill()?;
+                child.wait()?;
+                stats.kills += 1;
+                continue;
+            }
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("applying {} failed with {}", patch.display(), status)));
+        }
+        stats.typical_apply = Some(started.elapsed());
+        stats.applies += 1;
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "{} was interrupted {} times in a row",
+        patch.display(),
+        MAX_ATTEMPTS
+    )))
+}
+
+fn generate_build(dir: &Path, rng: &mut Rng) -> io::Result<()> {
+    for index in 0..20 + rng.below(40) {
+        let name = format!("dir{}/sub{}/file{}.bin", rng.below(4), rng.below(3), index);
+        write_random_file(&dir.join(name), rng)?;
+    }
+    Ok(())
+}
+
+/// Copies `old_dir` to `new_dir` with the kinds of changes real builds have: untouched files,
+/// small edits that become bsdiff patches, rewritten files, deleted files and whole deleted
+/// folders, and new files.
+fn mutate_build(old_dir: &Path, new_dir: &Path, rng: &mut Rng) -> io::Result<()> {
+    let removed_dir = format!("dir{}/", rng.below(8));
+    for name in collect_files(old_dir)? {
+        if name.starts_with(&removed_dir) {
+            continue;
+        }
+        let target = new_dir.join(&name);
+        match rng.below(10) {
+            0..=4 => {
+                fs::create_dir_all(target.parent().unwrap())?;
+                fs::copy(old_dir.join(&name), &target)?;
+            }
+            5..=7 => {
+                let mut contents = fs::read(old_dir.join(&name))?;
+                let start = rng.below(contents.len() as u64) as usize;
+                let end = (start + 1 + rng.below(1024) as usize).min(contents.len());
+                rng.fill(&mut contents[start..end]);
+                fs::create_dir_all(target.parent().unwrap())?;
+                fs::write(&target, contents)?;
+            }
+            8 => write_random_file(&target, rng)?,
+            _ => {}
+        }
+    }
+    for index in 0..rng.below(10) {
+        write_random_file(&new_dir.join(format!("added{}/file{}.bin", rng.below(2), index)), rng)?;
+    }
+    Ok(())
+}
+
+fn write_random_file(path: &Path, rng: &mut Rng) -> io::Result<()> {
+    let mut contents = vec![0; 1 + rng.below(MAX_FILE_SIZE as u64) as usize];
+    rng.fill(&mut contents);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, contents)
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    for name in collect_files(from)? {
+        let target = to.join(&name);
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(from.join(&name), target)?;
+    }
+    Ok(())
+}
+
+/// Lists every difference between the install and the expected build, including leftover
+/// journals and temporary files.
+fn compare_trees(install_dir: &Path, expected_dir: &Path) -> io::Result<Vec<String>> {
+    let installed: Vec<String> = collect_files(install_dir)?
+        .into_iter()
+        .filter(|name| name != "install_manifest.txt")
+        .collect();
+    let expected = collect_files(expected_dir)?;
+    let mut problems = Vec::new();
+    for name in &expected {
+        if !installed.contains(name) {
+            problems.push(format!("Missing after updating: {}", name));
+        } else if fs::read(install_dir.join(name))? != fs::read(expected_dir.join(name))? {
+            problems.push(format!("Content differs: {}", name));
+        }
+    }
+    for name in &installed {
+        if !expected.contains(name) {
+            problems.push(format!("Left behind: {}", name));
+        }
+    }
+    Ok(problems)
+}
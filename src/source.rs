@@ -0,0 +1,314 @@
+//! Where artifacts are fetched from, chosen by the scheme of their URL in the manifest:
+//!
+//! - `https://` and `http://` for the download server and mirrors,
+//! - `s3://bucket/key` for S3-compatible storage, signed when credentials are configured,
+//! - `gs://bucket/object` for Google Cloud Storage,
+//! - `file://`, absolute and UNC paths for offline media and network shares.
+//!
+//! Every source streams chunks the same way, so downloads keep their progress, hashing, speed
+//! limits and retries whatever the artifact comes from.
+
+use bytes::Bytes;
+use std::error::Error;
+use std::fs::File;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::error::UpdaterError;
+use crate::hashing::{hmac_sha256, sha256_bytes, to_hex};
+use crate::{diagnostics, http};
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+const FILE_CHUNK_SIZE: usize = 1024 * 1024;
+/// Signed S3 URLs stay valid this long, enough to start the download and retry it.
+const S3_SIGNATURE_EXPIRY_SECONDS: u64 = 3600;
+
+pub trait Source {
+    /// The location for logs, without credentials.
+    fn describe(&self) -> String;
+
+    /// Starts reading the artifact at byte `offset`, to continue an interrupted download.
+    fn open(&self, offset: u64) -> BoxFuture<'_, Result<Box<dyn Body>, Box<dyn Error>>>;
+}
+
+pub trait Body {
+    /// Size of the rest of the artifact from `start`, when the source knows it.
+    fn size(&self) -> Option<u64>;
+
+    /// Where the chunks start in the artifact: the offset asked for, or 0 when the source can
+    /// only send all of it.
+    fn start(&self) -> u64;
+
+    /// The next chunk, or `None` at the end.
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Bytes>, Box<dyn Error>>>;
+}
+
+/// Picks the source for `url` by its scheme.
+pub fn for_url(url: &str) -> Box<dyn Source> {
+    if let Some(location) = url.strip_prefix("s3://") {
+        let (bucket, key) = location.split_once('/').unwrap_or((location, ""));
+        return Box::new(S3Source {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        });
+    }
+    if let Some(location) = url.strip_prefix("gs://") {
+        return Box::new(HttpSource {
+            url: format!("{}/{}", GCS_ENDPOINT, location),
+        });
+    }
+    match local_path(url) {
+        Some(path) => Box::new(FileSource { path }),
+        None => Box::new(HttpSource { url: url.to_string() }),
+    }
+}
+
+/// The HTTP URL `url` is fetched from, presigned for S3, or `None` for local files. For requests
+/// other than a plain download, like reading parts of an archive.
+pub fn http_url(url: &str) -> Option<String> {
+    if let Some(location) = url.strip_prefix("s3://") {
+        let (bucket, key) = location.split_once('/').unwrap_or((location, ""));
+        let source = S3Source {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        };
+        return Some(source.url());
+    }
+    if let Some(location) = url.strip_prefix("gs://") {
+        return Some(format!("{}/{}", GCS_ENDPOINT, location));
+    }
+    match local_path(url) {
+        Some(_) => None,
+        None => Some(url.to_string()),
+    }
+}
+
+/// Whether `url` names its location completely, as opposed to relative to the channel's folder.
+pub fn is_absolute(url: &str) -> bool {
+    ["https://", "http://", "s3://", "gs://", "file://"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+        || Path::new(url).is_absolute()
+}
+
+/// The local path of `url`, for `file://`, absolute and UNC paths.
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        // `file:///C:/Games` on Windows, `file:///mnt/games` elsewhere.
+        let path = if cfg!(windows) && path.get(2..3) == Some(":") {
+            path.trim_start_matches('/')
+        } else {
+            path
+        };
+        return Some(PathBuf::from(path));
+    }
+    if url.contains("://") {
+        return None;
+    }
+    Some(PathBuf::from(url)).filter(|path| path.is_absolute())
+}
+
+struct HttpSource {
+    url: String,
+}
+
+impl Source for HttpSource {
+    fn describe(&self) -> String {
+        // Signed URLs carry their credentials in the query.
+        self.url.split('?').next().unwrap_or(&self.url).to_string()
+    }
+
+    fn open(&self, offset: u64) -> BoxFuture<'_, Result<Box<dyn Body>, Box<dyn Error>>> {
+        Box::pin(self.get(offset))
+    }
+}
+
+impl HttpSource {
+    async fn get(&self, offset: u64) -> Result<Box<dyn Body>, Box<dyn Error>> {
+        let client = http::async_client()?;
+        let description = self.describe();
+        let mut request = client.get(&self.url);
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let response = request.send().await.inspect_err(|e| {
+            diagnostics::record_request(&description, &e.to_string());
+        })?;
+        diagnostics::record_request(&description, response.status().as_str());
+        log::info!("GET {} -> {}", description, response.status());
+        if !response.status().is_success() {
+            return Err(Box::new(UpdaterError::HttpStatus(response.status())));
+        }
+        // Servers without range support answer with the whole artifact.
+        let start = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            offset
+        } else {
+            0
+        };
+        Ok(Box::new(HttpBody { response, start }))
+    }
+}
+
+struct HttpBody {
+    response: reqwest::Response,
+    start: u64,
+}
+
+impl Body for HttpBody {
+    fn size(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    fn start(&self) -> u64 {
+        self.start
+    }
+
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Bytes>, Box<dyn Error>>> {
+        Box::pin(async move { self.response.chunk().await.map_err(|e| e.into()) })
+    }
+}
+
+struct FileSource {
+    path: PathBuf,
+}
+
+impl Source for FileSource {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn open(&self, offset: u64) -> BoxFuture<'_, Result<Box<dyn Body>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let mut file = File::open(&self.path).map_err(|e| Box::new(e) as Box<dyn Error>)?;
+            let len = file.metadata().ok().map(|metadata| metadata.len());
+            let start = offset.min(len.unwrap_or(0));
+            file.seek(SeekFrom::Start(start))
+                .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+            log::info!("Reading {}", self.path.display());
+            let size = len.map(|len| len - start);
+            Ok(Box::new(FileBody { file, size, start }) as Box<dyn Body>)
+        })
+    }
+}
+
+struct FileBody {
+    file: File,
+    size: Option<u64>,
+    start: u64,
+}
+
+impl Body for FileBody {
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn start(&self) -> u64 {
+        self.start
+    }
+
+    fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Bytes>, Box<dyn Error>>> {
+        // Network shares can block for a while, which must not hold up the runtime's other tasks.
+        let mut buffer = vec![0u8; FILE_CHUNK_SIZE];
+        let result = tokio::task::block_in_place(|| self.file.read(&mut buffer)).map(|n| {
+            buffer.truncate(n);
+            Some(Bytes::from(buffer)).filter(|chunk| !chunk.is_empty())
+        });
+        Box::pin(async move { result.map_err(|e| e.into()) })
+    }
+}
+
+/// Endpoint and credentials for `s3://` URLs, from the `s3` section of `updater.json` or the usual
+/// `AWS_*` environment variables.
+#[derive(Clone, Default)]
+pub struct S3Settings {
+    /// Base URL of an S3-compatible service, e.g. MinIO or R2. Defaults to AWS for the region.
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+static S3_SETTINGS: OnceLock<S3Settings> = OnceLock::new();
+
+pub fn configure_s3(settings: S3Settings) {
+    S3_SETTINGS.set(settings).ok();
+}
+
+struct S3Source {
+    bucket: String,
+    key: String,
+}
+
+impl S3Source {
+    /// Path-style URL of the object, which works with every S3-compatible service. With
+    /// credentials, it is presigned with AWS Signature Version 4.
+    fn url(&self) -> String {
+        let settings = S3_SETTINGS.get().cloned().unwrap_or_default();
+        let region = settings.region.unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = settings
+            .endpoint
+            .map(|endpoint| endpoint.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let path = format!("/{}/{}", uri_encode(&self.bucket, false), uri_encode(&self.key, true));
+        let (Some(access_key_id), Some(secret_access_key)) = (settings.access_key_id, settings.secret_access_key)
+        else {
+            return format!("{}{}", endpoint, path);
+        };
+
+        let host = endpoint.split("://").nth(1).unwrap_or(&endpoint);
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .replace(['-', ':'], "");
+        let date = &timestamp[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, region);
+        let query = format!(
+            "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+            uri_encode(&format!("{}/{}", access_key_id, scope), false),
+            timestamp,
+            S3_SIGNATURE_EXPIRY_SECONDS
+        );
+        let canonical_request = format!("GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", path, query, host);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            sha256_bytes(canonical_request.as_bytes())
+        );
+        let mut key = hmac_sha256(format!("AWS4{}", secret_access_key).as_bytes(), date.as_bytes());
+        for part in [region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = to_hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!("{}{}?{}&X-Amz-Signature={}", endpoint, path, query, signature)
+    }
+}
+
+impl Source for S3Source {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.key)
+    }
+
+    fn open(&self, offset: u64) -> BoxFuture<'_, Result<Box<dyn Body>, Box<dyn Error>>> {
+        let source = HttpSource { url: self.url() };
+        Box::pin(async move { source.get(offset).await })
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, and slashes in keys.
+fn uri_encode(value: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slashes => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
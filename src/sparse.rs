@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::platform::set_sparse;
+
+const SPARSE_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Writes to `file`, skipping over zero-filled blocks instead of writing them.
+///
+/// The file is only marked sparse once the first zero block is found. On filesystems without
+/// sparse file support the zeros are written out as usual. `finish` must be called once all data
+/// has been written.
+pub struct SparseWriter<'a> {
+    file: &'a mut File,
+    block: Vec<u8>,
+    written: u64,
+    sparse_supported: Option<bool>,
+}
+
+impl<'a> SparseWriter<'a> {
+    pub fn new(file: &'a mut File) -> Self {
+        Self {
+            file,
+            block: Vec::with_capacity(SPARSE_BLOCK_SIZE),
+            written: 0,
+            sparse_supported: None,
+        }
+    }
+
+    /// Continues writing at `offset`, keeping everything before it.
+    pub fn starting_at(file: &'a mut File, offset: u64) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut writer = Self::new(file);
+        writer.written = offset;
+        Ok(writer)
+    }
+
+    /// Writes out buffered data and makes sure it reached the disk, extending the file over
+    /// skipped zero blocks first.
+    pub fn sync_data(&mut self) -> io::Result<()> {
+        self.flush_block()?;
+        self.file.set_len(self.written)?;
+        self.file.sync_data()
+    }
+
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.flush_block()?;
+        // Extends the file over a trailing run of skipped zero blocks.
+        self.file.set_len(self.written)?;
+        Ok(self.written)
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        let is_zero_block =
+            self.block.len() == SPARSE_BLOCK_SIZE && self.block.iter().all(|&b| b == 0);
+        let file = &*self.file;
+        if is_zero_block && *self.sparse_supported.get_or_insert_with(|| set_sparse(file).is_ok()) {
+            self.file.seek(SeekFrom::Current(self.block.len() as i64))?;
+        } else {
+            self.file.write_all(&self.block)?;
+        }
+        self.written += self.block.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl Write for SparseWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(SPARSE_BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+        if self.block.len() == SPARSE_BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
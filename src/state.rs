@@ -0,0 +1,109 @@
+//! `updater_state.json` holds what the updater records about an install: the update channel, its
+//! rollout bucket and the files Windows replaces at the next restart because they were in use.
+//! `version.json` belongs to the game build: the updater reads it and replaces it only by applying
+//! update archives, never edits it, so the game can read and write it freely while the updater
+//! runs.
+
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::hashing;
+
+const STATE_FILE_NAME: &str = "updater_state.json";
+/// Installs are split into this many rollout buckets.
+pub const ROLLOUT_BUCKETS: u64 = 100;
+
+/// Entries are applied on several threads, which must not overwrite each other's changes.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+fn load(base_path: &Path) -> Value {
+    fs::read_to_string(base_path.join(STATE_FILE_NAME))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_else(|| json!({}))
+}
+
+pub fn load_channel(base_path: &Path) -> Option<String> {
+    load(base_path)["channel"].as_str().map(|c| c.to_string())
+}
+
+pub fn save_channel(base_path: &Path, channel: &str) -> io::Result<()> {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = load(base_path);
+    if state["channel"].as_str() == Some(channel) {
+        return Ok(());
+    }
+    state["channel"] = Value::String(channel.to_string());
+    save(base_path, &state)
+}
+
+/// The install's rollout bucket, below `ROLLOUT_BUCKETS`. It is drawn at random the first time it
+/// is needed and kept, so telemetry of one install always counts towards the same cohort.
+pub fn rollout_bucket(base_path: &Path) -> u64 {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = load(base_path);
+    if let Some(bucket) = state["rolloutBucket"].as_u64().filter(|&bucket| bucket < ROLLOUT_BUCKETS) {
+        return bucket;
+    }
+    // Installs share their default path, so the time and process make the draw differ.
+    let seed = format!("{:?} {} {}", SystemTime::now(), process::id(), base_path.display());
+    let hash = hashing::sha256_bytes(seed.as_bytes());
+    let bucket = u64::from_str_radix(&hash[..12], 16).unwrap_or(0) % ROLLOUT_BUCKETS;
+    state["rolloutBucket"] = json!(bucket);
+    if let Err(e) = save(base_path, &state) {
+        log::warn!("Failed to save the rollout bucket: {}", e);
+    }
+    bucket
+}
+
+/// Records files waiting for the restart that moves or deletes them. Each path is the file that
+/// disappears once that happened.
+pub fn record_reboot_pending(base_path: &Path, paths: &[PathBuf]) -> io::Result<()> {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = load(base_path);
+    let mut pending: Vec<Value> = state["rebootPending"].as_array().cloned().unwrap_or_default();
+    for path in paths {
+        let path = Value::String(path.to_string_lossy().into_owned());
+        if !pending.contains(&path) {
+            pending.push(path);
+        }
+    }
+    state["rebootPending"] = Value::Array(pending);
+    save(base_path, &state)
+}
+
+/// Files still waiting for a restart. Entries the restart took care of are forgotten.
+pub fn reboot_pending(base_path: &Path) -> Vec<PathBuf> {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut state = load(base_path);
+    let Some(recorded) = state["rebootPending"].as_array() else {
+        return Vec::new();
+    };
+    let pending: Vec<PathBuf> = recorded
+        .iter()
+        .filter_map(|path| path.as_str())
+        .map(PathBuf::from)
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .collect();
+    if pending.len() != recorded.len() {
+        state["rebootPending"] = pending
+            .iter()
+            .map(|path| Value::String(path.to_string_lossy().into_owned()))
+            .collect();
+        save(base_path, &state).ok();
+    }
+    pending
+}
+
+// Written to a temporary file first so a crash never leaves a half-written state file.
+fn save(base_path: &Path, state: &Value) -> io::Result<()> {
+    let path = base_path.join(STATE_FILE_NAME);
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&temp_path, &path)
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+/// Samples closer together than this are merged, so single reads do not skew the rate.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+/// Time constant of the moving average. Bursts shorter than this barely move the estimate.
+const SMOOTHING_WINDOW: Duration = Duration::from_secs(3);
+
+/// Transfer speed and ETA smoothed with an exponentially weighted moving average, so the estimate
+/// stays steady on bursty connections.
+pub struct TransferStats {
+    start: Instant,
+    last_sample: Instant,
+    last_bytes: u64,
+    bytes_per_sec: Option<f64>,
+}
+
+impl Default for TransferStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferStats {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last_sample: now,
+            last_bytes: 0,
+            bytes_per_sec: None,
+        }
+    }
+
+    /// Records that `transferred` bytes have been transferred in total so far.
+    pub fn record(&mut self, transferred: u64) {
+        let now = Instant::now();
+        let interval = now.duration_since(self.last_sample);
+        if interval < SAMPLE_INTERVAL {
+            return;
+        }
+
+        let sample = transferred.saturating_sub(self.last_bytes) as f64 / interval.as_secs_f64();
+        // Weighting by the sample's duration keeps the smoothing independent of how often samples arrive.
+        let weight = 1.0 - (-interval.as_secs_f64() / SMOOTHING_WINDOW.as_secs_f64()).exp();
+        self.bytes_per_sec = Some(match self.bytes_per_sec {
+            Some(average) => average + weight * (sample - average),
+            None => sample,
+        });
+        self.last_sample = now;
+        self.last_bytes = transferred;
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes_per_sec.unwrap_or(0.0)
+    }
+
+    /// Estimated time left for `remaining` bytes, or zero while the speed is still unknown.
+    pub fn eta(&self, remaining: u64) -> Duration {
+        match self.bytes_per_sec {
+            Some(rate) if rate > 0.0 => {
+                Duration::try_from_secs_f64(remaining as f64 / rate).unwrap_or(Duration::MAX)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
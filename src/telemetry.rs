@@ -0,0 +1,86 @@
+//! Opt-in failure reports, so patches that fail in the wild show up without waiting for support
+//! requests. Reports only contain the error category, the version transition, the channel, the
+//! install's rollout bucket and the OS version: no paths, host names or error messages. The bucket
+//! lets failures be compared between the cohorts of a staged rollout.
+
+use serde_json::json;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use sysinfo::{System, SystemExt};
+
+use crate::error::ErrorCategory;
+use crate::{hashing, http};
+
+struct Transition {
+    channel: String,
+    from: Option<String>,
+    to: String,
+}
+
+static TRANSITION: Mutex<Option<Transition>> = Mutex::new(None);
+
+/// Remembers which update is being installed for the next failure report.
+pub fn record_transition(channel: &str, from: Option<&str>, to: &str) {
+    if let Ok(mut transition) = TRANSITION.lock() {
+        *transition = Some(Transition {
+            channel: channel.to_string(),
+            from: from.map(|v| v.to_string()),
+            to: to.to_string(),
+        });
+    }
+}
+
+/// What players see of an error: a short explanation, and a support code that identifies the
+/// exact error in `updater.log`, which always has the full message.
+pub struct ErrorSummary {
+    pub text: &'static str,
+    pub code: String,
+}
+
+impl std::fmt::Display for ErrorSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} Support code: {}", self.text, self.code)
+    }
+}
+
+pub fn summarize(message: &str, category: ErrorCategory) -> ErrorSummary {
+    let text = match category {
+        ErrorCategory::Network => "Could not reach the update server. Please check your internet connection.",
+        ErrorCategory::Http => "The update server did not respond as expected.",
+        ErrorCategory::Patch => "Some game files could not be updated.",
+        ErrorCategory::Disk => "There is not enough free disk space for the update.",
+        ErrorCategory::Permissions => "The updater is not allowed to change the game files.",
+        ErrorCategory::Antivirus => "Your antivirus software blocked a game file.",
+        ErrorCategory::Version => "The installed version of the game could not be determined.",
+        ErrorCategory::Other => "The update did not finish.",
+    };
+    ErrorSummary {
+        text,
+        code: format!("{}-{}", category.name().to_uppercase(), &hashing::sha256_bytes(message.as_bytes())[..6]),
+    }
+}
+
+/// Posts a failure report to `endpoint` in the background. Failing to send it is ignored.
+pub fn send_failure_report(endpoint: &str, category: ErrorCategory, rollout_bucket: u64) {
+    let transition = TRANSITION.lock().ok().and_then(|mut t| t.take());
+    let system = System::new();
+    let report = json!({
+        "category": category.name(),
+        "channel": transition.as_ref().map(|t| t.channel.clone()),
+        "fromVersion": transition.as_ref().and_then(|t| t.from.clone()),
+        "toVersion": transition.as_ref().map(|t| t.to.clone()),
+        "rolloutBucket": rollout_bucket,
+        "os": system.long_os_version(),
+        "updaterVersion": env!("CARGO_PKG_VERSION"),
+    });
+    log::info!("Sending failure report to {}: {}", endpoint, report);
+
+    let endpoint = endpoint.to_string();
+    thread::spawn(move || {
+        let result = http::client(Duration::from_secs(10)).and_then(|client| client.post(&endpoint).json(&report).send());
+        if let Err(e) = result {
+            log::warn!("Failed to send failure report: {}", e);
+        }
+    });
+}
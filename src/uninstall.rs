@@ -0,0 +1,126 @@
+//! Removes the game. `uninstall` runs from the console and `--uninstall` from Add/Remove Programs.
+//! Only the files the game was installed with and those the updater created are removed, so saves,
+//! settings and protected paths in the install folder survive a reinstall; `--purge` removes the
+//! whole folder. The updater deletes itself last, after it exits.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::platform;
+use crate::protect::ProtectedPaths;
+
+/// What the updater itself leaves in the install folder. `updater.json` is a setting the player
+/// made and stays unless purging.
+const UPDATER_FILES: [&str; 13] = [
+    "updater_state.json",
+    "update_pin.json",
+    "manifest_cache.json",
+    "update_journal.json",
+    "update_state.json",
+    "update.lock",
+    "update.zip",
+    "update.zip.part",
+    "nettest_report.txt",
+    "updater.log",
+    "updater.log.1",
+    "updater.log.2",
+    "updater.log.3",
+];
+const UPDATER_DIRS: [&str; 2] = ["prefetch", "update_backup"];
+
+pub fn run_uninstall(args: &[String]) -> i32 {
+    let mut purge = false;
+    let mut assume_yes = false;
+    for arg in args {
+        match arg.as_str() {
+            "--purge" => purge = true,
+            "--yes" | "-y" => assume_yes = true,
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                eprintln!("Usage: DreamioUpdater uninstall [--purge] [--yes]");
+                return 2;
+            }
+        }
+    }
+
+    let exe_path = env::current_exe().unwrap_or_default();
+    let install_path = exe_path.parent().unwrap_or(Path::new("."));
+    if !assume_yes {
+        let what = if purge {
+            "DREAMIO and everything else in"
+        } else {
+            "DREAMIO, keeping saves and settings, from"
+        };
+        print!("This removes {} {}. Continue? [y/N] ", what, install_path.display());
+        io::stdout().flush().ok();
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer).ok();
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("Uninstall cancelled.");
+            return 1;
+        }
+    }
+
+    let failed = uninstall(&exe_path, install_path, purge);
+    if failed > 0 {
+        println!("{} file(s) could not be removed, they may be in use.", failed);
+    }
+    println!("DREAMIO was uninstalled. The updater removes itself after closing.");
+    0
+}
+
+/// Removes the install the updater at `exe_path` belongs to and returns how many files could not
+/// be removed. The updater itself is removed after it exits.
+pub fn uninstall(exe_path: &Path, install_path: &Path, purge: bool) -> usize {
+    let manifest_path = install_path.join("install_manifest.txt");
+    let mut failed = 0;
+    let mut remove_directory = purge;
+    let protected = ProtectedPaths::load(install_path);
+
+    if let Ok(content) = fs::read_to_string(&manifest_path) {
+        let mut entries: Vec<&str> = content.lines().filter(|l| !l.is_empty()).collect();
+        // Deepest first, so folders are empty by the time they are removed.
+        entries.sort_by_key(|a| std::cmp::Reverse(a.len()));
+        for entry in entries {
+            if !purge && protected.covers(entry) {
+                continue;
+            }
+            let target_path = install_path.join(entry);
+            if target_path.is_dir() {
+                // Folders still holding saves or settings stay.
+                fs::remove_dir(&target_path).ok();
+            } else if target_path.exists() && fs::remove_file(&target_path).is_err() {
+                failed += 1;
+            }
+        }
+        fs::remove_file(&manifest_path).ok();
+    } else {
+        // Installs older than the manifest are recognized by their folder name.
+        let path_str = install_path.to_string_lossy().to_string();
+        if path_str.to_lowercase().contains("dreamio") {
+            remove_directory = true;
+        }
+    }
+
+    for name in UPDATER_FILES {
+        let path = install_path.join(name);
+        if path.exists() && fs::remove_file(&path).is_err() {
+            failed += 1;
+        }
+    }
+    for name in UPDATER_DIRS {
+        let path = install_path.join(name);
+        if path.exists() && fs::remove_dir_all(&path).is_err() {
+            failed += 1;
+        }
+    }
+
+    platform::unschedule(install_path).ok();
+    platform::unregister_notifications(install_path);
+    platform::remove_shortcuts();
+    platform::unregister_uninstaller();
+    platform::remove_install(exe_path, install_path, remove_directory);
+    failed
+}
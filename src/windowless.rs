@@ -0,0 +1,235 @@
+//! `--windowless` runs an update without the updater window, for when the game starts the updater
+//! itself. Progress is written to stdout as one JSON object per line, e.g.
+//!
+//! ```text
+//! {"event":"status","message":"Downloading update..."}
+//! {"event":"releaseNotes","version":"124","markdown":"## Fixes\n- ..."}
+//! {"event":"download","downloaded":1048576,"total":5242880,"bytesPerSec":524288.0}
+//! {"event":"complete"}
+//! ```
+//!
+//! After Ctrl+C or closing the console, the update stops at a safe point, emits `interrupted` and
+//! exits with code 130. The next run continues it. Failed updates exit with the code of their
+//! failure, see `exit`.
+//!
+//! With `--ipc <target>`, the same events are also streamed to the game's launcher, see `ipc`.
+//!
+//! The only window shown is an error dialog when the update fails, since the player has to know
+//! why the game did not update. The game is not started afterwards unless `afterUpdate` or
+//! `--after-update` ask for it; a notification tells the player when an update was installed.
+
+use rfd::{MessageButtons, MessageDialog, MessageLevel};
+use serde_json::{json, Value};
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::ErrorCategory;
+use crate::exit;
+use crate::ipc::{self, IpcTarget};
+use crate::mods::ModChoice;
+use crate::platform::{self, NotificationClick};
+use crate::postupdate::{self, PostUpdateAction};
+use crate::state;
+use crate::{
+    get_version_info, help, prefetch_updates, report, spawn_update_task, successor_args, telemetry,
+    Options, UpdateMessage, Verbosity,
+};
+
+pub fn run(options: Options) -> i32 {
+    if let Some(target) = &options.ipc {
+        let connected = match IpcTarget::parse(target) {
+            Some(target) => ipc::connect(&target).map_err(|e| e.to_string()),
+            None => Err(format!("{} is not pipe:<name> or tcp:<port> on this computer", target)),
+        };
+        if let Err(e) = connected {
+            log::warn!("Failed to connect to the launcher for --ipc: {}", e);
+            eprintln!("Could not connect to the launcher: {}. Progress is only written to stdout.", e);
+        }
+    }
+    let target_path = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let old_version = get_version_info(&target_path).ok().map(|info| info.version_code);
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    spawn_update_task(sender, target_path.clone(), options.clone());
+
+    let mut errors = Vec::new();
+    let mut deferred_downloads = Vec::new();
+    for message in receiver {
+        match message {
+            UpdateMessage::Log(message) => {
+                log::info!("{}", message);
+                emit(json!({ "event": "log", "message": message }));
+            }
+            UpdateMessage::Detail(level, message) => {
+                if options.verbosity >= level {
+                    emit(json!({ "event": "log", "message": message }));
+                }
+            }
+            UpdateMessage::Error(message, category, response) => {
+                let summary = telemetry::summarize(&message, category);
+                log::error!("[{}] {}", summary.code, message);
+                report::record_error(&message, category);
+                if let Some(response) = &response {
+                    log::error!("Server response: {}", response);
+                }
+                emit(json!({
+                    "event": "error",
+                    "message": summary.text,
+                    "code": summary.code,
+                    "details": message,
+                }));
+                errors.push((message, category));
+            }
+            UpdateMessage::Status(message) => emit(json!({ "event": "status", "message": message })),
+            UpdateMessage::Progress(fraction) => emit(json!({ "event": "progress", "fraction": fraction })),
+            UpdateMessage::ProgressUpdate(update) => emit(json!({
+                "event": "download",
+                "downloaded": update.downloaded,
+                "total": update.total,
+                "bytesPerSec": update.bytes_per_sec,
+            })),
+            UpdateMessage::ApplyingProgress(message) => emit(json!({ "event": "applying", "message": message })),
+            UpdateMessage::FileProgress(_) => {}
+            UpdateMessage::Step(index, count, description) => emit(json!({
+                "event": "step",
+                "index": index,
+                "count": count,
+                "description": description,
+            })),
+            // Nobody is there to choose, so optional updates are installed like mandatory ones.
+            UpdateMessage::OfferLaunchAnyway(_, _, reply) => {
+                reply.send(false).ok();
+            }
+            // The UAC prompt asks the player anyway, and the update cannot go ahead without it.
+            UpdateMessage::OfferElevation(_, reply) => {
+                reply.send(true).ok();
+            }
+            UpdateMessage::DeferUpdate(downloads) => deferred_downloads = downloads,
+            // Scripts get the installed version right away instead of waiting for the release.
+            UpdateMessage::ReleaseCountdown(_, _, reply) => {
+                reply.send(true).ok();
+            }
+            // Nothing is lost this way, and the game ends up in the state the update expects.
+            UpdateMessage::ModifiedFiles(files, reply) => {
+                emit(json!({ "event": "modified", "files": files }));
+                reply.send(vec![ModChoice::BackUpAndReplace; files.len()]).ok();
+            }
+            UpdateMessage::ReleaseNotes(notes) => emit(json!({
+                "event": "releaseNotes",
+                "version": notes.version,
+                "markdown": notes.markdown,
+            })),
+            UpdateMessage::RestartUpdater => {
+                return match env::current_exe().and_then(|exe| Command::new(exe).args(successor_args()).spawn()) {
+                    Ok(_) => {
+                        report::record_restart();
+                        emit(json!({ "event": "restart" }));
+                        0
+                    }
+                    Err(e) => {
+                        let message = format!("Failed to restart the updater: {}", e);
+                        let category = ErrorCategory::of(&e);
+                        report::record_error(&message, category);
+                        fail(&options, &target_path, vec![(message, category)], Some(category))
+                    }
+                };
+            }
+            UpdateMessage::RelaunchedElevated => {
+                report::record_restart();
+                emit(json!({ "event": "restart" }));
+                return 0;
+            }
+            UpdateMessage::UpdateComplete => {
+                emit(json!({ "event": "complete" }));
+                match postupdate::action(&options) {
+                    // Without a window, the notification is the summary.
+                    PostUpdateAction::Nothing | PostUpdateAction::Summary => {
+                        notify_updated(&target_path, old_version.as_deref())
+                    }
+                    action => {
+                        if let Err(message) = postupdate::run(&target_path, &action, &options.launch_args) {
+                            log::error!("{}", message);
+                            emit(json!({ "event": "error", "message": message }));
+                        }
+                    }
+                }
+                // Downloaded while the game runs, like after closing the window.
+                if !deferred_downloads.is_empty() {
+                    prefetch_updates(&target_path, &deferred_downloads);
+                }
+                return 0;
+            }
+            UpdateMessage::UpdateFailed(category) => return fail(&options, &target_path, errors, Some(category)),
+            UpdateMessage::Interrupted => {
+                emit(json!({ "event": "interrupted" }));
+                return exit::CANCELLED;
+            }
+        }
+    }
+    let category = errors.last().map(|(_, category)| *category);
+    fail(&options, &target_path, errors, category)
+}
+
+/// Tells the player the game was updated while nothing else shows it, since the game is not
+/// started. Clicking the notification starts it.
+fn notify_updated(target_path: &Path, old_version: Option<&str>) {
+    let Ok(info) = get_version_info(target_path) else {
+        return;
+    };
+    if old_version == Some(info.version_code.as_str()) {
+        return;
+    }
+    platform::show_notification(
+        target_path,
+        "DREAMIO update complete",
+        &format!("Version {} is installed. Click to play.", info.version_string),
+        NotificationClick::Game,
+    );
+}
+
+fn emit(event: Value) {
+    let line = event.to_string();
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line).ok();
+    stdout.flush().ok();
+    ipc::send(&line);
+}
+
+/// Ends an update that failed with an error of `category`. `errors` holds every error message
+/// with its category.
+fn fail(
+    options: &Options,
+    target_path: &Path,
+    errors: Vec<(String, ErrorCategory)>,
+    category: Option<ErrorCategory>,
+) -> i32 {
+    emit(json!({ "event": "failed" }));
+    if let (Some(endpoint), Some(category), false) = (&options.telemetry_endpoint, category, options.no_telemetry) {
+        telemetry::send_failure_report(endpoint, category, state::rollout_bucket(target_path));
+    }
+    // The launcher shows the failure on its own update screen.
+    if ipc::is_connected() {
+        return exit::for_failure(category);
+    }
+    let description = if errors.is_empty() {
+        "The update did not finish.".to_string()
+    } else if options.verbosity >= Verbosity::Verbose {
+        errors.iter().map(|(message, _)| message.as_str()).collect::<Vec<_>>().join("\n")
+    } else {
+        let summaries: Vec<String> = errors
+            .iter()
+            .map(|(message, category)| telemetry::summarize(message, *category).to_string())
+            .collect();
+        format!("{}\n\nThe full details are in updater.log.", summaries.join("\n"))
+    };
+    let help = help::for_error(target_path, category.unwrap_or(ErrorCategory::Other));
+    let description = format!("{}\n\n{}\n{}", description, help.text, help.url);
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title("DREAMIO update failed")
+        .set_description(description)
+        .set_buttons(MessageButtons::Ok)
+        .show();
+    exit::for_failure(category)
+}
@@ -0,0 +1,95 @@
+//! The threads that extract and patch files alongside each other. On CPUs with few cores they can
+//! starve the game when it is started while an update is still being applied, for example by a
+//! player who restarts it while `--windowless` or a maintenance update runs. How many there are,
+//! their priority and the cores they run on can be set in `updater.json`:
+//!
+//! ```json
+//! { "applyWorkers": 2, "applyWorkerPriority": "low", "applyWorkerCores": [2, 3] }
+//! ```
+//!
+//! By default, one core is left free on CPUs with four cores or fewer, and the workers run at low
+//! priority while the game is running.
+
+use serde_json::Value;
+use std::path::Path;
+use std::thread;
+
+use crate::is_game_running;
+use crate::platform::{self, ThreadPriority};
+
+/// Extraction and bspatch are CPU-bound, but more threads than this only fight over the disk.
+const MAX_WORKERS: usize = 8;
+/// CPUs with this many cores or fewer keep one free for the game and the system.
+const FEW_CORES: usize = 4;
+
+#[derive(Default)]
+pub struct WorkerSettings {
+    /// `None` picks the count from the number of cores.
+    pub count: Option<usize>,
+    /// `None` lowers the priority while the game is running.
+    pub priority: Option<ThreadPriority>,
+    /// Cores the workers are kept on, numbered from 0. Empty for any.
+    pub cores: Vec<usize>,
+}
+
+impl WorkerSettings {
+    pub fn parse(json: &Value) -> Self {
+        Self {
+            count: json["applyWorkers"].as_u64().filter(|&n| n > 0).map(|n| n as usize),
+            priority: json["applyWorkerPriority"].as_str().and_then(parse_priority),
+            cores: json["applyWorkerCores"]
+                .as_array()
+                .map(|cores| cores.iter().filter_map(|core| core.as_u64()).map(|core| core as usize).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let count = self.count.unwrap_or(if cores <= FEW_CORES { cores - 1 } else { cores });
+        count.clamp(1, MAX_WORKERS)
+    }
+
+    /// The priority of workers applying an update to `base_path`, checked when they start.
+    pub fn priority(&self, base_path: &Path) -> ThreadPriority {
+        self.priority.unwrap_or_else(|| {
+            if is_game_running(base_path) {
+                ThreadPriority::Low
+            } else {
+                ThreadPriority::Normal
+            }
+        })
+    }
+
+    /// Applies the priority and cores to the calling worker thread. Failures only cost speed, so
+    /// they are logged and the worker carries on.
+    pub fn enter(&self, priority: ThreadPriority) {
+        if priority != ThreadPriority::Normal {
+            if let Err(e) = platform::set_thread_priority(priority) {
+                log::warn!("Failed to lower the priority of an apply worker: {}", e);
+            }
+        }
+        if !self.cores.is_empty() {
+            if let Err(e) = platform::set_thread_affinity(&self.cores) {
+                log::warn!("Failed to keep an apply worker on cores {:?}: {}", self.cores, e);
+            }
+        }
+    }
+}
+
+fn parse_priority(name: &str) -> Option<ThreadPriority> {
+    match name.to_lowercase().as_str() {
+        "normal" => Some(ThreadPriority::Normal),
+        "low" => Some(ThreadPriority::Low),
+        "idle" => Some(ThreadPriority::Idle),
+        _ => None,
+    }
+}
+
+pub fn priority_name(priority: ThreadPriority) -> &'static str {
+    match priority {
+        ThreadPriority::Normal => "normal",
+        ThreadPriority::Low => "low",
+        ThreadPriority::Idle => "idle",
+    }
+}